@@ -55,6 +55,9 @@ impl fmt::Debug for GlyphPosition {
     }
 }
 
+/// `GlyphPosition`s are in HarfBuzz units: 1/128 px (see `text_shaping::HB_SCALE_FACTOR`)
+pub(crate) const HB_SCALE_FACTOR: f32 = 128.0;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FontMetrics {
     /// Font size that these metrics were created for, usually 1000px
@@ -68,6 +71,12 @@ pub struct FontMetrics {
     pub descender: i64,
     pub height: i64,
     pub max_advance: i64,
+    /// Set if the font has a broken `units_per_em` value and the metrics were
+    /// calculated with a best-guess value instead (so they might be wrong)
+    pub metrics_suspect: bool,
+    /// Maximum number of characters that any OpenType feature of the font looks at
+    /// at once (`usMaxContext` of the `OS/2` table), `None` if the font doesn't declare it
+    pub us_max_context: Option<u16>,
 }
 
 impl FontMetrics {
@@ -84,6 +93,8 @@ impl FontMetrics {
             descender: 0,
             height: 0,
             max_advance: 0,
+            metrics_suspect: false,
+            us_max_context: None,
         }
     }
 
@@ -126,6 +137,31 @@ impl FontMetrics {
         let s = self.max_advance as f32;
         s / (self.font_size as f32) * target_font_size
     }
+
+    /// Scales the `advances` (in font units) to the `target_font_size` and writes the rounded
+    /// cumulative advances (the pixel position of the pen after each glyph) into `out`.
+    ///
+    /// The positions are accumulated in 32.32 fixed point and only rounded when they are written,
+    /// so the rounding error never exceeds half a pixel, no matter how many glyphs there are
+    /// (rounding each advance before summing them up drifts by up to half a pixel per glyph).
+    pub fn scale_advances_rounded(&self, advances: &[u16], target_font_size: f32, out: &mut Vec<i32>) {
+
+        const FRACTIONAL_BITS: u32 = 32;
+
+        // Pixels per font unit in 32.32 fixed point: `x_scale` is a 16.16 value that converts
+        // font units to 26.6 pixels at `font_size / 64` pixels, so these factors cancel out
+        let scale = (self.x_scale as f64 * target_font_size as f64 / self.font_size as f64 * 65536.0).round() as i64;
+        let half = 1_i64 << (FRACTIONAL_BITS - 1);
+
+        out.clear();
+        out.reserve(advances.len());
+
+        let mut pen_position = 0_i64;
+        for advance in advances {
+            pen_position += *advance as i64 * scale;
+            out.push(((pen_position + half) >> FRACTIONAL_BITS) as i32);
+        }
+    }
 }
 
 #[repr(C)]
@@ -476,8 +512,27 @@ pub struct ScaledWords {
     pub space_advance_px: f32,
     /// Glyph index of the space character
     pub space_codepoint: u32,
+    /// Shaped ellipsis (`…`, or `...` if the font has no glyph for it),
+    /// which is inserted for `TextOverflowBehaviour::Ellipsis`
+    pub ellipsis: ScaledWord,
     /// Metrics necessary for baseline calculation
     pub font_metrics: FontMetrics,
+    /// Base (paragraph) direction that the words were shaped with
+    pub direction: TextDirection,
+}
+
+impl ScaledWords {
+    /// Returns the bidi embedding level of each word (i.e. for `InlineTextLayout::justify`):
+    /// words in the base direction are at the paragraph level (`0` for left-to-right, `1` for
+    /// right-to-left paragraphs), words in the opposite direction are one level higher
+    pub fn get_word_levels(&self) -> Vec<u8> {
+        self.items.iter().map(|word| match (self.direction, word.direction) {
+            (TextDirection::LeftToRight, TextDirection::LeftToRight) => 0,
+            (TextDirection::LeftToRight, TextDirection::RightToLeft) => 1,
+            (TextDirection::RightToLeft, TextDirection::RightToLeft) => 1,
+            (TextDirection::RightToLeft, TextDirection::LeftToRight) => 2,
+        }).collect()
+    }
 }
 
 /// Word that is scaled (to a font / font instance), but not yet positioned
@@ -490,6 +545,47 @@ pub struct ScaledWord {
     pub glyph_positions: Vec<GlyphPosition>,
     /// The sum of the width of all the characters in this word
     pub word_width: f32,
+    /// Base direction that the word was shaped with. The glyphs are always stored in
+    /// visual order (left to right on the screen), even if the direction is right-to-left.
+    pub direction: TextDirection,
+    /// For words shaped for a vertical writing mode: whether each glyph is set sideways
+    /// (rotated by 90° clockwise), empty for horizontal text
+    pub rotated_glyphs: Vec<bool>,
+}
+
+/// Base (paragraph) direction of a text
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TextDirection {
+    LeftToRight,
+    RightToLeft,
+}
+
+impl Default for TextDirection {
+    fn default() -> Self {
+        TextDirection::LeftToRight
+    }
+}
+
+/// Kind of whitespace that a `WhitespaceMarker` stands for
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WhitespaceKind {
+    /// Drawn as `·` in most editors
+    Space,
+    /// Drawn as `→` in most editors
+    Tab,
+    /// Drawn as `¶` or `↵` in most editors
+    LineEnd,
+}
+
+/// Position of a whitespace character in the layouted text, so that a renderer
+/// can draw a symbol for it without changing the text itself
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct WhitespaceMarker {
+    /// Same coordinate space as the `WordPositions::word_positions`
+    pub position: LayoutPoint,
+    /// Horizontal space that the whitespace takes up (`0.0` for line ends)
+    pub width: f32,
+    pub kind: WhitespaceKind,
 }
 
 /// Stores the positions of the vertically laid out texts
@@ -518,12 +614,20 @@ pub struct WordPositions {
     /// Note that the vertical extent can be larger than the last words' position,
     /// because of trailing negative glyph advances.
     pub content_size: LayoutSize,
+    /// Positions of the spaces, tabs and line breaks in the text, only
+    /// filled if `ResolvedTextLayoutOptions::show_whitespace` is set
+    pub whitespace_markers: Vec<WhitespaceMarker>,
+    /// Indices of the empty lines that separate two paragraphs, only
+    /// filled if `ResolvedTextLayoutOptions::paragraph_spacing` is set
+    pub paragraph_breaks: Vec<usize>,
 }
 
 /// Returns the layouted glyph instances
 #[derive(Debug, Clone, PartialEq)]
 pub struct LayoutedGlyphs {
     pub glyphs: Vec<GlyphInstance>,
+    /// Index of the word (see `ScaledWords::items`) that each glyph in `glyphs` belongs to
+    pub word_indices: Vec<usize>,
 }
 
 /// Iterator over glyphs that returns information about the cluster that this glyph belongs to.
@@ -586,6 +690,43 @@ impl<'a> Iterator for ClusterIterator<'a> {
 
 impl ScaledWord {
 
+    /// Creates a word with one glyph per character, each with the given advance (in pixels),
+    /// no kerning and no offsets. The glyph index of each glyph is the codepoint of the
+    /// character and the cluster is the byte offset of the character in the word.
+    ///
+    /// Only intended for testing the width / layout functions without having to load a font.
+    pub fn from_advances(advances: &[(char, u16)]) -> Self {
+
+        let mut cluster = 0;
+        let glyph_infos = advances.iter().map(|(c, _)| {
+            let glyph_info = GlyphInfo {
+                codepoint: *c as u32,
+                mask: 0,
+                cluster,
+                var1: HbVarIntT { u32: 0 },
+                var2: HbVarIntT { u32: 0 },
+            };
+            cluster += c.len_utf8() as u32;
+            glyph_info
+        }).collect();
+
+        let glyph_positions = advances.iter().map(|(_, advance)| GlyphPosition {
+            x_advance: (*advance as f32 * HB_SCALE_FACTOR) as i32,
+            y_advance: 0,
+            x_offset: 0,
+            y_offset: 0,
+            var: HbVarIntT { u32: 0 },
+        }).collect();
+
+        Self {
+            glyph_infos,
+            glyph_positions,
+            word_width: advances.iter().map(|(_, advance)| *advance as f32).sum(),
+            direction: TextDirection::LeftToRight,
+            rotated_glyphs: Vec::new(),
+        }
+    }
+
     /// Creates an iterator over clusters instead of glyphs
     pub fn cluster_iter<'a>(&'a self) -> ClusterIterator<'a> {
         ClusterIterator {
@@ -599,6 +740,79 @@ impl ScaledWord {
     pub fn number_of_clusters(&self) -> usize {
         self.cluster_iter().last().map(|l| l.cluster_idx).unwrap_or(0)
     }
+
+    /// Returns the advance of the word with `letter_spacing_px` added between its clusters.
+    ///
+    /// Spacing is never baked into `word_width` (which stays the natural advance from
+    /// the font), so the layout can be recomputed with a different spacing at any time.
+    pub fn get_adjusted_width(&self, letter_spacing_px: Option<f32>) -> f32 {
+        match letter_spacing_px {
+            None => self.word_width,
+            Some(spacing) => self.word_width + spacing * self.number_of_clusters().saturating_sub(1) as f32,
+        }
+    }
+
+    /// Returns the index of the (first) glyph that the source character with the given index is
+    /// drawn with, i.e. for placing a caret. `char_index` is in the same unit as the cluster of the
+    /// glyphs (byte offsets for text that was shaped from a `&str`, character indices for text that
+    /// was shaped from a `&[char]`). All characters of a ligature map to the ligature glyph, a character
+    /// that was decomposed into multiple glyphs maps to the first of them (in visual order).
+    ///
+    /// Returns `None` if the word has no glyphs or the character is before the first cluster.
+    pub fn glyph_for_char(&self, char_index: usize) -> Option<usize> {
+        // The glyph covering the character is the glyph with the highest cluster that starts
+        // at or before the character (works for both left-to-right and right-to-left glyph order)
+        let cluster = self.glyph_infos.iter()
+            .map(|glyph_info| glyph_info.cluster as usize)
+            .filter(|cluster| *cluster <= char_index)
+            .max()?;
+        self.glyph_infos.iter().position(|glyph_info| glyph_info.cluster as usize == cluster)
+    }
+
+    /// Returns how many leading clusters of the word fit into `max_width` pixels, i.e. for
+    /// truncating a single line of text without appending an ellipsis. A cluster (i.e. a
+    /// ligature or a base character with its combining marks) is never split, it either fits
+    /// as a whole or not at all. Returns `number_of_clusters()` if the whole word fits.
+    pub fn chars_fitting_width(&self, max_width: f32) -> usize {
+
+        const EPSILON: f32 = 0.001;
+
+        let mut current_width = 0.0;
+
+        for cluster_info in self.cluster_iter() {
+            current_width += self.glyph_positions[cluster_info.glyph_idx].x_advance as f32 / HB_SCALE_FACTOR;
+            if current_width > max_width + EPSILON {
+                // Cluster indices start at 1, so all clusters before this one fit completely
+                return cluster_info.cluster_idx - 1;
+            }
+        }
+
+        self.number_of_clusters()
+    }
+
+    /// Returns the visual position (index into `glyph_infos`, which are stored in visual order)
+    /// of every glyph, in logical order - i.e. `logical_order()[0]` is the glyph of the first
+    /// character of the word. Glyphs are ordered by their cluster, glyphs of the same cluster
+    /// are in reading direction of the word. For left-to-right words this is the identity.
+    pub fn logical_order(&self) -> Vec<usize> {
+        let is_rtl = self.direction == TextDirection::RightToLeft;
+        let mut order = (0..self.glyph_infos.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| {
+            self.glyph_infos[*a].cluster.cmp(&self.glyph_infos[*b].cluster)
+                .then_with(|| if is_rtl { b.cmp(a) } else { a.cmp(b) })
+        });
+        order
+    }
+
+    /// Inverse of `logical_order()`: returns the logical index of the glyph
+    /// at every visual position (i.e. for every glyph in `glyph_infos`)
+    pub fn visual_order(&self) -> Vec<usize> {
+        let mut order = vec![0; self.glyph_infos.len()];
+        for (logical_idx, visual_idx) in self.logical_order().into_iter().enumerate() {
+            order[visual_idx] = logical_idx;
+        }
+        order
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -726,6 +940,18 @@ impl AppResources {
         self.currently_registered_fonts.get(pipeline_id).and_then(|map| map.get(font_id))
     }
 
+    /// Returns the font instance (font + size) that the text of a node with the given style is
+    /// rendered with, or `None` if the font (or the instance for the font size) isn't loaded
+    pub fn get_font_instance_key(&self, pipeline_id: &PipelineId, rect_style: &RectStyle) -> Option<FontInstanceKey> {
+        let css_font_id = get_font_id(rect_style);
+        let font_id = match self.get_css_font_id(css_font_id) {
+            Some(s) => ImmediateFontId::Resolved(*s),
+            None => ImmediateFontId::Unresolved(css_font_id.to_string()),
+        };
+        let font_size_au = font_size_to_au(get_font_size(rect_style));
+        self.get_loaded_font(pipeline_id, &font_id)?.font_instances.get(&font_size_au).copied()
+    }
+
     // -- TextId cache
 
     /// Adds a string to the internal text cache, but only store it as a string,
@@ -928,6 +1154,60 @@ impl Default for FontInstanceOptions {
 }
 
 
+/// How finely the horizontal subpixel position of a glyph is quantized
+/// before the glyph is rasterized, see `GlyphRasterKey`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum SubpixelQuantization {
+    /// Glyphs are always rasterized at whole pixels
+    None,
+    /// Glyphs are rasterized at 0, 1/3 and 2/3 of a pixel
+    Thirds,
+    /// Glyphs are rasterized at 0, 1/4, 2/4 and 3/4 of a pixel
+    Quarters,
+}
+
+impl Default for SubpixelQuantization {
+    fn default() -> Self { SubpixelQuantization::None }
+}
+
+impl SubpixelQuantization {
+
+    /// Returns the number of subpixel positions that a glyph can be rasterized at
+    pub const fn bucket_count(&self) -> u8 {
+        match self {
+            SubpixelQuantization::None => 1,
+            SubpixelQuantization::Thirds => 3,
+            SubpixelQuantization::Quarters => 4,
+        }
+    }
+
+    /// Returns the subpixel bucket that the horizontal position `x` (in pixels) falls into
+    pub fn get_bucket(&self, x: f32) -> u8 {
+        let buckets = self.bucket_count();
+        let fraction = x - x.floor();
+        ((fraction * buckets as f32) as u8).min(buckets - 1)
+    }
+}
+
+/// Key for caching the rasterized glyph: Glyphs that only differ by a subpixel
+/// offset within the same bucket share the same raster
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct GlyphRasterKey {
+    pub font_instance_key: FontInstanceKey,
+    pub glyph_index: GlyphIndex,
+    pub subpixel_bucket: u8,
+}
+
+impl GlyphRasterKey {
+    pub fn new(font_instance_key: FontInstanceKey, glyph_index: GlyphIndex, x: f32, quantization: SubpixelQuantization) -> Self {
+        Self {
+            font_instance_key,
+            glyph_index,
+            subpixel_bucket: quantization.get_bucket(x),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub struct SyntheticItalics {
     pub angle: i16,
@@ -1542,4 +1822,118 @@ fn test_premultiply() {
     let mut color = [255, 0, 0, 127];
     premultiply(&mut color);
     assert_eq!(color, [127, 0, 0, 127]);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_subpixel_quantization_buckets() {
+    let q = SubpixelQuantization::Thirds;
+    assert_eq!(q.get_bucket(0.1), q.get_bucket(0.2));
+    assert_ne!(q.get_bucket(0.1), q.get_bucket(0.4));
+    assert_eq!(q.get_bucket(5.1), q.get_bucket(0.1));
+    assert_eq!(SubpixelQuantization::None.get_bucket(0.9), 0);
+    assert_eq!(SubpixelQuantization::Quarters.get_bucket(0.99), 3);
+
+    let key = FontInstanceKey { namespace: IdNamespace(0), key: 0 };
+    assert_eq!(GlyphRasterKey::new(key, 5, 10.1, q), GlyphRasterKey::new(key, 5, 10.2, q));
+    assert_ne!(GlyphRasterKey::new(key, 5, 10.1, q), GlyphRasterKey::new(key, 5, 10.4, q));
+}
+
+#[test]
+fn test_scaled_word_from_advances() {
+    let word = ScaledWord::from_advances(&[('a', 10), ('ü', 12), ('c', 8)]);
+    assert_eq!(word.word_width, 30.0);
+    assert_eq!(word.glyph_positions.iter().map(|p| p.x_advance).sum::<i32>(), 30 * 128);
+    assert_eq!(word.glyph_infos.iter().map(|i| i.cluster).collect::<Vec<_>>(), vec![0, 1, 3]);
+    assert_eq!(word.number_of_clusters(), 3);
+    assert_eq!(word.get_adjusted_width(Some(1.0)), 32.0);
+}
+
+#[test]
+fn test_scaled_word_glyph_for_char() {
+
+    // "x" + "fi" ligature (one glyph for chars 1 and 2) + "é" decomposed into two glyphs (chars 3)
+    let mut word = ScaledWord::from_advances(&[('x', 10), ('f', 10), ('e', 10), ('\u{301}', 0)]);
+    word.glyph_infos[0].cluster = 0;
+    word.glyph_infos[1].cluster = 1;
+    word.glyph_infos[2].cluster = 3;
+    word.glyph_infos[3].cluster = 3;
+
+    assert_eq!(word.glyph_for_char(0), Some(0));
+    assert_eq!(word.glyph_for_char(1), Some(1));
+    assert_eq!(word.glyph_for_char(2), Some(1));
+    assert_eq!(word.glyph_for_char(3), Some(2));
+
+    // Right-to-left words store the glyphs in visual order (descending clusters)
+    word.glyph_infos.reverse();
+    assert_eq!(word.glyph_for_char(3), Some(0));
+    assert_eq!(word.glyph_for_char(2), Some(2));
+    assert_eq!(word.glyph_for_char(0), Some(3));
+
+    assert_eq!(ScaledWord::from_advances(&[]).glyph_for_char(0), None);
+}
+
+#[test]
+fn test_scaled_word_logical_visual_order() {
+
+    let ltr = ScaledWord::from_advances(&[('a', 10), ('b', 10), ('c', 10)]);
+    assert_eq!(ltr.logical_order(), vec![0, 1, 2]);
+    assert_eq!(ltr.visual_order(), vec![0, 1, 2]);
+
+    // Right-to-left word, reordered into visual order: chars 0, 1 and 2 (where char 2 is
+    // drawn with a base glyph and a mark glyph) are displayed as [2 (mark), 2 (base), 1, 0]
+    let mut rtl = ScaledWord::from_advances(&[('\u{5BC}', 0), ('c', 10), ('b', 10), ('a', 10)]);
+    rtl.direction = TextDirection::RightToLeft;
+    for (glyph_info, cluster) in rtl.glyph_infos.iter_mut().zip([2, 2, 1, 0].iter()) {
+        glyph_info.cluster = *cluster;
+    }
+
+    assert_eq!(rtl.logical_order(), vec![3, 2, 1, 0]);
+    assert_eq!(rtl.visual_order(), vec![3, 2, 1, 0]);
+
+    let logical_order = rtl.logical_order();
+    let visual_order = rtl.visual_order();
+    for visual_idx in 0..rtl.glyph_infos.len() {
+        assert_eq!(logical_order[visual_order[visual_idx]], visual_idx);
+    }
+}
+
+#[test]
+fn test_scaled_word_chars_fitting_width() {
+
+    let word = ScaledWord::from_advances(&[('a', 10), ('b', 10), ('c', 10), ('d', 10)]);
+    assert_eq!(word.chars_fitting_width(25.0), 2);
+    assert_eq!(word.chars_fitting_width(30.0), 3);
+    assert_eq!(word.chars_fitting_width(5.0), 0);
+    assert_eq!(word.chars_fitting_width(100.0), 4);
+
+    // "f" + "i" form one cluster (like a ligature), which doesn't fit partially
+    let mut word = ScaledWord::from_advances(&[('x', 10), ('f', 6), ('i', 6), ('y', 10)]);
+    word.glyph_infos[2].cluster = word.glyph_infos[1].cluster;
+    assert_eq!(word.number_of_clusters(), 3);
+    assert_eq!(word.chars_fitting_width(20.0), 1);
+    assert_eq!(word.chars_fitting_width(22.0), 2);
+}
+
+#[test]
+fn test_font_metrics_scale_advances_rounded() {
+
+    // 2048 units per em, measured at a font size of 1000 (as returned by FreeType)
+    let font_metrics = FontMetrics { x_scale: 1000 * 65536 / 2048, .. FontMetrics::zero() };
+    let pixels_per_unit = font_metrics.get_x_scale(13.0) as f64 / 65536.0;
+
+    // Pseudo-random advances between 0 and 2047 font units
+    let advances = (0..1000_u32).map(|i| ((i * 7919 + 13) % 2048) as u16).collect::<Vec<_>>();
+
+    let mut rounded = vec![42];
+    font_metrics.scale_advances_rounded(&advances, 13.0, &mut rounded);
+    assert_eq!(rounded.len(), advances.len());
+
+    let mut reference = 0.0_f64;
+    for (advance, rounded) in advances.iter().zip(rounded.iter()) {
+        reference += *advance as f64 * pixels_per_unit;
+        assert!((*rounded as f64 - reference).abs() <= 1.0, "{} != {}", rounded, reference);
+    }
+
+    // No error accumulates over the 1000 glyphs
+    assert!((*rounded.last().unwrap() as f64 - reference).abs() <= 0.5 + 1e-3);
+}
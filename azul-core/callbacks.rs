@@ -13,7 +13,7 @@ use azul_css_parser::CssPathParseError;
 use crate::{
     FastHashMap,
     app_resources::{Words, WordPositions, ScaledWords, LayoutedGlyphs},
-    dom::{Dom, DomId, TagId, NodeType, NodeData},
+    dom::{Dom, DomId, TagId, NodeType, NodeData, EventFilter},
     display_list::CachedDisplayList,
     ui_state::UiState,
     ui_description::UiDescription,
@@ -185,6 +185,25 @@ pub struct HitTestItem {
     pub point_relative_to_item: LayoutPoint,
 }
 
+/// Data model for tests that only need a `Dom` with callbacks
+#[cfg(test)]
+pub(crate) struct TestLayout;
+
+/// Callback for tests that only check which callbacks are registered / fired
+#[cfg(test)]
+pub(crate) fn do_nothing(_: CallbackInfo<TestLayout>) -> CallbackReturn { DontRedraw }
+
+/// Hit on the display item with the given tag, at the origin of the viewport
+#[cfg(test)]
+pub(crate) fn get_test_hit_test_item(tag: TagId) -> HitTestItem {
+    HitTestItem {
+        pipeline: PipelineId::DUMMY,
+        tag,
+        point_in_viewport: LayoutPoint::zero(),
+        point_relative_to_item: LayoutPoint::zero(),
+    }
+}
+
 /// Implements `Display, Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Hash`
 /// for a Callback with a `.0` field:
 ///
@@ -346,6 +365,9 @@ pub struct DefaultCallbackInfo<'a, T> {
     pub cursor_relative_to_item: Option<(f32, f32)>,
     /// The (x, y) position of the mouse cursor, **relative to top left of the window**.
     pub cursor_in_viewport: Option<(f32, f32)>,
+    /// The event that triggered this callback (i.e. `Hover(MouseUp)` for a click). The pressed
+    /// keys / typed characters of keyboard events are in the `get_keyboard_state()`.
+    pub event: EventFilter,
 }
 
 /// Callback that is invoked "by default", for example a text field that always
@@ -408,6 +430,9 @@ pub struct CallbackInfo<'a, T: 'a> {
     pub cursor_relative_to_item: Option<(f32, f32)>,
     /// The (x, y) position of the mouse cursor, **relative to top left of the window**.
     pub cursor_in_viewport: Option<(f32, f32)>,
+    /// The event that triggered this callback (i.e. `Hover(MouseUp)` for a click). The pressed
+    /// keys / typed characters of keyboard events are in the `get_keyboard_state()`.
+    pub event: EventFilter,
 }
 pub type CallbackReturn = UpdateScreen;
 pub type CallbackType<T> = fn(CallbackInfo<T>) -> CallbackReturn;
@@ -432,6 +457,7 @@ impl<'a, T: 'a> fmt::Debug for CallbackInfo<'a, T> {
             hit_dom_node: {:?}, \
             cursor_relative_to_item: {:?}, \
             cursor_in_viewport: {:?}, \
+            event: {:?}, \
         }}",
             self.current_window_state,
             self.modifiable_window_state,
@@ -445,6 +471,7 @@ impl<'a, T: 'a> fmt::Debug for CallbackInfo<'a, T> {
             self.hit_dom_node,
             self.cursor_relative_to_item,
             self.cursor_in_viewport,
+            self.event,
         )
     }
 }
@@ -1,10 +1,11 @@
 use std::{
     fmt,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
 };
 use crate::{
     id_tree::{NodeId, NodeDataContainer},
-    dom::{Dom, NodeData},
+    dom::{Dom, DomId, DomString, NodeData},
+    window::FullWindowState,
 };
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -61,6 +62,9 @@ impl fmt::Display for DomChange {
 pub struct DomDiff {
     /// What the actual changes nodes (not trees / subtrees) were in this diff, in order of appearance
     pub changed_nodes: Vec<DomChange>,
+    /// Nodes of the old DOM with a key (or an ID) -> the node with the same key in
+    /// the new DOM, `None` if there is no node with that key in the new DOM anymore
+    pub keyed_nodes: BTreeMap<NodeId, Option<NodeId>>,
 }
 
 impl DomDiff {
@@ -68,6 +72,11 @@ impl DomDiff {
 
         // TODO: Check if old root = new root, if not, change entire tree
 
+        let new_keys = get_node_keys(new);
+        let keyed_nodes = get_node_keys(old).into_iter()
+            .map(|(key, old_node_id)| (old_node_id, new_keys.get(&key).cloned()))
+            .collect();
+
         let mut changes = BTreeSet::new();
         let mut visited_nodes = NodeDataContainer::new(vec![false; new.len()]);
 
@@ -84,7 +93,8 @@ impl DomDiff {
             add_visited_nodes(visited_nodes, &mut changes);
 
             Self {
-                changed_nodes: optimize_changeset(changes)
+                changed_nodes: optimize_changeset(changes),
+                keyed_nodes,
             }
 
         } else {
@@ -101,11 +111,48 @@ impl DomDiff {
             }));
 
             Self {
-                changed_nodes: optimize_changeset(changes)
+                changed_nodes: optimize_changeset(changes),
+                keyed_nodes,
             }
         }
     }
 
+    /// Returns the ID that a node of the old DOM has in the new DOM: Nodes with a key (or an ID)
+    /// are matched by their key, regardless of their position. Returns `None` if the keyed node
+    /// doesn't exist in the new DOM anymore. Nodes without a key keep their node ID.
+    pub fn remap_node_id(&self, old_node_id: NodeId) -> Option<NodeId> {
+        match self.keyed_nodes.get(&old_node_id) {
+            Some(new_node_id) => *new_node_id,
+            None => Some(old_node_id),
+        }
+    }
+
+    /// Carries the runtime state of the nodes (focus, hover - which also determines the
+    /// `:active` state - and the nodes that the mouse was pressed / clicked on, so that
+    /// drags and clicks continue) of the DOM `dom_id` over to the new DOM
+    pub fn preserve_node_state(&self, dom_id: &DomId, window_state: &mut FullWindowState) {
+
+        if let Some((focused_dom, focused_node)) = window_state.focused_node.clone() {
+            if focused_dom == *dom_id {
+                window_state.focused_node = self.remap_node_id(focused_node).map(|node_id| (focused_dom, node_id));
+            }
+        }
+
+        if let Some(hovered_nodes) = window_state.hovered_nodes.get_mut(dom_id) {
+            *hovered_nodes = hovered_nodes.iter()
+                .filter_map(|(node_id, hit_test_item)| Some((self.remap_node_id(*node_id)?, *hit_test_item)))
+                .collect();
+        }
+
+        if let Some(pressed_nodes) = window_state.pressed_nodes.get_mut(dom_id) {
+            *pressed_nodes = pressed_nodes.iter().filter_map(|node_id| self.remap_node_id(*node_id)).collect();
+        }
+
+        if let Some((_, last_clicked_nodes)) = window_state.last_clicked_nodes.get_mut(dom_id) {
+            *last_clicked_nodes = last_clicked_nodes.iter().filter_map(|node_id| self.remap_node_id(*node_id)).collect();
+        }
+    }
+
     /// Formats the diff into a git-like `+ Node1 / - Node3` form
     pub fn format_nicely<T>(&self, old: &Dom<T>, new: &Dom<T>) -> String {
         use self::DomChange::*;
@@ -148,6 +195,7 @@ const NODE_CHANGED_NOTHING: u8  = 0x01;
 const NODE_CHANGED_TYPE: u8     = 0x02;
 const NODE_CHANGED_CLASSES: u8  = 0x04;
 const NODE_CHANGED_IDS: u8      = 0x08;
+const NODE_CHANGED_KEY: u8      = 0x10;
 
 // In order to test two DOM nodes for "equality", you'd need to
 // test if the node type, the classes and the ids are the same.
@@ -171,6 +219,10 @@ fn node_has_changed<T>(old: &NodeData<T>, new: &NodeData<T>) -> u8 {
         result &= NODE_CHANGED_IDS;
     }
 
+    if old.get_key() != new.get_key() {
+        result &= NODE_CHANGED_KEY;
+    }
+
     result
 }
 
@@ -242,6 +294,31 @@ fn diff_tree_inner<T>(
     }
 }
 
+/// Returns the key of every node (or the first ID of the node, if it doesn't
+/// have a key). Keys that are used by more than one node are ambiguous and ignored.
+fn get_node_keys<T>(dom: &Dom<T>) -> BTreeMap<DomString, NodeId> {
+
+    let mut keys = BTreeMap::new();
+    let mut duplicate_keys = BTreeSet::new();
+
+    for node_id in dom.arena.node_data.linear_iter() {
+        let node_data = &dom.arena.node_data[node_id];
+        let key = match node_data.get_key().as_ref().or(node_data.get_ids().first()) {
+            Some(s) => s.clone(),
+            None => continue,
+        };
+        if keys.insert(key.clone(), node_id).is_some() {
+            duplicate_keys.insert(key);
+        }
+    }
+
+    for key in duplicate_keys {
+        keys.remove(&key);
+    }
+
+    keys
+}
+
 fn add_visited_nodes(
     visited_nodes: NodeDataContainer<bool>,
     changes: &mut BTreeSet<DomChange>,
@@ -249,7 +326,7 @@ fn add_visited_nodes(
     changes.extend(
         visited_nodes
         .linear_iter()
-        .filter_map(|node_id| if visited_nodes[node_id] { None } else { Some(node_id) })
+        .filter(|node_id| !visited_nodes[*node_id])
         .map(|node_id| DomChange::Added(DomRange::single_node(node_id)))
     );
 }
@@ -258,3 +335,42 @@ fn optimize_changeset(changes: BTreeSet<DomChange>) -> Vec<DomChange> {
     // TODO: optimize changeset into larger chunks!
     changes.into_iter().collect()
 }
+
+#[test]
+fn test_keyed_node_keeps_focus_across_diff() {
+
+    struct Mock;
+
+    let old: Dom<Mock> = Dom::div()
+        .with_child(Dom::div().key("a"))
+        .with_child(Dom::div().key("b"))
+        .with_child(Dom::div().key("c"));
+
+    let new: Dom<Mock> = Dom::div()
+        .with_child(Dom::label("inserted"))
+        .with_child(Dom::div().key("a"))
+        .with_child(Dom::div().key("b"));
+
+    let diff = DomDiff::new(&old, &new);
+
+    assert_eq!(diff.remap_node_id(NodeId::new(2)), Some(NodeId::new(3)));
+    assert_eq!(diff.remap_node_id(NodeId::new(3)), None);
+    assert_eq!(diff.remap_node_id(NodeId::ZERO), Some(NodeId::ZERO));
+
+    let mut window_state = FullWindowState {
+        focused_node: Some((DomId::ROOT_ID, NodeId::new(2))),
+        .. FullWindowState::default()
+    };
+    diff.preserve_node_state(&DomId::ROOT_ID, &mut window_state);
+    assert_eq!(window_state.focused_node, Some((DomId::ROOT_ID, NodeId::new(3))));
+
+    // Focused node was removed from the DOM
+    window_state.focused_node = Some((DomId::ROOT_ID, NodeId::new(3)));
+    diff.preserve_node_state(&DomId::ROOT_ID, &mut window_state);
+    assert_eq!(window_state.focused_node, None);
+
+    // The mouse was pressed on "a" and "c" (i.e. at the start of a drag)
+    window_state.pressed_nodes.insert(DomId::ROOT_ID, vec![NodeId::new(1), NodeId::new(3)].into_iter().collect());
+    diff.preserve_node_state(&DomId::ROOT_ID, &mut window_state);
+    assert_eq!(window_state.pressed_nodes[&DomId::ROOT_ID], vec![NodeId::new(2)].into_iter().collect());
+}
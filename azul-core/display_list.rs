@@ -440,11 +440,15 @@ impl DisplayList {
 
         let mut override_warnings = Vec::new();
 
-        let display_rect_arena = arena.node_data.transform(|_, node_id| {
+        let display_rect_arena = arena.node_data.transform(|node_data, node_id| {
             let tag = ui_state.node_ids_to_tag_ids.get(&node_id).map(|tag| *tag);
             let style = &ui_description.styled_nodes[node_id];
             let mut rect = DisplayRectangle::new(tag);
             override_warnings.append(&mut populate_css_properties(&mut rect, node_id, &ui_description.dynamic_css_overrides, &style));
+            // `display_none` takes precedence over any `display` property from the CSS
+            if node_data.get_display_none() {
+                rect.layout.display = Some(CssPropertyValue::None);
+            }
             rect
         });
 
@@ -840,6 +844,17 @@ impl SolvedLayout {
 
         // Delete unused font and image keys (that were not used in this display list)
         garbage_collect_fonts_and_images(app_resources, render_api, &pipeline_id);
+
+        // Drop the cached text of the nodes whose font instance changed in the meantime
+        for (dom_id, layout_result) in solved_layout_cache.solved_layouts.iter_mut() {
+            let rectangles = &solved_layout_cache.display_lists[dom_id].rectangles;
+            let text_nodes = layout_result.scaled_words.keys().copied().collect::<Vec<_>>();
+            for node_id in text_nodes {
+                if let Some(font_instance_key) = app_resources.get_font_instance_key(&pipeline_id, &rectangles[node_id].style) {
+                    layout_result.invalidate_stale_font_instance(&node_id, &font_instance_key);
+                }
+            }
+        }
         // Add the new GL textures to the RenderApi
         add_resources(app_resources, render_api, &pipeline_id, Vec::new(), image_resource_updates);
 
@@ -921,8 +936,6 @@ pub fn get_nodes_that_need_scroll_clip<T>(
     pipeline_id: PipelineId,
 ) -> ScrolledNodes {
 
-    use azul_css::Overflow;
-
     let mut nodes = BTreeMap::new();
     let mut tags_to_node_ids = BTreeMap::new();
 
@@ -930,20 +943,16 @@ pub fn get_nodes_that_need_scroll_clip<T>(
 
         let parent_rect = &layouted_rects[*parent];
 
-        let children_scroll_rect = match parent_rect.bounds.get_scroll_rect(parent.children(&node_hierarchy).map(|child_id| layouted_rects[child_id].bounds)) {
-            None => continue,
-            Some(sum) => sum,
-        };
-
-        // Check if the scroll rect overflows the parent bounds
-        if contains_rect_rounded(&parent_rect.bounds, children_scroll_rect) {
+        // "overflow: scroll" always needs a scroll frame, "overflow: auto" only if
+        // the content of the node (its children or its text) overflows the node
+        if !parent_rect.needs_scroll_frame() {
             continue;
         }
 
-        // If the overflow isn't "scroll", then there doesn't need to be a scroll frame
-        if parent_rect.overflow == Overflow::Visible || parent_rect.overflow == Overflow::Hidden {
-            continue;
-        }
+        // The scrollable area covers the children as well as the content size of the node
+        let content_rect = LayoutRect::new(parent_rect.bounds.origin, parent_rect.content_size.unwrap_or(parent_rect.bounds.size));
+        let children_rects = parent.children(&node_hierarchy).map(|child_id| layouted_rects[child_id].bounds);
+        let children_scroll_rect = parent_rect.bounds.get_scroll_rect(children_rects.chain(Some(content_rect))).unwrap_or(parent_rect.bounds);
 
         let parent_dom_hash = dom_rects[*parent].calculate_node_data_hash();
 
@@ -997,6 +1006,18 @@ pub fn push_rectangles_into_displaylist<'a, T>(
     referenced_content: &DisplayListParametersRef<'a, T>,
 ) -> DisplayListMsg {
 
+    let dom_id = &referenced_content.dom_id;
+    let node_data = &referenced_content.ui_state_cache[dom_id].dom.arena.node_data[root_content_group.root];
+
+    // Hidden nodes keep their layout rectangle, but neither
+    // the node itself nor any of its children are drawn or hit-tested
+    if node_data.get_display_none() || node_data.get_visibility_hidden() {
+        let bounds = referenced_content.layout_result.solved_layouts[dom_id].rects[root_content_group.root].bounds;
+        let mut frame = DisplayListFrame::root(bounds.size);
+        frame.rect = bounds;
+        return DisplayListMsg::Frame(frame);
+    }
+
     let mut content = displaylist_handle_rect(
         root_content_group.root,
         referenced_content,
@@ -1104,13 +1125,18 @@ pub fn displaylist_handle_rect<'a, T>(
     match html_node {
         Div => { },
         Text(_) | Label(_) => {
-            if let Some(layouted_glyphs) = layout_result.solved_layouts.get(dom_id).and_then(|lr| lr.layouted_glyph_cache.get(&rect_idx)).cloned() {
+            // Only draw the glyphs if they were positioned with the current font instance of the node
+            let layouted_glyphs = app_resources.get_font_instance_key(pipeline_id, &rect.style).and_then(|font_instance_key| {
+                let solved_layout = layout_result.solved_layouts.get(dom_id)?;
+                solved_layout.get_word_positions(&rect_idx, &font_instance_key)?;
+                Some((solved_layout.layouted_glyph_cache.get(&rect_idx).cloned()?, font_instance_key))
+            });
+
+            if let Some((layouted_glyphs, font_instance_key)) = layouted_glyphs {
 
                 use crate::ui_solver::DEFAULT_FONT_COLOR;
 
                 let text_color = rect.style.text_color.and_then(|tc| tc.get_property().cloned()).unwrap_or(DEFAULT_FONT_COLOR).0;
-                let positioned_words = &layout_result.solved_layouts[dom_id].positioned_word_cache[&rect_idx];
-                let font_instance_key = positioned_words.1;
 
                 frame.content.push(get_text(
                     display_list_rect_bounds,
@@ -1254,18 +1280,9 @@ pub fn get_text(
 }
 
 /// Subtracts the padding from the bounds, returning the new bounds
-///
-/// Warning: The resulting rectangle may have negative width or height
+/// (the width and height of the resulting rectangle are clamped at zero)
 pub fn subtract_padding(bounds: &LayoutRect, padding: &ResolvedOffsets) -> LayoutRect {
-
-    let mut new_bounds = *bounds;
-
-    new_bounds.origin.x += padding.left;
-    new_bounds.size.width -= padding.right + padding.left;
-    new_bounds.origin.y += padding.top;
-    new_bounds.size.height -= padding.top + padding.bottom;
-
-    new_bounds
+    padding.inset_rect(bounds)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
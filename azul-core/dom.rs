@@ -28,6 +28,11 @@ pub struct TagId(pub u64);
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub struct ScrollTagId(pub TagId);
 
+/// Backend-neutral form of a `TagId` that hit-testing backends operate on
+/// (a distinct type, so that it can't be mixed up with other `u64` IDs).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub struct HitTestTag(pub u64);
+
 impl TagId {
     pub fn new() -> Self {
         TagId(TAG_ID.fetch_add(1, Ordering::SeqCst) as u64)
@@ -35,6 +40,16 @@ impl TagId {
     pub fn reset() {
         TAG_ID.swap(1, Ordering::SeqCst);
     }
+    /// Converts the tag into the form that is submitted to the hit-testing backend
+    #[inline(always)]
+    pub const fn into_hit_test_tag(self) -> HitTestTag {
+        HitTestTag(self.0)
+    }
+    /// Converts a tag returned by the hit-testing backend back into a `TagId`
+    #[inline(always)]
+    pub const fn from_hit_test_tag(tag: HitTestTag) -> Self {
+        TagId(tag.0)
+    }
 }
 
 impl ScrollTagId {
@@ -236,6 +251,13 @@ pub enum On {
     /// (Specialization of `MouseUp`). Fires only if the right mouse button has
     /// been released while cursor was over the element
     RightMouseUp,
+    /// A mouse button has been pressed and released again while the cursor
+    /// was over the element (i.e. a "click" - pressing the button on one element
+    /// and releasing it on another one does not fire this event)
+    Click,
+    /// Two `Click`s on the same element within `DEFAULT_DOUBLE_CLICK_THRESHOLD_MS`.
+    /// Note that the `Click` callback still fires for both clicks.
+    DoubleClick,
     /// Mouse cursor has entered the element
     MouseEnter,
     /// Mouse cursor has left the element
@@ -254,6 +276,11 @@ pub enum On {
     VirtualKeyDown,
     /// A **virtual keycode** was release. See `VirtualKeyDown` for more info.
     VirtualKeyUp,
+    /// Same as `VirtualKeyDown`, but only fires if the element is focused
+    /// (i.e. for handling keyboard input in a text field)
+    KeyDown,
+    /// Same as `VirtualKeyUp`, but only fires if the element is focused
+    KeyUp,
     /// A file has been dropped on the element
     HoveredFile,
     /// A file is being hovered on the element
@@ -348,6 +375,8 @@ impl From<On> for EventFilter {
             LeftMouseUp          => EventFilter::Hover(HoverEventFilter::LeftMouseUp),
             MiddleMouseUp        => EventFilter::Hover(HoverEventFilter::MiddleMouseUp),
             RightMouseUp         => EventFilter::Hover(HoverEventFilter::RightMouseUp),
+            Click                => EventFilter::Hover(HoverEventFilter::Click),
+            DoubleClick          => EventFilter::Hover(HoverEventFilter::DoubleClick),
 
             MouseEnter           => EventFilter::Hover(HoverEventFilter::MouseEnter),
             MouseLeave           => EventFilter::Hover(HoverEventFilter::MouseLeave),
@@ -355,6 +384,8 @@ impl From<On> for EventFilter {
             TextInput            => EventFilter::Focus(FocusEventFilter::TextInput),            // focus!
             VirtualKeyDown       => EventFilter::Window(WindowEventFilter::VirtualKeyDown),     // window!
             VirtualKeyUp         => EventFilter::Window(WindowEventFilter::VirtualKeyUp),       // window!
+            KeyDown              => EventFilter::Focus(FocusEventFilter::VirtualKeyDown),       // focus!
+            KeyUp                => EventFilter::Focus(FocusEventFilter::VirtualKeyUp),         // focus!
             HoveredFile          => EventFilter::Hover(HoverEventFilter::HoveredFile),
             DroppedFile          => EventFilter::Hover(HoverEventFilter::DroppedFile),
             HoveredFileCancelled => EventFilter::Hover(HoverEventFilter::HoveredFileCancelled),
@@ -376,6 +407,8 @@ pub enum HoverEventFilter {
     LeftMouseUp,
     RightMouseUp,
     MiddleMouseUp,
+    Click,
+    DoubleClick,
     MouseEnter,
     MouseLeave,
     Scroll,
@@ -402,6 +435,9 @@ impl HoverEventFilter {
             LeftMouseUp => Some(FocusEventFilter::LeftMouseUp),
             RightMouseUp => Some(FocusEventFilter::RightMouseUp),
             MiddleMouseUp => Some(FocusEventFilter::MiddleMouseUp),
+            // Click and DoubleClick are synthesized per hovered node, see `determine_callbacks`
+            Click => None,
+            DoubleClick => None,
             MouseEnter => Some(FocusEventFilter::MouseEnter),
             MouseLeave => Some(FocusEventFilter::MouseLeave),
             Scroll => Some(FocusEventFilter::Scroll),
@@ -409,7 +445,7 @@ impl HoverEventFilter {
             ScrollEnd => Some(FocusEventFilter::ScrollEnd),
             TextInput => Some(FocusEventFilter::TextInput),
             VirtualKeyDown => Some(FocusEventFilter::VirtualKeyDown),
-            VirtualKeyUp => Some(FocusEventFilter::VirtualKeyDown),
+            VirtualKeyUp => Some(FocusEventFilter::VirtualKeyUp),
             HoveredFile => None,
             DroppedFile => None,
             HoveredFileCancelled => None,
@@ -497,7 +533,7 @@ impl WindowEventFilter {
             ScrollEnd => Some(HoverEventFilter::ScrollEnd),
             TextInput => Some(HoverEventFilter::TextInput),
             VirtualKeyDown => Some(HoverEventFilter::VirtualKeyDown),
-            VirtualKeyUp => Some(HoverEventFilter::VirtualKeyDown),
+            VirtualKeyUp => Some(HoverEventFilter::VirtualKeyUp),
             HoveredFile => Some(HoverEventFilter::HoveredFile),
             DroppedFile => Some(HoverEventFilter::DroppedFile),
             HoveredFileCancelled => Some(HoverEventFilter::HoveredFileCancelled),
@@ -549,6 +585,16 @@ pub struct NodeData<T> {
     /// Note that without this, there can be no `On::FocusReceived` (equivalent to onfocus),
     /// `On::FocusLost` (equivalent to onblur), etc. events.
     tab_index: Option<TabIndex>,
+    /// Whether this node (and all its children) is removed from the layout,
+    /// similar to `display: none` in CSS. The node takes up no space and is not drawn.
+    display_none: bool,
+    /// Whether this node (and all its children) is invisible, similar to `visibility: hidden`
+    /// in CSS. Unlike `display_none`, the node still takes up space in the layout.
+    visibility_hidden: bool,
+    /// Stable identity of the node across frames (similar to `key` in React), so that
+    /// the runtime state of the node (focus, hover) is carried over by the `DomDiff`,
+    /// even if the node moves to a different position in the DOM.
+    key: Option<DomString>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
@@ -607,7 +653,10 @@ impl<T> PartialEq for NodeData<T> {
         self.default_callbacks == other.default_callbacks &&
         self.dynamic_css_overrides == other.dynamic_css_overrides &&
         self.is_draggable == other.is_draggable &&
-        self.tab_index == other.tab_index
+        self.tab_index == other.tab_index &&
+        self.display_none == other.display_none &&
+        self.visibility_hidden == other.visibility_hidden &&
+        self.key == other.key
     }
 }
 
@@ -639,6 +688,9 @@ impl<T> Hash for NodeData<T> {
         }
         self.is_draggable.hash(state);
         self.tab_index.hash(state);
+        self.display_none.hash(state);
+        self.visibility_hidden.hash(state);
+        self.key.hash(state);
     }
 }
 
@@ -653,6 +705,9 @@ impl<T> Clone for NodeData<T> {
             dynamic_css_overrides: self.dynamic_css_overrides.clone(),
             is_draggable: self.is_draggable.clone(),
             tab_index: self.tab_index.clone(),
+            display_none: self.display_none.clone(),
+            visibility_hidden: self.visibility_hidden.clone(),
+            key: self.key.clone(),
         }
     }
 }
@@ -747,6 +802,9 @@ impl<T> fmt::Debug for NodeData<T> {
                 \tdynamic_css_overrides: {:?}, \
                 \tis_draggable: {:?}, \
                 \ttab_index: {:?}, \
+                \tdisplay_none: {:?}, \
+                \tvisibility_hidden: {:?}, \
+                \tkey: {:?}, \
             }}",
             self.node_type,
             self.ids,
@@ -756,6 +814,9 @@ impl<T> fmt::Debug for NodeData<T> {
             self.dynamic_css_overrides,
             self.is_draggable,
             self.tab_index,
+            self.display_none,
+            self.visibility_hidden,
+            self.key,
         )
     }
 }
@@ -776,6 +837,9 @@ impl<T> NodeData<T> {
             dynamic_css_overrides: Vec::new(),
             is_draggable: false,
             tab_index: None,
+            display_none: false,
+            visibility_hidden: false,
+            key: None,
         }
     }
 
@@ -860,6 +924,12 @@ impl<T> NodeData<T> {
     pub const fn get_is_draggable(&self) -> bool { self.is_draggable }
     #[inline(always)]
     pub const fn get_tab_index(&self) -> Option<TabIndex> { self.tab_index }
+    #[inline(always)]
+    pub const fn get_display_none(&self) -> bool { self.display_none }
+    #[inline(always)]
+    pub const fn get_visibility_hidden(&self) -> bool { self.visibility_hidden }
+    #[inline(always)]
+    pub const fn get_key(&self) -> &Option<DomString> { &self.key }
 
     #[inline(always)]
     pub fn set_node_type(&mut self, node_type: NodeType<T>) { self.node_type = node_type; }
@@ -877,6 +947,12 @@ impl<T> NodeData<T> {
     pub fn set_is_draggable(&mut self, is_draggable: bool) { self.is_draggable = is_draggable; }
     #[inline(always)]
     pub fn set_tab_index(&mut self, tab_index: Option<TabIndex>) { self.tab_index = tab_index; }
+    #[inline(always)]
+    pub fn set_display_none(&mut self, display_none: bool) { self.display_none = display_none; }
+    #[inline(always)]
+    pub fn set_visibility_hidden(&mut self, visibility_hidden: bool) { self.visibility_hidden = visibility_hidden; }
+    #[inline(always)]
+    pub fn set_key(&mut self, key: Option<DomString>) { self.key = key; }
 
     #[inline(always)]
     pub fn with_node_type(self, node_type: NodeType<T>) -> Self { Self { node_type, .. self } }
@@ -894,6 +970,12 @@ impl<T> NodeData<T> {
     pub fn is_draggable(self, is_draggable: bool) -> Self { Self { is_draggable, .. self } }
     #[inline(always)]
     pub fn with_tab_index(self, tab_index: Option<TabIndex>) -> Self { Self { tab_index, .. self } }
+    #[inline(always)]
+    pub fn with_display_none(self, display_none: bool) -> Self { Self { display_none, .. self } }
+    #[inline(always)]
+    pub fn with_visibility_hidden(self, visibility_hidden: bool) -> Self { Self { visibility_hidden, .. self } }
+    #[inline(always)]
+    pub fn with_key(self, key: Option<DomString>) -> Self { Self { key, .. self } }
 }
 
 /// Most strings are known at compile time, spares a bit of
@@ -1197,6 +1279,19 @@ impl<T> Dom<T> {
         &mut self.arena.node_data[self.head]
     }
 
+    /// Replaces the content of the `NodeType::Label` at `node` in place, so that
+    /// dynamic labels don't have to rebuild their subtree. Returns `false` (and
+    /// does nothing) if `node` doesn't exist or isn't a text node.
+    pub fn set_text<S: Into<String>>(&mut self, node: NodeId, text: S) -> bool {
+        match self.arena.node_data.get_mut(node).map(|node_data| &mut node_data.node_type) {
+            Some(NodeType::Label(content)) => {
+                *content = DomString::Heap(text.into());
+                true
+            },
+            _ => false,
+        }
+    }
+
     /// Adds a child DOM to the current DOM
     pub fn add_child(&mut self, mut child: Self) {
 
@@ -1320,6 +1415,25 @@ impl<T> Dom<T> {
         self
     }
 
+    #[inline]
+    pub fn with_display_none(mut self, display_none: bool) -> Self {
+        self.set_display_none(display_none);
+        self
+    }
+
+    #[inline]
+    pub fn with_visibility_hidden(mut self, visibility_hidden: bool) -> Self {
+        self.set_visibility_hidden(visibility_hidden);
+        self
+    }
+
+    /// Same as `set_key`, but easier to use for method chaining in a builder-style pattern
+    #[inline]
+    pub fn key<S: Into<DomString>>(mut self, key: S) -> Self {
+        self.set_key(key);
+        self
+    }
+
     #[inline]
     pub fn add_id<S: Into<DomString>>(&mut self, id: S) {
         self.arena.node_data[self.head].ids.push(id.into());
@@ -1355,6 +1469,22 @@ impl<T> Dom<T> {
         self.arena.node_data[self.head].is_draggable = draggable;
     }
 
+    #[inline]
+    pub fn set_display_none(&mut self, display_none: bool) {
+        self.arena.node_data[self.head].display_none = display_none;
+    }
+
+    #[inline]
+    pub fn set_visibility_hidden(&mut self, visibility_hidden: bool) {
+        self.arena.node_data[self.head].visibility_hidden = visibility_hidden;
+    }
+
+    /// Gives the node a stable identity across frames, see `DomDiff::remap_node_id`
+    #[inline]
+    pub fn set_key<S: Into<DomString>>(&mut self, key: S) {
+        self.arena.node_data[self.head].key = Some(key.into());
+    }
+
     /// Returns a debug formatted version of the DOM for easier debugging
     pub fn debug_dump(&self) -> String {
         let mut s = String::new();
@@ -1464,6 +1594,30 @@ fn test_dom_from_iter_1() {
     }));
 }
 
+#[test]
+fn test_tag_id_hit_test_tag_roundtrip() {
+    let tag_id = TagId(5);
+    assert_eq!(tag_id.into_hit_test_tag(), HitTestTag(5));
+    assert_eq!(TagId::from_hit_test_tag(tag_id.into_hit_test_tag()), tag_id);
+    assert_eq!(TagId::from_hit_test_tag(HitTestTag(5)).into_hit_test_tag(), HitTestTag(5));
+}
+
+#[test]
+fn test_dom_set_text() {
+
+    struct TestLayout;
+
+    let mut dom: Dom<TestLayout> = Dom::div().with_child(Dom::label("hello"));
+
+    assert!(dom.set_text(NodeId::new(1), "world"));
+    assert_eq!(dom.arena.node_data[NodeId::new(1)].node_type, NodeType::Label(DomString::Heap(String::from("world"))));
+
+    assert!(!dom.set_text(NodeId::new(0), "world"));
+    assert_eq!(dom.arena.node_data[NodeId::new(0)].node_type, NodeType::Div);
+
+    assert!(!dom.set_text(NodeId::new(2), "world"));
+}
+
 /// Test that there shouldn't be a DOM that has 0 nodes
 #[test]
 fn test_zero_size_dom() {
@@ -253,6 +253,10 @@ impl<T> NodeDataContainer<T> {
         self.internal.get(id.index())
     }
 
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.internal.get_mut(id.index())
+    }
+
     pub fn iter(&self) -> Iter<T> {
         self.internal.iter()
     }
@@ -267,6 +271,16 @@ impl<T> NodeDataContainer<T> {
             position: 0,
         }
     }
+
+    /// Iterates over all `(NodeId, &T)` pairs in the container, in the order of the node IDs
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.internal.iter().enumerate().map(|(node_id, node)| (NodeId::new(node_id), node))
+    }
+
+    /// Same as `iter_with_ids`, but with mutable access to the node data
+    pub fn iter_with_ids_mut(&mut self) -> impl Iterator<Item = (NodeId, &mut T)> {
+        self.internal.iter_mut().enumerate().map(|(node_id, node)| (NodeId::new(node_id), node))
+    }
 }
 
 impl<T> Index<NodeId> for NodeDataContainer<T> {
@@ -657,4 +671,23 @@ impl<'a> Iterator for ReverseTraverse<'a> {
             None => None
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_node_data_container_iter_with_ids() {
+
+    let mut container = NodeDataContainer::new(vec!["a", "b", "c"]);
+
+    assert_eq!(
+        container.iter_with_ids().map(|(id, value)| (id.index(), *value)).collect::<Vec<_>>(),
+        vec![(0, "a"), (1, "b"), (2, "c")]
+    );
+
+    for (id, value) in container.iter_with_ids_mut() {
+        if id == NodeId::new(1) {
+            *value = "d";
+        }
+    }
+
+    assert_eq!(container.iter().cloned().collect::<Vec<_>>(), vec!["a", "d", "c"]);
+}
@@ -371,12 +371,15 @@ macro_rules! impl_callback_info_api {() => (
 
     /// If the node is a text node, return the shaped glyphs (on a per-word basis, unpositioned)
     pub fn get_scaled_words(&self, (dom_id, node_id): &(DomId, NodeId)) -> Option<&ScaledWords> {
-        self.layout_result.get(&dom_id).as_ref().and_then(|lr| lr.scaled_words.get(&node_id).as_ref().map(|sw| &sw.0))
+        let layout_result = self.layout_result.get(&dom_id)?;
+        layout_result.get_scaled_words(node_id, &layout_result.get_font_instance_key(node_id)?)
     }
 
-    /// If the node is a text node, return the shaped glyphs (on a per-word basis, unpositioned)
+    /// If the node is a text node, return the positions of the words. Returns `None` if the words
+    /// were positioned with a different font instance than the one they were shaped with.
     pub fn get_word_positions(&self, (dom_id, node_id): &(DomId, NodeId)) -> Option<&WordPositions> {
-        self.layout_result.get(&dom_id).as_ref().and_then(|lr| lr.positioned_word_cache.get(&node_id).as_ref().map(|sw| &sw.0))
+        let layout_result = self.layout_result.get(&dom_id)?;
+        layout_result.get_word_positions(node_id, &layout_result.get_font_instance_key(node_id)?)
     }
 
     pub fn get_layouted_glyphs(&self, (dom_id, node_id): &(DomId, NodeId)) -> Option<&LayoutedGlyphs> {
@@ -1,14 +1,15 @@
 use std::collections::BTreeMap;
 use azul_css::{
-    LayoutRect, PixelValue, LayoutSize, StyleFontSize,
+    LayoutRect, LayoutPoint, PixelValue, LayoutSize, StyleFontSize,
     StyleTextColor, ColorU as StyleColorU, Overflow,
     StyleTextAlignmentHorz, StyleTextAlignmentVert,
 };
 use crate::{
-    app_resources::{Words, ScaledWords, FontInstanceKey, WordPositions, LayoutedGlyphs},
+    app_resources::{Words, ScaledWords, FontInstanceKey, FontMetrics, WordPositions, LayoutedGlyphs, TextDirection},
     id_tree::{NodeId, NodeDataContainer},
     dom::{DomHash, ScrollTagId},
     callbacks::PipelineId,
+    display_list::GlyphInstance,
 };
 
 pub const DEFAULT_FONT_SIZE_PX: isize = 16;
@@ -19,6 +20,8 @@ pub const DEFAULT_LINE_HEIGHT: f32 = 1.0;
 pub const DEFAULT_WORD_SPACING: f32 = 1.0;
 pub const DEFAULT_LETTER_SPACING: f32 = 0.0;
 pub const DEFAULT_TAB_WIDTH: f32 = 4.0;
+/// Maximum time between two clicks on the same node for them to count as an `On::DoubleClick`
+pub const DEFAULT_DOUBLE_CLICK_THRESHOLD_MS: u64 = 500;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct InlineTextLayout {
@@ -32,16 +35,609 @@ pub struct InlineTextLine {
     pub word_start: usize,
     /// At which word does this line end
     pub word_end: usize,
+    /// Vertical position of the baseline that the glyphs of this line are drawn at,
+    /// in the same coordinate space as the `bounds`
+    pub baseline_y: f32,
+    /// Extra space above and below the text (half of the difference between the line
+    /// height and the natural height of the text), already included in the `bounds`
+    pub half_leading: f32,
+}
+
+/// Vertical alignment of an inline box (i.e. an image) relative to the text line it sits in
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InlineVerticalAlign {
+    /// Bottom edge of the box sits on the baseline of the line
+    Baseline,
+    /// Top edge of the box is aligned with the top of the line
+    Top,
+    /// Box is centered vertically in the line
+    Middle,
+    /// Bottom edge of the box is aligned with the bottom of the line
+    Bottom,
+}
+
+impl InlineTextLine {
+
+    /// Returns the rectangle of an inline box with the given size that is placed
+    /// `advance_x` pixels after the start of the line, in the same coordinate space as the
+    /// glyphs of the line. Note that the box can overflow the line if it is taller than the line.
+    #[must_use]
+    pub fn place_inline_box(&self, advance_x: f32, box_size: LayoutSize, valign: InlineVerticalAlign) -> LayoutRect {
+        use azul_css::LayoutPoint;
+        let line_top = self.bounds.origin.y;
+        let line_bottom = self.bounds.origin.y + self.bounds.size.height;
+        let box_y = match valign {
+            InlineVerticalAlign::Baseline => self.baseline_y - box_size.height,
+            InlineVerticalAlign::Top => line_top,
+            InlineVerticalAlign::Middle => line_top + (self.bounds.size.height - box_size.height) / 2.0,
+            InlineVerticalAlign::Bottom => line_bottom - box_size.height,
+        };
+        LayoutRect::new(LayoutPoint::new(self.bounds.origin.x + advance_x, box_y), box_size)
+    }
+
+    /// Start and end of the line across the lines: the y range of a row, the x range of a column
+    /// (negated in `VerticalRl`, so that the following lines always have larger values)
+    fn block_range(&self, writing_mode: WritingMode) -> (f32, f32) {
+        match writing_mode {
+            WritingMode::HorizontalTb => (self.bounds.origin.y, self.bounds.origin.y + self.bounds.size.height),
+            WritingMode::VerticalLr => (self.bounds.origin.x, self.bounds.origin.x + self.bounds.size.width),
+            WritingMode::VerticalRl => (-self.bounds.origin.x - self.bounds.size.width, -self.bounds.origin.x),
+        }
+    }
+
+    /// Start and length of the line along the line (the glyphs of a column flow top to bottom)
+    fn inline_range(&self, writing_mode: WritingMode) -> (f32, f32) {
+        if writing_mode.is_vertical() {
+            (self.bounds.origin.y, self.bounds.size.height)
+        } else {
+            (self.bounds.origin.x, self.bounds.size.width)
+        }
+    }
+}
+
+/// Direction of a caret movement on the screen (i.e. the arrow key that was pressed),
+/// independent of the direction of the text
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VisualDirection {
+    Left,
+    Right,
 }
 
 impl InlineTextLayout {
 
+    /// Creates a left-aligned layout with one line per entry in `widths`, stacked
+    /// vertically without any spacing. Each line contains exactly one (virtual) word
+    /// and has its baseline at the bottom edge of the line.
+    ///
+    /// Only intended for testing the geometry functions (alignment, overflow, etc.)
+    /// without having to load a font and run the full text layout pipeline.
+    pub fn from_line_widths(widths: &[f32], line_height: f32) -> Self {
+        use azul_css::{LayoutPoint, LayoutSize};
+        Self {
+            lines: widths.iter().enumerate().map(|(line_idx, width)| InlineTextLine {
+                bounds: LayoutRect::new(
+                    LayoutPoint::new(0.0, line_idx as f32 * line_height),
+                    LayoutSize::new(*width, line_height),
+                ),
+                word_start: line_idx,
+                word_end: line_idx + 1,
+                baseline_y: (line_idx + 1) as f32 * line_height,
+                half_leading: 0.0,
+            }).collect(),
+        }
+    }
+
     #[inline]
     #[must_use]
     pub fn get_bounds(&self) -> LayoutRect {
         LayoutRect::union(self.lines.iter().map(|c| c.bounds)).unwrap_or(LayoutRect::zero())
     }
 
+    /// Returns the indices of the lines that have to be repainted when this layout replaces
+    /// the `old` layout: lines whose bounds, word range or baseline changed, as well as lines
+    /// that only exist in one of the two layouts (added or removed lines).
+    #[must_use]
+    pub fn diff_lines(&self, old: &InlineTextLayout) -> Vec<usize> {
+        (0..self.lines.len().max(old.lines.len()))
+            .filter(|line_idx| self.lines.get(*line_idx) != old.lines.get(*line_idx))
+            .collect()
+    }
+
+    /// Returns the x position (in the coordinate space of the `container`) at which the content of
+    /// the line starts in reading direction: the left edge of the first glyph for left-to-right
+    /// text, the right edge of the first glyph for right-to-left text.
+    ///
+    /// The horizontal offset of the line (`line.bounds.origin.x`, i.e. from the text alignment) and
+    /// the `leading` of the first line are measured from the start edge of the `container`, so a
+    /// right-to-left layout is the mirror image of the left-to-right layout. If the start of the
+    /// line falls into one of the `holes` (relative to the `container`), the line starts after
+    /// the hole. Returns the start edge of the container if the line doesn't exist.
+    pub fn line_start_x(&self, line_idx: usize, container: LayoutRect, opts: &ResolvedTextLayoutOptions, direction: TextDirection) -> f32 {
+
+        let (line_offset, line_top, line_bottom) = match self.lines.get(line_idx) {
+            Some(line) => (line.bounds.origin.x, line.bounds.origin.y, line.bounds.origin.y + line.bounds.size.height),
+            None => (0.0, 0.0, 0.0),
+        };
+
+        let indent = if line_idx == 0 { opts.leading.unwrap_or(0.0) } else { 0.0 };
+
+        // Distance from the start edge of the container
+        let mut start = line_offset + indent;
+
+        // Holes can be next to each other, so repeat until the start is outside of all holes
+        let mut moved = true;
+        while moved {
+            moved = false;
+            for hole in &opts.holes {
+                let overlaps_line = hole.origin.y < line_bottom && line_top < hole.origin.y + hole.size.height;
+                let (hole_start, hole_end) = match direction {
+                    TextDirection::LeftToRight => (hole.origin.x, hole.origin.x + hole.size.width),
+                    TextDirection::RightToLeft => (container.size.width - hole.origin.x - hole.size.width, container.size.width - hole.origin.x),
+                };
+                if overlaps_line && hole_start <= start && start < hole_end {
+                    start = hole_end;
+                    moved = true;
+                }
+            }
+        }
+
+        match direction {
+            TextDirection::LeftToRight => container.origin.x + start,
+            TextDirection::RightToLeft => container.origin.x + container.size.width - start,
+        }
+    }
+
+    /// Stacks the lines of `other` below the lines of this layout, with `gap` pixels
+    /// of space between the bottom of this layout and the top of `other`.
+    ///
+    /// The word indices of the appended lines are rebased to start after the last word of
+    /// this layout, i.e. both layouts are assumed to refer to one shared word list, in which
+    /// the words of `other` directly follow the words of `self`.
+    pub fn append_below(&mut self, other: InlineTextLayout, gap: f32) {
+
+        if self.lines.is_empty() {
+            self.lines = other.lines;
+            return;
+        }
+
+        let self_bounds = self.get_bounds();
+        let other_bounds = other.get_bounds();
+        let y_offset = (self_bounds.origin.y + self_bounds.size.height + gap) - other_bounds.origin.y;
+        let word_offset = self.lines.last().map(|line| line.word_end).unwrap_or(0);
+
+        self.lines.extend(other.lines.into_iter().map(|mut line| {
+            line.bounds.origin.y += y_offset;
+            line.baseline_y += y_offset;
+            line.word_start += word_offset;
+            line.word_end += word_offset;
+            line
+        }));
+    }
+
+    /// Returns the vertical position of the baseline of the first line (the top of
+    /// the line + the half-leading + the ascender of the font), i.e. for aligning the
+    /// text to a baseline grid. Returns 0.0 if the layout has no lines.
+    #[must_use]
+    pub fn first_baseline(&self) -> f32 {
+        self.lines.first().map(|line| line.baseline_y).unwrap_or(0.0)
+    }
+
+    /// Returns one rectangle per line that spans the full `container_width`
+    /// (instead of only the text width), at the vertical bounds of the line.
+    /// Useful for drawing current-line or selection backgrounds.
+    #[must_use]
+    pub fn line_highlight_rects(&self, container_width: f32) -> Vec<LayoutRect> {
+        use azul_css::{LayoutPoint, LayoutSize};
+        self.lines.iter().map(|line| LayoutRect::new(
+            LayoutPoint::new(0.0, line.bounds.origin.y),
+            LayoutSize::new(container_width, line.bounds.size.height),
+        )).collect()
+    }
+
+    /// Returns the indices of all lines that are wider than `max_width`, i.e. for
+    /// highlighting text that overflows its container in a debug overlay.
+    #[must_use]
+    pub fn overflowing_lines(&self, max_width: f32) -> Vec<usize> {
+        const EPSILON: f32 = 0.001;
+        self.lines.iter().enumerate()
+            .filter(|(_, line)| line.bounds.size.width > max_width + EPSILON)
+            .map(|(line_idx, _)| line_idx)
+            .collect()
+    }
+
+    /// Returns the index of the line whose vertical bounds contain `y`. If `y` is
+    /// between two lines, returns the line below, positions above the first or below
+    /// the last line are clamped to the first / last line. Returns `None` if there are no lines.
+    pub fn line_at_y(&self, y: f32) -> Option<usize> {
+        let last_line_idx = self.lines.len().checked_sub(1)?;
+        let line_idx = self.lines.iter()
+            .position(|line| y < line.bounds.origin.y + line.bounds.size.height)
+            .unwrap_or(last_line_idx);
+        Some(line_idx)
+    }
+
+    /// Moves the lines down so that the baseline of every line is on a multiple of `grid`
+    /// (a baseline grid, i.e. every 4px). The baseline of a line is at the half-leading plus the
+    /// ascender of the font (at `size_px`) below the top of the line, the snapped baseline
+    /// is stored in the `baseline_y` of the line.
+    ///
+    /// Lines are only ever moved down (to the next grid line, the first line may get additional
+    /// leading), and every line is moved at least as much as the line above, so the lines keep
+    /// their order and are never closer to each other than before. Does nothing if `grid <= 0`
+    /// or `grid` is NaN.
+    pub fn snap_baselines_to_grid(&mut self, grid: f32, metrics: &FontMetrics, size_px: f32) {
+
+        const EPSILON: f32 = 0.001;
+
+        if grid.is_nan() || grid <= 0.0 {
+            return;
+        }
+
+        let ascender = metrics.get_ascender(size_px);
+        let mut offset_y = 0.0;
+
+        for line in self.lines.iter_mut() {
+            let baseline = line.bounds.origin.y + offset_y + line.half_leading + ascender;
+            let snapped_baseline = (baseline / grid - EPSILON).ceil() * grid;
+            offset_y += snapped_baseline - baseline;
+            line.bounds.origin.y += offset_y;
+            line.baseline_y = snapped_baseline;
+        }
+    }
+
+    /// For single-line text inputs: returns the new horizontal scroll offset of the text,
+    /// so that the caret (at `caret_x`, relative to the start of the text) stays visible
+    /// in a field that is `field_width` wide. Scrolls as little as possible and never
+    /// scrolls further than the end of the text.
+    #[must_use]
+    pub fn single_line_scroll_offset(&self, caret_x: f32, field_width: f32, current_offset: f32) -> f32 {
+
+        let mut offset = current_offset;
+
+        if caret_x > offset + field_width {
+            offset = caret_x - field_width;
+        }
+
+        if caret_x < offset {
+            offset = caret_x;
+        }
+
+        let max_offset = (self.get_bounds().size.width.max(caret_x) - field_width).max(0.0);
+        offset.min(max_offset).max(0.0)
+    }
+
+    /// Moves the caret (the logical character index `current`, relative to the `words`)
+    /// one position visually to the left or right within its line.
+    ///
+    /// `char_levels` are the resolved bidi embedding levels of each character of the `words`
+    /// (even = left-to-right, odd = right-to-left, missing levels are treated as `0`),
+    /// so in right-to-left runs, moving to the right decreases the logical index.
+    /// At a boundary between two runs, the caret is drawn at the edge of the character with the
+    /// lower level. Returns `current` if the caret is already at the visual edge of its line.
+    #[must_use]
+    pub fn move_caret_visual(&self, current: usize, direction: VisualDirection, words: &Words, char_levels: &[u8]) -> usize {
+
+        let line_char_range = self.lines.iter().filter_map(|line| {
+            let start = words.items.get(line.word_start)?.start;
+            let end = words.items.get(line.word_end.checked_sub(1)?)?.end;
+            Some(start..end)
+        }).find(|range| range.start <= current && current <= range.end);
+
+        let line_char_range = match line_char_range {
+            Some(s) => s,
+            None => return current,
+        };
+
+        let line_start = line_char_range.start;
+        let line_levels = line_char_range.map(|char_idx| char_levels.get(char_idx).cloned().unwrap_or(0)).collect::<Vec<_>>();
+        let is_rtl = |char_idx: usize| line_levels[char_idx] % 2 == 1;
+
+        // visual_order[visual index] = logical index, visual_positions[logical index] = visual index
+        let visual_order = get_visual_order(&line_levels);
+        let mut visual_positions = vec![0; visual_order.len()];
+        for (visual_idx, logical_idx) in visual_order.iter().enumerate() {
+            visual_positions[*logical_idx] = visual_idx;
+        }
+
+        // Edge between two visual characters (0 = left edge of the line) that a logical caret is drawn at
+        let leading_edge = |char_idx: usize| if is_rtl(char_idx) { visual_positions[char_idx] + 1 } else { visual_positions[char_idx] };
+        let trailing_edge = |char_idx: usize| if is_rtl(char_idx) { visual_positions[char_idx] } else { visual_positions[char_idx] + 1 };
+        let caret_edge = |caret: usize| {
+            let previous_char = caret.checked_sub(1);
+            let next_char = if caret < line_levels.len() { Some(caret) } else { None };
+            match (previous_char, next_char) {
+                (Some(p), Some(n)) => if line_levels[n] < line_levels[p] { leading_edge(n) } else { trailing_edge(p) },
+                (Some(p), None) => trailing_edge(p),
+                (None, Some(n)) => leading_edge(n),
+                (None, None) => 0,
+            }
+        };
+
+        // Logical carets that can be drawn at the given visual edge
+        let carets_at_edge = |edge: usize| {
+            let left_char = edge.checked_sub(1).map(|visual_idx| visual_order[visual_idx]);
+            let right_char = visual_order.get(edge).cloned();
+            let left_caret = left_char.map(|c| if is_rtl(c) { c } else { c + 1 });
+            let right_caret = right_char.map(|c| if is_rtl(c) { c + 1 } else { c });
+            [left_caret, right_caret]
+        };
+
+        let current_caret = current - line_start;
+        let current_edge = caret_edge(current_caret);
+
+        let next_edges: Box<dyn Iterator<Item = usize>> = match direction {
+            VisualDirection::Left => Box::new((0..current_edge).rev()),
+            VisualDirection::Right => Box::new((current_edge + 1)..=visual_order.len()),
+        };
+
+        for edge in next_edges {
+            if let Some(caret) = carets_at_edge(edge).iter().flatten().find(|caret| **caret != current_caret && caret_edge(**caret) == edge) {
+                return line_start + caret;
+            }
+        }
+
+        current
+    }
+
+    /// Returns where the caret has to be drawn for the character at `char_index` (into the
+    /// `words.internal_chars`): the top of the line that the character is on and the leading
+    /// edge of its glyph, so the caret is `line.bounds.size.height` pixels high.
+    ///
+    /// Whitespace and line breaks are placed after the preceding content of their line,
+    /// empty lines place the caret at their left edge. `char_index == internal_chars.len()`
+    /// returns the trailing edge of the last glyph. Returns `None` if the `char_index`
+    /// is out of range or the layout has no lines.
+    ///
+    /// In vertical writing modes, the caret is horizontal: the point is on the left edge of the
+    /// column and the caret is `line.bounds.size.width` pixels wide.
+    ///
+    /// `positions` and `scaled_words` have to be the ones that this layout was created from.
+    pub fn get_caret_position(&self, char_index: usize, words: &Words, positions: &WordPositions, scaled_words: &ScaledWords) -> Option<LayoutPoint> {
+        let (line_idx, offset) = *self.get_caret_positions(words, positions, scaled_words).get(char_index)?;
+        let line = self.lines.get(line_idx)?;
+        if positions.text_layout_options.writing_mode.is_vertical() {
+            Some(LayoutPoint::new(line.bounds.origin.x, line.bounds.origin.y + offset))
+        } else {
+            Some(LayoutPoint::new(line.bounds.origin.x + offset, line.bounds.origin.y))
+        }
+    }
+
+    /// Returns the character index (into the `words.internal_chars`) of the caret position that is
+    /// closest to the `point`, i.e. for placing the caret on a click. The line is determined by
+    /// `point.y`, on that line, the closest character boundary to `point.x` wins (so clicking on
+    /// the right half of a glyph places the caret after it). Points above the first line return
+    /// `0`, points below the last line return the length of the text.
+    ///
+    /// In vertical writing modes, the column is determined by `point.x` and the closest character
+    /// boundary by `point.y`. Points before the first column (to the right of it in `VerticalRl`)
+    /// return `0`, points after the last column return the length of the text.
+    ///
+    /// Returns `None` if the layout has no lines.
+    pub fn hit_test(&self, point: LayoutPoint, words: &Words, positions: &WordPositions, scaled_words: &ScaledWords) -> Option<usize> {
+
+        let first_line = self.lines.first()?;
+        let last_line = self.lines.last()?;
+
+        // Position of the point across and along the lines, see `InlineTextLine::block_range`
+        let writing_mode = positions.text_layout_options.writing_mode;
+        let (point_block, point_inline) = match writing_mode {
+            WritingMode::HorizontalTb => (point.y, point.x),
+            WritingMode::VerticalLr => (point.x, point.y),
+            WritingMode::VerticalRl => (-point.x, point.y),
+        };
+
+        if point_block < first_line.block_range(writing_mode).0 {
+            return Some(0);
+        }
+
+        if point_block >= last_line.block_range(writing_mode).1 {
+            return Some(words.internal_chars.len());
+        }
+
+        let line_idx = self.lines.iter()
+            .position(|line| point_block < line.block_range(writing_mode).1)
+            .unwrap_or(self.lines.len() - 1);
+        let (line_start, _) = self.lines[line_idx].inline_range(writing_mode);
+
+        let mut closest: Option<(usize, f32)> = None;
+        for (char_index, (caret_line_idx, caret_offset)) in self.get_caret_positions(words, positions, scaled_words).into_iter().enumerate() {
+            if caret_line_idx != line_idx {
+                continue;
+            }
+            let distance = (line_start + caret_offset - point_inline).abs();
+            if closest.map(|(_, closest_distance)| distance < closest_distance).unwrap_or(true) {
+                closest = Some((char_index, distance));
+            }
+        }
+
+        // A line without any characters (shouldn't happen, since every line ends with a line break)
+        Some(closest.map(|(char_index, _)| char_index).unwrap_or(0))
+    }
+
+    /// Returns the glyphs of the `layouted_glyphs` that belong to the words of the given line.
+    /// Yields nothing if the line doesn't exist.
+    pub fn line_glyphs<'a>(&'a self, line_index: usize, layouted_glyphs: &'a LayoutedGlyphs) -> impl Iterator<Item = &'a GlyphInstance> + 'a {
+        let (word_start, word_end) = self.lines.get(line_index).map(|line| (line.word_start, line.word_end)).unwrap_or((0, 0));
+        layouted_glyphs.glyphs.iter()
+            .zip(layouted_glyphs.word_indices.iter())
+            .filter(move |(_, word_idx)| **word_idx >= word_start && **word_idx < word_end)
+            .map(|(glyph, _)| glyph)
+    }
+
+    /// Returns the rectangles that cover the characters `start..end` (indices into the
+    /// `words.internal_chars`), one for each line that the range touches, with the full height
+    /// of the line - i.e. for drawing a text selection. The rectangle of the first line starts at
+    /// the leading edge of `start`, the one of the last line ends at the leading edge of `end`,
+    /// lines in between span their entire width. Returns no rectangles for an empty range.
+    ///
+    /// In vertical writing modes, the rectangles span the width of the columns instead.
+    pub fn get_selection_rects(&self, start: usize, end: usize, words: &Words, positions: &WordPositions, scaled_words: &ScaledWords) -> Vec<LayoutRect> {
+
+        use azul_css::LayoutSize;
+
+        let carets = self.get_caret_positions(words, positions, scaled_words);
+        let end = end.min(carets.len() - 1);
+
+        if start >= end {
+            return Vec::new();
+        }
+
+        let writing_mode = positions.text_layout_options.writing_mode;
+        let (first_line_idx, start_offset) = carets[start];
+        let (last_line_idx, _) = carets[end - 1];
+
+        (first_line_idx..=last_line_idx).filter_map(|line_idx| {
+            let line = self.lines.get(line_idx)?;
+            let (line_start, line_length) = line.inline_range(writing_mode);
+            let selection_start = if line_idx == first_line_idx { line_start + start_offset } else { line_start };
+            let selection_end = match carets[end] {
+                (end_line_idx, end_offset) if end_line_idx == line_idx => line_start + end_offset,
+                _ => line_start + line_length,
+            };
+            let selection_length = (selection_end - selection_start).max(0.0);
+            Some(if writing_mode.is_vertical() {
+                LayoutRect::new(
+                    LayoutPoint::new(line.bounds.origin.x, selection_start),
+                    LayoutSize::new(line.bounds.size.width, selection_length),
+                )
+            } else {
+                LayoutRect::new(
+                    LayoutPoint::new(selection_start, line.bounds.origin.y),
+                    LayoutSize::new(selection_length, line.bounds.size.height),
+                )
+            })
+        }).collect()
+    }
+
+    /// Returns the line index and the offset along the line (the x position in horizontal text,
+    /// the y position in a column) of the caret in front of every character of the `words`,
+    /// plus the caret at the end of the text
+    fn get_caret_positions(&self, words: &Words, positions: &WordPositions, scaled_words: &ScaledWords) -> Vec<(usize, f32)> {
+
+        use crate::app_resources::{WordType, HB_SCALE_FACTOR};
+
+        let options = &positions.text_layout_options;
+        let word_spacing_px = scaled_words.space_advance_px * options.word_spacing.unwrap_or(DEFAULT_WORD_SPACING);
+        let tab_width_px = scaled_words.space_advance_px * options.tab_width.unwrap_or(DEFAULT_TAB_WIDTH);
+        let letter_spacing_px = options.letter_spacing.unwrap_or(0.0);
+
+        // Glyph clusters are byte offsets into the `internal_str`
+        let byte_offsets = words.internal_str.char_indices().map(|(byte_idx, _)| byte_idx).collect::<Vec<_>>();
+        let line_of_word = |word_idx: usize| self.lines.iter().position(|line| line.word_start <= word_idx && word_idx < line.word_end);
+
+        let mut carets = Vec::with_capacity(words.internal_chars.len() + 1);
+
+        // Line and x position (relative to the line) after the content that was visited so far
+        let mut line_idx = 0;
+        let mut pen_x = 0.0;
+        let mut word_idx = 0;
+
+        for (item_idx, item) in words.items.iter().enumerate() {
+
+            while carets.len() < item.start {
+                carets.push((line_idx, pen_x));
+            }
+
+            match item.word_type {
+                WordType::Word => {
+                    let (word_position, scaled_word) = match (positions.word_positions.get(word_idx), scaled_words.items.get(word_idx)) {
+                        (Some(p), Some(s)) => (p, s),
+                        _ => break,
+                    };
+                    line_idx = line_of_word(word_idx).unwrap_or(line_idx);
+
+                    for char_index in item.start..item.end {
+                        let byte_index = byte_offsets.get(char_index).cloned().unwrap_or(0);
+                        let glyph_idx = scaled_word.glyph_for_char(byte_index).unwrap_or(0);
+                        let advance = scaled_word.glyph_positions[..glyph_idx.min(scaled_word.glyph_positions.len())].iter()
+                            .map(|glyph_position| glyph_position.x_advance as f32 / HB_SCALE_FACTOR)
+                            .sum::<f32>();
+                        let cluster_idx = scaled_word.cluster_iter().nth(glyph_idx).map(|c| c.cluster_idx).unwrap_or(0);
+                        carets.push((line_idx, word_position.x + advance + letter_spacing_px * cluster_idx as f32));
+                    }
+
+                    pen_x = word_position.x + scaled_word.get_adjusted_width(options.letter_spacing);
+                    word_idx += 1;
+                },
+                WordType::Return => {
+                    for _ in item.start..item.end {
+                        carets.push((line_idx, pen_x));
+                    }
+                    line_idx += 1;
+                    pen_x = 0.0;
+                },
+                WordType::Space | WordType::Tab => {
+                    let advance = if item.word_type == WordType::Space { word_spacing_px } else { word_spacing_px + tab_width_px };
+                    for _ in item.start..item.end {
+                        carets.push((line_idx, pen_x));
+                        pen_x += advance;
+                    }
+                    // Whitespace never extends past the next word on the same line (i.e. at tab stops)
+                    let next_word_x = match words.items.get(item_idx + 1) {
+                        Some(next) if next.word_type == WordType::Word && line_of_word(word_idx) == Some(line_idx) => positions.word_positions.get(word_idx).map(|p| p.x),
+                        _ => None,
+                    };
+                    pen_x = next_word_x.unwrap_or(pen_x);
+                },
+            }
+        }
+
+        while carets.len() <= words.internal_chars.len() {
+            carets.push((line_idx, pen_x));
+        }
+
+        carets
+    }
+
+    /// Justifies every line except the last one (which ends the paragraph) to `max_width`
+    /// by distributing the remaining space evenly between the words of the line.
+    ///
+    /// `word_positions` and `word_widths` are indexed by the word indices of the lines (i.e.
+    /// `WordPositions::word_positions` and the adjusted widths of the `ScaledWords`),
+    /// `word_levels` are the resolved bidi embedding levels of each word (even = left-to-right,
+    /// odd = right-to-left, missing levels are treated as `0`, see `ScaledWords::get_word_levels`).
+    /// The `word_positions` are expected in visual order (as placed by `position_words`), so the
+    /// extra space is added at the gaps that are visible on the screen (i.e. between the reversed
+    /// words of a right-to-left run) and the last visual word ends exactly at `max_width`.
+    pub fn justify(&mut self, word_positions: &mut [LayoutPoint], word_widths: &[f32], word_levels: &[u8], max_width: f32) {
+
+        let line_count = self.lines.len();
+
+        for line in self.lines.iter_mut().take(line_count.saturating_sub(1)) {
+
+            let word_range = line.word_start..line.word_end.min(word_positions.len()).min(word_widths.len());
+            let word_count = word_range.len();
+            if word_count < 2 {
+                continue;
+            }
+
+            let line_levels = word_range.clone().map(|word_idx| word_levels.get(word_idx).cloned().unwrap_or(0)).collect::<Vec<_>>();
+            let widths = &word_widths[word_range.clone()];
+            let positions = &mut word_positions[word_range.clone()];
+
+            // visual_gaps[i] = gap between the visual words i and i + 1
+            let visual_order = get_visual_order(&line_levels);
+            let line_start_x = positions[visual_order[0]].x;
+            let visual_gaps = visual_order.windows(2)
+                .map(|pair| (positions[pair[1]].x - positions[pair[0]].x - widths[pair[0]]).max(0.0))
+                .collect::<Vec<_>>();
+
+            let natural_width = line_start_x + widths.iter().sum::<f32>() + visual_gaps.iter().sum::<f32>();
+            let extra_per_gap = (max_width - natural_width) / visual_gaps.len() as f32;
+            if extra_per_gap <= 0.0 {
+                continue;
+            }
+
+            let mut caret_x = line_start_x;
+            for (visual_idx, logical_idx) in visual_order.iter().enumerate() {
+                positions[*logical_idx].x = caret_x;
+                caret_x += widths[*logical_idx] + visual_gaps.get(visual_idx).map(|gap| gap + extra_per_gap).unwrap_or(0.0);
+            }
+
+            line.bounds.size.width = max_width;
+        }
+    }
+
     #[must_use]
     pub fn get_children_horizontal_diff_to_right_edge(&self, parent: &LayoutRect) -> Vec<f32> {
         let parent_right_edge = parent.origin.x + parent.size.width;
@@ -62,7 +658,7 @@ impl InlineTextLayout {
         let self_bounds = self.get_bounds();
         let horz_diff = self.get_children_horizontal_diff_to_right_edge(&self_bounds);
 
-        for (line, shift) in self.lines.iter_mut().zip(horz_diff.into_iter()) {
+        for (line, shift) in self.lines.iter_mut().zip(horz_diff.iter()) {
             line.bounds.origin.x += shift * shift_multiplier;
         }
     }
@@ -85,10 +681,47 @@ impl InlineTextLayout {
 
         for line in &mut self.lines {
             line.bounds.origin.y += shift * shift_multiplier;
+            line.baseline_y += shift * shift_multiplier;
         }
     }
 }
 
+/// Reorders the characters of a line according to their bidi embedding levels
+/// (rule L2 of the Unicode bidi algorithm), returns the logical indices in visual order
+pub fn get_visual_order(levels: &[u8]) -> Vec<usize> {
+
+    let mut visual_order = (0..levels.len()).collect::<Vec<_>>();
+
+    let max_level = match levels.iter().max() {
+        Some(s) => *s,
+        None => return visual_order,
+    };
+
+    let lowest_odd_level = match levels.iter().filter(|level| *level % 2 == 1).min() {
+        Some(s) => *s,
+        None => return visual_order,
+    };
+
+    // From the highest level to the lowest odd level, reverse any
+    // contiguous sequence of characters that are at that level or higher
+    for level in (lowest_odd_level..=max_level).rev() {
+        let mut run_start = None;
+        for visual_idx in 0..=visual_order.len() {
+            let in_run = visual_idx < visual_order.len() && levels[visual_order[visual_idx]] >= level;
+            match (in_run, run_start) {
+                (true, None) => run_start = Some(visual_idx),
+                (false, Some(start)) => {
+                    visual_order[start..visual_idx].reverse();
+                    run_start = None;
+                },
+                _ => { },
+            }
+        }
+    }
+
+    visual_order
+}
+
 #[inline]
 pub fn calculate_horizontal_shift_multiplier(horizontal_alignment: StyleTextAlignmentHorz) -> Option<f32> {
     use azul_css::StyleTextAlignmentHorz::*;
@@ -96,6 +729,7 @@ pub fn calculate_horizontal_shift_multiplier(horizontal_alignment: StyleTextAlig
         Left => None,
         Center => Some(0.5), // move the line by the half width
         Right => Some(1.0), // move the line by the full width
+        Justify => None, // lines aren't moved, see `InlineTextLayout::justify`
     }
 }
 
@@ -137,6 +771,116 @@ pub struct LayoutResult {
     pub node_depths: Vec<(usize, NodeId)>,
 }
 
+impl LayoutResult {
+
+    /// Returns the font instance that the text of the node was shaped with
+    pub fn get_font_instance_key(&self, node_id: &NodeId) -> Option<FontInstanceKey> {
+        self.scaled_words.get(node_id).map(|(_, font_instance_key)| *font_instance_key)
+    }
+
+    /// Returns the cached scaled words of the node, or `None` if there is no cached entry
+    /// or if the words were scaled with a different font instance than `font_instance_key`
+    /// (i.e. because the font of the node changed), so that the caller recomputes them.
+    pub fn get_scaled_words(&self, node_id: &NodeId, font_instance_key: &FontInstanceKey) -> Option<&ScaledWords> {
+        match self.scaled_words.get(node_id)? {
+            (scaled_words, key) if key == font_instance_key => Some(scaled_words),
+            _ => None,
+        }
+    }
+
+    /// Same as `get_scaled_words`, but for the cached word positions of the node
+    pub fn get_word_positions(&self, node_id: &NodeId, font_instance_key: &FontInstanceKey) -> Option<&WordPositions> {
+        match self.positioned_word_cache.get(node_id)? {
+            (word_positions, key) if key == font_instance_key => Some(word_positions),
+            _ => None,
+        }
+    }
+
+    /// Removes the cached scaled words, word positions and glyphs of the node if they were
+    /// created with a different font instance than the current `font_instance_key` of the node.
+    /// Returns whether anything was removed (i.e. whether the text of the node has to be recomputed).
+    pub fn invalidate_stale_font_instance(&mut self, node_id: &NodeId, font_instance_key: &FontInstanceKey) -> bool {
+
+        let scaled_words_stale = self.scaled_words.get(node_id).map(|(_, key)| key != font_instance_key).unwrap_or(false);
+        let word_positions_stale = self.positioned_word_cache.get(node_id).map(|(_, key)| key != font_instance_key).unwrap_or(false);
+
+        if !scaled_words_stale && !word_positions_stale {
+            return false;
+        }
+
+        self.scaled_words.remove(node_id);
+        self.positioned_word_cache.remove(node_id);
+        self.layouted_glyph_cache.remove(node_id);
+        true
+    }
+}
+
+/// What happens to a line of text that is wider than the `max_horizontal_width`
+/// (i.e. a single word that is too long to be wrapped) - default: `Clip`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TextOverflowBehaviour {
+    /// The line overflows (and is clipped by the parent rectangle, if necessary)
+    Clip,
+    /// The glyphs at the end of the line are replaced with an ellipsis (`…`),
+    /// so that the line fits into the `max_horizontal_width`
+    Ellipsis,
+}
+
+impl Default for TextOverflowBehaviour {
+    fn default() -> Self {
+        TextOverflowBehaviour::Clip
+    }
+}
+
+/// Direction in which the glyphs of a line and the lines themselves flow - default: `HorizontalTb`
+///
+/// In the vertical modes, the `WordPositions` are still relative to the line (`x` = offset along
+/// the line, `y` = offset of the line), the `InlineTextLine`s of the `InlineTextLayout` are columns
+/// instead of rows. The `max_horizontal_width` is the maximum height of a column.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WritingMode {
+    /// Glyphs flow left to right, lines top to bottom
+    HorizontalTb,
+    /// Glyphs flow top to bottom, columns right to left (i.e. Chinese / Japanese)
+    VerticalRl,
+    /// Glyphs flow top to bottom, columns left to right (i.e. Mongolian)
+    VerticalLr,
+}
+
+impl WritingMode {
+    pub fn is_vertical(&self) -> bool {
+        *self != WritingMode::HorizontalTb
+    }
+}
+
+impl Default for WritingMode {
+    fn default() -> Self {
+        WritingMode::HorizontalTb
+    }
+}
+
+/// How the text following a tab character is aligned to its `TabStop`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TabAlign {
+    /// Text starts at the tab stop
+    Left,
+    /// Text ends at the tab stop
+    Right,
+    /// Text is centered around the tab stop
+    Center,
+    /// The first `.` in the text is placed at the tab stop
+    /// (text without a `.` is right-aligned instead)
+    Decimal,
+}
+
+/// Horizontal position (in pixels, relative to the start of the line)
+/// that a tab character advances the caret to
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct TabStop {
+    pub position: f32,
+    pub align: TabAlign,
+}
+
 /// Layout options that can impact the flow of word positions
 #[derive(Debug, Clone, PartialEq, PartialOrd, Default)]
 pub struct TextLayoutOptions {
@@ -151,8 +895,16 @@ pub struct TextLayoutOptions {
     /// How many spaces should a tab character emulate
     /// (multiplying value, i.e. `4.0` = one tab = 4 spaces)?
     pub tab_width: Option<f32>,
-    /// Maximum width of the text (in pixels) - if the text is set to `overflow:visible`, set this to None.
-    pub max_horizontal_width: Option<f32>,
+    /// Tab stops, sorted by position. A tab advances to the first stop right of
+    /// the caret, tabs after the last stop fall back to `tab_width`.
+    pub tab_stops: Vec<TabStop>,
+    /// Maximum width of the text (%-based values are relative to the width of the container)
+    /// - if the text is set to `overflow:visible`, set this to None.
+    pub max_horizontal_width: Option<PixelValue>,
+    /// What to do with lines that are wider than the `max_horizontal_width`
+    pub text_overflow: TextOverflowBehaviour,
+    /// Direction of the glyphs and lines
+    pub writing_mode: WritingMode,
     /// How many pixels of leading does the first line have? Note that this added onto to the holes,
     /// so for effects like `:first-letter`, use a hole instead of a leading.
     pub leading: Option<f32>,
@@ -161,6 +913,35 @@ pub struct TextLayoutOptions {
     ///
     /// TODO: Currently unused!
     pub holes: Vec<LayoutRect>,
+    /// Whether to report the positions of spaces, tabs and line breaks
+    /// (`WordPositions::whitespace_markers`), i.e. for visible whitespace in code editors
+    pub show_whitespace: bool,
+    /// Vertical gap between two paragraphs (text blocks separated by an empty line, i.e. `"a\n\nb"`),
+    /// which replaces the advance of the empty line. If `None`, the empty line is a regular line.
+    pub paragraph_spacing: Option<PixelValue>,
+}
+
+impl TextLayoutOptions {
+    /// Resolves the `PixelValue`s to pixels - `max_horizontal_width` is resolved against the
+    /// `container_width`, all other %-based values against the (default) font size
+    pub fn resolve(&self, container_width: f32) -> ResolvedTextLayoutOptions {
+        let font_size_px = self.font_size_px.to_pixels(DEFAULT_FONT_SIZE_PX as f32);
+        ResolvedTextLayoutOptions {
+            font_size_px,
+            line_height: self.line_height,
+            letter_spacing: self.letter_spacing.map(|ls| ls.to_pixels(DEFAULT_LETTER_SPACING)),
+            word_spacing: self.word_spacing.map(|ws| ws.to_pixels(DEFAULT_WORD_SPACING)),
+            tab_width: self.tab_width,
+            tab_stops: self.tab_stops.clone(),
+            max_horizontal_width: self.max_horizontal_width.map(|mw| mw.to_pixels(container_width)),
+            text_overflow: self.text_overflow,
+            writing_mode: self.writing_mode,
+            leading: self.leading,
+            holes: self.holes.clone(),
+            show_whitespace: self.show_whitespace,
+            paragraph_spacing: self.paragraph_spacing.map(|ps| ps.to_pixels(font_size_px)),
+        }
+    }
 }
 
 /// Same as `TextLayoutOptions`, but with the widths / heights of the `PixelValue`s
@@ -178,8 +959,15 @@ pub struct ResolvedTextLayoutOptions {
     /// How many spaces should a tab character emulate
     /// (multiplying value, i.e. `4.0` = one tab = 4 spaces)?
     pub tab_width: Option<f32>,
+    /// Tab stops, sorted by position. A tab advances to the first stop right of
+    /// the caret, tabs after the last stop fall back to `tab_width`.
+    pub tab_stops: Vec<TabStop>,
     /// Maximum width of the text (in pixels) - if the text is set to `overflow:visible`, set this to None.
     pub max_horizontal_width: Option<f32>,
+    /// What to do with lines that are wider than the `max_horizontal_width`
+    pub text_overflow: TextOverflowBehaviour,
+    /// Direction of the glyphs and lines
+    pub writing_mode: WritingMode,
     /// How many pixels of leading does the first line have? Note that this added onto to the holes,
     /// so for effects like `:first-letter`, use a hole instead of a leading.
     pub leading: Option<f32>,
@@ -188,6 +976,12 @@ pub struct ResolvedTextLayoutOptions {
     ///
     /// TODO: Currently unused!
     pub holes: Vec<LayoutRect>,
+    /// Whether to report the positions of spaces, tabs and line breaks
+    /// (`WordPositions::whitespace_markers`), i.e. for visible whitespace in code editors
+    pub show_whitespace: bool,
+    /// Vertical gap between two paragraphs (text blocks separated by an empty line, i.e. `"a\n\nb"`),
+    /// which replaces the advance of the empty line. If `None`, the empty line is a regular line.
+    pub paragraph_spacing: Option<f32>,
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
@@ -202,6 +996,32 @@ impl ResolvedOffsets {
     pub const fn zero() -> Self { Self { top: 0.0, left: 0.0, right: 0.0, bottom: 0.0 } }
     pub fn total_vertical(&self) -> f32 { self.top + self.bottom }
     pub fn total_horizontal(&self) -> f32 { self.left + self.right }
+
+    /// Shrinks the `rect` by these offsets (i.e. border box -> padding box),
+    /// the size of the resulting rect is clamped at zero
+    pub fn inset_rect(&self, rect: &LayoutRect) -> LayoutRect {
+        use azul_css::{LayoutPoint, LayoutSize};
+        LayoutRect::new(
+            LayoutPoint::new(rect.origin.x + self.left, rect.origin.y + self.top),
+            LayoutSize::new(
+                (rect.size.width - self.total_horizontal()).max(0.0),
+                (rect.size.height - self.total_vertical()).max(0.0),
+            ),
+        )
+    }
+
+    /// Grows the `rect` by these offsets (i.e. border box -> margin box),
+    /// the size of the resulting rect is clamped at zero
+    pub fn outset_rect(&self, rect: &LayoutRect) -> LayoutRect {
+        use azul_css::{LayoutPoint, LayoutSize};
+        LayoutRect::new(
+            LayoutPoint::new(rect.origin.x - self.left, rect.origin.y - self.top),
+            LayoutSize::new(
+                (rect.size.width + self.total_horizontal()).max(0.0),
+                (rect.size.height + self.total_vertical()).max(0.0),
+            ),
+        )
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -220,11 +1040,98 @@ pub struct PositionedRectangle {
     /// If this is an inline rectangle, resolve the %-based font sizes
     /// and store them here.
     pub resolved_text_layout_options: Option<(ResolvedTextLayoutOptions, InlineTextLayout, LayoutRect)>,
-    /// Determines if the rect should be clipped or not (TODO: x / y as separate fields!)
-    pub overflow: Overflow,
+    /// Determines if the rect should be clipped horizontally or not
+    pub overflow_x: Overflow,
+    /// Determines if the rect should be clipped vertically or not
+    pub overflow_y: Overflow,
+}
+
+/// How far the content of a rectangle extends past the rectangle, in pixels.
+/// Positive values mean that the content overflows the rectangle.
+#[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd)]
+pub struct OverflowInfo {
+    pub overflow_x: f32,
+    pub overflow_y: f32,
+}
+
+impl OverflowInfo {
+
+    fn new(bounds: &LayoutRect, content_size: Option<LayoutSize>) -> Self {
+        let content_size = content_size.unwrap_or(bounds.size);
+        Self {
+            overflow_x: content_size.width - bounds.size.width,
+            overflow_y: content_size.height - bounds.size.height,
+        }
+    }
+
+    pub fn is_overflowing(&self) -> bool {
+        self.overflow_x > 0.0 || self.overflow_y > 0.0
+    }
+
+    /// Returns whether a rectangle with the given `overflow` property should show a scroll bar
+    /// (`overflow: auto` only shows the scroll bar if the content overflows the rectangle)
+    pub fn needs_scrollbar(&self, overflow: Overflow) -> bool {
+        match overflow {
+            Overflow::Scroll => true,
+            Overflow::Auto => self.is_overflowing(),
+            Overflow::Hidden | Overflow::Visible => false,
+        }
+    }
+}
+
+/// Returns the padding box of the rectangle on the clipped axes, see `PositionedRectangle::get_clip_rect`
+fn get_clip_rect(bounds: &LayoutRect, border_widths: &ResolvedOffsets, overflow_x: Overflow, overflow_y: Overflow) -> Option<LayoutRect> {
+
+    use azul_css::{LayoutPoint, LayoutSize};
+
+    // Origin and size of the clip rect on an axis that isn't clipped
+    const UNBOUNDED_ORIGIN: f32 = f32::MIN / 2.0;
+    const UNBOUNDED_SIZE: f32 = f32::MAX;
+
+    if overflow_x.is_overflow_visible() && overflow_y.is_overflow_visible() {
+        return None;
+    }
+
+    let padding_box = border_widths.inset_rect(bounds);
+
+    let (x, width) = if overflow_x.is_overflow_visible() {
+        (UNBOUNDED_ORIGIN, UNBOUNDED_SIZE)
+    } else {
+        (padding_box.origin.x, padding_box.size.width)
+    };
+
+    let (y, height) = if overflow_y.is_overflow_visible() {
+        (UNBOUNDED_ORIGIN, UNBOUNDED_SIZE)
+    } else {
+        (padding_box.origin.y, padding_box.size.height)
+    };
+
+    Some(LayoutRect::new(LayoutPoint::new(x, y), LayoutSize::new(width, height)))
 }
 
 impl PositionedRectangle {
+
+    /// Compares the `content_size` to the size of the rectangle
+    pub fn get_overflow_info(&self) -> OverflowInfo {
+        OverflowInfo::new(&self.bounds, self.content_size)
+    }
+
+    /// Returns whether the rectangle needs a scroll frame (and scroll bars): always for
+    /// `overflow: scroll`, for `overflow: auto` only if the content (children or text) overflows
+    pub fn needs_scroll_frame(&self) -> bool {
+        let overflow_info = self.get_overflow_info();
+        overflow_info.needs_scrollbar(self.overflow_x) || overflow_info.needs_scrollbar(self.overflow_y)
+    }
+
+    /// Returns the rectangle that the children of this rectangle have to be clipped to
+    /// (the padding box), or `None` if the overflow is visible on both axes.
+    ///
+    /// Only the axes with a `hidden`, `scroll` or `auto` overflow are clipped, on an axis with
+    /// `overflow: visible`, the clip rect extends infinitely in both directions.
+    pub fn get_clip_rect(&self) -> Option<LayoutRect> {
+        get_clip_rect(&self.bounds, &self.border_widths, self.overflow_x, self.overflow_y)
+    }
+
     pub fn to_layouted_rectangle(&self) -> LayoutedRectangle {
         LayoutedRectangle {
             bounds: self.bounds,
@@ -232,7 +1139,8 @@ impl PositionedRectangle {
             margin: self.margin,
             border_widths: self.border_widths,
             content_size: self.content_size,
-            overflow: self.overflow,
+            overflow_x: self.overflow_x,
+            overflow_y: self.overflow_y,
         }
     }
 }
@@ -251,6 +1159,434 @@ pub struct LayoutedRectangle {
     /// Size of the content, for example if a div contains an image or text,
     /// that image or the text block can be bigger than the actual rect
     pub content_size: Option<LayoutSize>,
-    /// Determines if the rect should be clipped or not (TODO: x / y as separate fields!)
-    pub overflow: Overflow,
-}
\ No newline at end of file
+    /// Determines if the rect should be clipped horizontally or not
+    pub overflow_x: Overflow,
+    /// Determines if the rect should be clipped vertically or not
+    pub overflow_y: Overflow,
+}
+
+impl LayoutedRectangle {
+    /// Compares the `content_size` to the size of the rectangle
+    pub fn get_overflow_info(&self) -> OverflowInfo {
+        OverflowInfo::new(&self.bounds, self.content_size)
+    }
+
+    /// See `PositionedRectangle::get_clip_rect`
+    pub fn get_clip_rect(&self) -> Option<LayoutRect> {
+        get_clip_rect(&self.bounds, &self.border_widths, self.overflow_x, self.overflow_y)
+    }
+}
+
+#[test]
+fn test_inline_text_layout_from_line_widths() {
+    let layout = InlineTextLayout::from_line_widths(&[100.0, 50.0, 80.0], 10.0);
+    assert_eq!(layout.lines.len(), 3);
+    assert_eq!(layout.lines[2].bounds.origin.y, 20.0);
+    assert_eq!(layout.get_bounds().size.width, 100.0);
+    assert_eq!(layout.get_bounds().size.height, 30.0);
+}
+
+#[test]
+fn test_inline_text_layout_align_synthetic_lines() {
+
+    let mut centered = InlineTextLayout::from_line_widths(&[100.0, 50.0, 80.0], 10.0);
+    centered.align_children_horizontal(StyleTextAlignmentHorz::Center);
+    let x: Vec<f32> = centered.lines.iter().map(|l| l.bounds.origin.x).collect();
+    assert_eq!(x, vec![0.0, 25.0, 10.0]);
+
+    let mut right = InlineTextLayout::from_line_widths(&[100.0, 50.0, 80.0], 10.0);
+    right.align_children_horizontal(StyleTextAlignmentHorz::Right);
+    let x: Vec<f32> = right.lines.iter().map(|l| l.bounds.origin.x).collect();
+    assert_eq!(x, vec![0.0, 50.0, 20.0]);
+
+    let mut left = InlineTextLayout::from_line_widths(&[100.0, 50.0], 10.0);
+    left.align_children_horizontal(StyleTextAlignmentHorz::Left);
+    assert_eq!(left, InlineTextLayout::from_line_widths(&[100.0, 50.0], 10.0));
+}
+
+#[test]
+fn test_inline_text_layout_line_highlight_rects() {
+
+    // The middle line is empty
+    let layout = InlineTextLayout::from_line_widths(&[100.0, 0.0, 50.0], 20.0);
+    let rects = layout.line_highlight_rects(200.0);
+
+    assert_eq!(rects.len(), 3);
+    for (line_idx, rect) in rects.iter().enumerate() {
+        assert_eq!(rect.origin.x, 0.0);
+        assert_eq!(rect.origin.y, line_idx as f32 * 20.0);
+        assert_eq!(rect.size.width, 200.0);
+        assert_eq!(rect.size.height, 20.0);
+    }
+}
+
+#[test]
+fn test_inline_text_layout_overflowing_lines() {
+    let layout = InlineTextLayout::from_line_widths(&[100.0, 150.0, 100.0004], 10.0);
+    assert_eq!(layout.overflowing_lines(100.0), vec![1]);
+    assert!(layout.overflowing_lines(150.0).is_empty());
+}
+
+#[test]
+fn test_inline_text_layout_line_at_y() {
+
+    // Lines at 0..10, 15..25 (after a 5px gap) and 25..35
+    let mut layout = InlineTextLayout::from_line_widths(&[100.0, 50.0, 80.0], 10.0);
+    layout.lines[1].bounds.origin.y = 15.0;
+    layout.lines[2].bounds.origin.y = 25.0;
+
+    assert_eq!(layout.line_at_y(0.0), Some(0));
+    assert_eq!(layout.line_at_y(9.9), Some(0));
+    assert_eq!(layout.line_at_y(12.0), Some(1));
+    assert_eq!(layout.line_at_y(25.0), Some(2));
+
+    assert_eq!(layout.line_at_y(-50.0), Some(0));
+    assert_eq!(layout.line_at_y(500.0), Some(2));
+
+    assert_eq!(InlineTextLayout::from_line_widths(&[], 10.0).line_at_y(0.0), None);
+}
+
+#[test]
+fn test_inline_text_layout_snap_baselines_to_grid() {
+
+    // 10px lines, the baseline is 8px below the top of each line (at 8, 18 and 28)
+    let metrics = FontMetrics { ascender: 800, .. FontMetrics::zero() };
+    let mut layout = InlineTextLayout::from_line_widths(&[100.0, 50.0, 80.0], 10.0);
+
+    layout.snap_baselines_to_grid(4.0, &metrics, 10.0);
+
+    let baselines = layout.lines.iter().map(|line| line.baseline_y).collect::<Vec<_>>();
+    assert_eq!(baselines, vec![8.0, 20.0, 32.0]);
+    for line in &layout.lines {
+        assert_eq!(line.baseline_y % 4.0, 0.0);
+        assert_eq!(line.baseline_y - line.bounds.origin.y, 8.0);
+    }
+
+    // Lines never move closer to each other
+    for lines in layout.lines.windows(2) {
+        assert!(lines[1].bounds.origin.y - lines[0].bounds.origin.y >= 10.0);
+    }
+
+    // Snapping again doesn't move anything
+    let snapped = layout.clone();
+    layout.snap_baselines_to_grid(4.0, &metrics, 10.0);
+    assert_eq!(layout, snapped);
+
+    layout.snap_baselines_to_grid(f32::NAN, &metrics, 10.0);
+    layout.snap_baselines_to_grid(0.0, &metrics, 10.0);
+    assert_eq!(layout, snapped);
+}
+
+#[test]
+fn test_resolved_offsets_inset_outset_rect() {
+
+    use azul_css::{LayoutPoint, LayoutSize};
+
+    let rect = LayoutRect::new(LayoutPoint::new(10.0, 20.0), LayoutSize::new(100.0, 50.0));
+    let offsets = ResolvedOffsets { top: 1.0, left: 2.0, right: 3.0, bottom: 4.0 };
+
+    let inset = offsets.inset_rect(&rect);
+    assert_eq!(inset, LayoutRect::new(LayoutPoint::new(12.0, 21.0), LayoutSize::new(95.0, 45.0)));
+    assert_eq!(offsets.outset_rect(&inset), rect);
+    assert_eq!(offsets.inset_rect(&offsets.outset_rect(&rect)), rect);
+
+    // Insetting by more than the size clamps the size at zero
+    let huge = ResolvedOffsets { top: 30.0, left: 60.0, right: 60.0, bottom: 30.0 };
+    assert_eq!(huge.inset_rect(&rect).size, LayoutSize::zero());
+}
+
+#[test]
+fn test_inline_text_layout_single_line_scroll_offset() {
+
+    let layout = InlineTextLayout::from_line_widths(&[300.0], 10.0);
+
+    // Caret past the right edge scrolls right, just enough to show the caret
+    assert_eq!(layout.single_line_scroll_offset(150.0, 100.0, 0.0), 50.0);
+    // Caret before the left edge scrolls left
+    assert_eq!(layout.single_line_scroll_offset(20.0, 100.0, 50.0), 20.0);
+    // Caret inside the field keeps the offset
+    assert_eq!(layout.single_line_scroll_offset(80.0, 100.0, 50.0), 50.0);
+    // Never scroll past the end of the text
+    assert_eq!(layout.single_line_scroll_offset(250.0, 100.0, 500.0), 200.0);
+}
+
+#[test]
+fn test_move_caret_visual_mixed_direction() {
+
+    use crate::app_resources::{Word, WordType};
+
+    // "abcאבג" - displayed as "abcגבא"
+    let text = "abc\u{5D0}\u{5D1}\u{5D2}";
+    let words = Words {
+        items: vec![Word { start: 0, end: 6, word_type: WordType::Word }],
+        internal_str: text.to_string(),
+        internal_chars: text.chars().collect(),
+    };
+    let levels = [0, 0, 0, 1, 1, 1];
+    let layout = InlineTextLayout::from_line_widths(&[60.0], 10.0);
+    let move_caret = |current, direction| layout.move_caret_visual(current, direction, &words, &levels);
+
+    assert_eq!(move_caret(2, VisualDirection::Right), 3);
+    // Moving right at the LTR / RTL boundary enters the RTL run at its logical end
+    assert_eq!(move_caret(3, VisualDirection::Right), 5);
+    assert_eq!(move_caret(5, VisualDirection::Right), 4);
+    assert_eq!(move_caret(5, VisualDirection::Left), 3);
+    assert_eq!(move_caret(0, VisualDirection::Left), 0);
+
+    // In a purely right-to-left line, moving right decreases the logical index
+    let rtl_levels = [1; 6];
+    assert_eq!(layout.move_caret_visual(3, VisualDirection::Right, &words, &rtl_levels), 2);
+    assert_eq!(layout.move_caret_visual(3, VisualDirection::Left, &words, &rtl_levels), 4);
+}
+
+#[test]
+fn test_place_inline_box() {
+
+    use azul_css::LayoutPoint;
+
+    let layout = InlineTextLayout::from_line_widths(&[100.0, 50.0], 20.0);
+    let line = &layout.lines[1];
+    let box_size = LayoutSize::new(10.0, 8.0);
+
+    let baseline_box = line.place_inline_box(30.0, box_size, InlineVerticalAlign::Baseline);
+    assert_eq!(baseline_box, LayoutRect::new(LayoutPoint::new(30.0, 32.0), box_size));
+    assert_eq!(baseline_box.origin.y + baseline_box.size.height, line.baseline_y);
+
+    assert_eq!(line.place_inline_box(30.0, box_size, InlineVerticalAlign::Top).origin.y, 20.0);
+    assert_eq!(line.place_inline_box(30.0, box_size, InlineVerticalAlign::Middle).origin.y, 26.0);
+    assert_eq!(line.place_inline_box(30.0, box_size, InlineVerticalAlign::Bottom).origin.y, 32.0);
+}
+
+#[test]
+fn test_first_baseline() {
+
+    let mut metrics = FontMetrics::zero();
+    metrics.ascender = 800;
+
+    // line top + half-leading + ascender (12.8px at 16px)
+    let mut layout = InlineTextLayout::from_line_widths(&[100.0, 100.0], 30.0);
+    layout.lines[0].half_leading = 5.0;
+    layout.lines[0].baseline_y = layout.lines[0].bounds.origin.y + 5.0 + metrics.get_ascender(16.0);
+    assert_eq!(layout.first_baseline(), 17.8);
+
+    let empty = InlineTextLayout::from_line_widths(&[], 20.0);
+    assert_eq!(empty.first_baseline(), 0.0);
+}
+
+#[test]
+fn test_inline_text_layout_append_below() {
+
+    let mut layout = InlineTextLayout::from_line_widths(&[100.0, 80.0, 60.0], 20.0);
+    let other = InlineTextLayout::from_line_widths(&[50.0, 40.0], 10.0);
+    layout.append_below(other, 5.0);
+
+    assert_eq!(layout.lines.len(), 5);
+    let tops = layout.lines.iter().map(|line| line.bounds.origin.y).collect::<Vec<_>>();
+    assert_eq!(tops, vec![0.0, 20.0, 40.0, 65.0, 75.0]);
+    assert_eq!(layout.lines[3].baseline_y, 75.0);
+    assert_eq!((layout.lines[3].word_start, layout.lines[3].word_end), (3, 4));
+    assert_eq!((layout.lines[4].word_start, layout.lines[4].word_end), (4, 5));
+    assert_eq!(layout.get_bounds().size.height, 85.0);
+}
+
+#[test]
+fn test_inline_text_layout_line_start_x() {
+
+    use azul_css::LayoutPoint;
+
+    let layout = InlineTextLayout::from_line_widths(&[150.0, 100.0], 20.0);
+    let container = LayoutRect::new(LayoutPoint::new(10.0, 0.0), LayoutSize::new(200.0, 40.0));
+    let opts = ResolvedTextLayoutOptions { leading: Some(30.0), .. Default::default() };
+
+    // Only the first line is indented
+    assert_eq!(layout.line_start_x(0, container, &opts, TextDirection::LeftToRight), 40.0);
+    assert_eq!(layout.line_start_x(1, container, &opts, TextDirection::LeftToRight), 10.0);
+
+    // Right-to-left lines start at the right edge of the container
+    assert_eq!(layout.line_start_x(1, container, &opts, TextDirection::RightToLeft), 210.0);
+    assert_eq!(layout.line_start_x(0, container, &opts, TextDirection::RightToLeft), 180.0);
+
+    // A hole at the start of the second line (i.e. a floated image) pushes the line start to its edge
+    let opts = ResolvedTextLayoutOptions { holes: vec![LayoutRect::new(LayoutPoint::new(0.0, 25.0), LayoutSize::new(50.0, 10.0))], .. opts };
+    assert_eq!(layout.line_start_x(1, container, &opts, TextDirection::LeftToRight), 60.0);
+    assert_eq!(layout.line_start_x(1, container, &opts, TextDirection::RightToLeft), 210.0);
+    assert_eq!(layout.line_start_x(0, container, &opts, TextDirection::LeftToRight), 40.0);
+}
+
+#[test]
+fn test_get_clip_rect() {
+
+    use azul_css::LayoutPoint;
+
+    let get_rect = |overflow_x: Overflow, overflow_y: Overflow| PositionedRectangle {
+        bounds: LayoutRect::new(LayoutPoint::new(10.0, 20.0), LayoutSize::new(100.0, 50.0)),
+        padding: ResolvedOffsets::zero(),
+        margin: ResolvedOffsets::zero(),
+        border_widths: ResolvedOffsets { top: 1.0, bottom: 1.0, left: 2.0, right: 2.0 },
+        content_size: Some(LayoutSize::new(200.0, 200.0)),
+        resolved_text_layout_options: None,
+        overflow_x,
+        overflow_y,
+    };
+
+    let is_unbounded = |origin: f32, size: f32| origin < -1_000_000.0 && origin + size > 1_000_000.0;
+
+    // visible / visible: no clipping at all
+    assert_eq!(get_rect(Overflow::Visible, Overflow::Visible).get_clip_rect(), None);
+
+    // hidden / hidden: clipped to the padding box
+    let clip = get_rect(Overflow::Hidden, Overflow::Hidden).get_clip_rect().unwrap();
+    assert_eq!(clip, LayoutRect::new(LayoutPoint::new(12.0, 21.0), LayoutSize::new(96.0, 48.0)));
+
+    // hidden / visible: only clipped horizontally
+    let clip = get_rect(Overflow::Hidden, Overflow::Visible).get_clip_rect().unwrap();
+    assert_eq!((clip.origin.x, clip.size.width), (12.0, 96.0));
+    assert!(is_unbounded(clip.origin.y, clip.size.height));
+
+    // visible / scroll: only clipped vertically
+    let clip = get_rect(Overflow::Visible, Overflow::Scroll).get_clip_rect().unwrap();
+    assert!(is_unbounded(clip.origin.x, clip.size.width));
+    assert_eq!((clip.origin.y, clip.size.height), (21.0, 48.0));
+    assert_eq!(get_rect(Overflow::Visible, Overflow::Scroll).to_layouted_rectangle().get_clip_rect(), Some(clip));
+}
+
+#[test]
+fn test_overflow_info() {
+
+    use azul_css::LayoutPoint;
+
+    let get_rect = |content_size: LayoutSize| PositionedRectangle {
+        bounds: LayoutRect::new(LayoutPoint::new(10.0, 10.0), LayoutSize::new(100.0, 50.0)),
+        padding: ResolvedOffsets::zero(),
+        margin: ResolvedOffsets::zero(),
+        border_widths: ResolvedOffsets::zero(),
+        content_size: Some(content_size),
+        resolved_text_layout_options: None,
+        overflow_x: Overflow::Auto,
+        overflow_y: Overflow::Auto,
+    };
+
+    let overflowing = get_rect(LayoutSize::new(120.0, 80.0)).get_overflow_info();
+    assert_eq!(overflowing, OverflowInfo { overflow_x: 20.0, overflow_y: 30.0 });
+    assert!(overflowing.needs_scrollbar(Overflow::Auto));
+
+    let fitting = get_rect(LayoutSize::new(100.0, 50.0)).get_overflow_info();
+    assert!(fitting.overflow_x <= 0.0 && fitting.overflow_y <= 0.0);
+    assert!(!fitting.needs_scrollbar(Overflow::Auto));
+    assert!(fitting.needs_scrollbar(Overflow::Scroll));
+
+    // Scroll frames are created from the overflow of the content, not only from the CSS
+    assert!(get_rect(LayoutSize::new(120.0, 80.0)).needs_scroll_frame());
+    assert!(!get_rect(LayoutSize::new(100.0, 50.0)).needs_scroll_frame());
+    let hidden = PositionedRectangle { overflow_x: Overflow::Hidden, overflow_y: Overflow::Hidden, .. get_rect(LayoutSize::new(120.0, 80.0)) };
+    assert!(!hidden.needs_scroll_frame());
+    let scroll = PositionedRectangle { overflow_y: Overflow::Scroll, .. get_rect(LayoutSize::new(100.0, 50.0)) };
+    assert!(scroll.needs_scroll_frame());
+}
+
+#[test]
+fn test_justify_mixed_direction_line() {
+
+    // Line 1: "ab CD EF gh" where "CD EF" is a right-to-left run, line 2 is the last line
+    let mut layout = InlineTextLayout::from_line_widths(&[80.0, 10.0], 10.0);
+    layout.lines[0].word_start = 0;
+    layout.lines[0].word_end = 4;
+    layout.lines[1].word_start = 4;
+    layout.lines[1].word_end = 5;
+
+    let word_widths = [10.0, 20.0, 10.0, 10.0, 10.0];
+    let word_levels = [0, 1, 1, 0, 0];
+    // Visual positions: "ab" at 0, "EF" at 20, "CD" at 40, "gh" at 70
+    let mut word_positions = vec![
+        LayoutPoint::new(0.0, 0.0),
+        LayoutPoint::new(40.0, 0.0),
+        LayoutPoint::new(20.0, 0.0),
+        LayoutPoint::new(70.0, 0.0),
+        LayoutPoint::new(0.0, 10.0),
+    ];
+
+    layout.justify(&mut word_positions, &word_widths, &word_levels, 110.0);
+
+    // Visual order is "ab EF CD gh": the 30px of free space are split into 3 x 10px,
+    // so every visual gap grows from 10px to 20px, including the one inside the RTL run
+    assert_eq!(word_positions[0].x, 0.0);   // ab
+    assert_eq!(word_positions[2].x, 30.0);  // EF
+    assert_eq!(word_positions[1].x, 60.0);  // CD
+    assert_eq!(word_positions[3].x, 100.0); // gh, ends at the right edge
+    assert_eq!(layout.lines[0].bounds.size.width, 110.0);
+
+    // The last line is not stretched
+    assert_eq!(word_positions[4].x, 0.0);
+    assert_eq!(layout.lines[1].bounds.size.width, 10.0);
+}
+
+#[test]
+fn test_layout_result_font_instance_key_mismatch() {
+
+    use crate::app_resources::{IdNamespace, ScaledWord, TextDirection};
+
+    let old_key = FontInstanceKey { namespace: IdNamespace(0), key: 1 };
+    let new_key = FontInstanceKey { namespace: IdNamespace(0), key: 2 };
+    let node_id = NodeId::new(0);
+
+    let scaled_words = ScaledWords {
+        font_size_px: 16.0,
+        baseline_px: 12.0,
+        items: Vec::new(),
+        longest_word_width: 0.0,
+        space_advance_px: 4.0,
+        space_codepoint: 32,
+        ellipsis: ScaledWord {
+            glyph_infos: Vec::new(),
+            glyph_positions: Vec::new(),
+            word_width: 0.0,
+            direction: TextDirection::LeftToRight,
+            rotated_glyphs: Vec::new(),
+        },
+        font_metrics: FontMetrics::zero(),
+        direction: TextDirection::LeftToRight,
+    };
+
+    let mut layout_result = LayoutResult::default();
+    layout_result.scaled_words.insert(node_id, (scaled_words, old_key));
+    layout_result.layouted_glyph_cache.insert(node_id, LayoutedGlyphs { glyphs: Vec::new(), word_indices: Vec::new() });
+
+    assert!(layout_result.get_scaled_words(&node_id, &old_key).is_some());
+    assert!(layout_result.get_scaled_words(&node_id, &new_key).is_none());
+
+    // Same font: the cache stays valid
+    assert!(!layout_result.invalidate_stale_font_instance(&node_id, &old_key));
+    assert!(layout_result.layouted_glyph_cache.contains_key(&node_id));
+
+    // Font changed: the cached text has to be recomputed
+    assert!(layout_result.invalidate_stale_font_instance(&node_id, &new_key));
+    assert!(layout_result.scaled_words.get(&node_id).is_none());
+    assert!(layout_result.layouted_glyph_cache.get(&node_id).is_none());
+}
+
+#[test]
+fn test_text_layout_options_resolve_percent_max_width() {
+    let options = TextLayoutOptions {
+        font_size_px: PixelValue::px(20.0),
+        max_horizontal_width: Some(PixelValue::percent(50.0)),
+        .. Default::default()
+    };
+    let resolved = options.resolve(800.0);
+    assert_eq!(resolved.max_horizontal_width, Some(400.0));
+    assert_eq!(resolved.font_size_px, 20.0);
+}
+
+#[test]
+fn test_inline_text_layout_diff_lines() {
+
+    let old = InlineTextLayout::from_line_widths(&[100.0, 80.0, 120.0], 20.0);
+    assert!(old.diff_lines(&old).is_empty());
+
+    let changed = InlineTextLayout::from_line_widths(&[100.0, 60.0, 120.0], 20.0);
+    assert_eq!(changed.diff_lines(&old), vec![1]);
+
+    // Added / removed lines
+    let added = InlineTextLayout::from_line_widths(&[100.0, 80.0, 120.0, 40.0], 20.0);
+    assert_eq!(added.diff_lines(&old), vec![3]);
+    assert_eq!(old.diff_lines(&added), vec![3]);
+}
@@ -7,7 +7,7 @@ use crate::{
     FastHashMap,
     id_tree::NodeId,
     dom::{
-        Dom, DomId, TagId, TabIndex, DomString,
+        Dom, DomId, TagId, HitTestTag, TabIndex, DomString,
         HoverEventFilter, FocusEventFilter, NotEventFilter,
         WindowEventFilter,
     },
@@ -399,6 +399,24 @@ impl<T> UiState<T> {
         Self::new(dom, parent_dom)
     }
 
+    /// Returns the hit-testing tag of the given node or `None` if the node
+    /// doesn't take part in hit-testing (no callbacks, not focusable, not draggable)
+    pub fn tag_of(&self, node_id: NodeId) -> Option<HitTestTag> {
+        self.node_ids_to_tag_ids.get(&node_id).map(|tag_id| tag_id.into_hit_test_tag())
+    }
+
+    /// Resolves a tag returned by the hit-testing backend back to the node it was assigned to
+    pub fn node_for_tag(&self, tag: HitTestTag) -> Option<NodeId> {
+        self.tag_ids_to_node_ids.get(&TagId::from_hit_test_tag(tag)).cloned()
+    }
+
+    /// Returns the hit-testing tag of the last node in insertion order (the last node of the
+    /// arena, i.e. the innermost node of the last child that was added to the DOM)
+    pub fn last_tag(&self) -> Option<HitTestTag> {
+        let last_node_id = self.dom.arena.len().checked_sub(1)?;
+        self.tag_of(NodeId::new(last_node_id))
+    }
+
     pub fn create_tags_for_hover_nodes(&mut self, hover_nodes: &BTreeMap<NodeId, HoverGroup>) {
 
         for (hover_node_id, hover_group) in hover_nodes {
@@ -435,3 +453,153 @@ impl<T> UiState<T> {
         }).collect()
     }
 }
+
+#[test]
+fn test_ui_state_collects_callbacks_with_distinct_tags() {
+
+    use crate::{
+        dom::On,
+        callbacks::{TestLayout, do_nothing},
+    };
+
+    let dom: Dom<TestLayout> = Dom::div()
+        .with_child(Dom::div().with_callback(On::MouseUp, do_nothing))
+        .with_child(Dom::div())
+        .with_child(Dom::div().with_callback(On::MouseOver, do_nothing));
+
+    let ui_state = UiState::new(dom, None);
+
+    let first = NodeId::new(1);
+    let second = NodeId::new(3);
+
+    // Nodes without callbacks don't get a tag
+    assert_eq!(ui_state.hover_callbacks.len(), 2);
+    assert!(ui_state.hover_callbacks.contains_key(&first));
+    assert!(ui_state.hover_callbacks.contains_key(&second));
+    assert_eq!(ui_state.node_ids_to_tag_ids.len(), 2);
+
+    let first_tag = ui_state.node_ids_to_tag_ids[&first];
+    let second_tag = ui_state.node_ids_to_tag_ids[&second];
+    assert_ne!(first_tag, second_tag);
+    assert_eq!(ui_state.tag_ids_to_node_ids[&first_tag], first);
+    assert_eq!(ui_state.tag_ids_to_node_ids[&second_tag], second);
+}
+
+#[test]
+fn test_ui_state_collects_callbacks_per_event_filter() {
+
+    use crate::{
+        dom::{On, HoverEventFilter, FocusEventFilter},
+        callbacks::{TestLayout, do_nothing},
+    };
+
+    let dom: Dom<TestLayout> = Dom::div()
+        .with_child(Dom::div()
+            .with_callback(On::MouseUp, do_nothing)
+            .with_callback(On::MouseEnter, do_nothing))
+        .with_child(Dom::div()
+            .with_callback(On::MouseOver, do_nothing)
+            .with_callback(On::FocusReceived, do_nothing));
+
+    let ui_state = UiState::new(dom, None);
+
+    let first = NodeId::new(1);
+    let second = NodeId::new(2);
+
+    assert_eq!(
+        ui_state.hover_callbacks[&first].keys().copied().collect::<Vec<_>>(),
+        vec![HoverEventFilter::MouseUp, HoverEventFilter::MouseEnter],
+    );
+    assert_eq!(
+        ui_state.hover_callbacks[&second].keys().copied().collect::<Vec<_>>(),
+        vec![HoverEventFilter::MouseOver],
+    );
+    assert_eq!(
+        ui_state.focus_callbacks[&second].keys().copied().collect::<Vec<_>>(),
+        vec![FocusEventFilter::FocusReceived],
+    );
+    assert!(!ui_state.focus_callbacks.contains_key(&first));
+
+    // Hover and focus callbacks of the same node share one tag
+    assert_eq!(ui_state.node_ids_to_tag_ids.len(), 2);
+    assert_eq!(ui_state.node_for_tag(ui_state.tag_of(first).unwrap()), Some(first));
+    assert_eq!(ui_state.node_for_tag(ui_state.tag_of(second).unwrap()), Some(second));
+}
+
+#[test]
+fn test_ui_state_collects_key_callbacks_as_focus_callbacks() {
+
+    use std::collections::HashSet;
+    use crate::{
+        dom::{On, FocusEventFilter, WindowEventFilter},
+        callbacks::{TestLayout, do_nothing},
+        window_state::{get_hover_events, get_focus_events},
+    };
+
+    // Text input: only reacts to keys while focused
+    let dom: Dom<TestLayout> = Dom::div()
+        .with_child(Dom::div()
+            .with_tab_index(TabIndex::Auto)
+            .with_callback(On::KeyDown, do_nothing)
+            .with_callback(On::KeyUp, do_nothing));
+
+    let ui_state = UiState::new(dom, None);
+    let input = NodeId::new(1);
+
+    assert_eq!(
+        ui_state.focus_callbacks[&input].keys().copied().collect::<Vec<_>>(),
+        vec![FocusEventFilter::VirtualKeyDown, FocusEventFilter::VirtualKeyUp],
+    );
+    assert!(ui_state.window_callbacks.is_empty());
+    assert!(ui_state.tag_of(input).is_some());
+
+    // Releasing a key fires the focus callbacks for VirtualKeyUp (not VirtualKeyDown)
+    let window_events = [WindowEventFilter::VirtualKeyUp].iter().copied().collect::<HashSet<_>>();
+    let focus_events = get_focus_events(&get_hover_events(&window_events));
+    assert!(focus_events.contains(&FocusEventFilter::VirtualKeyUp));
+    assert!(!focus_events.contains(&FocusEventFilter::VirtualKeyDown));
+}
+
+#[test]
+fn test_ui_state_last_tag() {
+
+    use crate::{
+        dom::On,
+        callbacks::{TestLayout, do_nothing},
+    };
+
+    let with_event = UiState::new(Dom::<TestLayout>::div().with_callback(On::MouseUp, do_nothing), None);
+    assert!(with_event.last_tag().is_some());
+    assert_eq!(with_event.last_tag(), with_event.tag_of(NodeId::ZERO));
+
+    let without_event = UiState::new(Dom::<TestLayout>::div(), None);
+    assert_eq!(without_event.last_tag(), None);
+
+    // The tag of a nested child, not the one of the root
+    let nested: Dom<TestLayout> = Dom::div()
+        .with_callback(On::MouseUp, do_nothing)
+        .with_child(Dom::div()
+            .with_child(Dom::div().with_callback(On::MouseDown, do_nothing)));
+    let nested = UiState::new(nested, None);
+    assert!(nested.last_tag().is_some());
+    assert_eq!(nested.last_tag(), nested.tag_of(NodeId::new(2)));
+    assert_ne!(nested.last_tag(), nested.tag_of(NodeId::ZERO));
+}
+
+#[test]
+fn test_ui_state_node_for_tag() {
+
+    use crate::{
+        dom::On,
+        callbacks::{TestLayout, do_nothing},
+    };
+
+    let dom: Dom<TestLayout> = Dom::div()
+        .with_child(Dom::div().with_callback(On::MouseUp, do_nothing));
+
+    let ui_state = UiState::new(dom, None);
+    let tag = ui_state.tag_of(NodeId::new(1)).unwrap();
+
+    assert_eq!(ui_state.node_for_tag(tag), Some(NodeId::new(1)));
+    assert_eq!(ui_state.node_for_tag(HitTestTag(u64::max_value())), None);
+}
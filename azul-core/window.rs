@@ -1,9 +1,10 @@
 use std::{
     fmt,
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     sync::atomic::{AtomicUsize, Ordering},
     marker::PhantomData,
     path::PathBuf,
+    time::Instant,
 };
 #[cfg(target_os = "windows")]
 use std::ffi::c_void;
@@ -16,13 +17,17 @@ use azul_css::{Css, CssPath};
 use azul_css::HotReloadHandler;
 use crate::{
     dom::{DomId, EventFilter},
-    id_tree::NodeId,
+    id_tree::{NodeId, NodeHierarchy},
     callbacks::{Callback, DefaultCallback, HitTestItem, UpdateScreen, Redraw},
 };
 
 pub const DEFAULT_TITLE: &str = "Azul App";
 pub const DEFAULT_WIDTH: f32 = 800.0;
 pub const DEFAULT_HEIGHT: f32 = 600.0;
+/// Distance in logical pixels that the cursor has to travel away from the point
+/// where a mouse button was pressed before the movement counts as a drag and
+/// not as a click. Can be overridden per window via `MouseState::drag_threshold`.
+pub const DEFAULT_DRAG_THRESHOLD: f32 = 5.0;
 
 static LAST_WINDOW_ID: AtomicUsize = AtomicUsize::new(0);
 
@@ -152,6 +157,11 @@ pub struct MouseState {
     pub scroll_x: Option<f32>,
     /// Scroll amount in pixels in the vertical direction. Gets reset to 0 after every frame (READONLY)
     pub scroll_y: Option<f32>,
+    /// Where the cursor was when the currently held mouse button was pressed (READONLY)
+    pub mouse_down_position: Option<LogicalPosition>,
+    /// How far the cursor has to move away from `mouse_down_position` to start a drag,
+    /// defaults to `DEFAULT_DRAG_THRESHOLD` (READWRITE)
+    pub drag_threshold: f32,
 }
 
 impl Default for MouseState {
@@ -165,6 +175,8 @@ impl Default for MouseState {
             middle_down: false,
             scroll_x: None,
             scroll_y: None,
+            mouse_down_position: None,
+            drag_threshold: DEFAULT_DRAG_THRESHOLD,
         }
     }
 }
@@ -176,6 +188,22 @@ impl MouseState {
         self.right_down || self.left_down || self.middle_down
     }
 
+    /// Returns whether a mouse button is held down and the cursor has moved
+    /// further than `drag_threshold` away from the point where it was pressed.
+    /// Below the threshold, the movement is treated as jitter during a click.
+    pub fn is_dragging(&self) -> bool {
+        if !self.mouse_down() {
+            return false;
+        }
+        match (self.mouse_down_position, self.cursor_position.get_position()) {
+            (Some(start), Some(current)) => {
+                let (dx, dy) = (current.x - start.x, current.y - start.y);
+                (dx * dx + dy * dy).sqrt() > self.drag_threshold
+            },
+            _ => false,
+        }
+    }
+
     pub fn get_scroll_x(&self) -> f32 {
         self.scroll_x.unwrap_or(0.0)
     }
@@ -314,6 +342,11 @@ pub struct FullWindowState {
     /// Currently hovered nodes, default to an empty Vec. Important for
     /// styling `:hover` elements.
     pub hovered_nodes: BTreeMap<DomId, BTreeMap<NodeId, HitTestItem>>,
+    /// Nodes that were hovered when the mouse button was pressed down. Used to
+    /// determine which nodes receive an `On::Click` when the button is released.
+    pub pressed_nodes: BTreeMap<DomId, BTreeSet<NodeId>>,
+    /// When the last `On::Click` happened and on which nodes, for detecting `On::DoubleClick`
+    pub last_clicked_nodes: BTreeMap<DomId, (Instant, BTreeSet<NodeId>)>,
 }
 
 impl Default for FullWindowState {
@@ -337,6 +370,8 @@ impl Default for FullWindowState {
             dropped_file: None,
             focused_node: None,
             hovered_nodes: BTreeMap::default(),
+            pressed_nodes: BTreeMap::default(),
+            last_clicked_nodes: BTreeMap::default(),
         }
     }
 }
@@ -1047,12 +1082,50 @@ impl<T> fmt::Debug for CallbacksOfHitTest<T> {
     }
 }
 
+/// Whether a callback in the `CallbacksOfHitTest` is a default callback (set by a widget via
+/// `Dom::with_default_callback`) or a normal, user-defined callback
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CallbackKind {
+    Default,
+    Normal,
+}
+
 impl<T> CallbacksOfHitTest<T> {
     /// Returns whether there is any
     pub fn should_call_callbacks(&self) -> bool {
         !self.nodes_with_callbacks.is_empty() &&
         self.nodes_with_callbacks.values().any(|n| n.has_any_callbacks())
     }
+
+    /// Returns the nodes with callbacks in the order that their callbacks are invoked in:
+    /// the innermost node (the target of the event) first, then its ancestors, from the inside
+    /// out. Nodes at the same depth (i.e. siblings) are ordered by their `NodeId`.
+    pub fn get_nodes_in_event_order(&self, node_hierarchy: &NodeHierarchy) -> Vec<NodeId> {
+        let mut nodes = self.nodes_with_callbacks.keys().map(|node_id| {
+            let depth = node_id.ancestors(node_hierarchy).count();
+            (depth, *node_id)
+        }).collect::<Vec<_>>();
+        nodes.sort_by(|(depth_a, node_a), (depth_b, node_b)| depth_b.cmp(depth_a).then(node_a.cmp(node_b)));
+        nodes.into_iter().map(|(_, node_id)| node_id).collect()
+    }
+
+    /// Returns every callback that is invoked for this hit test, in the order they are invoked in:
+    ///
+    /// 1. All default callbacks, going from the target node to its ancestors
+    ///    (see `get_nodes_in_event_order`), so that widgets can update their state first
+    /// 2. All normal callbacks, in the same node order
+    ///
+    /// Callbacks on the same node are invoked in the order of their `EventFilter`.
+    pub fn get_callback_order(&self, node_hierarchy: &NodeHierarchy) -> Vec<(CallbackKind, NodeId, EventFilter)> {
+        let nodes = self.get_nodes_in_event_order(node_hierarchy);
+        let default_callbacks = nodes.iter().flat_map(|node_id| {
+            self.nodes_with_callbacks[node_id].default_callbacks.keys().map(move |event_filter| (CallbackKind::Default, *node_id, *event_filter))
+        });
+        let normal_callbacks = nodes.iter().flat_map(|node_id| {
+            self.nodes_with_callbacks[node_id].normal_callbacks.keys().map(move |event_filter| (CallbackKind::Normal, *node_id, *event_filter))
+        });
+        default_callbacks.chain(normal_callbacks).collect()
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
@@ -1402,4 +1475,68 @@ impl ::std::hash::Hash for TaskBarIcon {
     fn hash<H>(&self, state: &mut H) where H: ::std::hash::Hasher {
         self.key.hash(state);
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_mouse_state_drag_threshold() {
+
+    let mut mouse_state = MouseState {
+        left_down: true,
+        mouse_down_position: Some(LogicalPosition::new(0.0, 0.0)),
+        drag_threshold: 5.0,
+        .. Default::default()
+    };
+
+    mouse_state.cursor_position = CursorPosition::InWindow(LogicalPosition::new(2.0, 0.0));
+    assert!(!mouse_state.is_dragging());
+
+    mouse_state.cursor_position = CursorPosition::InWindow(LogicalPosition::new(10.0, 0.0));
+    assert!(mouse_state.is_dragging());
+
+    mouse_state.left_down = false;
+    assert!(!mouse_state.is_dragging());
+}
+
+#[test]
+fn test_callback_order() {
+
+    use crate::{
+        dom::{On, HoverEventFilter},
+        id_tree::Node,
+        callbacks::{CallbackInfo, DefaultCallbackInfo, CallbackReturn, DontRedraw},
+    };
+
+    fn normal_callback(_: CallbackInfo<()>) -> CallbackReturn { DontRedraw }
+    fn default_callback(_: DefaultCallbackInfo<()>) -> CallbackReturn { DontRedraw }
+
+    // 0: root, 1: container, 2: button inside the container
+    let node_hierarchy = NodeHierarchy::new(vec![
+        Node { parent: None, previous_sibling: None, next_sibling: None, first_child: Some(NodeId::new(1)), last_child: Some(NodeId::new(1)) },
+        Node { parent: Some(NodeId::new(0)), previous_sibling: None, next_sibling: None, first_child: Some(NodeId::new(2)), last_child: Some(NodeId::new(2)) },
+        Node { parent: Some(NodeId::new(1)), previous_sibling: None, next_sibling: None, first_child: None, last_child: None },
+    ]);
+
+    let mouse_down = EventFilter::from(On::MouseDown);
+    let mouse_over = EventFilter::Hover(HoverEventFilter::MouseOver);
+
+    let mut container = DetermineCallbackResult::<()>::default();
+    container.normal_callbacks.insert(mouse_down, Callback(normal_callback));
+    container.default_callbacks.insert(mouse_down, DefaultCallback(default_callback));
+
+    let mut button = DetermineCallbackResult::<()>::default();
+    button.normal_callbacks.insert(mouse_over, Callback(normal_callback));
+    button.normal_callbacks.insert(mouse_down, Callback(normal_callback));
+
+    let mut callbacks = CallbacksOfHitTest::default();
+    callbacks.nodes_with_callbacks.insert(NodeId::new(1), container);
+    callbacks.nodes_with_callbacks.insert(NodeId::new(2), button);
+
+    assert_eq!(callbacks.get_nodes_in_event_order(&node_hierarchy), vec![NodeId::new(2), NodeId::new(1)]);
+    assert_eq!(callbacks.get_callback_order(&node_hierarchy), vec![
+        (CallbackKind::Default, NodeId::new(1), mouse_down),
+        (CallbackKind::Normal, NodeId::new(2), mouse_over),
+        (CallbackKind::Normal, NodeId::new(2), mouse_down),
+        (CallbackKind::Normal, NodeId::new(1), mouse_down),
+    ]);
+}
+
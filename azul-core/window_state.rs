@@ -1,11 +1,15 @@
-use std::collections::{HashSet, BTreeMap};
+use std::{
+    collections::{HashSet, BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
 use crate::{
     dom::{EventFilter, NotEventFilter, HoverEventFilter, FocusEventFilter, WindowEventFilter},
     callbacks:: {CallbackInfo, Callback, CallbackType, HitTestItem, DefaultCallback, UpdateScreen},
     id_tree::NodeId,
     ui_state::UiState,
+    ui_solver::DEFAULT_DOUBLE_CLICK_THRESHOLD_MS,
     window::{
-        AcceleratorKey, FullWindowState, CallbacksOfHitTest, DetermineCallbackResult,
+        AcceleratorKey, FullWindowState, MouseState, CallbacksOfHitTest, DetermineCallbackResult,
     },
 };
 
@@ -19,28 +23,41 @@ pub fn determine_callbacks<T>(
     ui_state: &UiState<T>,
 ) -> CallbacksOfHitTest<T> {
 
-    use std::collections::BTreeSet;
-
     let mut needs_hover_redraw = false;
     let mut needs_hover_relayout = false;
     let mut nodes_with_callbacks: BTreeMap<NodeId, DetermineCallbackResult<T>> = BTreeMap::new();
 
     let current_window_events = get_window_events(window_state);
-    let current_hover_events = get_hover_events(&current_window_events);
-    let current_focus_events = get_focus_events(&current_hover_events);
+    let mut current_hover_events = get_hover_events(&current_window_events);
 
     let event_was_mouse_down    = current_window_events.contains(&WindowEventFilter::MouseDown);
     let event_was_mouse_release = current_window_events.contains(&WindowEventFilter::MouseUp);
     let event_was_mouse_enter   = current_window_events.contains(&WindowEventFilter::MouseEnter);
     let event_was_mouse_leave   = current_window_events.contains(&WindowEventFilter::MouseLeave);
 
+    // If the cursor moved further than the drag threshold while the button was held down,
+    // releasing the button ends a drag: the nodes under the cursor don't get a MouseUp / Click /
+    // DoubleClick (the window-level MouseUp still fires, so that the drag can be stopped)
+    let event_was_drag_release = event_was_mouse_release && window_state.previous_window_state.as_ref().map_or(false, |previous| {
+        MouseState { cursor_position: window_state.mouse_state.cursor_position, .. previous.mouse_state }.is_dragging()
+    });
+
+    if event_was_drag_release {
+        current_hover_events.retain(|hover_event| match hover_event {
+            HoverEventFilter::MouseUp |
+            HoverEventFilter::LeftMouseUp |
+            HoverEventFilter::RightMouseUp |
+            HoverEventFilter::MiddleMouseUp => false,
+            _ => true,
+        });
+    }
+
+    let current_focus_events = get_focus_events(&current_hover_events);
+
     // Store the current window state so we can set it in this.previous_window_state later on
     let mut previous_state = Box::new(window_state.clone());
     previous_state.previous_window_state = None;
 
-    // TODO: If the current mouse is down, but the event
-    // wasn't a click, that means it was a drag
-
     // Figure out what the hovered NodeIds are
     let mut new_hit_node_ids: BTreeMap<NodeId, HitTestItem> = hit_test_items.iter().filter_map(|hit_test_item| {
         ui_state.tag_ids_to_node_ids
@@ -142,6 +159,50 @@ pub fn determine_callbacks<T>(
         insert_callbacks!(hover_node_id, Some(hit_test_item.clone()), hover_callbacks, hover_default_callbacks, current_hover_events, Hover);
     }
 
+    // Remember which nodes the mouse was pressed on, so that On::Click only fires
+    // if the button is released on the same node
+    if event_was_mouse_down {
+        window_state.pressed_nodes.insert(ui_state.dom_id.clone(), new_hit_node_ids.keys().copied().collect());
+    }
+
+    // Insert (normal + default) Click and DoubleClick events
+    if event_was_mouse_release {
+
+        let pressed_nodes = window_state.pressed_nodes.remove(&ui_state.dom_id).unwrap_or_default();
+        let clicked_nodes = new_hit_node_ids.keys()
+            .filter(|node_id| pressed_nodes.contains(node_id))
+            .copied()
+            .collect::<BTreeSet<NodeId>>();
+
+        if !clicked_nodes.is_empty() && !event_was_drag_release {
+
+            let now = Instant::now();
+            let double_click_threshold = Duration::from_millis(DEFAULT_DOUBLE_CLICK_THRESHOLD_MS);
+
+            let double_clicked_nodes = match window_state.last_clicked_nodes.remove(&ui_state.dom_id) {
+                Some((last_click, last_clicked_nodes)) if now.duration_since(last_click) <= double_click_threshold => {
+                    clicked_nodes.intersection(&last_clicked_nodes).copied().collect()
+                },
+                _ => BTreeSet::new(),
+            };
+
+            // Only remember single clicks, so that a triple click doesn't fire two DoubleClicks
+            if double_clicked_nodes.is_empty() {
+                window_state.last_clicked_nodes.insert(ui_state.dom_id.clone(), (now, clicked_nodes.clone()));
+            }
+
+            for clicked_node_id in &clicked_nodes {
+                let current_click_events = if double_clicked_nodes.contains(clicked_node_id) {
+                    vec![HoverEventFilter::Click, HoverEventFilter::DoubleClick]
+                } else {
+                    vec![HoverEventFilter::Click]
+                };
+                let hit_test_item = new_hit_node_ids.get(clicked_node_id).cloned();
+                insert_callbacks!(clicked_node_id, hit_test_item, hover_callbacks, hover_default_callbacks, current_click_events, Hover);
+            }
+        }
+    }
+
     // Insert (normal + default) focus events
     if let Some(current_focused_node) = &window_state.focused_node {
         insert_callbacks!(&current_focused_node.1, None, focus_callbacks, focus_default_callbacks, current_focus_events, Focus);
@@ -515,4 +576,209 @@ pub fn keymap<T>(
         })
         .next()
         .and_then(|(_, callback)| (callback)(info))
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_click_fires_only_on_the_pressed_node() {
+
+    use crate::{
+        dom::{Dom, On},
+        callbacks::{TestLayout, do_nothing, get_test_hit_test_item},
+        window::{CursorPosition, LogicalPosition},
+    };
+
+    // Simulates one frame where the left mouse button is (not) held down while
+    // the cursor hovers over `hit_nodes`, returns the events that fired per node
+    fn frame(
+        window_state: &mut FullWindowState,
+        ui_state: &UiState<TestLayout>,
+        left_down: bool,
+        hit_nodes: &[NodeId],
+    ) -> BTreeMap<NodeId, Vec<EventFilter>> {
+        let hit_test_items = hit_nodes.iter().map(|node_id| get_test_hit_test_item(ui_state.node_ids_to_tag_ids[node_id])).collect::<Vec<_>>();
+        window_state.mouse_state.cursor_position = CursorPosition::InWindow(LogicalPosition::new(0.0, 0.0));
+        window_state.mouse_state.left_down = left_down;
+        determine_callbacks(window_state, &hit_test_items, ui_state).nodes_with_callbacks
+            .into_iter()
+            .map(|(node_id, result)| (node_id, result.normal_callbacks.keys().copied().collect()))
+            .collect()
+    }
+
+    let dom: Dom<TestLayout> = Dom::div()
+        .with_child(Dom::div().with_callback(On::Click, do_nothing))
+        .with_child(Dom::div().with_callback(On::Click, do_nothing));
+
+    let ui_state = UiState::new(dom, None);
+    let first = NodeId::new(1);
+    let second = NodeId::new(2);
+    let click = vec![EventFilter::Hover(HoverEventFilter::Click)];
+
+    let mut window_state = FullWindowState::default();
+    frame(&mut window_state, &ui_state, false, &[first]);
+
+    // Press and release on the same node: click
+    assert!(frame(&mut window_state, &ui_state, true, &[first]).is_empty());
+    assert_eq!(frame(&mut window_state, &ui_state, false, &[first]).get(&first), Some(&click));
+
+    // Press on the first node, release on the second one: no click on either node
+    frame(&mut window_state, &ui_state, true, &[first]);
+    assert!(frame(&mut window_state, &ui_state, false, &[second]).is_empty());
+
+    // Pressing and releasing on the second node is a click again
+    frame(&mut window_state, &ui_state, true, &[second]);
+    assert_eq!(frame(&mut window_state, &ui_state, false, &[second]).get(&second), Some(&click));
+}
+
+#[test]
+fn test_drag_suppresses_click() {
+
+    use crate::{
+        dom::{Dom, On},
+        callbacks::{TestLayout, do_nothing, get_test_hit_test_item},
+        window::{CursorPosition, LogicalPosition, DEFAULT_DRAG_THRESHOLD},
+    };
+
+    // Presses the left mouse button at (0, 0) on `node_id`, moves the cursor to `(x, 0)`
+    // and releases the button there, returns the events that fired on release
+    fn press_move_release(
+        window_state: &mut FullWindowState,
+        ui_state: &UiState<TestLayout>,
+        node_id: NodeId,
+        x: f32,
+    ) -> BTreeMap<NodeId, Vec<EventFilter>> {
+        let hit_test_items = [get_test_hit_test_item(ui_state.node_ids_to_tag_ids[&node_id])];
+        window_state.mouse_state.cursor_position = CursorPosition::InWindow(LogicalPosition::new(0.0, 0.0));
+        window_state.mouse_state.mouse_down_position = Some(LogicalPosition::new(0.0, 0.0));
+        window_state.mouse_state.left_down = true;
+        determine_callbacks(window_state, &hit_test_items, ui_state);
+        window_state.mouse_state.cursor_position = CursorPosition::InWindow(LogicalPosition::new(x, 0.0));
+        determine_callbacks(window_state, &hit_test_items, ui_state);
+        window_state.mouse_state.left_down = false;
+        window_state.mouse_state.mouse_down_position = None;
+        determine_callbacks(window_state, &hit_test_items, ui_state).nodes_with_callbacks
+            .into_iter()
+            .map(|(node_id, result)| (node_id, result.normal_callbacks.keys().copied().collect()))
+            .collect()
+    }
+
+    let dom: Dom<TestLayout> = Dom::div()
+        .with_callback(EventFilter::Window(WindowEventFilter::MouseUp), do_nothing)
+        .with_child(Dom::div()
+            .with_callback(On::Click, do_nothing)
+            .with_callback(On::DoubleClick, do_nothing)
+            .with_callback(On::MouseUp, do_nothing));
+
+    let ui_state = UiState::new(dom, None);
+    let root = NodeId::new(0);
+    let button = NodeId::new(1);
+    let window_mouse_up = vec![EventFilter::Window(WindowEventFilter::MouseUp)];
+
+    let mut window_state = FullWindowState::default();
+    window_state.previous_window_state = Some(Box::new(window_state.clone()));
+
+    // Jitter below the drag threshold is still a click
+    let events = press_move_release(&mut window_state, &ui_state, button, DEFAULT_DRAG_THRESHOLD / 2.0);
+    assert_eq!(events.get(&button), Some(&vec![EventFilter::Hover(HoverEventFilter::MouseUp), EventFilter::Hover(HoverEventFilter::Click)]));
+    assert_eq!(events.get(&root), Some(&window_mouse_up));
+
+    // Dragging past the threshold: no MouseUp, Click or DoubleClick on the node, only on the window
+    let events = press_move_release(&mut window_state, &ui_state, button, DEFAULT_DRAG_THRESHOLD * 2.0);
+    assert_eq!(events.get(&button), None);
+    assert_eq!(events.get(&root), Some(&window_mouse_up));
+}
+
+#[test]
+fn test_callbacks_are_keyed_by_their_event() {
+
+    use crate::{
+        dom::{Dom, On},
+        callbacks::{CallbackReturn, Redraw, TestLayout, do_nothing, get_test_hit_test_item},
+        window::{CursorPosition, LogicalPosition},
+    };
+
+    fn on_mouse_down(_: CallbackInfo<TestLayout>) -> CallbackReturn { Redraw }
+
+    // `call_callbacks` passes the key of each callback as the `CallbackInfo::event`,
+    // so every callback has to be stored under the event that it was registered for
+    let dom: Dom<TestLayout> = Dom::div()
+        .with_callback(On::MouseDown, on_mouse_down)
+        .with_callback(On::LeftMouseDown, do_nothing);
+
+    let ui_state = UiState::new(dom, None);
+    let node_id = NodeId::new(0);
+    let hit_test_items = [get_test_hit_test_item(ui_state.node_ids_to_tag_ids[&node_id])];
+
+    let mut window_state = FullWindowState::default();
+    window_state.previous_window_state = Some(Box::new(window_state.clone()));
+    window_state.mouse_state.cursor_position = CursorPosition::InWindow(LogicalPosition::new(0.0, 0.0));
+    window_state.mouse_state.left_down = true;
+
+    let callbacks = determine_callbacks(&mut window_state, &hit_test_items, &ui_state);
+    let normal_callbacks = &callbacks.nodes_with_callbacks[&node_id].normal_callbacks;
+
+    assert_eq!(normal_callbacks.len(), 2);
+    assert_eq!(normal_callbacks.get(&EventFilter::Hover(HoverEventFilter::MouseDown)), Some(&Callback(on_mouse_down)));
+    assert_eq!(normal_callbacks.get(&EventFilter::Hover(HoverEventFilter::LeftMouseDown)), Some(&Callback(do_nothing)));
+}
+
+#[test]
+fn test_double_click() {
+
+    use crate::{
+        dom::{Dom, On},
+        callbacks::{TestLayout, do_nothing, get_test_hit_test_item},
+        window::{CursorPosition, LogicalPosition},
+    };
+
+    // Presses and releases the left mouse button on `node_id`,
+    // returns the events that fired on release
+    fn click(
+        window_state: &mut FullWindowState,
+        ui_state: &UiState<TestLayout>,
+        node_id: NodeId,
+    ) -> Vec<EventFilter> {
+        let hit_test_items = [get_test_hit_test_item(ui_state.node_ids_to_tag_ids[&node_id])];
+        window_state.mouse_state.cursor_position = CursorPosition::InWindow(LogicalPosition::new(0.0, 0.0));
+        window_state.mouse_state.left_down = true;
+        determine_callbacks(window_state, &hit_test_items, ui_state);
+        window_state.mouse_state.left_down = false;
+        determine_callbacks(window_state, &hit_test_items, ui_state).nodes_with_callbacks
+            .get(&node_id)
+            .map(|result| result.normal_callbacks.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    let dom: Dom<TestLayout> = Dom::div()
+        .with_child(Dom::div()
+            .with_callback(On::Click, do_nothing)
+            .with_callback(On::DoubleClick, do_nothing))
+        .with_child(Dom::div()
+            .with_callback(On::Click, do_nothing)
+            .with_callback(On::DoubleClick, do_nothing));
+
+    let ui_state = UiState::new(dom, None);
+    let first = NodeId::new(1);
+    let second = NodeId::new(2);
+    let single_click = vec![EventFilter::Hover(HoverEventFilter::Click)];
+    let double_click = vec![EventFilter::Hover(HoverEventFilter::Click), EventFilter::Hover(HoverEventFilter::DoubleClick)];
+
+    let mut window_state = FullWindowState::default();
+    window_state.previous_window_state = Some(Box::new(window_state.clone()));
+
+    // Two quick clicks on the same node, the third click starts over
+    assert_eq!(click(&mut window_state, &ui_state, first), single_click);
+    assert_eq!(click(&mut window_state, &ui_state, first), double_click);
+    assert_eq!(click(&mut window_state, &ui_state, first), single_click);
+
+    // Two quick clicks on different nodes
+    window_state.last_clicked_nodes.clear();
+    assert_eq!(click(&mut window_state, &ui_state, first), single_click);
+    assert_eq!(click(&mut window_state, &ui_state, second), single_click);
+
+    // Two clicks on the same node, but too slow
+    let too_long_ago = Duration::from_millis(DEFAULT_DOUBLE_CLICK_THRESHOLD_MS + 100);
+    for (last_click, _) in window_state.last_clicked_nodes.values_mut() {
+        *last_click = Instant::now().checked_sub(too_long_ago).unwrap();
+    }
+    assert_eq!(click(&mut window_state, &ui_state, second), single_click);
+}
@@ -2452,7 +2452,8 @@ multi_type_parser!(parse_layout_overflow, Overflow,
 multi_type_parser!(parse_layout_text_align, StyleTextAlignmentHorz,
                     ["center", Center],
                     ["left", Left],
-                    ["right", Right]);
+                    ["right", Right],
+                    ["justify", Justify]);
 
 #[cfg(test)]
 mod css_tests {
@@ -2174,12 +2174,15 @@ impl Overflow {
     }
 }
 
-/// Horizontal text alignment enum (left, center, right) - default: `Center`
+/// Horizontal text alignment enum (left, center, right, justify) - default: `Center`
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum StyleTextAlignmentHorz {
     Left,
     Center,
     Right,
+    /// Lines are stretched to the full width by widening the gaps between the words,
+    /// except for the last line of each paragraph (which is left-aligned)
+    Justify,
 }
 
 impl Default for StyleTextAlignmentHorz {
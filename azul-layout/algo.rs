@@ -85,6 +85,12 @@ pub(crate) fn compute<T: GetTextLayout>(
 
     use azul_css::Overflow as LayoutOverflow;
 
+    let translate_overflow = |overflow: Overflow| match overflow {
+        Overflow::Scroll => LayoutOverflow::Scroll,
+        Overflow::Hidden => LayoutOverflow::Hidden,
+        Overflow::Visible => LayoutOverflow::Visible,
+    };
+
     let root_min_size = node_styles[root_id].min_size;
     let root_max_size = node_styles[root_id].max_size;
 
@@ -168,26 +174,45 @@ pub(crate) fn compute<T: GetTextLayout>(
     }
 
     node_rects.transform(|rect, node_id| {
+
+        let origin = LayoutPoint { x: rect.origin.x.unwrap_or_zero(), y: rect.origin.y.unwrap_or_zero() };
+
+        // Size that the children and the text of the node need, not clamped to the size of the node
+        let children_extents = node_id.children(node_hierarchy).map(|child_id| {
+            let child = &node_rects[child_id];
+            LayoutSize {
+                width: child.origin.x.unwrap_or_zero() + child.size.width.unwrap_or_zero() - origin.x,
+                height: child.origin.y.unwrap_or_zero() + child.size.height.unwrap_or_zero() - origin.y,
+            }
+        });
+        let text_extents = resolved_text_layout_options.get(&node_id).map(|(_, inline_text_layout, _)| {
+            let text_bounds = inline_text_layout.get_bounds();
+            LayoutSize {
+                width: text_bounds.origin.x + text_bounds.size.width,
+                height: text_bounds.origin.y + text_bounds.size.height,
+            }
+        });
+        let content_size = children_extents.chain(text_extents).fold(LayoutSize::zero(), |a, b| LayoutSize {
+            width: a.width.max(b.width),
+            height: a.height.max(b.height),
+        });
+
         PositionedRectangle {
             bounds: LayoutRect {
-                origin: LayoutPoint { x: rect.origin.x.unwrap_or_zero(), y: rect.origin.y.unwrap_or_zero() },
+                origin,
                 size: LayoutSize { width: rect.size.width.unwrap_or_zero(), height: rect.size.height.unwrap_or_zero() },
             },
-            content_size: None, // TODO
+            content_size: Some(content_size),
             padding: rect.padding,
             border_widths: rect.border_widths,
             margin: rect.margin,
             resolved_text_layout_options: resolved_text_layout_options.get(&node_id).cloned(),
-            overflow: match node_styles[node_id].overflow {
-                Overflow::Scroll => LayoutOverflow::Scroll,
-                Overflow::Hidden => LayoutOverflow::Hidden,
-                Overflow::Visible => LayoutOverflow::Visible,
-            },
+            overflow_x: translate_overflow(node_styles[node_id].overflow_x),
+            overflow_y: translate_overflow(node_styles[node_id].overflow_y),
         }
     })
 }
 
-
 fn resolve_offsets(input: Offsets<f32>) -> ResolvedOffsets {
     ResolvedOffsets {
         top: input.top,
@@ -274,16 +299,18 @@ fn compute_internal<T: GetTextLayout>(
         match content {
             Text(t) => {
 
-                use azul_core::ui_solver::{DEFAULT_FONT_SIZE_PX, DEFAULT_LETTER_SPACING, DEFAULT_WORD_SPACING};
+                use azul_core::ui_solver::{DEFAULT_FONT_SIZE_PX, DEFAULT_LETTER_SPACING, DEFAULT_WORD_SPACING, TextOverflowBehaviour, WritingMode};
 
                 let text_holes = Vec::new(); // TODO: All children that have float:left / float:right!
                 let rect_style = &node_styles[node_id];
                 let parent_id = node_hierarchy[node_id].parent.unwrap_or(NodeId::ZERO);
                 let parent_style = &node_styles[parent_id];
-                let allows_overflow = parent_style.overflow == Overflow::Visible;
+                let allows_overflow = parent_style.overflow_x == Overflow::Visible;
 
                 let text_layout_options = ResolvedTextLayoutOptions {
                     max_horizontal_width: if allows_overflow { None } else { available_space.width.to_option() },
+                    text_overflow: TextOverflowBehaviour::Clip, // TODO: text-overflow CSS property!
+                    writing_mode: WritingMode::HorizontalTb, // TODO: writing-mode CSS property!
                     leading: None, // TODO!
                     holes: text_holes.clone(),
                     font_size_px: rect_style.font_size_px.to_pixels(DEFAULT_FONT_SIZE_PX as f32),
@@ -291,6 +318,9 @@ fn compute_internal<T: GetTextLayout>(
                     word_spacing: rect_style.word_spacing.map(|ls| ls.to_pixels(DEFAULT_WORD_SPACING)),
                     line_height: rect_style.line_height,
                     tab_width: rect_style.tab_width,
+                    tab_stops: Vec::new(),
+                    show_whitespace: false,
+                    paragraph_spacing: None,
                 };
 
                 let layouted_inline_text = t.get_text_layout(&text_layout_options);
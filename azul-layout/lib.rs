@@ -150,6 +150,17 @@ impl GetStyle for DisplayRectangle {
             }
         }
 
+        #[inline]
+        fn translate_overflow(input: Option<CssPropertyValue<LayoutOverflow>>) -> Overflow {
+            match input.unwrap_or_default().get_property_or_default() {
+                Some(LayoutOverflow::Scroll) => Overflow::Scroll,
+                Some(LayoutOverflow::Auto) => Overflow::Scroll,
+                Some(LayoutOverflow::Hidden) => Overflow::Hidden,
+                Some(LayoutOverflow::Visible) => Overflow::Visible,
+                None => Overflow::Scroll,
+            }
+        }
+
         Style {
             display: match rect_layout.display {
                 None => Display::Flex,
@@ -184,13 +195,8 @@ impl GetStyle for DisplayRectangle {
                 Some(LayoutWrap::NoWrap) => FlexWrap::NoWrap,
                 None => FlexWrap::Wrap,
             },
-            overflow: match rect_layout.overflow_x.unwrap_or_default().get_property_or_default() {
-                Some(LayoutOverflow::Scroll) => Overflow::Scroll,
-                Some(LayoutOverflow::Auto) => Overflow::Scroll,
-                Some(LayoutOverflow::Hidden) => Overflow::Hidden,
-                Some(LayoutOverflow::Visible) => Overflow::Visible,
-                None => Overflow::Scroll,
-            },
+            overflow_x: translate_overflow(rect_layout.overflow_x),
+            overflow_y: translate_overflow(rect_layout.overflow_y),
             align_items: match rect_layout.align_items.unwrap_or_default().get_property_or_default() {
                 Some(LayoutAlignItems::Stretch) => AlignItems::Stretch,
                 Some(LayoutAlignItems::Center) => AlignItems::Center,
@@ -253,7 +253,8 @@ pub struct Style {
     pub direction: Direction,
     pub flex_direction: FlexDirection,
     pub flex_wrap: FlexWrap,
-    pub overflow: Overflow,
+    pub overflow_x: Overflow,
+    pub overflow_y: Overflow,
     pub align_items: AlignItems,
     pub align_self: AlignSelf,
     pub align_content: AlignContent,
@@ -285,7 +286,8 @@ impl Default for Style {
             direction: Default::default(),
             flex_direction: Default::default(),
             flex_wrap: Default::default(),
-            overflow: Default::default(),
+            overflow_x: Default::default(),
+            overflow_y: Default::default(),
             align_items: Default::default(),
             align_self: Default::default(),
             align_content: Default::default(),
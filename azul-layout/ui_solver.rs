@@ -43,8 +43,8 @@ pub fn do_the_layout<T>(
     // TODO: overflowing rects!
 
     // Get the final word positions
-    let positioned_word_cache = create_word_positions(&word_cache, &scaled_words, &solved_ui.solved_rects);
-    let layouted_glyph_cache = get_glyphs(node_hierarchy, &scaled_words, &positioned_word_cache, &display_rects, &mut solved_ui.solved_rects);
+    let mut positioned_word_cache = create_word_positions(&word_cache, &scaled_words, &solved_ui.solved_rects);
+    let layouted_glyph_cache = get_glyphs(node_hierarchy, &word_cache, &scaled_words, &mut positioned_word_cache, &display_rects, &mut solved_ui.solved_rects);
     let node_depths = node_hierarchy.get_parents_sorted_by_depth();
 
     // TODO: Set the final content sizes on layouted_rects!
@@ -85,7 +85,7 @@ pub fn create_scaled_words(
 ) -> BTreeMap<NodeId, (ScaledWords, FontInstanceKey)> {
 
     use azul_core::{
-        app_resources::{ImmediateFontId, font_size_to_au, get_font_id, get_font_size},
+        app_resources::{ImmediateFontId, get_font_id, get_font_size},
         ui_solver::DEFAULT_FONT_SIZE_PX,
     };
     use azul_text_layout::text_layout::words_to_scaled_words;
@@ -94,7 +94,6 @@ pub fn create_scaled_words(
 
         let style = &display_rects[*node_id].style;
         let font_size = get_font_size(&style);
-        let font_size_au = font_size_to_au(font_size);
         let css_font_id = get_font_id(&style);
         let font_id = match app_resources.get_css_font_id(css_font_id) {
             Some(s) => ImmediateFontId::Resolved(*s),
@@ -102,7 +101,7 @@ pub fn create_scaled_words(
         };
 
         let loaded_font = app_resources.get_loaded_font(pipeline_id, &font_id)?;
-        let font_instance_key = loaded_font.font_instances.get(&font_size_au)?;
+        let font_instance_key = app_resources.get_font_instance_key(pipeline_id, &style)?;
 
         let scaled_words = words_to_scaled_words(
             words,
@@ -112,7 +111,7 @@ pub fn create_scaled_words(
             font_size.0.to_pixels(DEFAULT_FONT_SIZE_PX as f32),
         );
 
-        Some((*node_id, (scaled_words, *font_instance_key)))
+        Some((*node_id, (scaled_words, font_instance_key)))
     }).collect()
 }
 
@@ -157,19 +156,20 @@ fn create_word_positions<'a>(
 
 fn get_glyphs(
     node_hierarchy: &NodeHierarchy,
+    words: &BTreeMap<NodeId, Words>,
     scaled_words: &BTreeMap<NodeId, (ScaledWords, FontInstanceKey)>,
-    positioned_word_cache: &BTreeMap<NodeId, (WordPositions, FontInstanceKey)>,
+    positioned_word_cache: &mut BTreeMap<NodeId, (WordPositions, FontInstanceKey)>,
     display_rects: &NodeDataContainer<DisplayRectangle>,
     positioned_rectangles: &mut NodeDataContainer<PositionedRectangle>,
 ) -> BTreeMap<NodeId, LayoutedGlyphs> {
 
-    use azul_text_layout::text_layout::get_layouted_glyphs;
+    use azul_text_layout::text_layout::{get_layouted_glyphs, justify_inline_text_layout};
 
     scaled_words
     .iter()
     .filter_map(|(node_id, (scaled_words, _))| {
 
-        let (word_positions, _) = positioned_word_cache.get(node_id)?;
+        let (word_positions, _) = positioned_word_cache.get_mut(node_id)?;
         let display_rect = &display_rects[*node_id];
         let (horz_alignment, vert_alignment) = determine_text_alignment(&display_rect.style, &display_rect.layout);
         let parent_bounds = match &node_hierarchy[*node_id].parent {
@@ -178,6 +178,9 @@ fn get_glyphs(
         };
         let bounds = positioned_rectangles[*node_id].bounds;
         let (_, inline_text_layout, _) = positioned_rectangles[*node_id].resolved_text_layout_options.as_mut()?;
+        if horz_alignment == StyleTextAlignmentHorz::Justify {
+            justify_inline_text_layout(inline_text_layout, word_positions, words.get(node_id)?, scaled_words);
+        }
         inline_text_layout.align_children_horizontal(horz_alignment);
         inline_text_layout.align_children_vertical_in_parent_bounds(&parent_bounds, vert_alignment);
 
@@ -224,6 +227,58 @@ fn determine_text_alignment(
     (horz_alignment, vert_alignment)
 }
 
+#[test]
+fn test_display_none_and_visibility_hidden() {
+
+    use azul_css::{Css, CssPropertyValue, LayoutHeight, PixelValue};
+    use azul_core::{
+        dom::Dom,
+        ui_state::UiState,
+        ui_description::UiDescription,
+        display_list::DisplayList,
+    };
+
+    struct Mock;
+
+    fn layout_siblings(middle_sibling: Dom<Mock>) -> LayoutResult {
+
+        let dom = Dom::div()
+            .with_child(Dom::div())
+            .with_child(middle_sibling)
+            .with_child(Dom::div());
+
+        let mut ui_state = UiState::new(dom, None);
+        let ui_description = UiDescription::new(&mut ui_state, &Css::default(), &None, &BTreeMap::new(), false);
+        let mut display_list = DisplayList::new(&ui_description, &ui_state);
+        for rect in display_list.rectangles.internal.iter_mut().skip(1) {
+            rect.layout.height = Some(CssPropertyValue::Exact(LayoutHeight(PixelValue::px(50.0))));
+        }
+
+        do_the_layout(
+            &ui_state.dom.arena.node_layout,
+            &ui_state.dom.arena.node_data,
+            &display_list.rectangles,
+            &AppResources::new(),
+            &PipelineId(0, 0),
+            LayoutRect::new(azul_css::LayoutPoint::zero(), azul_css::LayoutSize::new(400.0, 400.0)),
+        )
+    }
+
+    let last_sibling = NodeId::new(3);
+
+    let visible = layout_siblings(Dom::div());
+    assert_eq!(visible.rects[last_sibling].bounds.origin.y, 100.0);
+
+    // `display_none` removes the node from the layout
+    let display_none = layout_siblings(Dom::div().with_display_none(true));
+    assert_eq!(display_none.rects[last_sibling].bounds.origin.y, 50.0);
+
+    // `visibility_hidden` nodes still take up space
+    let visibility_hidden = layout_siblings(Dom::div().with_visibility_hidden(true));
+    assert_eq!(visibility_hidden.rects[NodeId::new(2)].bounds.size.height, 50.0);
+    assert_eq!(visibility_hidden.rects[last_sibling].bounds.origin.y, 100.0);
+}
+
 #[cfg(test)]
 mod layout_tests {
 
@@ -0,0 +1,64 @@
+//! Measures how much the advance cache of `HbFont::get_advance` saves when the advances
+//! of the same text are looked up repeatedly (i.e. when re-laying out a paragraph), both
+//! for direct lookups and for shaping (HarfBuzz looks the advances up through the cache).
+//!
+//! Run with `cargo bench -p azul-text-layout --bench advance_cache`
+
+extern crate azul_text_layout;
+
+use std::time::{Duration, Instant};
+use azul_text_layout::text_shaping::{HbFont, HbScaledFont, ShapingOptions, shape_with_options};
+
+const FONT: &[u8] = include_bytes!("../../assets/fonts/KoHo-Light.ttf");
+const TEXT: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor \
+                    incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud \
+                    exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat.";
+const ITERATIONS: u32 = 10_000;
+
+/// Runs `f` `ITERATIONS` times (after one warm-up run), returns the average time per run.
+/// `f` returns the sum of the advances, so that the lookups can't be optimized away.
+fn measure<F: FnMut() -> u32>(mut f: F) -> Duration {
+    let mut checksum = f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        checksum = checksum.wrapping_add(f());
+    }
+    let elapsed = start.elapsed();
+    assert!(checksum != 0);
+    elapsed / ITERATIONS
+}
+
+fn main() {
+
+    let mut hb_font = HbFont::from_bytes(FONT, 0);
+    let glyphs = TEXT.chars().map(|c| hb_font.lookup_glyph_index(c as u32) as u16).collect::<Vec<_>>();
+    let sum_advances = |hb_font: &HbFont| glyphs.iter().map(|glyph| hb_font.get_advance(*glyph).unwrap_or(0) as u32).sum::<u32>();
+
+    // Every run starts with an empty cache, so the first lookup of each glyph reads the tables
+    let uncached = measure(|| {
+        hb_font.clear_caches();
+        sum_advances(&hb_font)
+    });
+
+    // Every lookup is a cache hit
+    let cached = measure(|| sum_advances(&hb_font));
+
+    println!("{} advances ({} distinct glyphs), {} iterations:", glyphs.len(), hb_font.cached_advance_count(), ITERATIONS);
+    println!("    empty cache: {:?} per text", uncached);
+    println!("    warm cache:  {:?} per text", cached);
+
+    let shape = |hb_font: &HbFont| {
+        let shaped_word = shape_with_options(TEXT, &HbScaledFont::from_font(hb_font, 16.0), &ShapingOptions::default());
+        shaped_word.glyph_positions.iter().map(|p| p.x_advance as u32).sum::<u32>()
+    };
+
+    let uncached_shaping = measure(|| {
+        hb_font.clear_caches();
+        shape(&hb_font)
+    });
+    let cached_shaping = measure(|| shape(&hb_font));
+
+    println!("shaping, {} iterations:", ITERATIONS);
+    println!("    empty cache: {:?} per text", uncached_shaping);
+    println!("    warm cache:  {:?} per text", cached_shaping);
+}
@@ -1,6 +1,7 @@
 extern crate azul_css;
 extern crate azul_core;
 extern crate unicode_normalization;
+extern crate unicode_segmentation;
 extern crate harfbuzz_sys;
 extern crate freetype; // necessary to get baseline of font
 
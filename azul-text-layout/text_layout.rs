@@ -1,14 +1,16 @@
-use azul_css::{LayoutSize, LayoutRect, LayoutPoint};
+use std::ops::Range;
+use azul_css::{LayoutSize, LayoutRect, LayoutPoint, ColorU};
+use crate::text_shaping::{ColorGlyphs, HbFont};
 pub use azul_core::{
     app_resources::{
         Words, Word, WordType, GlyphInfo, GlyphPosition,
         ScaledWords, ScaledWord, WordIndex, GlyphIndex, LineLength, IndexOfLineBreak,
         RemainingSpaceToRight, LineBreaks, WordPositions, LayoutedGlyphs,
-        ClusterIterator, ClusterInfo, FontMetrics,
+        ClusterIterator, ClusterInfo, FontMetrics, WhitespaceMarker, WhitespaceKind, TextDirection,
     },
     display_list::GlyphInstance,
     ui_solver::{
-        ResolvedTextLayoutOptions, TextLayoutOptions, InlineTextLayout,
+        ResolvedTextLayoutOptions, TextLayoutOptions, InlineTextLayout, TabStop, TabAlign, WritingMode, TextOverflowBehaviour,
         DEFAULT_LINE_HEIGHT, DEFAULT_WORD_SPACING, DEFAULT_LETTER_SPACING, DEFAULT_TAB_WIDTH,
     },
 };
@@ -116,11 +118,6 @@ pub fn split_text_into_words(text: &str) -> Words {
         });
     }
 
-    // If the last item is a `Return`, remove it
-    if let Some(Word { word_type: WordType::Return, .. }) = words.last() {
-        words.pop();
-    }
-
     Words {
         items: words,
         internal_str: normalized_string,
@@ -129,7 +126,8 @@ pub fn split_text_into_words(text: &str) -> Words {
 }
 
 /// Takes a text broken into semantic items and a font instance and
-/// scales the font accordingly.
+/// scales the font accordingly. The base direction of the text is the direction
+/// of its first strongly directional character, see `get_paragraph_direction`.
 pub fn words_to_scaled_words(
     words: &Words,
     font_bytes: &[u8],
@@ -137,9 +135,29 @@ pub fn words_to_scaled_words(
     font_metrics: FontMetrics,
     font_size_px: f32,
 ) -> ScaledWords {
+    let base_direction = crate::text_shaping::get_paragraph_direction(&words.internal_chars);
+    words_to_scaled_words_with_direction(words, font_bytes, font_index, font_metrics, font_size_px, base_direction)
+}
+
+/// Same as `words_to_scaled_words`, but with an explicit base direction of the text.
+///
+/// The text is split into runs of the same bidi level (see `get_bidi_levels`) and each run is
+/// shaped in its own direction, so the glyphs of each word are in visual order and the words
+/// of right-to-left runs are marked as `TextDirection::RightToLeft`. The words themselves stay
+/// in logical order, `position_words` places them in visual order.
+pub fn words_to_scaled_words_with_direction(
+    words: &Words,
+    font_bytes: &[u8],
+    font_index: u32,
+    font_metrics: FontMetrics,
+    font_size_px: f32,
+    base_direction: TextDirection,
+) -> ScaledWords {
 
     use std::mem;
     use std::char;
+    use std::cmp::Ordering;
+    use azul_core::ui_solver::get_visual_order;
     use crate::text_shaping::{self, HB_SCALE_FACTOR, HbBuffer, HbFont, HbScaledFont};
 
     let hb_font = HbFont::from_bytes(font_bytes, font_index);
@@ -151,57 +169,121 @@ pub fn words_to_scaled_words(
     let space_advance_px = hb_shaped_space.glyph_positions[0].x_advance as f32 / HB_SCALE_FACTOR;
     let space_codepoint = hb_shaped_space.glyph_infos[0].codepoint;
 
+    // Shape the ellipsis for `text-overflow: ellipsis`, fall back to three periods
+    // if the font doesn't have a glyph for U+2026
+    let mut ellipsis = text_shaping::shape_bidi(&['\u{2026}'], TextDirection::LeftToRight, &hb_scaled_font);
+    if ellipsis.glyph_infos.iter().any(|glyph_info| glyph_info.codepoint == 0) {
+        ellipsis = text_shaping::shape_bidi(&['.', '.', '.'], TextDirection::LeftToRight, &hb_scaled_font);
+    }
+
     let internal_str = words.internal_str.replace(char::is_whitespace, " ");
+    let internal_chars = internal_str.chars().collect::<Vec<_>>();
+
+    // The clusters of the shaped glyphs are byte offsets into the `internal_str`
+    let char_byte_offsets = internal_str.char_indices().map(|(byte_idx, _)| byte_idx).collect::<Vec<_>>();
+    let get_byte_offset = |char_idx: usize| char_byte_offsets.get(char_idx).copied().unwrap_or(internal_str.len());
+
+    let text_words = words.items.iter().filter(|w| w.word_type == WordType::Word).collect::<Vec<_>>();
+    let word_byte_ranges = text_words.iter().map(|w| get_byte_offset(w.start)..get_byte_offset(w.end)).collect::<Vec<_>>();
+
+    // Runs of characters with the same bidi level (byte range + level)
+    let char_levels = text_shaping::get_bidi_levels(&internal_chars, base_direction);
+    let mut runs = Vec::<(Range<usize>, u8)>::new();
+    for (char_idx, level) in char_levels.iter().enumerate() {
+        let char_end = get_byte_offset(char_idx + 1);
+        match runs.last_mut() {
+            Some((range, run_level)) if run_level == level => range.end = char_end,
+            _ => runs.push((get_byte_offset(char_idx)..char_end, *level)),
+        }
+    }
 
-    let hb_buffer_entire_paragraph = HbBuffer::from_str(&internal_str);
-    let hb_shaped_entire_paragraph = text_shaping::shape_word_hb(&hb_buffer_entire_paragraph, &hb_scaled_font);
+    // Glyphs of each word, split into the runs that the word is part of (run index + glyphs)
+    let mut word_segments = vec![Vec::<(usize, Vec<GlyphInfo>, Vec<GlyphPosition>)>::new(); text_words.len()];
 
-    let mut shaped_word_positions = Vec::<Vec<GlyphPosition>>::new();
-    let mut shaped_word_infos = Vec::<Vec<GlyphInfo>>::new();
-    let mut current_word_positions = Vec::new();
-    let mut current_word_infos = Vec::new();
+    for (run_idx, (byte_range, level)) in runs.iter().enumerate() {
 
-    for i in 0..hb_shaped_entire_paragraph.glyph_positions.len() {
-        let glyph_info = hb_shaped_entire_paragraph.glyph_infos[i];
-        let glyph_position = hb_shaped_entire_paragraph.glyph_positions[i];
+        let direction = if level % 2 == 0 { TextDirection::LeftToRight } else { TextDirection::RightToLeft };
+        let hb_buffer = HbBuffer::from_str_range(&internal_str, byte_range.clone(), direction);
+        let hb_shaped_run = text_shaping::shape_word_hb(&hb_buffer, &hb_scaled_font);
+
+        for (glyph_info, glyph_position) in hb_shaped_run.glyph_infos.iter().zip(hb_shaped_run.glyph_positions.iter()) {
+
+            let cluster = glyph_info.cluster as usize;
+            let word_idx = word_byte_ranges.binary_search_by(|range| {
+                if range.end <= cluster { Ordering::Less } else if range.start > cluster { Ordering::Greater } else { Ordering::Equal }
+            });
+
+            // Glyphs of the whitespace between the words don't belong to any word
+            let segments = match word_idx {
+                Ok(word_idx) => &mut word_segments[word_idx],
+                Err(_) => continue,
+            };
+
+            if segments.last().map(|(segment_run, _, _)| *segment_run) != Some(run_idx) {
+                segments.push((run_idx, Vec::new(), Vec::new()));
+            }
 
-        let is_space = glyph_info.codepoint == space_codepoint;
-        if is_space {
-            shaped_word_positions.push(current_word_positions.clone());
-            shaped_word_infos.push(current_word_infos.clone());
-            current_word_positions.clear();
-            current_word_infos.clear();
-        } else {
             // azul-core::GlyphInfo and hb_position_t have the same size / layout
             // (both are repr(C)), so it's safe to just transmute them here
-            current_word_positions.push(unsafe { mem::transmute(glyph_position) });
-            current_word_infos.push(unsafe { mem::transmute(glyph_info) });
+            if let Some((_, segment_infos, segment_positions)) = segments.last_mut() {
+                segment_infos.push(unsafe { mem::transmute(*glyph_info) });
+                segment_positions.push(unsafe { mem::transmute(*glyph_position) });
+            }
+        }
+    }
+
+    // Words that contain multiple runs (i.e. "abc\u{5D0}\u{5D1}") are reordered visually
+    let mut shaped_word_infos = Vec::<Vec<GlyphInfo>>::with_capacity(text_words.len());
+    let mut shaped_word_positions = Vec::<Vec<GlyphPosition>>::with_capacity(text_words.len());
+
+    for segments in &word_segments {
+        let segment_levels = segments.iter().map(|(run_idx, _, _)| runs[*run_idx].1).collect::<Vec<_>>();
+        let mut word_infos = Vec::new();
+        let mut word_positions = Vec::new();
+        for segment_idx in get_visual_order(&segment_levels) {
+            word_infos.extend_from_slice(&segments[segment_idx].1);
+            word_positions.extend_from_slice(&segments[segment_idx].2);
         }
+        shaped_word_infos.push(word_infos);
+        shaped_word_positions.push(word_positions);
     }
 
-    if !current_word_positions.is_empty() {
-        shaped_word_positions.push(current_word_positions);
-        shaped_word_infos.push(current_word_infos);
+    for (word_positions, word_infos) in shaped_word_positions.iter_mut().zip(shaped_word_infos.iter()) {
+        hb_font.apply_zero_mark_advance(word_infos, word_positions);
+    }
+
+    // Apply the size-dependent tracking of the font (`trak` table) between the clusters of each word
+    let tracking = (hb_font.tracking_adjustment(font_size_px) * HB_SCALE_FACTOR) as i32;
+    if tracking != 0 {
+        for (word_positions, word_infos) in shaped_word_positions.iter_mut().zip(shaped_word_infos.iter()) {
+            for (glyph_idx, glyph_position) in word_positions.iter_mut().enumerate() {
+                let is_end_of_cluster = word_infos.get(glyph_idx + 1).map(|next| next.cluster != word_infos[glyph_idx].cluster);
+                if is_end_of_cluster == Some(true) {
+                    glyph_position.x_advance += tracking;
+                }
+            }
+        }
     }
 
     let mut longest_word_width = 0.0_f32;
 
-    let scaled_words = words.items.iter()
-        .filter(|w| w.word_type == WordType::Word)
-        .enumerate()
-        .filter_map(|(word_idx, _)| {
+    let scaled_words = text_words.iter()
+        .zip(shaped_word_infos.into_iter().zip(shaped_word_positions.into_iter()))
+        .map(|(word, (hb_glyph_infos, hb_glyph_positions))| {
 
-            let hb_glyph_positions = shaped_word_positions.get(word_idx)?.clone();
-            let hb_glyph_infos = shaped_word_infos.get(word_idx)?.clone();
             let hb_word_width = text_shaping::get_word_visual_width_hb(&hb_glyph_positions);
-
             longest_word_width = longest_word_width.max(hb_word_width.abs());
 
-            Some(ScaledWord {
+            // The direction of a word is the direction of its first character
+            let word_level = char_levels.get(word.start).copied().unwrap_or(0);
+
+            ScaledWord {
                 glyph_infos: hb_glyph_infos,
                 glyph_positions: hb_glyph_positions,
                 word_width: hb_word_width,
-            })
+                direction: if word_level % 2 == 0 { TextDirection::LeftToRight } else { TextDirection::RightToLeft },
+                rotated_glyphs: Vec::new(),
+            }
         }).collect();
 
     ScaledWords {
@@ -212,11 +294,315 @@ pub fn words_to_scaled_words(
         longest_word_width: longest_word_width,
         space_advance_px,
         space_codepoint,
+        ellipsis,
+        direction: base_direction,
+    }
+}
+
+/// Returns the horizontal distance from the start of the word to the glyph at `glyph_idx`, i.e. for
+/// drawing a caret or selection without having to re-layout the word. Since the glyph positions
+/// are already scaled to the font size (and include the kerning), no font metrics are necessary.
+///
+/// `0` returns `0.0`, `scaled_word.glyph_positions.len()` (or higher) returns the width of the entire word.
+pub fn advance_to_glyph(scaled_word: &ScaledWord, glyph_idx: usize) -> f32 {
+    use crate::text_shaping;
+    let glyph_idx = glyph_idx.min(scaled_word.glyph_positions.len());
+    text_shaping::get_word_visual_width_hb(&scaled_word.glyph_positions[..glyph_idx])
+}
+
+/// Returns the width of the glyphs that draw the characters in `char_range`, i.e. for measuring
+/// a selection that doesn't start at the beginning of the word. The range is in the same unit as
+/// the clusters of the glyphs (see `ScaledWord::glyph_for_char`). Like `advance_to_glyph`, the glyph
+/// positions are already scaled to the font size and include the kerning.
+///
+/// A glyph that covers multiple characters (i.e. a ligature) is counted in full if its first
+/// character is inside the range, and not at all otherwise. So a ligature that straddles the end
+/// of the range is included, one that straddles the start of the range is excluded.
+pub fn range_width(scaled_word: &ScaledWord, char_range: Range<usize>) -> f32 {
+    use crate::text_shaping::HB_SCALE_FACTOR;
+    scaled_word.glyph_infos.iter()
+        .zip(scaled_word.glyph_positions.iter())
+        .filter(|(glyph_info, _)| char_range.contains(&(glyph_info.cluster as usize)))
+        .map(|(_, glyph_position)| glyph_position.x_advance as f32 / HB_SCALE_FACTOR)
+        .sum()
+}
+
+/// Returns the byte range of the word (according to the word boundaries of Unicode Standard Annex #29)
+/// that contains the `byte_idx`, i.e. for selecting a word on double-click.
+///
+/// If the `byte_idx` is on a whitespace character, the range of the entire run
+/// of whitespace around it is returned. Returns `None` if `byte_idx` is out of range.
+pub fn word_bounds_at(text: &str, byte_idx: usize) -> Option<Range<usize>> {
+
+    use unicode_segmentation::UnicodeSegmentation;
+
+    let segments = text.split_word_bound_indices()
+        .map(|(start, segment)| (start..(start + segment.len()), segment.chars().all(char::is_whitespace)))
+        .collect::<Vec<_>>();
+
+    let segment_idx = segments.iter().position(|(range, _)| range.contains(&byte_idx))?;
+    let (range, is_whitespace) = segments[segment_idx].clone();
+
+    if !is_whitespace {
+        return Some(range);
+    }
+
+    // Depending on the Unicode version, consecutive spaces can be split into multiple segments
+    let start = segments[..segment_idx].iter().rev()
+        .take_while(|(_, is_whitespace)| *is_whitespace)
+        .last()
+        .map(|(range, _)| range.start)
+        .unwrap_or(range.start);
+
+    let end = segments[(segment_idx + 1)..].iter()
+        .take_while(|(_, is_whitespace)| *is_whitespace)
+        .last()
+        .map(|(range, _)| range.end)
+        .unwrap_or(range.end);
+
+    Some(start..end)
+}
+
+/// Splits a shaped word into the glyphs before and after the character at `char_idx`
+/// (relative to the start of the `word`), without reshaping either half - for example, to
+/// insert text in the middle of a word and only reshape the inserted text.
+///
+/// If the character is part of a ligature (a cluster of multiple characters), the word is split
+/// at the start of the ligature instead, so the ligature ends up in the second half.
+/// The glyph advances are not modified, so contextual shaping across the split point
+/// (kerning, cursive joining) is kept as it was in the original word.
+///
+/// Needs the cluster values of the glyphs to be byte offsets into the `words.internal_str`
+/// (as returned by `words_to_scaled_words`).
+pub fn split_scaled_word_at_char(words: &Words, word: &Word, scaled_word: &ScaledWord, char_idx: usize) -> (ScaledWord, ScaledWord) {
+
+    use crate::text_shaping;
+
+    let get_byte_offset = |char_idx: usize| words.internal_str.char_indices()
+        .nth(char_idx)
+        .map(|(byte_idx, _)| byte_idx as u32)
+        .unwrap_or(words.internal_str.len() as u32);
+
+    let split_byte = get_byte_offset(word.start + char_idx);
+
+    // Start of the cluster that the split character belongs to
+    let split_cluster = if split_byte >= get_byte_offset(word.end) {
+        split_byte
+    } else {
+        scaled_word.glyph_infos.iter()
+            .map(|glyph_info| glyph_info.cluster)
+            .filter(|cluster| *cluster <= split_byte)
+            .max()
+            .unwrap_or(split_byte)
+    };
+
+    let mut before = (Vec::new(), Vec::new(), Vec::new());
+    let mut after = (Vec::new(), Vec::new(), Vec::new());
+
+    for (glyph_idx, (glyph_info, glyph_position)) in scaled_word.glyph_infos.iter().zip(scaled_word.glyph_positions.iter()).enumerate() {
+        let (infos, positions, rotated_glyphs) = if glyph_info.cluster < split_cluster { &mut before } else { &mut after };
+        infos.push(*glyph_info);
+        positions.push(*glyph_position);
+        rotated_glyphs.extend(scaled_word.rotated_glyphs.get(glyph_idx));
+    }
+
+    let to_scaled_word = |(glyph_infos, glyph_positions, rotated_glyphs): (Vec<GlyphInfo>, Vec<GlyphPosition>, Vec<bool>)| ScaledWord {
+        word_width: text_shaping::get_word_visual_width_hb(&glyph_positions),
+        glyph_infos,
+        glyph_positions,
+        direction: scaled_word.direction,
+        rotated_glyphs,
+    };
+
+    (to_scaled_word(before), to_scaled_word(after))
+}
+
+/// Forces every cluster of the `scaled_words` into a fixed cell width, regardless of the
+/// natural advance of the font (as terminal emulators do). Wide / fullwidth characters take
+/// up two cells, combining marks take up no cells. Glyphs are centered within their cells.
+///
+/// Needs to be called directly after `words_to_scaled_words`, since the cluster values of the
+/// glyphs have to be byte offsets into the `words.internal_str`.
+pub fn apply_cell_width(words: &Words, scaled_words: &mut ScaledWords, cell_width_px: f32) {
+
+    use crate::text_shaping::{self, HB_SCALE_FACTOR};
+
+    let cell_width = (cell_width_px * HB_SCALE_FACTOR) as i32;
+    let mut longest_word_width = 0.0_f32;
+
+    for scaled_word in scaled_words.items.iter_mut() {
+
+        let mut cluster_start = 0;
+
+        while cluster_start < scaled_word.glyph_infos.len() {
+
+            let cluster = scaled_word.glyph_infos[cluster_start].cluster;
+            let cluster_end = scaled_word.glyph_infos[cluster_start..].iter()
+                .position(|glyph_info| glyph_info.cluster != cluster)
+                .map(|len| cluster_start + len)
+                .unwrap_or(scaled_word.glyph_infos.len());
+
+            let cells = words.internal_str.get(cluster as usize..)
+                .and_then(|s| s.chars().next())
+                .map(get_cell_count)
+                .unwrap_or(1);
+
+            let glyph_positions = &mut scaled_word.glyph_positions[cluster_start..cluster_end];
+            let natural_advance = glyph_positions.iter().map(|pos| pos.x_advance).sum::<i32>();
+            let cell_advance = cell_width * cells as i32;
+
+            // Shift the whole cluster into the center of the cell, the last glyph
+            // of the cluster carries the difference to the cell advance
+            for glyph_position in glyph_positions.iter_mut() {
+                glyph_position.x_offset += (cell_advance - natural_advance) / 2;
+            }
+            if let Some(last) = glyph_positions.last_mut() {
+                last.x_advance += cell_advance - natural_advance;
+            }
+
+            cluster_start = cluster_end;
+        }
+
+        scaled_word.word_width = text_shaping::get_word_visual_width_hb(&scaled_word.glyph_positions);
+        longest_word_width = longest_word_width.max(scaled_word.word_width);
+    }
+
+    scaled_words.space_advance_px = cell_width_px;
+    scaled_words.longest_word_width = longest_word_width;
+}
+
+/// Same as `words_to_scaled_words`, but shapes the words for a vertical `WritingMode`
+/// (see `text_shaping::shape_vertical`): upright characters are shaped top to bottom with the
+/// vertical alternates and vertical advances of the font, the `x_advance` of every glyph is its
+/// advance along the column. The words are not reordered for bidi text.
+///
+/// The cluster values of the glyphs are byte offsets into the `words.internal_str`,
+/// the same as for horizontal text.
+pub fn words_to_scaled_words_vertical(
+    words: &Words,
+    font_bytes: &[u8],
+    font_index: u32,
+    font_metrics: FontMetrics,
+    font_size_px: f32,
+) -> ScaledWords {
+
+    use crate::text_shaping::{self, HbScaledFont};
+
+    let mut scaled_words = words_to_scaled_words(words, font_bytes, font_index, font_metrics, font_size_px);
+
+    let hb_font = HbFont::from_bytes(font_bytes, font_index);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, font_size_px);
+
+    let char_byte_offsets = words.internal_str.char_indices().map(|(byte_idx, _)| byte_idx).collect::<Vec<_>>();
+    let get_byte_offset = |char_idx: usize| char_byte_offsets.get(char_idx).copied().unwrap_or(words.internal_str.len());
+
+    let text_words = words.items.iter().filter(|w| w.word_type == WordType::Word);
+    let mut longest_word_width = 0.0_f32;
+
+    for (word, scaled_word) in text_words.zip(scaled_words.items.iter_mut()) {
+        *scaled_word = text_shaping::shape_vertical(&words.internal_chars[word.start..word.end], &hb_scaled_font);
+        for glyph_info in scaled_word.glyph_infos.iter_mut() {
+            glyph_info.cluster = get_byte_offset(word.start + glyph_info.cluster as usize) as u32;
+        }
+        longest_word_width = longest_word_width.max(scaled_word.word_width);
+    }
+
+    scaled_words.space_advance_px = text_shaping::shape_vertical(&[' '], &hb_scaled_font).word_width;
+    scaled_words.longest_word_width = longest_word_width;
+    scaled_words
+}
+
+/// Returns how many cells a character takes up in a monospace layout
+/// (`Ambiguous` characters are treated as narrow)
+fn get_cell_count(c: char) -> usize {
+    use crate::text_shaping::east_asian_width;
+    match c as u32 {
+        // Combining marks
+        0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0xFE20..=0xFE2F => 0,
+        _ => if east_asian_width(c).is_wide(false) { 2 } else { 1 },
+    }
+}
+
+/// Moves the (logically ordered) words of each line to their visual position according to their bidi
+/// embedding levels (rule L2 of the Unicode bidi algorithm), so that the words of right-to-left runs
+/// are placed from right to left. The line keeps its start position and the gap between two logically
+/// adjacent words, gaps between words that are not logically adjacent use the average gap of the line.
+fn reorder_words_visually(word_positions: &mut [LayoutPoint], line_breaks: &[(usize, f32)], word_widths: &[f32], word_levels: &[u8]) {
+
+    use azul_core::ui_solver::get_visual_order;
+
+    let mut line_start = 0;
+
+    for (line_end, _) in line_breaks {
+
+        let word_range = line_start..(*line_end).min(word_positions.len()).min(word_widths.len()).max(line_start);
+        line_start = *line_end;
+
+        let line_levels = word_range.clone().map(|word_idx| word_levels.get(word_idx).copied().unwrap_or(0)).collect::<Vec<_>>();
+        if line_levels.len() < 2 || line_levels.iter().all(|level| level % 2 == 0) {
+            continue;
+        }
+
+        let widths = &word_widths[word_range.clone()];
+        let positions = &mut word_positions[word_range];
+
+        // natural_gaps[i] = gap between the logical words i and i + 1
+        let natural_gaps = (0..(positions.len() - 1)).map(|i| (positions[i + 1].x - positions[i].x - widths[i]).max(0.0)).collect::<Vec<_>>();
+        let average_gap = natural_gaps.iter().sum::<f32>() / natural_gaps.len() as f32;
+
+        let visual_order = get_visual_order(&line_levels);
+        let mut caret_x = positions[0].x;
+        for (visual_idx, logical_idx) in visual_order.iter().enumerate() {
+            positions[*logical_idx].x = caret_x;
+            let gap = visual_order.get(visual_idx + 1).map(|next_idx| {
+                let (left, right) = ((*logical_idx).min(*next_idx), (*logical_idx).max(*next_idx));
+                if right - left == 1 { natural_gaps[left] } else { average_gap }
+            }).unwrap_or(0.0);
+            caret_x += widths[*logical_idx] + gap;
+        }
+    }
+}
+
+/// Vertical geometry of the line boxes, shared by `position_words` and
+/// `word_positions_to_inline_text_layout`, so that the word positions, the
+/// line boxes and the content size of the text agree with each other
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct LineBoxMetrics {
+    /// Natural height of the text plus the half-leading above and below the text
+    pub line_box_height: f32,
+    /// Half of the extra line height, distributed above and below the text
+    pub half_leading: f32,
+    /// Distance from the top of the text to the baseline
+    pub ascender: f32,
+}
+
+impl LineBoxMetrics {
+
+    pub fn new(scaled_words: &ScaledWords, text_layout_options: &ResolvedTextLayoutOptions) -> Self {
+        let font_size_px = text_layout_options.font_size_px;
+        let line_height_px = scaled_words.space_advance_px * text_layout_options.line_height.unwrap_or(DEFAULT_LINE_HEIGHT);
+        let half_leading = line_height_px.max(0.0) / 2.0;
+        Self {
+            line_box_height: scaled_words.font_metrics.get_height(font_size_px) + (half_leading * 2.0),
+            half_leading,
+            ascender: scaled_words.font_metrics.get_ascender(scaled_words.font_size_px),
+        }
+    }
+
+    /// Top of the line box of the given (0-indexed) line, without any paragraph spacing
+    pub fn get_line_top(&self, line_number: usize) -> f32 {
+        self.line_box_height * line_number as f32
+    }
+
+    /// Baseline of the given (0-indexed) line, without any paragraph spacing
+    pub fn get_baseline_y(&self, line_number: usize) -> f32 {
+        self.get_line_top(line_number) + self.half_leading + self.ascender
     }
 }
 
 /// Positions the words on the screen (does not layout any glyph positions!), necessary for estimating
-/// the intrinsic width + height of the text content.
+/// the intrinsic width + height of the text content. The y position of each word is the baseline
+/// of its line box (see `LineBoxMetrics`).
 pub fn position_words(
     words: &Words,
     scaled_words: &ScaledWords,
@@ -230,10 +616,16 @@ pub fn position_words(
     let space_advance = scaled_words.space_advance_px;
     let word_spacing_px = space_advance * text_layout_options.word_spacing.unwrap_or(DEFAULT_WORD_SPACING);
     let line_height_px = space_advance * text_layout_options.line_height.unwrap_or(DEFAULT_LINE_HEIGHT);
-    let tab_width_px = space_advance * text_layout_options.tab_width.unwrap_or(DEFAULT_TAB_WIDTH);
+    let line_box_metrics = LineBoxMetrics::new(scaled_words, text_layout_options);
 
     let mut line_breaks = Vec::new();
     let mut word_positions = Vec::new();
+    let mut whitespace_markers = Vec::new();
+    let mut paragraph_breaks = Vec::new();
+
+    // Difference between the paragraph spacing and the line advance of all empty
+    // lines above the current line (only if `paragraph_spacing` is set)
+    let mut paragraph_offset_y = 0.0;
 
     let mut line_number = 0;
     let mut line_caret_x = 0.0;
@@ -271,6 +663,10 @@ pub fn position_words(
     // NOTE: word_idx increases only on words, not on other symbols!
     let mut word_idx = 0;
 
+    // Set after a soft wrap, so that whitespace at the start of
+    // the wrapped line is collapsed (hard breaks keep it)
+    let mut skip_leading_whitespace = false;
+
     macro_rules! handle_word {() => ({
 
         let scaled_word = match scaled_words.items.get(word_idx) {
@@ -278,13 +674,8 @@ pub fn position_words(
             None => continue,
         };
 
-        let reserved_letter_spacing_px = match text_layout_options.letter_spacing {
-            None => 0.0,
-            Some(spacing_multiplier) => spacing_multiplier * scaled_word.number_of_clusters().saturating_sub(1) as f32,
-        };
-
         // Calculate where the caret would be for the next word
-        let word_advance_x = scaled_word.word_width + reserved_letter_spacing_px;
+        let word_advance_x = scaled_word.get_adjusted_width(text_layout_options.letter_spacing);
 
         let mut new_caret_x = line_caret_x + word_advance_x;
 
@@ -306,7 +697,7 @@ pub fn position_words(
         }
 
         if !is_line_break {
-            let line_caret_y = get_line_y_position(line_number, font_size_px, line_height_px);
+            let line_caret_y = line_box_metrics.get_baseline_y(line_number) + paragraph_offset_y;
             word_positions.push(LayoutPoint::new(line_caret_x, line_caret_y));
         }
 
@@ -321,7 +712,7 @@ pub fn position_words(
 
         // If there was a line break, the position needs to be determined after the line break happened
         if is_line_break {
-            let line_caret_y = get_line_y_position(line_number, font_size_px, line_height_px);
+            let line_caret_y = line_box_metrics.get_baseline_y(line_number) + paragraph_offset_y;
             word_positions.push(LayoutPoint::new(line_caret_x, line_caret_y));
             // important! - if the word is pushed onto the next line, the caret has to be
             // advanced by that words width!
@@ -334,46 +725,101 @@ pub fn position_words(
         current_word_idx = word_idx;
     })}
 
+    macro_rules! push_whitespace_marker {($kind:expr, $width:expr) => ({
+        if text_layout_options.show_whitespace {
+            let line_caret_y = line_box_metrics.get_baseline_y(line_number) + paragraph_offset_y;
+            whitespace_markers.push(WhitespaceMarker {
+                position: LayoutPoint::new(line_caret_x, line_caret_y),
+                width: $width,
+                kind: $kind,
+            });
+        }
+    })}
+
+    macro_rules! handle_whitespace {($advance:expr, $kind:expr) => ({
+        if !skip_leading_whitespace {
+            let previous_line_number = line_number;
+            let mut new_caret_x = $advance;
+            push_whitespace_marker!($kind, new_caret_x - line_caret_x);
+            advance_caret!(new_caret_x);
+            line_caret_x = new_caret_x;
+            skip_leading_whitespace = line_number != previous_line_number;
+        }
+    })}
+
     // The last word is a bit special: Any text must have at least one line break!
-    for word in words.items.iter().take(words.items.len().saturating_sub(1)) {
+    for (item_idx, word) in words.items.iter().enumerate().take(words.items.len().saturating_sub(1)) {
         match word.word_type {
             Word => {
                 handle_word!();
+                skip_leading_whitespace = false;
             },
             Return => {
+                push_whitespace_marker!(WhitespaceKind::LineEnd, 0.0);
                 line_breaks.push((current_word_idx, line_caret_x));
+                // A return directly after a return ends an empty line, which separates two paragraphs
+                let is_empty_line = item_idx > 0 && words.items[item_idx - 1].word_type == Return;
+                if let (true, Some(paragraph_spacing)) = (is_empty_line, text_layout_options.paragraph_spacing) {
+                    paragraph_breaks.push(line_number);
+                    paragraph_offset_y += paragraph_spacing - line_box_metrics.line_box_height;
+                }
                 line_number += 1;
                 let mut new_caret_x = 0.0;
                 advance_caret!(new_caret_x);
                 line_caret_x = new_caret_x;
+                skip_leading_whitespace = false;
             },
             Space => {
-                let mut new_caret_x = line_caret_x + word_spacing_px;
-                advance_caret!(new_caret_x);
-                line_caret_x = new_caret_x;
+                handle_whitespace!(line_caret_x + word_spacing_px, WhitespaceKind::Space);
             },
             Tab => {
-                let mut new_caret_x = line_caret_x + word_spacing_px + tab_width_px;
-                advance_caret!(new_caret_x);
-                line_caret_x = new_caret_x;
+                handle_whitespace!(get_tab_caret_x(
+                    words,
+                    scaled_words,
+                    text_layout_options,
+                    item_idx,
+                    word_idx,
+                    line_caret_x,
+                ), WhitespaceKind::Tab);
             },
         }
     }
 
-    // Handle the last word, but ignore any last Return, Space or Tab characters
+    // Handle the last word, but ignore any last Space or Tab characters
     for word in &words.items[words.items.len().saturating_sub(1)..] {
-        if word.word_type == Word {
-            handle_word!();
+        match word.word_type {
+            Word => {
+                handle_word!();
+            },
+            Return => {
+                // A trailing return still ends the current line, the
+                // (empty) last line is pushed below
+                push_whitespace_marker!(WhitespaceKind::LineEnd, 0.0);
+                line_breaks.push((current_word_idx, line_caret_x));
+                line_number += 1;
+                let mut new_caret_x = 0.0;
+                advance_caret!(new_caret_x);
+                line_caret_x = new_caret_x;
+            },
+            Space | Tab => { },
         }
         line_breaks.push((current_word_idx, line_caret_x));
     }
 
+    // The words were positioned in logical order, move the words of right-to-left runs to their visual position
+    let word_levels = scaled_words.get_word_levels();
+    if word_levels.iter().any(|level| level % 2 == 1) {
+        let word_widths = scaled_words.items.iter().map(|w| w.get_adjusted_width(text_layout_options.letter_spacing)).collect::<Vec<_>>();
+        reorder_words_visually(&mut word_positions, &line_breaks, &word_widths, &word_levels);
+    }
+
     let trailing = line_caret_x;
     let number_of_lines = line_number + 1;
     let number_of_words = current_word_idx + 1;
 
     let longest_line_width = line_breaks.iter().map(|(_word_idx, line_length)| *line_length).fold(0.0_f32, f32::max);
-    let content_size_y = get_line_y_position(line_number, font_size_px, line_height_px);
+    // the content ends at the bottom of the last line box (including its half-leading)
+    let content_size_y = line_box_metrics.get_line_top(line_number + 1) + paragraph_offset_y;
     let content_size_x = text_layout_options.max_horizontal_width.unwrap_or(longest_line_width);
     let content_size = LayoutSize::new(content_size_x, content_size_y);
 
@@ -385,10 +831,152 @@ pub fn position_words(
         content_size,
         word_positions,
         line_breaks,
+        whitespace_markers,
+        paragraph_breaks,
     }
 }
 
+/// Returns the width of the widest line of the text if it isn't wrapped (the max-content width),
+/// without positioning the individual words. Tabs are expanded the same way as in `position_words`
+/// (to the next tab stop or by `tab_width` spaces), so that the measured width is the same as the
+/// width of the laid out lines. `max_horizontal_width` and `holes` are ignored.
+pub fn measure_text_width(
+    words: &Words,
+    scaled_words: &ScaledWords,
+    text_layout_options: &ResolvedTextLayoutOptions,
+) -> f32 {
+
+    use self::WordType::*;
+
+    let word_spacing_px = scaled_words.space_advance_px * text_layout_options.word_spacing.unwrap_or(DEFAULT_WORD_SPACING);
+
+    let mut longest_line_width = 0.0_f32;
+    let mut line_caret_x = text_layout_options.leading.unwrap_or(0.0);
+    let mut word_idx = 0;
+
+    for (item_idx, word) in words.items.iter().enumerate() {
+        // Trailing whitespace at the end of the text is ignored, as in `position_words`
+        let is_last_item = item_idx + 1 == words.items.len();
+        match word.word_type {
+            Word => {
+                if let Some(scaled_word) = scaled_words.items.get(word_idx) {
+                    line_caret_x += scaled_word.get_adjusted_width(text_layout_options.letter_spacing);
+                }
+                word_idx += 1;
+            },
+            Return => {
+                longest_line_width = longest_line_width.max(line_caret_x);
+                line_caret_x = 0.0;
+            },
+            Space if !is_last_item => {
+                line_caret_x += word_spacing_px;
+            },
+            Tab if !is_last_item => {
+                line_caret_x = get_tab_caret_x(words, scaled_words, text_layout_options, item_idx, word_idx, line_caret_x);
+            },
+            Space | Tab => { },
+        }
+    }
+
+    longest_line_width.max(line_caret_x)
+}
+
+/// Returns where the caret has to jump to for the tab at `words.items[tab_item_idx]`: either to
+/// the next tab stop or, if there is no tab stop to the right of the caret, by `tab_width` spaces.
+///
+/// `next_word_idx` is the index (into `scaled_words`) of the first word after the tab.
+fn get_tab_caret_x(
+    words: &Words,
+    scaled_words: &ScaledWords,
+    text_layout_options: &ResolvedTextLayoutOptions,
+    tab_item_idx: usize,
+    next_word_idx: usize,
+    line_caret_x: f32,
+) -> f32 {
+
+    let space_advance = scaled_words.space_advance_px;
+    let word_spacing_px = space_advance * text_layout_options.word_spacing.unwrap_or(DEFAULT_WORD_SPACING);
+    let tab_width_px = space_advance * text_layout_options.tab_width.unwrap_or(DEFAULT_TAB_WIDTH);
+
+    get_tab_stop_caret_x(
+        words,
+        scaled_words,
+        text_layout_options,
+        tab_item_idx,
+        next_word_idx,
+        line_caret_x,
+        word_spacing_px,
+    ).unwrap_or(line_caret_x + word_spacing_px + tab_width_px)
+}
+
+/// Returns where the caret has to jump to for the tab at `words.items[tab_item_idx]`,
+/// so that the text up to the next tab or return is aligned to the next tab stop.
+/// Returns `None` if there is no tab stop to the right of the caret.
+///
+/// `next_word_idx` is the index (into `scaled_words`) of the first word after the tab.
+fn get_tab_stop_caret_x(
+    words: &Words,
+    scaled_words: &ScaledWords,
+    text_layout_options: &ResolvedTextLayoutOptions,
+    tab_item_idx: usize,
+    next_word_idx: usize,
+    line_caret_x: f32,
+    word_spacing_px: f32,
+) -> Option<f32> {
+
+    use crate::text_shaping::HB_SCALE_FACTOR;
+
+    let tab_stop = text_layout_options.tab_stops.iter().find(|stop| stop.position > line_caret_x)?;
+    let letter_spacing_px = text_layout_options.letter_spacing.unwrap_or(0.0);
+
+    // Width of the segment up to the next tab / return + width up to the first '.'
+    let mut segment_width = 0.0;
+    let mut width_before_decimal = None;
+    let mut word_idx = next_word_idx;
+
+    for word in &words.items[(tab_item_idx + 1)..] {
+        match word.word_type {
+            WordType::Word => {
+                let scaled_word = match scaled_words.items.get(word_idx) {
+                    Some(s) => s,
+                    None => break,
+                };
+                word_idx += 1;
+
+                if tab_stop.align == TabAlign::Decimal {
+                    if let Some(decimal_idx) = words.internal_chars[word.start..word.end].iter().position(|c| *c == '.') {
+                        let advance_before_decimal = scaled_word.cluster_iter()
+                            .zip(scaled_word.glyph_positions.iter())
+                            .take_while(|(cluster_info, _)| cluster_info.cluster_idx <= decimal_idx)
+                            .map(|(_, glyph_position)| glyph_position.x_advance as f32 / HB_SCALE_FACTOR)
+                            .sum::<f32>();
+                        width_before_decimal = Some(segment_width + advance_before_decimal + letter_spacing_px * decimal_idx as f32);
+                        break;
+                    }
+                }
+
+                segment_width += scaled_word.get_adjusted_width(text_layout_options.letter_spacing);
+            },
+            WordType::Space => { segment_width += word_spacing_px; },
+            WordType::Tab | WordType::Return => break,
+        }
+    }
+
+    let new_caret_x = match (tab_stop.align, width_before_decimal) {
+        (TabAlign::Left, _) => tab_stop.position,
+        (TabAlign::Center, _) => tab_stop.position - (segment_width / 2.0),
+        (TabAlign::Decimal, Some(width_before_decimal)) => tab_stop.position - width_before_decimal,
+        (TabAlign::Right, _) | (TabAlign::Decimal, None) => tab_stop.position - segment_width,
+    };
+
+    // Text that doesn't fit in front of the tab stop starts at the caret
+    Some(new_caret_x.max(line_caret_x))
+}
+
 /// Returns the (left-aligned!) bounding boxes of the indidividual text lines
+///
+/// The height of each line box is the natural height of the text plus the
+/// half-leading above and below the text, so that the text is centered in the line box.
 pub fn word_positions_to_inline_text_layout(
     word_positions: &WordPositions,
     scaled_words: &ScaledWords
@@ -396,30 +984,103 @@ pub fn word_positions_to_inline_text_layout(
 
     use azul_core::ui_solver::InlineTextLine;
 
-    let font_size_px = word_positions.text_layout_options.font_size_px;
-    let regular_line_height = scaled_words.font_metrics.get_height(font_size_px);
-    let space_advance = scaled_words.space_advance_px;
-    let line_height_px = space_advance * word_positions.text_layout_options.line_height.unwrap_or(DEFAULT_LINE_HEIGHT);
+    let line_box_metrics = LineBoxMetrics::new(scaled_words, &word_positions.text_layout_options);
+    let LineBoxMetrics { line_box_height, half_leading, .. } = line_box_metrics;
+
+    let paragraph_spacing = word_positions.text_layout_options.paragraph_spacing.unwrap_or(line_box_height);
 
     let mut last_word_index = 0;
+    let mut paragraph_offset_y = 0.0;
 
-    InlineTextLayout {
+    let mut inline_text_layout = InlineTextLayout {
         lines: word_positions.line_breaks
             .iter()
             .enumerate()
             .map(|(line_number, (word_idx, line_length))| {
                 let start_word_idx = last_word_index;
+                let line_top = line_box_metrics.get_line_top(line_number) + paragraph_offset_y;
+                let baseline_y = line_box_metrics.get_baseline_y(line_number) + paragraph_offset_y;
+                // Empty lines between paragraphs are only as high as the paragraph spacing
+                let is_paragraph_break = word_positions.paragraph_breaks.contains(&line_number);
+                if is_paragraph_break {
+                    paragraph_offset_y += paragraph_spacing - line_box_height;
+                }
                 let line = InlineTextLine {
                     bounds: LayoutRect {
-                        origin: LayoutPoint { x: 0.0, y: get_line_y_position(line_number, regular_line_height, line_height_px) },
-                        size: LayoutSize { width: *line_length, height: regular_line_height },
+                        origin: LayoutPoint { x: 0.0, y: line_top },
+                        size: LayoutSize { width: *line_length, height: if is_paragraph_break { paragraph_spacing } else { line_box_height } },
                     },
                     word_start: start_word_idx,
                     word_end: *word_idx,
+                    baseline_y,
+                    half_leading,
                 };
                 last_word_index = *word_idx;
                 line
         }).collect(),
+    };
+
+    // In vertical writing modes, the lines are columns: the line box height becomes the width
+    // of the column, the line length the height. `baseline_y` is the top of the column.
+    let writing_mode = word_positions.text_layout_options.writing_mode;
+    if writing_mode.is_vertical() {
+        let total_block_size = inline_text_layout.lines.last().map(|line| line.bounds.origin.y + line.bounds.size.height).unwrap_or(0.0);
+        for line in inline_text_layout.lines.iter_mut() {
+            let column_x = match writing_mode {
+                WritingMode::VerticalLr => line.bounds.origin.y,
+                _ => total_block_size - line.bounds.origin.y - line.bounds.size.height,
+            };
+            line.bounds = LayoutRect {
+                origin: LayoutPoint { x: column_x, y: 0.0 },
+                size: LayoutSize { width: line.bounds.size.height, height: line.bounds.size.width },
+            };
+            line.baseline_y = 0.0;
+        }
+    }
+
+    inline_text_layout
+}
+
+/// Justifies the text (`text-align: justify`) to the `max_horizontal_width` of the layout
+/// options, see `InlineTextLayout::justify`. The last line of every paragraph (the last line of
+/// the text and every line that ends with a line break) is not stretched. Does nothing if the
+/// text has no maximum width.
+pub fn justify_inline_text_layout(
+    inline_text_layout: &mut InlineTextLayout,
+    word_positions: &mut WordPositions,
+    words: &Words,
+    scaled_words: &ScaledWords,
+) {
+    let max_width = match word_positions.text_layout_options.max_horizontal_width {
+        Some(s) => s,
+        None => return,
+    };
+
+    let letter_spacing = word_positions.text_layout_options.letter_spacing;
+    let word_widths = scaled_words.items.iter().map(|w| w.get_adjusted_width(letter_spacing)).collect::<Vec<_>>();
+    let word_levels = scaled_words.get_word_levels();
+
+    // Index of the first word after each line break (lines that end at one of these end a paragraph)
+    let mut hard_breaks = Vec::new();
+    let mut word_count = 0;
+    for word in &words.items {
+        match word.word_type {
+            WordType::Word => word_count += 1,
+            WordType::Return => hard_breaks.push(word_count),
+            _ => { },
+        }
+    }
+
+    let mut paragraph_start = 0;
+    for line_idx in 0..inline_text_layout.lines.len() {
+        let is_last_line = line_idx + 1 == inline_text_layout.lines.len();
+        if !is_last_line && !hard_breaks.contains(&inline_text_layout.lines[line_idx].word_end) {
+            continue;
+        }
+        let mut paragraph = InlineTextLayout { lines: inline_text_layout.lines[paragraph_start..=line_idx].to_vec() };
+        paragraph.justify(&mut word_positions.word_positions, &word_widths, &word_levels, max_width);
+        inline_text_layout.lines.splice(paragraph_start..=line_idx, paragraph.lines);
+        paragraph_start = line_idx + 1;
     }
 }
 
@@ -434,28 +1095,147 @@ pub fn get_layouted_glyphs(
 
     let letter_spacing_px = word_positions.text_layout_options.letter_spacing.unwrap_or(0.0);
     let mut all_glyphs = Vec::with_capacity(scaled_words.items.len());
-    let baseline_px = scaled_words.font_metrics.get_ascender(scaled_words.font_size_px);
+    let mut word_indices = Vec::with_capacity(scaled_words.items.len());
+
+    // Lines are only truncated if there is a maximum width (and only in horizontal text)
+    let is_vertical = word_positions.text_layout_options.writing_mode.is_vertical();
+    let ellipsis_max_x = match word_positions.text_layout_options.text_overflow {
+        TextOverflowBehaviour::Ellipsis if !is_vertical => word_positions.text_layout_options.max_horizontal_width.map(|max_width| origin.x + max_width),
+        _ => None,
+    };
+
+    // Vertical text: the glyphs are placed at the left edge of the text in the column (after
+    // the half-leading), the advances of the words are along the y axis of the column
+    let ascender_px = scaled_words.font_metrics.get_ascender(scaled_words.font_size_px);
 
     for line in inline_text_layout.lines.iter() {
 
+        let line_glyphs_start = all_glyphs.len();
+
         let line_x = origin.x + line.bounds.origin.x;
-        let line_y = origin.y + line.bounds.origin.y - (line.bounds.size.height - baseline_px); // bottom left corner of the glyph (baseline)
+        let line_y = origin.y + line.baseline_y; // bottom left corner of the glyph (baseline)
 
         let scaled_words_in_this_line = &scaled_words.items[line.word_start..line.word_end];
         let word_positions_in_this_line = &word_positions.word_positions[line.word_start..line.word_end];
 
-        for (scaled_word, word_position) in scaled_words_in_this_line.iter().zip(word_positions_in_this_line.iter()) {
+        for (word_idx, (scaled_word, word_position)) in scaled_words_in_this_line.iter().zip(word_positions_in_this_line.iter()).enumerate() {
             let mut glyphs = text_shaping::get_glyph_instances_hb(&scaled_word.glyph_infos, &scaled_word.glyph_positions);
             for (glyph, cluster_info) in glyphs.iter_mut().zip(scaled_word.cluster_iter()) {
-                glyph.point.x += line_x + word_position.x + (letter_spacing_px * cluster_info.cluster_idx as f32);
-                glyph.point.y += line_y;
+                let inline_offset = word_position.x + (letter_spacing_px * cluster_info.cluster_idx as f32);
+                if is_vertical {
+                    let column_y = origin.y + line.bounds.origin.y + inline_offset + glyph.point.x + ascender_px;
+                    glyph.point = LayoutPoint::new(line_x + line.half_leading, column_y);
+                    glyph.size = LayoutSize::new(glyph.size.height, glyph.size.width);
+                } else {
+                    glyph.point.x += line_x + inline_offset;
+                    glyph.point.y += line_y;
+                }
             }
 
+            word_indices.extend(std::iter::repeat(line.word_start + word_idx).take(glyphs.len()));
             all_glyphs.append(&mut glyphs);
         }
+
+        if let Some(max_x) = ellipsis_max_x {
+            if let Some(glyphs_that_fit) = truncate_line_with_ellipsis(&mut all_glyphs, line_glyphs_start, &scaled_words.ellipsis, max_x, line_x, line_y) {
+                // The ellipsis belongs to the last word that is still (partially) visible
+                word_indices.truncate(line_glyphs_start + glyphs_that_fit);
+                let ellipsis_word_idx = word_indices[line_glyphs_start..].last().copied().unwrap_or(line.word_start);
+                word_indices.resize(all_glyphs.len(), ellipsis_word_idx);
+            }
+        }
+    }
+
+    LayoutedGlyphs { glyphs: all_glyphs, word_indices }
+}
+
+/// If the glyphs of the line (`glyphs[line_glyphs_start..]`) extend past the `max_x`, removes
+/// the glyphs at the end of the line until the `ellipsis` fits in front of the `max_x`
+/// and appends the ellipsis. Lines that fit are not modified.
+///
+/// Returns how many glyphs of the line were kept in front of the ellipsis,
+/// or `None` if the line wasn't truncated.
+fn truncate_line_with_ellipsis(
+    glyphs: &mut Vec<GlyphInstance>,
+    line_glyphs_start: usize,
+    ellipsis: &ScaledWord,
+    max_x: f32,
+    line_x: f32,
+    line_y: f32,
+) -> Option<usize> {
+    use crate::text_shaping;
+
+    let get_glyph_end = |glyph: &GlyphInstance| glyph.point.x + glyph.size.width;
+
+    if !glyphs[line_glyphs_start..].iter().any(|glyph| get_glyph_end(glyph) > max_x) {
+        return None;
+    }
+
+    let max_glyph_end = max_x - ellipsis.word_width;
+    let glyphs_that_fit = glyphs[line_glyphs_start..].iter()
+        .take_while(|glyph| get_glyph_end(glyph) <= max_glyph_end)
+        .count();
+
+    glyphs.truncate(line_glyphs_start + glyphs_that_fit);
+
+    let ellipsis_x = glyphs[line_glyphs_start..].last().map(get_glyph_end).unwrap_or(line_x);
+    let mut ellipsis_glyphs = text_shaping::get_glyph_instances_hb(&ellipsis.glyph_infos, &ellipsis.glyph_positions);
+    for glyph in ellipsis_glyphs.iter_mut() {
+        glyph.point.x += ellipsis_x;
+        glyph.point.y += line_y;
+    }
+
+    glyphs.append(&mut ellipsis_glyphs);
+
+    Some(glyphs_that_fit)
+}
+
+/// Glyph (or one layer of a color glyph) at its final position,
+/// ready to be drawn by a renderer without any further font lookups
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct PositionedColorGlyph {
+    pub index: u32,
+    pub point: LayoutPoint,
+    /// Color of the layer, `None` if the glyph should be drawn with the text color
+    /// (and no `text_color` was given to `get_layouted_glyphs_colored`)
+    pub color: Option<ColorU>,
+}
+
+/// Expands the color glyphs of the `layouted_glyphs` into their colored layers (using the
+/// colors from the given palette). All layers are placed at the position of the color glyph.
+/// Monochrome glyphs (or all glyphs, if `color_glyphs` is `None`) yield a single layer.
+///
+/// Monochrome glyphs and layers that use the text color are colored with the `text_color`
+/// (usually the resolved `color` of the node), so that the output can be drawn as-is.
+pub fn get_layouted_glyphs_colored(
+    layouted_glyphs: &LayoutedGlyphs,
+    color_glyphs: Option<&ColorGlyphs>,
+    palette: usize,
+    text_color: Option<ColorU>,
+) -> Vec<PositionedColorGlyph> {
+
+    let mut colored_glyphs = Vec::with_capacity(layouted_glyphs.glyphs.len());
+
+    for glyph in &layouted_glyphs.glyphs {
+        match color_glyphs.and_then(|c| Some((c, c.layers.get(&glyph.index)?))) {
+            Some((color_glyphs, layers)) => {
+                colored_glyphs.extend(layers.iter().map(|(layer_glyph, palette_entry)| PositionedColorGlyph {
+                    index: *layer_glyph,
+                    point: glyph.point,
+                    color: color_glyphs.get_layer_color(palette, *palette_entry).or(text_color),
+                }));
+            },
+            None => {
+                colored_glyphs.push(PositionedColorGlyph {
+                    index: glyph.index,
+                    point: glyph.point,
+                    color: text_color,
+                });
+            }
+        }
     }
 
-    LayoutedGlyphs { glyphs: all_glyphs }
+    colored_glyphs
 }
 
 pub fn word_item_is_return(item: &Word) -> bool {
@@ -649,6 +1429,516 @@ fn test_split_words() {
     assert_words(&words_single_str_expected, &words_single_str);
 }
 
+/// Creates fake `ScaledWords` (one word per entry in `word_widths`, without any glyphs),
+/// so that `position_words` can be tested without loading a font
+#[cfg(test)]
+fn get_test_scaled_words(word_widths: &[f32], space_advance_px: f32) -> ScaledWords {
+    ScaledWords {
+        font_size_px: 10.0,
+        baseline_px: 10.0,
+        items: word_widths.iter().map(|w| ScaledWord {
+            glyph_infos: Vec::new(),
+            glyph_positions: Vec::new(),
+            word_width: *w,
+            direction: TextDirection::LeftToRight,
+            rotated_glyphs: Vec::new(),
+        }).collect(),
+        longest_word_width: word_widths.iter().cloned().fold(0.0_f32, f32::max),
+        space_advance_px,
+        space_codepoint: 0,
+        ellipsis: ScaledWord {
+            glyph_infos: Vec::new(),
+            glyph_positions: Vec::new(),
+            word_width: 0.0,
+            direction: TextDirection::LeftToRight,
+            rotated_glyphs: Vec::new(),
+        },
+        font_metrics: FontMetrics::zero(),
+        direction: TextDirection::LeftToRight,
+    }
+}
+
+#[test]
+fn test_position_words_explicit_line_breaks() {
+
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, .. Default::default() };
+
+    let words = split_text_into_words("a\nb");
+    let scaled_words = get_test_scaled_words(&[10.0, 10.0], 5.0);
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+
+    let text_words = words.items.iter().filter(|w| w.word_type == WordType::Word).collect::<Vec<_>>();
+    assert_eq!(layout.lines.len(), 2);
+    assert_eq!((layout.lines[0].word_start, layout.lines[0].word_end), (0, 1));
+    assert_eq!((layout.lines[1].word_start, layout.lines[1].word_end), (1, 2));
+    assert_eq!(words.get_substr(text_words[0]), "a");
+    assert_eq!(words.get_substr(text_words[1]), "b");
+    assert_eq!(word_positions.word_positions[1].x, 0.0);
+    assert!(word_positions.word_positions[1].y > word_positions.word_positions[0].y);
+
+    // "\r\n" behaves the same as "\n"
+    let words = split_text_into_words("a\r\nb");
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    assert_eq!(word_positions.line_breaks.len(), 2);
+
+    // A trailing return creates a final, empty line
+    let words = split_text_into_words("a\n");
+    let scaled_words = get_test_scaled_words(&[10.0], 5.0);
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    assert_eq!(word_positions.line_breaks, vec![(1, 10.0), (1, 0.0)]);
+    assert_eq!(word_positions.number_of_lines, 2);
+}
+
+/// Same as `get_test_scaled_words`, but creates one glyph with an advance of
+/// `char_advance_px` for every character of every word in `words`
+#[cfg(test)]
+fn get_test_scaled_words_from_chars(words: &Words, char_advance_px: f32, space_advance_px: f32) -> ScaledWords {
+
+    use azul_core::app_resources::HbVarIntT;
+    use crate::text_shaping::HB_SCALE_FACTOR;
+
+    let items = words.items.iter().filter(|w| w.word_type == WordType::Word).map(|w| {
+        let char_count = w.end - w.start;
+        ScaledWord {
+            glyph_infos: (0..char_count).map(|i| GlyphInfo {
+                codepoint: 1,
+                mask: 0,
+                cluster: (w.start + i) as u32,
+                var1: HbVarIntT { u32: 0 },
+                var2: HbVarIntT { u32: 0 },
+            }).collect(),
+            glyph_positions: (0..char_count).map(|_| GlyphPosition {
+                x_advance: (char_advance_px * HB_SCALE_FACTOR) as i32,
+                y_advance: 0,
+                x_offset: 0,
+                y_offset: 0,
+                var: HbVarIntT { u32: 0 },
+            }).collect(),
+            word_width: char_advance_px * char_count as f32,
+            direction: TextDirection::LeftToRight,
+            rotated_glyphs: Vec::new(),
+        }
+    }).collect::<Vec<_>>();
+
+    // The ellipsis is a single glyph (with the glyph index 2)
+    let ellipsis = ScaledWord {
+        glyph_infos: vec![GlyphInfo { codepoint: 2, mask: 0, cluster: 0, var1: HbVarIntT { u32: 0 }, var2: HbVarIntT { u32: 0 } }],
+        glyph_positions: vec![GlyphPosition {
+            x_advance: (char_advance_px * HB_SCALE_FACTOR) as i32,
+            y_advance: 0,
+            x_offset: 0,
+            y_offset: 0,
+            var: HbVarIntT { u32: 0 },
+        }],
+        word_width: char_advance_px,
+        direction: TextDirection::LeftToRight,
+        rotated_glyphs: Vec::new(),
+    };
+
+    ScaledWords {
+        longest_word_width: items.iter().map(|w: &ScaledWord| w.word_width).fold(0.0_f32, f32::max),
+        items,
+        ellipsis,
+        .. get_test_scaled_words(&[], space_advance_px)
+    }
+}
+
+#[test]
+fn test_position_words_collapse_whitespace_after_soft_wrap() {
+
+    let text_layout_options = ResolvedTextLayoutOptions {
+        font_size_px: 10.0,
+        max_horizontal_width: Some(12.0),
+        .. Default::default()
+    };
+
+    // Every word is wider than the space left after the previous word
+    for text in &["a b c", "a  b   c"] {
+        let words = split_text_into_words(text);
+        let scaled_words = get_test_scaled_words(&[10.0, 10.0, 10.0], 5.0);
+        let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+        assert_eq!(word_positions.number_of_lines, 3);
+        for word_position in &word_positions.word_positions {
+            assert_eq!(word_position.x, 0.0);
+        }
+    }
+
+    // Whitespace after a hard break is preserved
+    let words = split_text_into_words("a\n  b");
+    let scaled_words = get_test_scaled_words(&[10.0, 10.0], 5.0);
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, .. Default::default() };
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    assert_eq!(word_positions.word_positions[1].x, 10.0);
+}
+
+#[test]
+fn test_position_words_tab_stops() {
+
+    let tab_stops = vec![TabStop { position: 100.0, align: TabAlign::Right }];
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, tab_stops, .. Default::default() };
+
+    // Right-aligned numbers of different length end at the same x
+    let words = split_text_into_words("a\t12\nb\t12345");
+    let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    assert_eq!(word_positions.word_positions[1].x, 80.0);
+    assert_eq!(word_positions.word_positions[3].x, 50.0);
+
+    let tab_stops = vec![TabStop { position: 100.0, align: TabAlign::Decimal }];
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, tab_stops, .. Default::default() };
+
+    // The decimal points of "1.5" and "123.25" land at the same x
+    let words = split_text_into_words("a\t1.5\nb\t123.25");
+    let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    assert_eq!(word_positions.word_positions[1].x + 10.0, 100.0);
+    assert_eq!(word_positions.word_positions[3].x + 30.0, 100.0);
+
+    // Numbers without a decimal point are right-aligned
+    let words = split_text_into_words("a\t12");
+    let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    assert_eq!(word_positions.word_positions[1].x, 80.0);
+}
+
+#[test]
+fn test_measure_text_width_expands_tabs() {
+
+    let words = split_text_into_words("ab\tc d\nabc\t\tde\t");
+    let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, tab_width: Some(2.0), .. Default::default() };
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    let measured_width = measure_text_width(&words, &scaled_words, &text_layout_options);
+    // "abc" (30) + 2 tabs (2 * (5 + 10)) + "de" (20)
+    assert_eq!(measured_width, 80.0);
+    assert_eq!(measured_width, word_positions.content_size.width);
+
+    let tab_stops = vec![TabStop { position: 100.0, align: TabAlign::Right }];
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, tab_stops, .. Default::default() };
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    assert_eq!(measure_text_width(&words, &scaled_words, &text_layout_options), word_positions.content_size.width);
+}
+
+#[test]
+fn test_get_layouted_glyphs_ellipsis() {
+
+    let get_glyphs = |text: &str, text_overflow: TextOverflowBehaviour| {
+        let options = ResolvedTextLayoutOptions { font_size_px: 10.0, max_horizontal_width: Some(45.0), text_overflow, .. Default::default() };
+        let words = split_text_into_words(text);
+        let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+        let word_positions = position_words(&words, &scaled_words, &options);
+        let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+        get_layouted_glyphs(&word_positions, &scaled_words, &layout, LayoutPoint::new(100.0, 0.0)).glyphs
+    };
+
+    // The long word is cut after "abc", so that "abc…" fits into 45px
+    let glyphs = get_glyphs("abcdefghij", TextOverflowBehaviour::Ellipsis);
+    assert_eq!(glyphs.iter().map(|g| g.index).collect::<Vec<_>>(), vec![1, 1, 1, 2]);
+    assert_eq!(glyphs[3].point.x, 130.0);
+
+    // Without an ellipsis, the word overflows
+    assert_eq!(get_glyphs("abcdefghij", TextOverflowBehaviour::Clip).len(), 10);
+
+    // Text that fits isn't modified
+    let glyphs = get_glyphs("abcd", TextOverflowBehaviour::Ellipsis);
+    assert_eq!(glyphs.iter().map(|g| g.index).collect::<Vec<_>>(), vec![1, 1, 1, 1]);
+}
+
+#[test]
+fn test_inline_text_layout_line_glyphs() {
+
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, max_horizontal_width: Some(65.0), .. Default::default() };
+
+    // Three lines: "abc de " / "fghi jk " / "lmn"
+    let words = split_text_into_words("abc de fghi jk lmn");
+    let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+    let layouted_glyphs = get_layouted_glyphs(&word_positions, &scaled_words, &layout, LayoutPoint::zero());
+    assert_eq!(layouted_glyphs.word_indices.len(), layouted_glyphs.glyphs.len());
+
+    let line_glyph_xs = |line_index| layout.line_glyphs(line_index, &layouted_glyphs).map(|g| g.point.x).collect::<Vec<_>>();
+
+    assert_eq!(line_glyph_xs(0), vec![0.0, 10.0, 20.0, 35.0, 45.0]);
+    assert_eq!(line_glyph_xs(1), vec![0.0, 10.0, 20.0, 30.0, 45.0, 55.0]);
+    assert_eq!(line_glyph_xs(2), vec![0.0, 10.0, 20.0]);
+    assert!(line_glyph_xs(3).is_empty());
+
+    let baseline_y = layout.lines[1].baseline_y;
+    assert!(layout.line_glyphs(1, &layouted_glyphs).all(|g| g.point.y == baseline_y));
+}
+
+#[test]
+fn test_get_caret_position() {
+
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, max_horizontal_width: Some(65.0), .. Default::default() };
+
+    // "abc de" fits on the first line, "fghi" is wrapped, followed by an empty line
+    let words = split_text_into_words("abc de fghi\n\nj");
+    let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+    let caret = |char_index| layout.get_caret_position(char_index, &words, &word_positions, &scaled_words).map(|p| (p.x, p.y));
+
+    let line_y = |line_idx: usize| layout.lines[line_idx].bounds.origin.y;
+
+    assert_eq!(caret(0), Some((0.0, line_y(0))));
+    assert_eq!(caret(2), Some((20.0, line_y(0))));
+    // The space after "abc"
+    assert_eq!(caret(3), Some((30.0, line_y(0))));
+    assert_eq!(caret(4), Some((35.0, line_y(0))));
+    // "fghi" on the second line
+    assert_eq!(caret(8), Some((10.0, line_y(1))));
+    // The line break after "fghi" is at the end of the second line
+    assert_eq!(caret(11), Some((40.0, line_y(1))));
+    // The empty line
+    assert_eq!(caret(12), Some((0.0, line_y(2))));
+    // End of the text: trailing edge of the last glyph
+    assert_eq!(caret(14), Some((10.0, line_y(3))));
+    assert_eq!(caret(15), None);
+}
+
+#[test]
+fn test_inline_text_layout_hit_test() {
+
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, max_horizontal_width: Some(65.0), .. Default::default() };
+
+    let words = split_text_into_words("abc de fghi\n\nj");
+    let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+    let hit_test = |x, line_idx: usize| {
+        let line = &layout.lines[line_idx];
+        let y = line.bounds.origin.y + line.bounds.size.height / 2.0;
+        layout.hit_test(LayoutPoint::new(x, y), &words, &word_positions, &scaled_words)
+    };
+
+    // Left / right half of the "b" glyph
+    assert_eq!(hit_test(12.0, 0), Some(1));
+    assert_eq!(hit_test(17.0, 0), Some(2));
+
+    // Gap between "abc" (ends at 30) and "de" (starts at 35)
+    assert_eq!(hit_test(31.0, 0), Some(3));
+    assert_eq!(hit_test(34.0, 0), Some(4));
+
+    // Past the end of a line: in front of the line break
+    assert_eq!(hit_test(60.0, 1), Some(11));
+    // Empty line
+    assert_eq!(hit_test(50.0, 2), Some(12));
+
+    // Every caret position maps back to its character index
+    for char_index in 0..=words.internal_chars.len() {
+        let caret = layout.get_caret_position(char_index, &words, &word_positions, &scaled_words).unwrap();
+        let hit = layout.hit_test(LayoutPoint::new(caret.x, caret.y + 1.0), &words, &word_positions, &scaled_words);
+        assert_eq!(hit, Some(char_index));
+    }
+
+    // Above the first / below the last line
+    assert_eq!(layout.hit_test(LayoutPoint::new(20.0, -5.0), &words, &word_positions, &scaled_words), Some(0));
+    assert_eq!(layout.hit_test(LayoutPoint::new(0.0, 1000.0), &words, &word_positions, &scaled_words), Some(words.internal_chars.len()));
+}
+
+#[test]
+fn test_inline_text_layout_get_selection_rects() {
+
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, max_horizontal_width: Some(65.0), .. Default::default() };
+
+    // Three lines: "abc de " / "fghi jk " / "lmn"
+    let words = split_text_into_words("abc de fghi jk lmn");
+    let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+    assert_eq!(layout.lines.len(), 3);
+
+    let rects = |start, end| layout.get_selection_rects(start, end, &words, &word_positions, &scaled_words)
+        .iter()
+        .map(|r| (r.origin.x, r.origin.y, r.size.width, r.size.height))
+        .collect::<Vec<_>>();
+    let line = |line_idx: usize| layout.lines[line_idx].bounds;
+
+    // "bc" within the first line
+    assert_eq!(rects(1, 3), vec![(10.0, line(0).origin.y, 20.0, line(0).size.height)]);
+
+    // "e fghi jk l": end of the first line, the entire second line, start of the third line
+    assert_eq!(rects(5, 16), vec![
+        (45.0, line(0).origin.y, line(0).size.width - 45.0, line(0).size.height),
+        (0.0, line(1).origin.y, line(1).size.width, line(1).size.height),
+        (0.0, line(2).origin.y, 10.0, line(2).size.height),
+    ]);
+
+    assert!(rects(4, 4).is_empty());
+}
+
+#[test]
+fn test_position_words_letter_spacing_is_reversible() {
+
+    let words = split_text_into_words("abc de");
+    let scaled_words = get_test_scaled_words_from_chars(&words, 10.0, 5.0);
+
+    let natural_options = ResolvedTextLayoutOptions { font_size_px: 10.0, .. Default::default() };
+    let spaced_options = ResolvedTextLayoutOptions { letter_spacing: Some(2.0), .. natural_options.clone() };
+
+    let natural = position_words(&words, &scaled_words, &natural_options);
+    let spaced = position_words(&words, &scaled_words, &spaced_options);
+    let natural_again = position_words(&words, &scaled_words, &natural_options);
+
+    // 2px between the 3 clusters of "abc" and the 2 clusters of "de"
+    assert_eq!(spaced.trailing, natural.trailing + 6.0);
+    assert_eq!(natural_again.trailing, natural.trailing);
+    assert_eq!(scaled_words.items[0].get_adjusted_width(None), scaled_words.items[0].word_width);
+}
+
+#[test]
+fn test_apply_cell_width() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+
+    let words = split_text_into_words("il WM \u{FF21}");
+    let font_metrics = crate::text_shaping::get_font_metrics_freetype(FONT, 0);
+    let mut scaled_words = words_to_scaled_words(&words, FONT, 0, font_metrics, 16.0);
+    apply_cell_width(&words, &mut scaled_words, 10.0);
+
+    // Narrow ("il") and wide ("WM") ASCII glyphs all get exactly one cell
+    assert_eq!(scaled_words.items[0].word_width, 20.0);
+    assert_eq!(scaled_words.items[1].word_width, 20.0);
+    for glyph_position in scaled_words.items[0].glyph_positions.iter().chain(scaled_words.items[1].glyph_positions.iter()) {
+        assert_eq!(glyph_position.x_advance, 10 * 128);
+    }
+
+    // Fullwidth "Ａ" takes up two cells
+    assert_eq!(scaled_words.items[2].word_width, 20.0);
+    assert_eq!(scaled_words.space_advance_px, 10.0);
+}
+
+#[test]
+fn test_vertical_writing_mode() {
+
+    // The CJK glyphs are one em (16px) wide, but advance by 1.25 em (20px) vertically
+    let font = crate::text_shaping::build_test_cjk_font();
+    let words = split_text_into_words("漢字 かな 漢字");
+    let font_metrics = crate::text_shaping::get_font_metrics_freetype(&font, 0);
+    let horizontal_words = words_to_scaled_words(&words, &font, 0, font_metrics, 16.0);
+    assert_eq!(horizontal_words.items[0].word_width, 32.0);
+    assert_eq!(horizontal_words.space_advance_px, 8.0);
+    let scaled_words = words_to_scaled_words_vertical(&words, &font, 0, font_metrics, 16.0);
+    assert_eq!(scaled_words.items[0].word_width, 40.0);
+    assert_eq!(scaled_words.items[0].rotated_glyphs, vec![false, false]);
+    assert_eq!(scaled_words.space_advance_px, 8.0);
+
+    let layout = |writing_mode| {
+        // Columns are at most 50px high, so every word gets its own column
+        let options = ResolvedTextLayoutOptions { font_size_px: 16.0, max_horizontal_width: Some(50.0), writing_mode, .. Default::default() };
+        let word_positions = position_words(&words, &scaled_words, &options);
+        let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+        let glyphs = get_layouted_glyphs(&word_positions, &scaled_words, &layout, LayoutPoint::zero());
+        (word_positions, layout, glyphs)
+    };
+
+    let (_, horizontal, _) = layout(WritingMode::HorizontalTb);
+    let column_width = horizontal.lines[0].bounds.size.height;
+    let assert_approx_eq = |a: f32, b: f32| assert!((a - b).abs() < 0.001, "{} != {}", a, b);
+
+    let (word_positions, vertical_rl, glyphs) = layout(WritingMode::VerticalRl);
+    assert_eq!(vertical_rl.lines.len(), 3);
+    for (line_idx, line) in vertical_rl.lines.iter().enumerate() {
+        // The first column is on the right
+        assert_approx_eq(line.bounds.origin.x, (2 - line_idx) as f32 * column_width);
+        assert_eq!(line.bounds.origin.y, 0.0);
+        assert_eq!(line.bounds.size.width, column_width);
+        assert!(line.bounds.size.height >= 40.0);
+    }
+
+    // The glyphs of a column are stacked from top to bottom at the same x position
+    let first_column = vertical_rl.line_glyphs(0, &glyphs).collect::<Vec<_>>();
+    assert_eq!(first_column.len(), 2);
+    assert_eq!(first_column[0].point.x, first_column[1].point.x);
+    assert_eq!(first_column[1].point.y - first_column[0].point.y, 20.0);
+    assert!(first_column[0].point.x >= vertical_rl.lines[0].bounds.origin.x);
+
+    // Carets are placed, hit tested and selected along the columns
+    let caret = |char_index| vertical_rl.get_caret_position(char_index, &words, &word_positions, &scaled_words).unwrap();
+    let column = |line_idx: usize| vertical_rl.lines[line_idx].bounds;
+    assert_eq!(caret(0), LayoutPoint::new(column(0).origin.x, 0.0));
+    assert_eq!(caret(1), LayoutPoint::new(column(0).origin.x, 20.0));
+    assert_eq!(caret(3), LayoutPoint::new(column(1).origin.x, 0.0));
+
+    for char_index in 0..=words.internal_chars.len() {
+        let caret = caret(char_index);
+        let hit = vertical_rl.hit_test(LayoutPoint::new(caret.x + 1.0, caret.y), &words, &word_positions, &scaled_words);
+        assert_eq!(hit, Some(char_index));
+    }
+
+    // Right of the first / left of the last column
+    let hit_test = |x| vertical_rl.hit_test(LayoutPoint::new(x, 10.0), &words, &word_positions, &scaled_words);
+    assert_eq!(hit_test(column(0).origin.x + column_width + 1.0), Some(0));
+    assert_eq!(hit_test(column(2).origin.x - 1.0), Some(words.internal_chars.len()));
+    // Lower half of "字"
+    assert_eq!(vertical_rl.hit_test(LayoutPoint::new(column(0).origin.x + 1.0, 31.0), &words, &word_positions, &scaled_words), Some(2));
+
+    // "字 か": from the middle of the first column to the end of "か" in the second column
+    let rects = vertical_rl.get_selection_rects(1, 4, &words, &word_positions, &scaled_words)
+        .iter()
+        .map(|r| (r.origin.x, r.origin.y, r.size.width, r.size.height))
+        .collect::<Vec<_>>();
+    assert_eq!(rects, vec![
+        (column(0).origin.x, 20.0, column_width, column(0).size.height - 20.0),
+        (column(1).origin.x, 0.0, column_width, 20.0),
+    ]);
+
+    let (_, vertical_lr, _) = layout(WritingMode::VerticalLr);
+    assert_eq!(vertical_lr.lines[0].bounds.origin.x, 0.0);
+    assert_approx_eq(vertical_lr.lines[2].bounds.origin.x, 2.0 * column_width);
+}
+
+#[test]
+fn test_get_layouted_glyphs_colored() {
+
+    use std::collections::BTreeMap;
+
+    let red = ColorU { r: 255, g: 0, b: 0, a: 255 };
+    let mut layers = BTreeMap::new();
+    layers.insert(5, vec![(6, 0), (7, 0xFFFF)]);
+    let color_glyphs = ColorGlyphs { layers, palettes: vec![vec![red]] };
+
+    let glyph = |index, x| GlyphInstance { index, point: LayoutPoint::new(x, 10.0), size: LayoutSize::zero() };
+    let layouted_glyphs = LayoutedGlyphs { glyphs: vec![glyph(1, 0.0), glyph(5, 8.0)], word_indices: vec![0, 0] };
+
+    let colored = get_layouted_glyphs_colored(&layouted_glyphs, Some(&color_glyphs), 0, None);
+    assert_eq!(colored, vec![
+        PositionedColorGlyph { index: 1, point: LayoutPoint::new(0.0, 10.0), color: None },
+        // Both layers of the color glyph are drawn at the same pen position
+        PositionedColorGlyph { index: 6, point: LayoutPoint::new(8.0, 10.0), color: Some(red) },
+        PositionedColorGlyph { index: 7, point: LayoutPoint::new(8.0, 10.0), color: None },
+    ]);
+
+    assert_eq!(get_layouted_glyphs_colored(&layouted_glyphs, None, 0, None).len(), 2);
+}
+
+#[test]
+fn test_get_layouted_glyphs_colored_text_color() {
+
+    use std::collections::BTreeMap;
+
+    let red = ColorU { r: 255, g: 0, b: 0, a: 255 };
+    let blue = ColorU { r: 0, g: 0, b: 255, a: 255 };
+    let mut layers = BTreeMap::new();
+    layers.insert(5, vec![(6, 0), (7, 0xFFFF)]);
+    let color_glyphs = ColorGlyphs { layers, palettes: vec![vec![red]] };
+
+    let glyph = |index, x| GlyphInstance { index, point: LayoutPoint::new(x, 10.0), size: LayoutSize::zero() };
+    let layouted_glyphs = LayoutedGlyphs { glyphs: vec![glyph(1, 0.0), glyph(5, 8.0)], word_indices: vec![0, 0] };
+
+    let colored = get_layouted_glyphs_colored(&layouted_glyphs, Some(&color_glyphs), 0, Some(blue));
+    assert_eq!(colored, vec![
+        PositionedColorGlyph { index: 1, point: LayoutPoint::new(0.0, 10.0), color: Some(blue) },
+        // Palette colors are kept, only the layer using the text color is recolored
+        PositionedColorGlyph { index: 6, point: LayoutPoint::new(8.0, 10.0), color: Some(red) },
+        PositionedColorGlyph { index: 7, point: LayoutPoint::new(8.0, 10.0), color: Some(blue) },
+    ]);
+}
+
 #[test]
 fn test_get_line_y_position() {
 
@@ -801,3 +2091,280 @@ fn test_caret_intersects_with_holes_4() {
 
     assert_eq!(result, LineCaretIntersection::NoIntersection);
 }
+
+#[test]
+fn test_split_scaled_word_at_char() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+
+    let words = split_text_into_words("xyz abcdef");
+    let font_metrics = crate::text_shaping::get_font_metrics_freetype(FONT, 0);
+    let scaled_words = words_to_scaled_words(&words, FONT, 0, font_metrics, 16.0);
+    let word = &words.items[2];
+    let scaled_word = &scaled_words.items[1];
+
+    let (before, after) = split_scaled_word_at_char(&words, word, scaled_word, 3);
+    assert_eq!(before.glyph_infos.len(), 3);
+    assert_eq!(after.glyph_infos.len(), 3);
+    assert_eq!(before.word_width + after.word_width, scaled_word.word_width);
+
+    let (before, after) = split_scaled_word_at_char(&words, word, scaled_word, 6);
+    assert_eq!(before.glyph_infos.len(), 6);
+    assert!(after.glyph_infos.is_empty());
+
+    // "fl" is a ligature in KoHo, splitting between "f" and "l" splits before the ligature
+    let words = split_text_into_words("flat");
+    let scaled_words = words_to_scaled_words(&words, FONT, 0, font_metrics, 16.0);
+    let (before, after) = split_scaled_word_at_char(&words, &words.items[0], &scaled_words.items[0], 1);
+    assert!(before.glyph_infos.is_empty());
+    assert_eq!(after.glyph_infos.len(), 3);
+}
+
+#[test]
+fn test_word_bounds_at() {
+    let text = "say hello,  world";
+    assert_eq!(word_bounds_at(text, 6), Some(4..9));
+    assert_eq!(word_bounds_at(text, 4), Some(4..9));
+    assert_eq!(word_bounds_at(text, 9), Some(9..10));
+    assert_eq!(word_bounds_at(text, 3), Some(3..4));
+    assert_eq!(word_bounds_at(text, 11), Some(10..12));
+    assert_eq!(word_bounds_at(text, text.len()), None);
+}
+
+#[test]
+fn test_advance_to_glyph() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/weblysleekuil.ttf");
+
+    let words = split_text_into_words("WAVE");
+    let font_metrics = crate::text_shaping::get_font_metrics_freetype(FONT, 0);
+    let scaled_words = words_to_scaled_words(&words, FONT, 0, font_metrics, 16.0);
+    let scaled_word = &scaled_words.items[0];
+    let glyph_count = scaled_word.glyph_positions.len();
+
+    assert_eq!(advance_to_glyph(scaled_word, 0), 0.0);
+    assert_eq!(advance_to_glyph(scaled_word, glyph_count), scaled_word.word_width);
+    assert_eq!(advance_to_glyph(scaled_word, glyph_count + 5), scaled_word.word_width);
+    assert_eq!(advance_to_glyph(scaled_word, 1), scaled_word.glyph_positions[0].x_advance as f32 / 128.0);
+    assert!(advance_to_glyph(scaled_word, 2) < advance_to_glyph(scaled_word, 3));
+}
+
+#[test]
+fn test_range_width() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/weblysleekuil.ttf");
+
+    let words = split_text_into_words("WAVE");
+    let font_metrics = crate::text_shaping::get_font_metrics_freetype(FONT, 0);
+    let scaled_words = words_to_scaled_words(&words, FONT, 0, font_metrics, 16.0);
+    let scaled_word = &scaled_words.items[0];
+
+    assert_eq!(range_width(scaled_word, 0..4), scaled_word.word_width);
+    // "AV", including the kerning between the "W" and the "A" and between the "V" and the "E"
+    assert_eq!(range_width(scaled_word, 1..3), advance_to_glyph(scaled_word, 3) - advance_to_glyph(scaled_word, 1));
+    assert_eq!(range_width(scaled_word, 2..2), 0.0);
+
+    // "afi" with an "fi" ligature (one glyph for the characters 1..3): the ligature
+    // only counts if the range contains its first character
+    let ligature = ScaledWord::from_advances(&[('a', 10), ('\u{FB01}', 20)]);
+    assert_eq!(range_width(&ligature, 0..2), 30.0);
+    assert_eq!(range_width(&ligature, 1..3), 20.0);
+    assert_eq!(range_width(&ligature, 2..3), 0.0);
+}
+
+#[test]
+fn test_words_to_scaled_words_bidi() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let font_metrics = crate::text_shaping::get_font_metrics_freetype(FONT, 0);
+    let options = ResolvedTextLayoutOptions { font_size_px: 16.0, .. Default::default() };
+    let get_x = |words: &Words, scaled_words: &ScaledWords| {
+        position_words(words, scaled_words, &options).word_positions.iter().map(|p| p.x).collect::<Vec<_>>()
+    };
+
+    // The two Hebrew words are a right-to-left run in a left-to-right paragraph
+    let words = split_text_into_words("abc \u{5D0}\u{5D1} \u{5D2}\u{5D3} def");
+    let scaled_words = words_to_scaled_words(&words, FONT, 0, font_metrics, 16.0);
+    assert_eq!(scaled_words.direction, TextDirection::LeftToRight);
+    assert_eq!(scaled_words.get_word_levels(), vec![0, 1, 1, 0]);
+
+    // Every word gets the glyphs of its own characters, right-to-left words in visual order
+    let glyph_counts = scaled_words.items.iter().map(|w| w.glyph_infos.len()).collect::<Vec<_>>();
+    assert_eq!(glyph_counts, vec![3, 2, 2, 3]);
+    assert!(scaled_words.items[1].glyph_infos[0].cluster > scaled_words.items[1].glyph_infos[1].cluster);
+
+    // Visual order: "abc", second Hebrew word, first Hebrew word, "def"
+    let x = get_x(&words, &scaled_words);
+    assert!(x[0] < x[2] && x[2] < x[1] && x[1] < x[3]);
+
+    // A Hebrew paragraph is right-to-left (the first word is on the right), numbers stay left-to-right
+    let words = split_text_into_words("\u{5D0}\u{5D1} \u{5D2}\u{5D3} 12");
+    let scaled_words = words_to_scaled_words(&words, FONT, 0, font_metrics, 16.0);
+    assert_eq!(scaled_words.direction, TextDirection::RightToLeft);
+    assert_eq!(scaled_words.get_word_levels(), vec![1, 1, 2]);
+    let x = get_x(&words, &scaled_words);
+    assert!(x[2] < x[1] && x[1] < x[0]);
+
+    // The direction can also be set explicitly
+    let scaled_words = words_to_scaled_words_with_direction(&words, FONT, 0, font_metrics, 16.0, TextDirection::LeftToRight);
+    assert_eq!(scaled_words.get_word_levels(), vec![1, 1, 0]);
+}
+
+#[test]
+fn test_line_box_half_leading() {
+
+    let text_layout_options = ResolvedTextLayoutOptions {
+        font_size_px: 10.0,
+        line_height: Some(2.0),
+        .. Default::default()
+    };
+
+    let mut scaled_words = get_test_scaled_words(&[10.0, 10.0], 5.0);
+    scaled_words.font_metrics.height = 1200;
+    scaled_words.font_metrics.ascender = 900;
+
+    let words = split_text_into_words("a\nb");
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+
+    // text height = 12px, extra line height = 2.0 * 5px space advance
+    let line = &layout.lines[0];
+    assert_eq!(line.half_leading, 5.0);
+    assert_eq!(line.bounds.size.height, 22.0);
+    assert_eq!(line.baseline_y, 14.0);
+    assert_eq!(layout.lines[1].bounds.origin.y, 22.0);
+
+    // baseline is centered in the line box: same space above and below the text
+    let text_top = line.baseline_y - 9.0;
+    let text_bottom = text_top + 12.0;
+    assert_eq!(text_top - line.bounds.origin.y, (line.bounds.origin.y + line.bounds.size.height) - text_bottom);
+
+    // word positions, line boxes and content size use the same line box geometry
+    assert_eq!(word_positions.word_positions[0].y, layout.lines[0].baseline_y);
+    assert_eq!(word_positions.word_positions[1].y, layout.lines[1].baseline_y);
+    let last_line = layout.lines.last().unwrap();
+    assert_eq!(word_positions.content_size.height, last_line.bounds.origin.y + last_line.bounds.size.height);
+}
+
+#[test]
+fn test_position_words_show_whitespace() {
+
+    let words = split_text_into_words("a b\tc\nd");
+    let scaled_words = get_test_scaled_words(&[10.0, 10.0, 10.0, 10.0], 5.0);
+
+    let hidden_options = ResolvedTextLayoutOptions { font_size_px: 10.0, .. Default::default() };
+    assert!(position_words(&words, &scaled_words, &hidden_options).whitespace_markers.is_empty());
+
+    let options = ResolvedTextLayoutOptions { show_whitespace: true, .. hidden_options };
+    let word_positions = position_words(&words, &scaled_words, &options);
+    let markers = &word_positions.whitespace_markers;
+
+    let kinds = markers.iter().map(|m| m.kind).collect::<Vec<_>>();
+    assert_eq!(kinds, vec![WhitespaceKind::Space, WhitespaceKind::Tab, WhitespaceKind::LineEnd]);
+
+    // markers start where the previous word ends and end where the next word starts
+    assert_eq!(markers[0].position.x, 10.0);
+    assert_eq!(markers[0].position.x + markers[0].width, word_positions.word_positions[1].x);
+    assert_eq!(markers[1].position.x, word_positions.word_positions[1].x + 10.0);
+    assert_eq!(markers[1].position.x + markers[1].width, word_positions.word_positions[2].x);
+    assert_eq!(markers[2].position.x, word_positions.word_positions[2].x + 10.0);
+    assert_eq!(markers[2].position.y, word_positions.word_positions[2].y);
+}
+
+#[test]
+fn test_position_words_paragraph_spacing() {
+
+    let words = split_text_into_words("a\nb\n\nc\nd");
+    let scaled_words = get_test_scaled_words(&[10.0, 10.0, 10.0, 10.0], 5.0);
+
+    // Without paragraph spacing, the empty line is a regular line
+    let regular_options = ResolvedTextLayoutOptions { font_size_px: 10.0, .. Default::default() };
+    let word_positions = position_words(&words, &scaled_words, &regular_options);
+    let line_advance = word_positions.word_positions[1].y - word_positions.word_positions[0].y;
+    assert!(word_positions.paragraph_breaks.is_empty());
+    assert_eq!(word_positions.word_positions[2].y - word_positions.word_positions[1].y, 2.0 * line_advance);
+
+    let options = ResolvedTextLayoutOptions { paragraph_spacing: Some(8.0), .. regular_options };
+    let word_positions = position_words(&words, &scaled_words, &options);
+    let y = word_positions.word_positions.iter().map(|p| p.y).collect::<Vec<_>>();
+    assert_eq!(word_positions.paragraph_breaks, vec![2]);
+
+    // Single line advances within the paragraphs, one line advance + the
+    // paragraph spacing (instead of the empty line) between "b" and "c"
+    assert_eq!(y[1] - y[0], line_advance);
+    assert_eq!(y[2] - y[1], line_advance + 8.0);
+    assert_eq!(y[3] - y[2], line_advance);
+
+    let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+    let line_box_height = layout.lines[0].bounds.size.height;
+    assert_eq!(layout.lines[2].bounds.size.height, 8.0);
+    assert_eq!(layout.lines[3].bounds.origin.y, layout.lines[1].bounds.origin.y + line_box_height + 8.0);
+    assert_eq!(layout.lines[4].bounds.origin.y, layout.lines[3].bounds.origin.y + line_box_height);
+
+    // The paragraph offset uses the same line box height as the line boxes, so the
+    // words stay on the baselines of their lines (also with a font height != font size)
+    let mut scaled_words = scaled_words;
+    scaled_words.font_metrics = FontMetrics { ascender: 800, descender: -200, height: 1200, .. FontMetrics::zero() };
+    let word_positions = position_words(&words, &scaled_words, &options);
+    let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+    let line_of_word = [0, 1, 3, 4];
+    for (word_position, line_idx) in word_positions.word_positions.iter().zip(line_of_word.iter()) {
+        assert_eq!(word_position.y, layout.lines[*line_idx].baseline_y);
+    }
+    let last_line = layout.lines.last().unwrap();
+    assert_eq!(word_positions.content_size.height, last_line.bounds.origin.y + last_line.bounds.size.height);
+}
+
+#[test]
+fn test_empty_line_has_regular_line_height() {
+
+    let words = split_text_into_words("a\n\nb");
+    let mut scaled_words = get_test_scaled_words(&[10.0, 10.0], 5.0);
+    scaled_words.font_metrics = FontMetrics { ascender: 800, descender: -200, height: 1200, .. FontMetrics::zero() };
+
+    let text_layout_options = ResolvedTextLayoutOptions { font_size_px: 10.0, .. Default::default() };
+    let word_positions = position_words(&words, &scaled_words, &text_layout_options);
+    let layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+
+    assert_eq!(layout.lines.len(), 3);
+    let line_box_height = layout.lines[0].bounds.size.height;
+    assert!(line_box_height >= scaled_words.font_metrics.get_height(10.0));
+
+    // The empty line doesn't collapse, "b" is two full lines below "a"
+    assert_eq!((layout.lines[1].word_start, layout.lines[1].word_end), (1, 1));
+    assert_eq!(layout.lines[1].bounds.size.height, line_box_height);
+    assert_eq!(layout.lines[1].bounds.origin.y, line_box_height);
+    assert_eq!(layout.lines[2].bounds.origin.y, 2.0 * line_box_height);
+
+    let single_break = position_words(&split_text_into_words("a\nb"), &scaled_words, &text_layout_options);
+    let line_advance = single_break.word_positions[1].y - single_break.word_positions[0].y;
+    assert_eq!(word_positions.word_positions[1].y - word_positions.word_positions[0].y, 2.0 * line_advance);
+}
+
+#[test]
+fn test_justify_inline_text_layout() {
+
+    // "aa bb cc dd" wraps after "bb" and "cc" has a hard break after it:
+    // line 0: "aa bb" (stretched), line 1: "cc" (ends the paragraph), line 2: "dd ee" (last line)
+    let words = split_text_into_words("aa bb cc\ndd ee");
+    let scaled_words = get_test_scaled_words(&[10.0, 10.0, 10.0, 10.0, 10.0], 5.0);
+    let options = ResolvedTextLayoutOptions { font_size_px: 10.0, max_horizontal_width: Some(30.0), .. Default::default() };
+    let mut word_positions = position_words(&words, &scaled_words, &options);
+    let mut layout = word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+    let natural_layout = layout.clone();
+    let natural_positions = word_positions.word_positions.clone();
+
+    let line_ranges = layout.lines.iter().map(|l| (l.word_start, l.word_end)).collect::<Vec<_>>();
+    assert_eq!(line_ranges, vec![(0, 2), (2, 3), (3, 5)]);
+
+    justify_inline_text_layout(&mut layout, &mut word_positions, &words, &scaled_words);
+
+    // First line fills the maximum width, "bb" ends exactly at the right edge
+    assert_eq!(layout.lines[0].bounds.size.width, 30.0);
+    assert_eq!(word_positions.word_positions[1].x + 10.0, 30.0);
+
+    // Last line of the paragraph and last line of the text are not stretched
+    assert_eq!(layout.lines[1], natural_layout.lines[1]);
+    assert_eq!(layout.lines[2], natural_layout.lines[2]);
+    assert_eq!(&word_positions.word_positions[2..], &natural_positions[2..]);
+}
@@ -1,25 +1,36 @@
 //! Contains functions for laying out single words (uses HarfBuzz for context-aware font shaping).
 //! Right now, words are laid out on a word-per-word basis, no inter-word font shaping is done.
 
-use std::{slice, ptr, u32, ops::Deref, os::raw::{c_char, c_uint}};
+use std::{fmt, slice, ptr, u32, cell::RefCell, ffi::CString, path::Path, ops::{Deref, Range, RangeInclusive}, os::raw::{c_char, c_int, c_uint, c_void}, collections::{BTreeMap, BTreeSet}, sync::atomic::{AtomicU32, Ordering}};
 use harfbuzz_sys::{
-    hb_blob_create, hb_blob_destroy,
+    hb_blob_create, hb_blob_destroy, hb_blob_create_from_file, hb_blob_get_length,
     hb_font_create, hb_font_destroy,
     hb_face_create, hb_face_destroy,
     hb_buffer_create, hb_buffer_destroy,
-    hb_shape, hb_font_set_scale, hb_buffer_add_utf8, hb_ot_font_set_funcs,
+    hb_shape, hb_font_set_scale, hb_buffer_add_utf8, hb_buffer_add_utf32, hb_ot_font_set_funcs,
+    hb_buffer_set_script, hb_buffer_set_language, hb_ot_tag_to_script, hb_ot_tag_to_language,
     hb_buffer_get_glyph_infos, hb_buffer_get_glyph_positions,
     hb_buffer_guess_segment_properties, hb_buffer_allocation_successful,
     hb_blob_t, hb_memory_mode_t, hb_buffer_t,
     hb_glyph_position_t, hb_glyph_info_t, hb_font_t, hb_face_t,
-    hb_feature_t, hb_tag_t,
+    hb_feature_t, hb_tag_t, hb_codepoint_t, hb_ot_math_constant_t,
+    hb_ot_math_has_data, hb_ot_math_get_constant, hb_ot_math_get_glyph_italics_correction,
+    hb_face_reference_table, hb_blob_get_data, hb_font_get_nominal_glyph,
+    hb_font_set_variations, hb_variation_t, hb_position_t, hb_font_funcs_t,
+    hb_font_create_sub_font, hb_font_get_parent, hb_font_set_funcs, hb_font_get_scale,
+    hb_font_funcs_create, hb_font_funcs_destroy, hb_font_funcs_make_immutable, hb_font_funcs_get_empty,
+    hb_font_funcs_set_glyph_h_advance_func, hb_font_get_var_coords_normalized, hb_face_get_upem,
+    hb_unicode_funcs_get_default, hb_unicode_script, hb_ot_tags_from_script_and_language,
+    hb_unicode_funcs_t, hb_buffer_set_direction, hb_script_get_horizontal_direction,
+    hb_direction_t, HB_DIRECTION_LTR, HB_DIRECTION_RTL, HB_DIRECTION_TTB, hb_font_get_glyph_h_advance,
     HB_MEMORY_MODE_READONLY,
 };
 use azul_core::{
-    display_list::GlyphInstance,
-    app_resources::{GlyphInfo, FontMetrics, GlyphPosition},
+    display_list::{GlyphInstance, GlyphIndex},
+    app_resources::{GlyphInfo, FontMetrics, GlyphPosition, ScaledWord, TextDirection},
+    ui_solver::get_visual_order,
 };
-use azul_css::{LayoutPoint, LayoutSize};
+use azul_css::{LayoutPoint, LayoutSize, ColorU};
 
 const MEMORY_MODE_READONLY: hb_memory_mode_t = HB_MEMORY_MODE_READONLY;
 pub(crate) const HB_SCALE_FACTOR: f32 = 128.0;
@@ -44,13 +55,83 @@ const KERN_TAG: hb_tag_t = create_hb_tag(('k', 'e', 'r', 'n'));
 const LIGA_TAG: hb_tag_t = create_hb_tag(('l', 'i', 'g', 'a'));
 // Contextual ligature substitution
 const CLIG_TAG: hb_tag_t = create_hb_tag(('c', 'l', 'i', 'g'));
+// Contextual alternates
+const CALT_TAG: hb_tag_t = create_hb_tag(('c', 'a', 'l', 't'));
+// Vertical alternates
+const VERT_TAG: hb_tag_t = create_hb_tag(('v', 'e', 'r', 't'));
+// Vertical alternates and rotation
+const VRT2_TAG: hb_tag_t = create_hb_tag(('v', 'r', 't', '2'));
 
 const FEATURE_KERNING_ON: hb_feature_t   = hb_feature_t { tag: KERN_TAG, value: 1, start: 0, end: u32::MAX };
 const FEATURE_LIGATURE_ON: hb_feature_t  = hb_feature_t { tag: LIGA_TAG, value: 1, start: 0, end: u32::MAX };
 const FEATURE_CLIG_ON: hb_feature_t      = hb_feature_t { tag: CLIG_TAG, value: 1, start: 0, end: u32::MAX };
 // const FEATURE_KERNING_OFF: hb_feature_t  = hb_feature_t { tag: KERN_TAG, value: 0, start: 0, end: u32::MAX };
 // const FEATURE_LIGATURE_OFF: hb_feature_t = hb_feature_t { tag: LIGA_TAG, value: 0, start: 0, end: u32::MAX };
-// const FEATURE_CLIG_OFF: hb_feature_t     = hb_feature_t { tag: CLIG_TAG, value: 0, start: 0, end: u32::MAX };
+const FEATURE_CLIG_OFF: hb_feature_t     = hb_feature_t { tag: CLIG_TAG, value: 0, start: 0, end: u32::MAX };
+const FEATURE_CALT_OFF: hb_feature_t     = hb_feature_t { tag: CALT_TAG, value: 0, start: 0, end: u32::MAX };
+const FEATURE_VERT_ON: hb_feature_t      = hb_feature_t { tag: VERT_TAG, value: 1, start: 0, end: u32::MAX };
+const FEATURE_VRT2_ON: hb_feature_t      = hb_feature_t { tag: VRT2_TAG, value: 1, start: 0, end: u32::MAX };
+
+/// Fonts that declare a larger `usMaxContext` than this are most likely broken or malicious
+/// (real fonts rarely look at more than a few dozen characters at once), so their
+/// contextual features are not applied, see `HbFont::has_excessive_max_context`
+pub const MAX_CONTEXT_LIMIT: u16 = 128;
+/// Context window for fonts that don't declare a `usMaxContext`, see `HbFont::context_window`
+pub const DEFAULT_MAX_CONTEXT: u16 = 16;
+
+// Small capitals
+const SMCP_TAG: hb_tag_t = create_hb_tag(('s', 'm', 'c', 'p'));
+
+/// OpenType features that text is shaped with, see `shape_with_options`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShapingOptions {
+    /// Standard and contextual ligatures (`liga` + `clig`), default: on
+    pub ligatures: bool,
+    /// Kerning (`kern`), default: on
+    pub kerning: bool,
+    /// Small capitals (`smcp`), default: off
+    pub small_caps: bool,
+    /// Additional features with their values (i.e. `ss01` = 1 or `salt` = 2), applied to the whole
+    /// text after the features above, so they can also override them
+    pub custom_features: Vec<(hb_tag_t, u32)>,
+}
+
+impl Default for ShapingOptions {
+    fn default() -> Self {
+        Self {
+            ligatures: true,
+            kerning: true,
+            small_caps: false,
+            custom_features: Vec::new(),
+        }
+    }
+}
+
+impl ShapingOptions {
+    fn get_hb_features(&self) -> Vec<hb_feature_t> {
+        let feature = |tag: hb_tag_t, value: u32| hb_feature_t { tag, value, start: 0, end: u32::MAX };
+        let mut features = vec![
+            feature(KERN_TAG, self.kerning as u32),
+            feature(LIGA_TAG, self.ligatures as u32),
+            feature(CLIG_TAG, self.ligatures as u32),
+        ];
+        if self.small_caps {
+            features.push(feature(SMCP_TAG, 1));
+        }
+        features.extend(self.custom_features.iter().map(|(tag, value)| feature(*tag, *value)));
+        features
+    }
+}
+
+// Access all alternates
+const AALT_TAG: hb_tag_t = create_hb_tag(('a', 'a', 'l', 't'));
+
+/// Returns a feature that substitutes the `alternate`-th (1-based) alternate glyph
+/// from the font's `aalt` feature for the character(s) in the given `cluster` of the buffer
+/// (for buffers created with `HbBuffer::from_str`, the cluster is the byte offset of the character)
+pub fn select_alternate_glyph(cluster: u32, alternate: u32) -> hb_feature_t {
+    hb_feature_t { tag: AALT_TAG, value: alternate, start: cluster, end: cluster + 1 }
+}
 
 // NOTE: kerning is a "feature" and has to be specifically turned on.
 static ACTIVE_HB_FEATURES: [hb_feature_t;3] = [
@@ -72,6 +153,207 @@ pub struct HbFont<'a> {
     hb_face_bytes: *mut hb_blob_t,
     hb_face: *mut hb_face_t,
     hb_font: *mut hb_font_t,
+    /// Parsed `trak` table, `None` if the font doesn't have one
+    tracking: Option<TrackingTable>,
+    /// Glyphs that the `GDEF` table classifies as combining marks
+    mark_glyphs: BTreeSet<u16>,
+    /// Whether combining marks should always have a zero advance, see `apply_zero_mark_advance`
+    force_zero_mark_advance: bool,
+    /// Parsed `post` table, `None` if the font doesn't have one or it doesn't store glyph names
+    glyph_names: Option<PostGlyphNames>,
+    /// Strings of the `name` table (family name, etc.), by name ID
+    names: BTreeMap<u16, String>,
+    /// Advances that HarfBuzz shapes with, see `get_advance`. Boxed, since HarfBuzz keeps a pointer to it.
+    glyph_advances: Box<GlyphAdvances<'a>>,
+}
+
+/// Horizontal advances of the glyphs of a font (from the `hmtx` table, adjusted by the `HVAR`
+/// table for an instance of a variable font), see `HbFont::get_advance`
+#[derive(Debug)]
+struct GlyphAdvances<'a> {
+    hhea: Option<&'a [u8]>,
+    hmtx: &'a [u8],
+    hvar: Option<&'a [u8]>,
+    /// Units per em that HarfBuzz scales the advances with
+    units_per_em: i64,
+    /// Normalized coordinates (F2Dot14) of the selected instance, empty for the default instance
+    coords: Vec<i32>,
+    /// Advances that were already looked up. `HbFont` isn't `Send` anyway
+    /// (because of the HarfBuzz pointers), so a `RefCell` is enough here.
+    cache: RefCell<BTreeMap<u16, Option<u16>>>,
+}
+
+impl<'a> GlyphAdvances<'a> {
+
+    fn new(font_bytes: &'a [u8], font_index: u32, units_per_em: u32) -> Self {
+        let get_table = |tag: [u8;4]| {
+            let (offset, length) = get_table_range(font_bytes, font_index, tag)?;
+            font_bytes.get(offset..(offset + length)).filter(|table| !table.is_empty())
+        };
+        Self {
+            hhea: get_table(*b"hhea"),
+            hmtx: get_table(*b"hmtx").unwrap_or_default(),
+            hvar: get_table(*b"HVAR"),
+            units_per_em: units_per_em.max(1) as i64,
+            coords: Vec::new(),
+            cache: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    fn get(&self, glyph_index: u16) -> Option<u16> {
+
+        if let Some(advance) = self.cache.borrow().get(&glyph_index) {
+            return *advance;
+        }
+
+        let advance = self.hhea.and_then(|hhea| get_metrics_advance(hhea, self.hmtx, glyph_index)).map(|advance| {
+            let delta = match self.hvar {
+                Some(hvar) if !self.coords.is_empty() => get_hvar_advance_delta(hvar, glyph_index, &self.coords).unwrap_or(0.0),
+                _ => 0.0,
+            };
+            (advance as f32 + delta).round().max(0.0).min(u16::MAX as f32) as u16
+        });
+        self.cache.borrow_mut().insert(glyph_index, advance);
+        advance
+    }
+}
+
+/// HarfBuzz callback that returns the horizontal advance of a glyph (in the scale of the `font`),
+/// so that shaping uses the cached advances of the `GlyphAdvances` (the `font_data`)
+unsafe extern "C" fn get_glyph_h_advance_hb(font: *mut hb_font_t, font_data: *mut c_void, glyph: hb_codepoint_t, _: *mut c_void) -> hb_position_t {
+
+    let glyph_advances = &*(font_data as *const GlyphAdvances);
+    let advance = glyph_advances.get(glyph as u16).unwrap_or(0) as i64;
+
+    let mut x_scale: c_int = 0;
+    let mut y_scale: c_int = 0;
+    hb_font_get_scale(font, &mut x_scale, &mut y_scale);
+
+    // Same rounding as HarfBuzz uses for scaling font units
+    let mult = ((x_scale as i64) << 16) / glyph_advances.units_per_em;
+    ((advance * mult + 32768) >> 16) as hb_position_t
+}
+
+/// Returns the adjustment (in font units) of the advance of a glyph from the `HVAR` table
+/// of a variable font, for the instance at the normalized `coords` (F2Dot14)
+fn get_hvar_advance_delta(hvar: &[u8], glyph_index: u16, coords: &[i32]) -> Option<f32> {
+
+    let item_variation_store = read_u32(hvar, 4)? as usize;
+    let advance_mapping = read_u32(hvar, 8)? as usize;
+
+    // Without a mapping, the inner index of a glyph is its glyph index
+    let (outer, inner) = if advance_mapping == 0 {
+        (0, glyph_index as usize)
+    } else {
+        get_delta_set_index(hvar.get(advance_mapping..)?, glyph_index)?
+    };
+
+    get_item_variation_delta(hvar.get(item_variation_store..)?, outer, inner, coords)
+}
+
+/// Returns the outer / inner index of the delta set of a glyph from a `DeltaSetIndexMap`
+fn get_delta_set_index(map: &[u8], glyph_index: u16) -> Option<(usize, usize)> {
+
+    let entry_format = *map.get(1)? as usize;
+    let (map_count, entries) = match map.first()? {
+        0 => (read_u16(map, 2)? as usize, 4),
+        1 => (read_u32(map, 2)? as usize, 6),
+        _ => return None,
+    };
+
+    if map_count == 0 {
+        return None;
+    }
+
+    // Glyphs after the end of the map use the last entry
+    let entry_size = ((entry_format >> 4) & 3) + 1;
+    let inner_bits = (entry_format & 0xF) + 1;
+    let entry_offset = entries + (glyph_index as usize).min(map_count - 1) * entry_size;
+    let entry = map.get(entry_offset..(entry_offset + entry_size))?.iter().fold(0, |entry, byte| (entry << 8) | *byte as usize);
+
+    Some((entry >> inner_bits, entry & ((1 << inner_bits) - 1)))
+}
+
+/// Returns the sum of the deltas of an item of an `ItemVariationStore`, each scaled by how much
+/// its region applies to the instance at the normalized `coords` (F2Dot14)
+fn get_item_variation_delta(store: &[u8], outer: usize, inner: usize, coords: &[i32]) -> Option<f32> {
+
+    if read_u16(store, 0)? != 1 || outer >= read_u16(store, 6)? as usize {
+        return None;
+    }
+
+    let region_list = store.get(read_u32(store, 2)? as usize..)?;
+    let axis_count = read_u16(region_list, 0)? as usize;
+    let region_count = read_u16(region_list, 2)? as usize;
+
+    let data = store.get(read_u32(store, 8 + outer * 4)? as usize..)?;
+    let item_count = read_u16(data, 0)? as usize;
+    let word_delta_count = read_u16(data, 2)?;
+    let region_index_count = read_u16(data, 4)? as usize;
+
+    if inner >= item_count {
+        return None;
+    }
+
+    // The first deltas of a row are 16 bit, the others 8 bit (32 / 16 bit with the LONG_WORDS flag)
+    let long_words = word_delta_count & 0x8000 != 0;
+    let word_count = (word_delta_count & 0x7FFF) as usize;
+    let (word_size, short_size) = if long_words { (4, 2) } else { (2, 1) };
+    let row_size = word_count * word_size + region_index_count.saturating_sub(word_count) * short_size;
+
+    let mut delta_offset = 6 + region_index_count * 2 + inner * row_size;
+    let mut delta = 0.0;
+
+    for region_idx in 0..region_index_count {
+
+        let region_index = read_u16(data, 6 + region_idx * 2)? as usize;
+        let value = match (region_idx < word_count, long_words) {
+            (true, true) => read_u32(data, delta_offset)? as i32,
+            (true, false) | (false, true) => read_u16(data, delta_offset)? as i16 as i32,
+            (false, false) => *data.get(delta_offset)? as i8 as i32,
+        };
+        delta_offset += if region_idx < word_count { word_size } else { short_size };
+
+        if region_index >= region_count {
+            return None;
+        }
+
+        let region = region_list.get((4 + region_index * axis_count * 6)..)?;
+        delta += value as f32 * get_region_scalar(region, axis_count, coords)?;
+    }
+
+    Some(delta)
+}
+
+/// Returns how much a region of a `VariationRegionList` applies to the instance
+/// at the normalized `coords` (F2Dot14), from 0 to 1
+fn get_region_scalar(region: &[u8], axis_count: usize, coords: &[i32]) -> Option<f32> {
+
+    let mut scalar = 1.0;
+
+    for axis_idx in 0..axis_count {
+
+        let read_f2dot14 = |offset: usize| Some(read_u16(region, axis_idx * 6 + offset)? as i16 as i32);
+        let (start, peak, end) = (read_f2dot14(0)?, read_f2dot14(2)?, read_f2dot14(4)?);
+        let coord = coords.get(axis_idx).cloned().unwrap_or(0);
+
+        // Axes that the region doesn't depend on (or invalid ranges, which are ignored as well)
+        if peak == 0 || coord == peak || start > peak || peak > end || (start < 0 && end > 0) {
+            continue;
+        }
+
+        if coord <= start || coord >= end {
+            return Some(0.0);
+        }
+
+        scalar *= if coord < peak {
+            (coord - start) as f32 / (peak - start) as f32
+        } else {
+            (end - coord) as f32 / (end - peak) as f32
+        };
+    }
+
+    Some(scalar)
 }
 
 impl<'a> HbFont<'a> {
@@ -86,262 +368,4701 @@ impl<'a> HbFont<'a> {
         let hb_face_bytes = unsafe {
             hb_blob_create(font_ptr, font_bytes.len() as u32, MEMORY_MODE_READONLY, user_data_ptr, destroy_func)
         };
+        Self::from_blob(font_bytes, hb_face_bytes, font_index)
+    }
+
+    /// Creates the font from a blob that contains the `font_bytes`, the font takes ownership of the blob
+    fn from_blob(font_bytes: &'a [u8], hb_face_bytes: *mut hb_blob_t, font_index: u32) -> Self {
+
         let hb_face = unsafe { hb_face_create(hb_face_bytes, font_index as c_uint) };
-        let hb_font = unsafe { hb_font_create(hb_face) };
-        unsafe { hb_ot_font_set_funcs(hb_font) };
 
-        Self {
+        // Only the horizontal advances are looked up by the font itself (see `get_advance`),
+        // everything else comes from the OpenType functions of HarfBuzz in the parent font
+        let hb_font = unsafe {
+            let hb_ot_font = hb_font_create(hb_face);
+            hb_ot_font_set_funcs(hb_ot_font);
+            let hb_font = hb_font_create_sub_font(hb_ot_font);
+            hb_font_destroy(hb_ot_font);
+            hb_font
+        };
+        let units_per_em = unsafe { hb_face_get_upem(hb_face) };
+
+        let mut font = Self {
             font_bytes,
             font_index,
             hb_face_bytes,
             hb_face,
             hb_font,
+            tracking: None,
+            mark_glyphs: BTreeSet::new(),
+            force_zero_mark_advance: true,
+            glyph_names: None,
+            names: BTreeMap::new(),
+            glyph_advances: Box::new(GlyphAdvances::new(font_bytes, font_index, units_per_em)),
+        };
+
+        if font.supports_horizontal() {
+            font.set_glyph_advance_funcs();
         }
-    }
-}
 
-impl<'a> Drop for HbFont<'a> {
-    fn drop(&mut self) {
-        unsafe { hb_font_destroy(self.hb_font) };
-        unsafe { hb_face_destroy(self.hb_face) };
-        // TODO: Is this safe - memory may be deleted twice?
-        unsafe { hb_blob_destroy(self.hb_face_bytes) };
+        font.tracking = font.parse_tracking_table();
+        font.mark_glyphs = font.parse_mark_glyphs().unwrap_or_default();
+        font.glyph_names = font.parse_glyph_names();
+        font.names = font.parse_names().unwrap_or_default();
+        font
     }
-}
 
-#[derive(Debug)]
-pub struct HbScaledFont<'a> {
-    pub font: &'a HbFont<'a>,
-    pub font_size_px: f32,
-}
+    /// Same as `from_bytes`, but selects an instance of a variable font by pinning the given
+    /// axes (i.e. `wght` = 700), axes that aren't given stay at their default value. Shaping
+    /// then uses the advances of the instance (`HVAR`). Fonts without an `fvar` table ignore the variations.
+    pub fn from_bytes_with_variations(font_bytes: &'a [u8], font_index: u32, variations: &[(hb_tag_t, f32)]) -> Self {
 
-impl<'a> HbScaledFont<'a> {
-    /// Create a `HbScaledFont` from a
-    pub fn from_font(font: &'a HbFont<'a>, font_size_px: f32) -> Self {
-        let px = (font_size_px * HB_SCALE_FACTOR) as i32;
-        unsafe { hb_font_set_scale(font.hb_font, px, px) };
-        Self {
-            font,
-            font_size_px,
+        let mut font = Self::from_bytes(font_bytes, font_index);
+        if variations.is_empty() {
+            return font;
         }
-    }
-}
-
-#[derive(Debug)]
-pub struct HbBuffer<'a> {
-    words: &'a str,
-    hb_buffer: *mut hb_buffer_t,
-}
 
-impl<'a> HbBuffer<'a> {
-    pub fn from_str(words: &'a str) -> Self {
-
-        let hb_buffer = unsafe { hb_buffer_create() };
-        unsafe { hb_buffer_allocation_successful(hb_buffer); };
-        let word_ptr = words.as_ptr() as *const c_char; // HB handles UTF-8
+        let hb_variations = variations.iter().map(|(tag, value)| hb_variation_t { tag: *tag, value: *value }).collect::<Vec<_>>();
+        unsafe {
+            // The parent font needs the instance as well, for the glyph outlines
+            hb_font_set_variations(hb_font_get_parent(font.hb_font), hb_variations.as_ptr(), hb_variations.len() as c_uint);
+            hb_font_set_variations(font.hb_font, hb_variations.as_ptr(), hb_variations.len() as c_uint);
+            let mut coords_len = 0;
+            let coords = hb_font_get_var_coords_normalized(font.hb_font, &mut coords_len);
+            if !coords.is_null() {
+                font.glyph_advances.coords = slice::from_raw_parts(coords, coords_len as usize).to_vec();
+            }
+        }
+        font.glyph_advances.cache.borrow_mut().clear();
 
-        let word_len = words.len() as i32;
+        // Without a `HVAR` table, the advances of an instance are only stored in the glyph
+        // outlines (`gvar`), so HarfBuzz has to look them up
+        if font.glyph_advances.hvar.is_none() && !font.glyph_advances.coords.is_empty() {
+            unsafe { hb_font_set_funcs(font.hb_font, hb_font_funcs_get_empty(), ptr::null_mut(), None) };
+        }
 
-        // NOTE: It's not possible to take a sub-string into a UTF-8 buffer!
+        font
+    }
 
+    /// Makes HarfBuzz shape with the advances of `get_advance`
+    fn set_glyph_advance_funcs(&mut self) {
         unsafe {
-            hb_buffer_add_utf8(hb_buffer, word_ptr, word_len, 0, word_len);
-            // Guess the script, language and direction from the buffer
-            hb_buffer_guess_segment_properties(hb_buffer);
+            let hb_font_funcs: *mut hb_font_funcs_t = hb_font_funcs_create();
+            hb_font_funcs_set_glyph_h_advance_func(hb_font_funcs, Some(get_glyph_h_advance_hb), ptr::null_mut(), None);
+            hb_font_funcs_make_immutable(hb_font_funcs);
+            let font_data = &*self.glyph_advances as *const GlyphAdvances as *mut c_void;
+            hb_font_set_funcs(self.hb_font, hb_font_funcs, font_data, None);
+            hb_font_funcs_destroy(hb_font_funcs);
         }
+    }
 
-        Self {
-            words,
-            hb_buffer,
-        }
+    /// Returns the variation axes of a variable font (from the `fvar` table),
+    /// empty if the font isn't a variable font
+    pub fn get_variation_axes(&self) -> Vec<VariationAxis> {
+        const FVAR_TAG: hb_tag_t = create_hb_tag(('f', 'v', 'a', 'r'));
+        self.get_table(FVAR_TAG).map(|fvar| parse_variation_axes(&fvar)).unwrap_or_default()
     }
-}
 
-impl<'a> Drop for HbBuffer<'a> {
-    fn drop(&mut self) {
-        unsafe { hb_buffer_destroy(self.hb_buffer) };
+    /// Whether combining marks should be forced to a zero advance (on by default).
+    /// Turn this off for fonts that intentionally use spacing marks.
+    pub fn set_force_zero_mark_advance(&mut self, force_zero_mark_advance: bool) {
+        self.force_zero_mark_advance = force_zero_mark_advance;
     }
-}
 
-// The glyph infos are allocated by HarfBuzz and freed
-// when the font is destroyed. This is a convenience wrapper that
-// directly dereferences the internal hb_glyph_info_t and
-// hb_glyph_position_t, to avoid extra allocations.
-#[derive(Debug)]
-pub struct CVec<T> {
-    ptr: *const T,
-    len: usize,
-}
+    /// Returns whether the `GDEF` table of the font classifies the glyph as a combining mark
+    pub fn is_mark_glyph(&self, glyph_index: u16) -> bool {
+        self.mark_glyphs.contains(&glyph_index)
+    }
 
-impl<T> Deref for CVec<T> {
-    type Target = [T];
+    /// Sets the horizontal advance of all combining marks (according to the `GDEF` table)
+    /// to zero, so that broken fonts that give marks a non-zero advance don't push the
+    /// following glyphs to the right. Does nothing if `set_force_zero_mark_advance(false)` was called.
+    pub fn apply_zero_mark_advance(&self, glyph_infos: &[GlyphInfo], glyph_positions: &mut [GlyphPosition]) {
+        if !self.force_zero_mark_advance || self.mark_glyphs.is_empty() {
+            return;
+        }
+        for (glyph_info, glyph_position) in glyph_infos.iter().zip(glyph_positions.iter_mut()) {
+            if self.is_mark_glyph(glyph_info.codepoint as u16) {
+                glyph_position.x_advance = 0;
+            }
+        }
+    }
 
-    fn deref(&self) -> &[T] {
-        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    fn parse_mark_glyphs(&self) -> Option<BTreeSet<u16>> {
+
+        const GDEF_TAG: hb_tag_t = create_hb_tag(('G', 'D', 'E', 'F'));
+        const GLYPH_CLASS_MARK: u16 = 3;
+
+        let gdef = self.get_table(GDEF_TAG)?;
+        let class_def = read_u16(&gdef, 4)? as usize;
+        if class_def == 0 {
+            return None;
+        }
+
+        let mut mark_glyphs = BTreeSet::new();
+
+        match read_u16(&gdef, class_def)? {
+            1 => {
+                let start_glyph = read_u16(&gdef, class_def + 2)?;
+                let glyph_count = read_u16(&gdef, class_def + 4)? as usize;
+                for i in 0..glyph_count {
+                    if read_u16(&gdef, class_def + 6 + i * 2)? == GLYPH_CLASS_MARK {
+                        mark_glyphs.insert(start_glyph.wrapping_add(i as u16));
+                    }
+                }
+            },
+            2 => {
+                let range_count = read_u16(&gdef, class_def + 2)? as usize;
+                for i in 0..range_count {
+                    let range = class_def + 4 + i * 6;
+                    if read_u16(&gdef, range + 4)? == GLYPH_CLASS_MARK {
+                        mark_glyphs.extend(read_u16(&gdef, range)?..=read_u16(&gdef, range + 2)?);
+                    }
+                }
+            },
+            _ => return None,
+        }
+
+        Some(mark_glyphs)
     }
-}
 
-pub type HbGlyphInfo = hb_glyph_info_t;
-pub type HbGlyphPosition = hb_glyph_position_t;
+    /// Returns the tracking (additional space between glyphs, in pixels) that the `trak` table of
+    /// the font defines for the given font size, linearly interpolated between the sizes in the table.
+    /// Sizes in the table are in points, which are treated as pixels (as at 72 DPI).
+    ///
+    /// Returns `0.0` if the font doesn't have a `trak` table.
+    pub fn tracking_adjustment(&self, size_px: f32) -> f32 {
+        match &self.tracking {
+            Some(tracking) => tracking.get_adjustment(size_px),
+            None => 0.0,
+        }
+    }
 
-/// Shaped word - memory of the glyph_infos and glyph_positions is owned by HarfBuzz,
-/// therefore the `buf` and `font` have to live as least as long as the word is in use.
-#[derive(Debug)]
-pub struct HbShapedWord<'a> {
-    pub buf: &'a HbBuffer<'a>,
-    pub scaled_font: &'a HbScaledFont<'a>,
-    pub glyph_infos: CVec<HbGlyphInfo>,
-    pub glyph_positions: CVec<HbGlyphPosition>,
-}
+    fn parse_tracking_table(&self) -> Option<TrackingTable> {
 
-pub(crate) fn shape_word_hb<'a>(
-    text: &'a HbBuffer<'a>,
-    scaled_font: &'a HbScaledFont<'a>,
-) -> HbShapedWord<'a> {
+        const TRAK_TAG: hb_tag_t = create_hb_tag(('t', 'r', 'a', 'k'));
+        const HEAD_TAG: hb_tag_t = create_hb_tag(('h', 'e', 'a', 'd'));
 
-    let features = if ACTIVE_HB_FEATURES.is_empty() {
-        ptr::null()
-    } else {
-        &ACTIVE_HB_FEATURES as *const _
-    };
+        let trak = self.get_table(TRAK_TAG)?;
+        let units_per_em = read_u16(&self.get_table(HEAD_TAG)?, 18)?;
 
-    let num_features = ACTIVE_HB_FEATURES.len() as u32;
+        // Only the horizontal tracking data is used
+        let track_data = read_u16(&trak, 6)? as usize;
+        if track_data == 0 || units_per_em == 0 {
+            return None;
+        }
 
-    unsafe { hb_shape(scaled_font.font.hb_font, text.hb_buffer, features, num_features) };
+        let num_tracks = read_u16(&trak, track_data)? as usize;
+        let num_sizes = read_u16(&trak, track_data + 2)? as usize;
+        let size_table = read_u32(&trak, track_data + 4)? as usize;
 
-    let mut glyph_count = 0;
-    let glyph_infos = unsafe { hb_buffer_get_glyph_infos(text.hb_buffer, &mut glyph_count) };
+        // Sizes are stored as 16.16 fixed point numbers
+        let sizes = (0..num_sizes)
+            .map(|size_idx| Some(read_u32(&trak, size_table + size_idx * 4)? as i32 as f32 / 65536.0))
+            .collect::<Option<Vec<_>>>()?;
 
-    let mut position_count = glyph_count;
-    let glyph_positions = unsafe { hb_buffer_get_glyph_positions(text.hb_buffer, &mut position_count) };
+        // Use the "normal" track (track value 0.0), ignore the tighter / looser tracks
+        let values_offset = (0..num_tracks).find_map(|track_idx| {
+            let entry = track_data + 8 + track_idx * 8;
+            if read_u32(&trak, entry)? == 0 { Some(read_u16(&trak, entry + 6)? as usize) } else { None }
+        })?;
 
-    // Assert that there are as many glyph infos as there are glyph positions
-    assert_eq!(glyph_count, position_count);
+        let values = (0..num_sizes)
+            .map(|size_idx| Some(read_u16(&trak, values_offset + size_idx * 2)? as i16))
+            .collect::<Option<Vec<_>>>()?;
 
-    HbShapedWord {
-        buf: text,
-        scaled_font,
-        glyph_infos: CVec {
-            ptr: glyph_infos,
-            len: glyph_count as usize,
-        },
-        glyph_positions: CVec {
-            ptr: glyph_positions,
-            len: glyph_count as usize,
-        },
+        if sizes.is_empty() {
+            return None;
+        }
+
+        Some(TrackingTable { units_per_em, sizes, values })
     }
 }
 
-pub(crate) fn get_word_visual_width_hb(glyph_positions: &[GlyphPosition]) -> f32 {
-    glyph_positions.iter().map(|pos| pos.x_advance as f32 / HB_SCALE_FACTOR).sum()
+/// Tracking values of the normal track of a `trak` table, in font units per size
+#[derive(Debug, Clone, PartialEq)]
+struct TrackingTable {
+    units_per_em: u16,
+    /// Sizes in points, in ascending order
+    sizes: Vec<f32>,
+    values: Vec<i16>,
 }
 
-pub(crate) fn get_glyph_instances_hb(
-    glyph_infos: &[GlyphInfo],
-    glyph_positions: &[GlyphPosition],
-) -> Vec<GlyphInstance> {
-
-    let mut current_cursor_x = 0.0;
-    let mut current_cursor_y = 0.0;
+impl TrackingTable {
+    fn get_adjustment(&self, size_px: f32) -> f32 {
 
-    glyph_infos.iter().zip(glyph_positions.iter()).map(|(glyph_info, glyph_pos)| {
-        let glyph_index = glyph_info.codepoint;
+        let upper_idx = self.sizes.iter().position(|size| *size >= size_px);
 
-        let x_offset = glyph_pos.x_offset as f32 / HB_SCALE_FACTOR;
-        let y_offset = glyph_pos.y_offset as f32 / HB_SCALE_FACTOR;
-        let x_advance = glyph_pos.x_advance as f32 / HB_SCALE_FACTOR;
-        let y_advance = glyph_pos.y_advance as f32 / HB_SCALE_FACTOR;
+        let tracking_font_units = match upper_idx {
+            // Sizes outside of the table use the tracking of the nearest size
+            None => *self.values.last().unwrap_or(&0) as f32,
+            Some(0) => self.values[0] as f32,
+            Some(upper_idx) => {
+                let (lower_size, upper_size) = (self.sizes[upper_idx - 1], self.sizes[upper_idx]);
+                let (lower_value, upper_value) = (self.values[upper_idx - 1] as f32, self.values[upper_idx] as f32);
+                let t = (size_px - lower_size) / (upper_size - lower_size);
+                lower_value + (upper_value - lower_value) * t
+            },
+        };
 
-        let point = LayoutPoint::new(current_cursor_x + x_offset, current_cursor_y + y_offset);
-        let size = LayoutSize::new(x_advance, y_advance);
+        tracking_font_units * size_px / self.units_per_em as f32
+    }
+}
 
-        current_cursor_x += x_advance;
-        current_cursor_y += y_advance;
+/// Axis of a variable font, i.e. `wght` from 100 to 900
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct VariationAxis {
+    pub tag: hb_tag_t,
+    pub min_value: f32,
+    pub default_value: f32,
+    pub max_value: f32,
+}
 
-        GlyphInstance {
-            index: glyph_index,
-            point,
-            size,
+impl VariationAxis {
+    /// Returns the value that the `variations` set this axis to (clamped to the range
+    /// of the axis), or the default value of the axis if it isn't set
+    pub fn resolve_value(&self, variations: &[(hb_tag_t, f32)]) -> f32 {
+        match variations.iter().rev().find(|(tag, _)| *tag == self.tag) {
+            Some((_, value)) => value.max(self.min_value).min(self.max_value),
+            None => self.default_value,
         }
-    }).collect()
+    }
 }
 
-/// Get the baseline for a font, you'll have to scale the
-/// font size then later on for your given font size
-pub fn get_font_metrics_freetype(font_bytes: &[u8], font_index: i32) -> FontMetrics {
+fn parse_variation_axes(fvar: &[u8]) -> Vec<VariationAxis> {
 
-    use std::convert::TryInto;
-    use freetype::freetype::{
-        FT_Long, FT_F26Dot6,
-        FT_Init_FreeType, FT_Done_FreeType, FT_New_Memory_Face,
-        FT_Done_Face, FT_Set_Char_Size, FT_Library, FT_Face,
-    };
+    let read_fixed = |offset: usize| Some(read_u32(fvar, offset)? as i32 as f32 / 65536.0);
 
-    const FT_ERR_OK: i32 = 0;
-    const FAKE_FONT_SIZE: FT_F26Dot6 = 1000;
+    let axes_offset = read_u16(fvar, 4).unwrap_or(0) as usize;
+    let axis_count = read_u16(fvar, 8).unwrap_or(0) as usize;
+    let axis_size = read_u16(fvar, 10).unwrap_or(0) as usize;
 
-    let mut baseline = FontMetrics {
-        font_size: FAKE_FONT_SIZE as usize,
-        x_ppem: 0,
-        y_ppem: 0,
-        x_scale: 0,
-        y_scale: 0,
-        ascender: 0,
-        descender: 0,
-        height: 0,
-        max_advance: 0,
+    (0..axis_count).map(|axis_idx| {
+        let axis = axes_offset + axis_idx * axis_size;
+        Some(VariationAxis {
+            tag: read_u32(fvar, axis)?,
+            min_value: read_fixed(axis + 4)?,
+            default_value: read_fixed(axis + 8)?,
+            max_value: read_fixed(axis + 12)?,
+        })
+    }).collect::<Option<Vec<_>>>().unwrap_or_default()
+}
+
+impl<'a> Drop for HbFont<'a> {
+    fn drop(&mut self) {
+        unsafe { hb_font_destroy(self.hb_font) };
+        unsafe { hb_face_destroy(self.hb_face) };
+        // TODO: Is this safe - memory may be deleted twice?
+        unsafe { hb_blob_destroy(self.hb_face_bytes) };
+    }
+}
+
+/// Colored layers of the glyphs of a font, parsed from the `COLR` (version 0) and `CPAL` tables
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ColorGlyphs {
+    /// Base glyph -> layers, from bottom to top, as (layer glyph, index into the palette)
+    pub layers: BTreeMap<GlyphIndex, Vec<(GlyphIndex, u16)>>,
+    /// Color palettes of the font, the first palette is the default one
+    pub palettes: Vec<Vec<ColorU>>,
+}
+
+/// Palette index of a color layer that should be drawn with the text color
+pub const PALETTE_INDEX_TEXT_COLOR: u16 = 0xFFFF;
+
+impl ColorGlyphs {
+
+    /// Returns how many palettes the font has (i.e. for light and dark themes)
+    pub fn palette_count(&self) -> usize {
+        self.palettes.len()
+    }
+
+    /// Returns the color of a layer in the given palette,
+    /// `None` if the layer should use the text color.
+    /// Out-of-range palettes fall back to the default palette (0).
+    pub fn get_layer_color(&self, palette: usize, palette_entry: u16) -> Option<ColorU> {
+        if palette_entry == PALETTE_INDEX_TEXT_COLOR {
+            return None;
+        }
+        let palette = if palette < self.palettes.len() { palette } else { 0 };
+        self.palettes.get(palette)?.get(palette_entry as usize).cloned()
+    }
+
+    /// Returns the layers of a color glyph with their colors from the given palette
+    /// (`None` = text color), or `None` if the glyph isn't a color glyph
+    pub fn get_color_glyph(&self, glyph_index: GlyphIndex, palette: usize) -> Option<Vec<(GlyphIndex, Option<ColorU>)>> {
+        let layers = self.layers.get(&glyph_index)?;
+        Some(layers.iter().map(|(layer_glyph, palette_entry)| (*layer_glyph, self.get_layer_color(palette, *palette_entry))).collect())
+    }
+
+    /// Same as `get_color_glyph`, but blends the layer colors of two palettes
+    /// (`t = 0.0` is `palette_a`, `t = 1.0` is `palette_b`), i.e. for animating a theme switch
+    pub fn get_color_glyph_blended(&self, glyph_index: GlyphIndex, palette_a: usize, palette_b: usize, t: f32) -> Option<Vec<(GlyphIndex, Option<ColorU>)>> {
+        let layers = self.layers.get(&glyph_index)?;
+        Some(layers.iter().map(|(layer_glyph, palette_entry)| {
+            let color_a = self.get_layer_color(palette_a, *palette_entry);
+            let color_b = self.get_layer_color(palette_b, *palette_entry);
+            (*layer_glyph, color_a.and_then(|a| Some(interpolate_color(a, color_b?, t))))
+        }).collect())
+    }
+}
+
+/// Linearly interpolates between two colors (including the alpha channel),
+/// `t` is clamped to `0.0..=1.0`
+pub fn interpolate_color(a: ColorU, b: ColorU, t: f32) -> ColorU {
+    let t = t.max(0.0).min(1.0);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    ColorU { r: mix(a.r, b.r), g: mix(a.g, b.g), b: mix(a.b, b.b), a: mix(a.a, b.a) }
+}
+
+impl<'a> HbFont<'a> {
+
+    /// Returns a copy of a table of the font (i.e. `COLR`) or `None` if the font doesn't have the table
+    pub fn get_table(&self, tag: hb_tag_t) -> Option<Vec<u8>> {
+        unsafe {
+            let blob = hb_face_reference_table(self.hb_face, tag);
+            let mut len = 0;
+            let data = hb_blob_get_data(blob, &mut len);
+            let table = if data.is_null() || len == 0 {
+                None
+            } else {
+                Some(slice::from_raw_parts(data as *const u8, len as usize).to_vec())
+            };
+            hb_blob_destroy(blob);
+            table
+        }
+    }
+
+    /// Same as `get_table`, but borrows the table from the font data instead of copying it
+    pub fn get_table_ref(&self, tag: hb_tag_t) -> Option<&[u8]> {
+        let (offset, length) = get_table_range(self.font_bytes, self.font_index, tag.to_be_bytes())?;
+        self.font_bytes.get(offset..(offset + length)).filter(|table| !table.is_empty())
+    }
+
+    /// Parses the color layers of the font, returns `None` if the font doesn't have a `COLR` / `CPAL` table
+    pub fn get_color_glyphs(&self) -> Option<ColorGlyphs> {
+
+        const COLR_TAG: hb_tag_t = create_hb_tag(('C', 'O', 'L', 'R'));
+        const CPAL_TAG: hb_tag_t = create_hb_tag(('C', 'P', 'A', 'L'));
+
+        let colr = self.get_table(COLR_TAG)?;
+        let cpal = self.get_table(CPAL_TAG)?;
+
+        let num_base_glyphs = read_u16(&colr, 2)? as usize;
+        let base_glyphs_offset = read_u32(&colr, 4)? as usize;
+        let layers_offset = read_u32(&colr, 8)? as usize;
+
+        let mut layers = BTreeMap::new();
+
+        for base_glyph_idx in 0..num_base_glyphs {
+            let record = base_glyphs_offset + base_glyph_idx * 6;
+            let glyph_id = read_u16(&colr, record)?;
+            let first_layer = read_u16(&colr, record + 2)? as usize;
+            let num_layers = read_u16(&colr, record + 4)? as usize;
+            let glyph_layers = (first_layer..(first_layer + num_layers)).map(|layer_idx| {
+                let layer = layers_offset + layer_idx * 4;
+                Some((read_u16(&colr, layer)? as GlyphIndex, read_u16(&colr, layer + 2)?))
+            }).collect::<Option<Vec<_>>>()?;
+            layers.insert(glyph_id as GlyphIndex, glyph_layers);
+        }
+
+        let num_palette_entries = read_u16(&cpal, 2)? as usize;
+        let num_palettes = read_u16(&cpal, 4)? as usize;
+        let color_records_offset = read_u32(&cpal, 8)? as usize;
+
+        let palettes = (0..num_palettes).map(|palette_idx| {
+            let first_color = read_u16(&cpal, 12 + palette_idx * 2)? as usize;
+            (first_color..(first_color + num_palette_entries)).map(|color_idx| {
+                // Color records are stored as BGRA
+                let bgra = cpal.get((color_records_offset + color_idx * 4)..(color_records_offset + color_idx * 4 + 4))?;
+                Some(ColorU { r: bgra[2], g: bgra[1], b: bgra[0], a: bgra[3] })
+            }).collect::<Option<Vec<_>>>()
+        }).collect::<Option<Vec<_>>>()?;
+
+        Some(ColorGlyphs { layers, palettes })
+    }
+}
+
+impl<'a> HbFont<'a> {
+
+    /// Returns the unscaled horizontal advance of a glyph (in font units) from the `hmtx` table.
+    ///
+    /// Glyphs after the last full metric record use the advance of the last record (as defined
+    /// by the OpenType spec). If the `hmtx` table is shorter than the `hhea` table claims,
+    /// the last advance that could be read is used, or `advanceWidthMax` if there is none.
+    /// Returns `None` if the font doesn't have a `hhea` table.
+    ///
+    /// For an instance of a variable font, the advance is adjusted by the `HVAR` table.
+    ///
+    /// Advances are cached, so only the first lookup of each glyph has to read the tables.
+    /// HarfBuzz looks the advances up through this function as well when shaping.
+    pub fn get_advance(&self, glyph_index: u16) -> Option<u16> {
+        self.glyph_advances.get(glyph_index)
+    }
+
+    /// Empties the cache of `get_advance`, the parsed tables are kept
+    pub fn clear_caches(&mut self) {
+        self.glyph_advances.cache.borrow_mut().clear();
+    }
+
+    /// Returns how many glyph advances are currently cached (for debugging)
+    pub fn cached_advance_count(&self) -> usize {
+        self.glyph_advances.cache.borrow().len()
+    }
+
+    /// Same as `get_advance`, but returns the vertical advance from the `vhea` / `vmtx` tables.
+    ///
+    /// Most fonts (i.e. nearly all Latin fonts) don't have vertical metrics, in that
+    /// case the advance is synthesized as one em (`units_per_em`), or `0` if the font
+    /// doesn't have a `head` table either.
+    pub fn get_vertical_advance(&self, glyph_index: u16) -> u16 {
+
+        const VHEA_TAG: hb_tag_t = create_hb_tag(('v', 'h', 'e', 'a'));
+        const VMTX_TAG: hb_tag_t = create_hb_tag(('v', 'm', 't', 'x'));
+        const HEAD_TAG: hb_tag_t = create_hb_tag(('h', 'e', 'a', 'd'));
+
+        self.get_table_ref(VHEA_TAG)
+            .and_then(|vhea| get_metrics_advance(vhea, self.get_table_ref(VMTX_TAG).unwrap_or_default(), glyph_index))
+            .or_else(|| read_u16(self.get_table_ref(HEAD_TAG)?, 18))
+            .unwrap_or(0)
+    }
+
+    /// Returns whether the font has usable horizontal metrics (a `hhea` table and a `hmtx` table
+    /// with at least one advance), i.e. whether it can be used for horizontal text at all
+    pub fn supports_horizontal(&self) -> bool {
+        const HHEA_TAG: hb_tag_t = create_hb_tag(('h', 'h', 'e', 'a'));
+        const HMTX_TAG: hb_tag_t = create_hb_tag(('h', 'm', 't', 'x'));
+        has_valid_metrics(self.get_table_ref(HHEA_TAG), self.get_table_ref(HMTX_TAG))
+    }
+
+    /// Same as `supports_horizontal`, but for the vertical metrics (`vhea` / `vmtx`). Note that
+    /// `get_vertical_advance` still synthesizes advances for fonts that return `false` here.
+    pub fn supports_vertical(&self) -> bool {
+        const VHEA_TAG: hb_tag_t = create_hb_tag(('v', 'h', 'e', 'a'));
+        const VMTX_TAG: hb_tag_t = create_hb_tag(('v', 'm', 't', 'x'));
+        has_valid_metrics(self.get_table_ref(VHEA_TAG), self.get_table_ref(VMTX_TAG))
+    }
+
+    /// Returns the `units_per_em` of the `head` table (the size of the em square in font units),
+    /// or `0` if the font doesn't have a `head` table.
+    pub fn units_per_em(&self) -> u16 {
+        const HEAD_TAG: hb_tag_t = create_hb_tag(('h', 'e', 'a', 'd'));
+        self.get_table_ref(HEAD_TAG).and_then(|head| read_u16(head, 18)).unwrap_or(0)
+    }
+
+    /// Returns the `usMaxContext` of the `OS/2` table (the maximum number of characters that any
+    /// feature of the font looks at at once), `None` if the font doesn't declare it (`OS/2` version < 2)
+    pub fn max_context(&self) -> Option<u16> {
+        const OS2_TAG: hb_tag_t = create_hb_tag(('O', 'S', '/', '2'));
+        get_max_context(self.get_table_ref(OS2_TAG)?)
+    }
+
+    /// Returns whether the font declares a `usMaxContext` above `MAX_CONTEXT_LIMIT`. Text
+    /// in such fonts is shaped without the contextual features (`clig` and `calt`).
+    pub fn has_excessive_max_context(&self) -> bool {
+        self.max_context().map(|max_context| max_context > MAX_CONTEXT_LIMIT).unwrap_or(false)
+    }
+
+    /// Returns how many characters around a piece of text the contextual features of the font
+    /// can look at: the `max_context` of the font, clamped to `MAX_CONTEXT_LIMIT`, or
+    /// `DEFAULT_MAX_CONTEXT` if the font doesn't declare it. Runs that are shaped separately
+    /// (see `shape_bidi`) get this many of the surrounding characters as context.
+    pub fn context_window(&self) -> usize {
+        self.max_context().unwrap_or(DEFAULT_MAX_CONTEXT).min(MAX_CONTEXT_LIMIT) as usize
+    }
+
+    /// Returns the kerning between two glyphs in font units (`0` if the pair isn't kerned)
+    /// without shaping any text. Only the pair adjustment lookups of the `kern` feature in the
+    /// `GPOS` table for the given OpenType `script` / `language` tags (i.e. `latn` / `ROM `)
+    /// are evaluated, fonts without a `GPOS` table (or without a `kern` feature in it) use
+    /// the legacy `kern` table.
+    pub fn kern_between(&self, left: u16, right: u16, script: hb_tag_t, language: hb_tag_t) -> i32 {
+
+        const GPOS_TAG: hb_tag_t = create_hb_tag(('G', 'P', 'O', 'S'));
+        const KERN_TAG: hb_tag_t = create_hb_tag(('k', 'e', 'r', 'n'));
+
+        let gpos = self.get_table_ref(GPOS_TAG).unwrap_or_default();
+        let kern_lookups = get_lang_sys_feature_lookups(gpos, script, language, KERN_TAG);
+
+        if kern_lookups.is_empty() {
+            return get_legacy_kerning(self.get_table_ref(KERN_TAG).unwrap_or_default(), left, right);
+        }
+
+        kern_lookups
+            .into_iter()
+            .filter_map(|lookup_idx| get_lookup_pair_adjustment(gpos, lookup_idx, left, right))
+            .map(|adjustment| adjustment as i32)
+            .sum()
+    }
+
+    /// Lists the alternates of a glyph from the `aalt` (access all alternates) feature,
+    /// in the order that `select_alternate_glyph` refers to them (alternate 1 is the first one)
+    pub fn alternates_for(&self, glyph_index: u16) -> Vec<u16> {
+        self.get_glyph_alternates(glyph_index, AALT_TAG)
+    }
+
+    /// Lists the glyphs that the single and alternate substitutions of the
+    /// `feature` in the `GSUB` table can replace the `glyph_index` with
+    pub fn get_glyph_alternates(&self, glyph_index: u16, feature: hb_tag_t) -> Vec<u16> {
+
+        const GSUB_TAG: hb_tag_t = create_hb_tag(('G', 'S', 'U', 'B'));
+
+        let gsub = match self.get_table(GSUB_TAG) {
+            Some(s) => s,
+            None => return Vec::new(),
+        };
+
+        let mut alternates = Vec::new();
+        for lookup_idx in get_feature_lookups(&gsub, feature) {
+            for alternate in get_lookup_alternates(&gsub, lookup_idx, glyph_index).unwrap_or_default() {
+                if !alternates.contains(&alternate) {
+                    alternates.push(alternate);
+                }
+            }
+        }
+        alternates
+    }
+}
+
+/// Reads the advance of a glyph from a `hmtx` / `vmtx` table (`header` is the `hhea` / `vhea`
+/// table, which have the same layout), see `HbFont::get_advance` for the fallback rules
+fn get_metrics_advance(header: &[u8], metrics: &[u8], glyph_index: u16) -> Option<u16> {
+
+    let advance_max = read_u16(header, 10)?;
+    let number_of_metrics = read_u16(header, 34)? as usize;
+    let valid_metrics = number_of_metrics.min(metrics.len() / 4);
+
+    if valid_metrics == 0 {
+        return Some(advance_max);
+    }
+
+    let metric_idx = (glyph_index as usize).min(valid_metrics - 1);
+    read_u16(metrics, metric_idx * 4)
+}
+
+// usMaxContext only exists since version 2 of the OS/2 table
+fn get_max_context(os2: &[u8]) -> Option<u16> {
+    if read_u16(os2, 0)? < 2 {
+        return None;
+    }
+    read_u16(os2, 92)
+}
+
+/// Returns whether the `hhea` / `vhea` table is complete and the `hmtx` / `vmtx`
+/// table contains at least one of the metric records that the header announces
+fn has_valid_metrics(header: Option<&[u8]>, metrics: Option<&[u8]>) -> bool {
+    match (header.and_then(|header| read_u16(header, 34)), metrics) {
+        (Some(number_of_metrics), Some(metrics)) => number_of_metrics > 0 && metrics.len() >= 4,
+        _ => false,
+    }
+}
+
+/// Returns the indices of all lookups that the `feature` refers to (in any script / language)
+fn get_feature_lookups(gsub: &[u8], feature: hb_tag_t) -> Vec<u16> {
+
+    let mut lookups = Vec::new();
+    let feature_list = match read_u16(gsub, 6) { Some(s) => s as usize, None => return lookups };
+    let feature_count = read_u16(gsub, feature_list).unwrap_or(0) as usize;
+
+    for feature_idx in 0..feature_count {
+        let record = feature_list + 2 + feature_idx * 6;
+        if read_u32(gsub, record) != Some(feature) {
+            continue;
+        }
+        let feature_table = match read_u16(gsub, record + 4) { Some(s) => feature_list + s as usize, None => continue };
+        let lookup_count = read_u16(gsub, feature_table + 2).unwrap_or(0) as usize;
+        for i in 0..lookup_count {
+            if let Some(lookup_idx) = read_u16(gsub, feature_table + 4 + i * 2) {
+                if !lookups.contains(&lookup_idx) {
+                    lookups.push(lookup_idx);
+                }
+            }
+        }
+    }
+
+    lookups
+}
+
+/// Same as `get_feature_lookups`, but only returns the lookups of the `feature` in the language system
+/// of the given OpenType `script` / `language` tags (falling back to the `DFLT` script and the
+/// default language system of the script), in the order of the language system
+fn get_lang_sys_feature_lookups(table: &[u8], script: hb_tag_t, language: hb_tag_t, feature: hb_tag_t) -> Vec<u16> {
+
+    const DFLT_TAG: hb_tag_t = create_hb_tag(('D', 'F', 'L', 'T'));
+
+    let find_record = |list: usize, count_offset: usize, tag: hb_tag_t| -> Option<usize> {
+        let count = read_u16(table, count_offset)? as usize;
+        (0..count)
+            .map(|i| count_offset + 2 + i * 6)
+            .find(|record| read_u32(table, *record) == Some(tag))
+            .and_then(|record| Some(list + read_u16(table, record + 4)? as usize))
     };
 
-    let buf_len: FT_Long = match font_bytes.len().try_into().ok() {
-        Some(s) => s,
-        None => return baseline, // font too large for freetype
+    let lang_sys = (|| {
+        let script_list = read_u16(table, 4)? as usize;
+        let script_table = find_record(script_list, script_list, script).or_else(|| find_record(script_list, script_list, DFLT_TAG))?;
+        find_record(script_table, script_table + 2, language).or_else(|| {
+            match read_u16(table, script_table)? {
+                0 => None,
+                default_lang_sys => Some(script_table + default_lang_sys as usize),
+            }
+        })
+    })();
+
+    let mut lookups = Vec::new();
+    let (lang_sys, feature_list) = match (lang_sys, read_u16(table, 6)) {
+        (Some(l), Some(f)) => (l, f as usize),
+        _ => return lookups,
     };
 
-    unsafe {
-        // Initialize library
-        let mut ft_library: FT_Library = ptr::null_mut();
-        let error = FT_Init_FreeType(&mut ft_library);
-        if error != FT_ERR_OK {
-            return baseline;
+    let feature_index_count = read_u16(table, lang_sys + 4).unwrap_or(0) as usize;
+    for i in 0..feature_index_count {
+        let record = match read_u16(table, lang_sys + 6 + i * 2) { Some(s) => feature_list + 2 + s as usize * 6, None => continue };
+        if read_u32(table, record) != Some(feature) {
+            continue;
         }
+        let feature_table = match read_u16(table, record + 4) { Some(s) => feature_list + s as usize, None => continue };
+        let lookup_count = read_u16(table, feature_table + 2).unwrap_or(0) as usize;
+        for i in 0..lookup_count {
+            if let Some(lookup_idx) = read_u16(table, feature_table + 4 + i * 2) {
+                if !lookups.contains(&lookup_idx) {
+                    lookups.push(lookup_idx);
+                }
+            }
+        }
+    }
 
-        // Load font
-        let mut ft_face: FT_Face = ptr::null_mut();
-        let error = FT_New_Memory_Face(ft_library, font_bytes.as_ptr(), buf_len, font_index as FT_Long, &mut ft_face);
-        if error != FT_ERR_OK {
-            FT_Done_FreeType(ft_library);
-            return baseline;
+    lookups
+}
+
+/// Returns the glyphs that a single (type 1) or alternate (type 3) substitution
+/// lookup replaces the `glyph_index` with, other lookup types are ignored
+fn get_lookup_alternates(gsub: &[u8], lookup_idx: u16, glyph_index: u16) -> Option<Vec<u16>> {
+
+    let lookup_list = read_u16(gsub, 8)? as usize;
+    let lookup = lookup_list + read_u16(gsub, lookup_list + 2 + lookup_idx as usize * 2)? as usize;
+    let lookup_type = read_u16(gsub, lookup)?;
+    let subtable_count = read_u16(gsub, lookup + 4)? as usize;
+
+    let mut alternates = Vec::new();
+
+    for subtable_idx in 0..subtable_count {
+
+        let mut subtable = lookup + read_u16(gsub, lookup + 6 + subtable_idx * 2)? as usize;
+        let mut subtable_type = lookup_type;
+
+        // Extension substitution: each subtable points to a subtable of the actual lookup type
+        if lookup_type == 7 {
+            subtable_type = read_u16(gsub, subtable + 2)?;
+            subtable += read_u32(gsub, subtable + 4)? as usize;
         }
 
-        const DPI: u32 = 72;
+        let coverage_idx = match get_coverage_index(gsub, subtable + read_u16(gsub, subtable + 2)? as usize, glyph_index) {
+            Some(s) => s,
+            None => continue,
+        };
 
-        // Set font size to fake 1000px
-        let error = FT_Set_Char_Size(ft_face, 0, FAKE_FONT_SIZE, DPI, DPI);
-        if error != FT_ERR_OK {
-            FT_Done_Face(ft_face);
-            FT_Done_FreeType(ft_library);
-            return baseline;
+        match (subtable_type, read_u16(gsub, subtable)?) {
+            (1, 1) => {
+                let delta = read_u16(gsub, subtable + 4)?;
+                alternates.push(glyph_index.wrapping_add(delta));
+            },
+            (1, 2) => {
+                alternates.push(read_u16(gsub, subtable + 6 + coverage_idx * 2)?);
+            },
+            (3, 1) => {
+                let alternate_set = subtable + read_u16(gsub, subtable + 6 + coverage_idx * 2)? as usize;
+                let glyph_count = read_u16(gsub, alternate_set)? as usize;
+                for i in 0..glyph_count {
+                    alternates.push(read_u16(gsub, alternate_set + 2 + i * 2)?);
+                }
+            },
+            _ => { },
         }
+    }
 
-        let ft_face_ref = &*ft_face;
-        let ft_size_ref = &*ft_face_ref.size;
-        let metrics = ft_size_ref.metrics;
+    Some(alternates)
+}
 
-        baseline = FontMetrics {
-            font_size: FAKE_FONT_SIZE as usize,
-            x_ppem: metrics.x_ppem,
-            y_ppem: metrics.y_ppem,
-            x_scale: metrics.x_scale as i64,
-            y_scale: metrics.y_scale as i64,
-            ascender: metrics.ascender as i64,
-            descender: metrics.descender as i64,
-            height: metrics.height as i64,
-            max_advance: metrics.max_advance as i64,
+/// Returns the x advance adjustment (in font units) of the first glyph of the pair
+/// from a pair adjustment (type 2) lookup, `None` if no subtable of the lookup covers the pair
+fn get_lookup_pair_adjustment(gpos: &[u8], lookup_idx: u16, left: u16, right: u16) -> Option<i16> {
+
+    const VALUE_FORMAT_X_ADVANCE: u16 = 0x0004;
+
+    let lookup_list = read_u16(gpos, 8)? as usize;
+    let lookup = lookup_list + read_u16(gpos, lookup_list + 2 + lookup_idx as usize * 2)? as usize;
+    let lookup_type = read_u16(gpos, lookup)?;
+    let subtable_count = read_u16(gpos, lookup + 4)? as usize;
+
+    for subtable_idx in 0..subtable_count {
+
+        let mut subtable = lookup + read_u16(gpos, lookup + 6 + subtable_idx * 2)? as usize;
+        let mut subtable_type = lookup_type;
+
+        // Extension positioning: each subtable points to a subtable of the actual lookup type
+        if lookup_type == 9 {
+            subtable_type = read_u16(gpos, subtable + 2)?;
+            subtable += read_u32(gpos, subtable + 4)? as usize;
+        }
+
+        if subtable_type != 2 {
+            return None;
+        }
+
+        let coverage_idx = match get_coverage_index(gpos, subtable + read_u16(gpos, subtable + 2)? as usize, left) {
+            Some(s) => s,
+            None => continue,
         };
 
-        FT_Done_Face(ft_face);
-        FT_Done_FreeType(ft_library);
+        let value_format_1 = read_u16(gpos, subtable + 4)?;
+        let value_format_2 = read_u16(gpos, subtable + 6)?;
+        let value_record_1_size = value_format_1.count_ones() as usize * 2;
+        let value_record_2_size = value_format_2.count_ones() as usize * 2;
+
+        // Offset of the x advance inside of the first value record
+        let read_x_advance = |value_record: usize| -> Option<i16> {
+            if value_format_1 & VALUE_FORMAT_X_ADVANCE == 0 {
+                return Some(0);
+            }
+            Some(read_u16(gpos, value_record + (value_format_1 & 0x0003).count_ones() as usize * 2)? as i16)
+        };
+
+        match read_u16(gpos, subtable)? {
+            1 => {
+                let pair_set = subtable + read_u16(gpos, subtable + 10 + coverage_idx * 2)? as usize;
+                let pair_value_count = read_u16(gpos, pair_set)? as usize;
+                let pair_value_record_size = 2 + value_record_1_size + value_record_2_size;
+                let pair_value_record = (0..pair_value_count)
+                    .map(|i| pair_set + 2 + i * pair_value_record_size)
+                    .find(|record| read_u16(gpos, *record) == Some(right));
+                match pair_value_record {
+                    Some(record) => return read_x_advance(record + 2),
+                    // Glyph pair isn't in this subtable, try the next one
+                    None => continue,
+                }
+            },
+            2 => {
+                let class_1 = get_glyph_class(gpos, subtable + read_u16(gpos, subtable + 8)? as usize, left)? as usize;
+                let class_2 = get_glyph_class(gpos, subtable + read_u16(gpos, subtable + 10)? as usize, right)? as usize;
+                let class_2_count = read_u16(gpos, subtable + 14)? as usize;
+                let class_2_record_size = value_record_1_size + value_record_2_size;
+                return read_x_advance(subtable + 16 + (class_1 * class_2_count + class_2) * class_2_record_size);
+            },
+            _ => continue,
+        }
     }
 
-    baseline
+    None
+}
+
+/// Returns the class of the `glyph_index` in an OpenType class definition table (`0` if not listed)
+fn get_glyph_class(table: &[u8], class_def: usize, glyph_index: u16) -> Option<u16> {
+    match read_u16(table, class_def)? {
+        1 => {
+            let start_glyph = read_u16(table, class_def + 2)?;
+            let glyph_count = read_u16(table, class_def + 4)?;
+            if glyph_index >= start_glyph && glyph_index - start_glyph < glyph_count {
+                read_u16(table, class_def + 6 + (glyph_index - start_glyph) as usize * 2)
+            } else {
+                Some(0)
+            }
+        },
+        2 => {
+            let range_count = read_u16(table, class_def + 2)? as usize;
+            Some((0..range_count).find_map(|i| {
+                let range = class_def + 4 + i * 6;
+                if glyph_index >= read_u16(table, range)? && glyph_index <= read_u16(table, range + 2)? {
+                    read_u16(table, range + 4)
+                } else {
+                    None
+                }
+            }).unwrap_or(0))
+        },
+        _ => None,
+    }
+}
+
+/// Returns the kerning value (in font units) of the glyph pair from the legacy `kern` table,
+/// only horizontal format 0 subtables are used
+fn get_legacy_kerning(kern: &[u8], left: u16, right: u16) -> i32 {
+
+    const COVERAGE_HORIZONTAL: u16 = 0x0001;
+    const COVERAGE_MINIMUM_CROSS_STREAM_OVERRIDE: u16 = 0x000E;
+
+    let mut kerning = 0;
+    let table_count = read_u16(kern, 2).unwrap_or(0) as usize;
+    let mut subtable = 4;
+
+    for _ in 0..table_count {
+        let (length, coverage) = match (read_u16(kern, subtable + 2), read_u16(kern, subtable + 4)) {
+            (Some(l), Some(c)) => (l as usize, c),
+            _ => break,
+        };
+
+        let is_format_0 = coverage >> 8 == 0;
+        if is_format_0 && coverage & COVERAGE_HORIZONTAL != 0 && coverage & COVERAGE_MINIMUM_CROSS_STREAM_OVERRIDE == 0 {
+            let pair_count = read_u16(kern, subtable + 6).unwrap_or(0) as usize;
+            let value = (0..pair_count).map(|i| subtable + 14 + i * 6).find_map(|pair| {
+                if read_u16(kern, pair)? == left && read_u16(kern, pair + 2)? == right {
+                    Some(read_u16(kern, pair + 4)? as i16)
+                } else {
+                    None
+                }
+            });
+            kerning += value.unwrap_or(0) as i32;
+        }
+
+        subtable += length.max(6);
+    }
+
+    kerning
+}
+
+/// Returns the index of the `glyph_index` in an OpenType coverage table
+fn get_coverage_index(table: &[u8], coverage: usize, glyph_index: u16) -> Option<usize> {
+    match read_u16(table, coverage)? {
+        1 => {
+            let glyph_count = read_u16(table, coverage + 2)? as usize;
+            (0..glyph_count).position(|i| read_u16(table, coverage + 4 + i * 2) == Some(glyph_index))
+        },
+        2 => {
+            let range_count = read_u16(table, coverage + 2)? as usize;
+            (0..range_count).find_map(|i| {
+                let range = coverage + 4 + i * 6;
+                let start = read_u16(table, range)?;
+                let end = read_u16(table, range + 2)?;
+                let start_coverage_idx = read_u16(table, range + 4)?;
+                if glyph_index >= start && glyph_index <= end {
+                    Some((start_coverage_idx + (glyph_index - start)) as usize)
+                } else {
+                    None
+                }
+            })
+        },
+        _ => None,
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    let b = bytes.get(offset..(offset + 2))?;
+    Some(u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..(offset + 4))?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// The replacement character (U+FFFD) that invalid codepoints are mapped to
+pub const REPLACEMENT_CHARACTER: u32 = 0xFFFD;
+
+/// Returns whether the codepoint is one of the 66 Unicode noncharacters
+/// (U+FDD0..=U+FDEF and the last two codepoints of every plane, U+xFFFE and U+xFFFF)
+pub fn is_noncharacter(codepoint: u32) -> bool {
+    (codepoint >= 0xFDD0 && codepoint <= 0xFDEF) ||
+    (codepoint <= 0x10FFFF && codepoint & 0xFFFE == 0xFFFE)
+}
+
+/// Maps surrogates, codepoints above U+10FFFF and noncharacters to U+FFFD,
+/// any other codepoint is returned unchanged.
+pub fn sanitize_codepoint(codepoint: u32) -> u32 {
+    if std::char::from_u32(codepoint).is_none() || is_noncharacter(codepoint) {
+        REPLACEMENT_CHARACTER
+    } else {
+        codepoint
+    }
+}
+
+impl<'a> HbFont<'a> {
+
+    /// Returns the glyph that the font maps the (raw, i.e. from FFI) `codepoint` to,
+    /// or `0` (notdef) if the font has no glyph for it. Invalid codepoints and noncharacters
+    /// are never passed to the cmap table, they are looked up as U+FFFD instead
+    /// (which usually results in notdef, if the font has no replacement glyph).
+    pub fn lookup_glyph_index(&self, codepoint: u32) -> u32 {
+        let mut glyph_index = 0;
+        let found = unsafe { hb_font_get_nominal_glyph(self.hb_font, sanitize_codepoint(codepoint), &mut glyph_index) };
+        if found != 0 { glyph_index } else { 0 }
+    }
+}
+
+impl<'a> HbFont<'a> {
+
+    /// Returns the position (in font units) of the baseline `baseline_tag` (i.e. `hang` for the
+    /// hanging baseline of Devanagari, `ideo` for CJK) for the OpenType `script`, from the horizontal
+    /// axis of the `BASE` table. Returns `None` if the font has no `BASE` table or no value for
+    /// the script / baseline.
+    pub fn script_baseline(&self, script: hb_tag_t, baseline_tag: hb_tag_t) -> Option<i16> {
+
+        const BASE_TAG: hb_tag_t = create_hb_tag(('B', 'A', 'S', 'E'));
+
+        let base = self.get_table(BASE_TAG)?;
+        let axis = read_u16(&base, 4)? as usize;
+        if axis == 0 {
+            return None;
+        }
+
+        let tag_list = axis + read_u16(&base, axis)? as usize;
+        let script_list = axis + read_u16(&base, axis + 2)? as usize;
+
+        let tag_count = read_u16(&base, tag_list)? as usize;
+        let baseline_idx = (0..tag_count).position(|i| read_u32(&base, tag_list + 2 + i * 4) == Some(baseline_tag))?;
+
+        let script_count = read_u16(&base, script_list)? as usize;
+        let script_record = (0..script_count)
+            .map(|i| script_list + 2 + i * 6)
+            .find(|record| read_u32(&base, *record) == Some(script))?;
+        let base_script = script_list + read_u16(&base, script_record + 4)? as usize;
+
+        let base_values_offset = read_u16(&base, base_script)? as usize;
+        if base_values_offset == 0 {
+            return None;
+        }
+        let base_values = base_script + base_values_offset;
+        let coord_count = read_u16(&base, base_values + 2)? as usize;
+        if baseline_idx >= coord_count {
+            return None;
+        }
+
+        // All BaseCoord formats start with the format + coordinate, the device / contour
+        // point adjustments of formats 2 and 3 are ignored
+        let base_coord = base_values + read_u16(&base, base_values + 4 + baseline_idx * 2)? as usize;
+        Some(read_u16(&base, base_coord + 2)? as i16)
+    }
+}
+
+/// Segment of a glyph contour, all coordinates are in font units
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum OutlineSegment {
+    /// Start of the contour
+    MoveTo(LayoutPoint),
+    LineTo(LayoutPoint),
+    /// Quadratic bezier curve (control point, end point)
+    QuadTo(LayoutPoint, LayoutPoint),
+    /// Cubic bezier curve (control point 1, control point 2, end point)
+    CubicTo(LayoutPoint, LayoutPoint, LayoutPoint),
+}
+
+/// Vector outline of a glyph, i.e. for exporting text as SVG / PDF paths
+#[derive(Debug, Default, Clone, PartialEq, PartialOrd)]
+pub struct GlyphOutline {
+    /// Closed contours of the glyph, each one starts with a `MoveTo`
+    pub contours: Vec<Vec<OutlineSegment>>,
+}
+
+/// One layer of a color glyph, see `HbFont::get_color_glyph`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorLayer {
+    /// Glyph that is drawn for this layer
+    pub glyph_index: GlyphIndex,
+    /// Outline of the layer glyph (in font units), `None` if the layer glyph has no outline
+    pub outline: Option<GlyphOutline>,
+    /// Color of the layer from the selected palette, `None` if the layer is drawn with the text color
+    pub color: Option<ColorU>,
+}
+
+/// Composite glyphs that reference each other deeper than this are treated as broken
+const MAX_COMPOSITE_GLYPH_DEPTH: usize = 8;
+
+impl<'a> HbFont<'a> {
+
+    /// Returns the layers of a color glyph (`COLR` version 0) from bottom to top, with their
+    /// outlines and colors from the given `CPAL` palette (out-of-range palettes fall back to
+    /// the default palette). Returns `None` if the glyph isn't a color glyph, so that
+    /// the caller can draw the regular outline instead.
+    pub fn get_color_glyph(&self, glyph_index: u16, palette: usize) -> Option<Vec<ColorLayer>> {
+        let layers = self.get_color_glyphs()?.get_color_glyph(glyph_index as GlyphIndex, palette)?;
+        Some(layers.into_iter().map(|(layer_glyph, color)| ColorLayer {
+            glyph_index: layer_glyph,
+            outline: self.get_glyph_outline(layer_glyph as u16),
+            color,
+        }).collect())
+    }
+
+    /// Returns the outline of a glyph (in font units) from the `glyf` / `loca` tables.
+    /// Components of composite glyphs are resolved with their transforms applied.
+    ///
+    /// Returns `None` for glyphs without an outline (i.e. the space glyph) and for
+    /// fonts without a `glyf` table (i.e. fonts with CFF outlines).
+    pub fn get_glyph_outline(&self, glyph_index: u16) -> Option<GlyphOutline> {
+
+        const HEAD_TAG: hb_tag_t = create_hb_tag(('h', 'e', 'a', 'd'));
+        const LOCA_TAG: hb_tag_t = create_hb_tag(('l', 'o', 'c', 'a'));
+        const GLYF_TAG: hb_tag_t = create_hb_tag(('g', 'l', 'y', 'f'));
+
+        let long_offsets = read_u16(self.get_table_ref(HEAD_TAG)?, 50)? != 0;
+        let loca = self.get_table_ref(LOCA_TAG)?;
+        let glyf = self.get_table_ref(GLYF_TAG)?;
+
+        let mut contours = Vec::new();
+        append_glyph_contours(glyf, loca, long_offsets, glyph_index, &[1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0, &mut contours)?;
+
+        if contours.is_empty() {
+            None
+        } else {
+            Some(GlyphOutline { contours })
+        }
+    }
+}
+
+/// Fraction of the varying white space next to a glyph that the glyph is moved into the margin
+const OPTICAL_MARGIN_FACTOR: f32 = 0.5;
+/// Number of heights (between the baseline and the cap height) at which the outline is sampled
+const OPTICAL_MARGIN_SAMPLES: usize = 16;
+/// Number of line segments that a curve is split into when sampling the outline
+const CURVE_FLATTENING_STEPS: usize = 8;
+
+impl<'a> HbFont<'a> {
+
+    /// Returns how many pixels the first glyph of a line (`at_line_start`) or the last glyph
+    /// of a line should be moved into the margin, so that the margin looks straight
+    /// (optical margin alignment) even if the line starts with a glyph like `T`, `A` or `"`.
+    ///
+    /// The outline of the glyph is sampled at several heights between the baseline and the cap
+    /// height: glyphs whose ink touches the edge at every height (i.e. `H`) aren't moved, the
+    /// more white space there is at the other heights, the further the glyph is moved. Negative
+    /// values move the glyph to the left (line start), positive values to the right (line end).
+    /// Returns `0.0` for empty words and glyphs without an outline.
+    pub fn optical_margin_offset(&self, scaled_word: &ScaledWord, at_line_start: bool, font_size_px: f32) -> f32 {
+
+        const HEAD_TAG: hb_tag_t = create_hb_tag(('h', 'e', 'a', 'd'));
+        const OS2_TAG: hb_tag_t = create_hb_tag(('O', 'S', '/', '2'));
+
+        let glyph_info = if at_line_start { scaled_word.glyph_infos.first() } else { scaled_word.glyph_infos.last() };
+        let glyph_index = match glyph_info {
+            Some(s) => s.codepoint as u16,
+            None => return 0.0,
+        };
+
+        let (outline, advance, units_per_em) = match (
+            self.get_glyph_outline(glyph_index),
+            self.get_advance(glyph_index),
+            self.get_table_ref(HEAD_TAG).and_then(|head| read_u16(head, 18)),
+        ) {
+            (Some(o), Some(a), Some(u)) if u != 0 => (o, a as f32, u as f32),
+            _ => return 0.0,
+        };
+
+        // sCapHeight only exists since version 2 of the OS/2 table
+        let cap_height = self.get_table_ref(OS2_TAG)
+            .filter(|os2| read_u16(os2, 0).unwrap_or(0) >= 2)
+            .and_then(|os2| read_u16(os2, 88))
+            .map(|cap_height| cap_height as i16 as f32)
+            .filter(|cap_height| *cap_height > 0.0)
+            .unwrap_or(units_per_em * 0.7);
+
+        let lines = flatten_outline(&outline);
+
+        // White space between the edge of the glyph and the ink at each sampled height
+        let gaps = (0..OPTICAL_MARGIN_SAMPLES).map(|sample_idx| {
+            let y = (sample_idx as f32 + 0.5) / OPTICAL_MARGIN_SAMPLES as f32 * cap_height;
+            let crossings = lines.iter().filter_map(|(a, b)| {
+                if (a.y <= y && y < b.y) || (b.y <= y && y < a.y) {
+                    Some(a.x + (y - a.y) / (b.y - a.y) * (b.x - a.x))
+                } else {
+                    None
+                }
+            });
+            let ink_edge = if at_line_start {
+                crossings.fold(None, |min: Option<f32>, x| Some(min.map_or(x, |m| m.min(x))))
+            } else {
+                crossings.fold(None, |max: Option<f32>, x| Some(max.map_or(x, |m| m.max(x)))).map(|x| advance - x)
+            };
+            ink_edge.unwrap_or(advance).max(0.0)
+        }).collect::<Vec<_>>();
+
+        let min_gap = gaps.iter().cloned().fold(f32::MAX, f32::min);
+        let average_gap = gaps.iter().sum::<f32>() / gaps.len() as f32;
+        let offset_px = (average_gap - min_gap) * OPTICAL_MARGIN_FACTOR / units_per_em * font_size_px;
+
+        if at_line_start { -offset_px } else { offset_px }
+    }
+}
+
+/// Approximates the contours of the outline with straight lines
+fn flatten_outline(outline: &GlyphOutline) -> Vec<(LayoutPoint, LayoutPoint)> {
+
+    let lerp = |a: LayoutPoint, b: LayoutPoint, t: f32| LayoutPoint::new(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t);
+    let mut lines = Vec::new();
+
+    for contour in &outline.contours {
+        let mut contour_start = LayoutPoint::zero();
+        let mut current = LayoutPoint::zero();
+        for segment in contour {
+            match *segment {
+                OutlineSegment::MoveTo(p) => {
+                    contour_start = p;
+                    current = p;
+                },
+                OutlineSegment::LineTo(p) => {
+                    lines.push((current, p));
+                    current = p;
+                },
+                OutlineSegment::QuadTo(c, p) => {
+                    let start = current;
+                    for step in 1..=CURVE_FLATTENING_STEPS {
+                        let t = step as f32 / CURVE_FLATTENING_STEPS as f32;
+                        let next = lerp(lerp(start, c, t), lerp(c, p, t), t);
+                        lines.push((current, next));
+                        current = next;
+                    }
+                },
+                OutlineSegment::CubicTo(c1, c2, p) => {
+                    let start = current;
+                    for step in 1..=CURVE_FLATTENING_STEPS {
+                        let t = step as f32 / CURVE_FLATTENING_STEPS as f32;
+                        let (a, b, c) = (lerp(start, c1, t), lerp(c1, c2, t), lerp(c2, p, t));
+                        let next = lerp(lerp(a, b, t), lerp(b, c, t), t);
+                        lines.push((current, next));
+                        current = next;
+                    }
+                },
+            }
+        }
+        // Contours are closed
+        lines.push((current, contour_start));
+    }
+
+    lines
+}
+
+/// Appends the contours of the glyph (transformed by the 2x3 matrix `transform`) to `contours`
+fn append_glyph_contours(
+    glyf: &[u8],
+    loca: &[u8],
+    long_offsets: bool,
+    glyph_index: u16,
+    transform: &[f32;6],
+    depth: usize,
+    contours: &mut Vec<Vec<OutlineSegment>>,
+) -> Option<()> {
+
+    if depth > MAX_COMPOSITE_GLYPH_DEPTH {
+        return None;
+    }
+
+    let (glyph_start, glyph_end) = if long_offsets {
+        (read_u32(loca, glyph_index as usize * 4)? as usize, read_u32(loca, glyph_index as usize * 4 + 4)? as usize)
+    } else {
+        (read_u16(loca, glyph_index as usize * 2)? as usize * 2, read_u16(loca, glyph_index as usize * 2 + 2)? as usize * 2)
+    };
+
+    // Empty glyph (i.e. space)
+    if glyph_end <= glyph_start {
+        return Some(());
+    }
+
+    let glyph = glyf.get(glyph_start..glyph_end)?;
+    let number_of_contours = read_u16(glyph, 0)? as i16;
+
+    if number_of_contours >= 0 {
+        let points = get_simple_glyph_points(glyph, number_of_contours as usize)?;
+        contours.extend(points.iter().map(|contour_points| {
+            let transformed = contour_points.iter().map(|(p, on_curve)| (transform_point(transform, *p), *on_curve)).collect::<Vec<_>>();
+            get_contour_segments(&transformed)
+        }).filter(|contour| !contour.is_empty()));
+        return Some(());
+    }
+
+    // Composite glyph
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    let read_f2dot14 = |offset: usize| Some(read_u16(glyph, offset)? as i16 as f32 / 16384.0);
+
+    let mut offset = 10;
+    loop {
+        let flags = read_u16(glyph, offset)?;
+        let component_glyph = read_u16(glyph, offset + 2)?;
+        offset += 4;
+
+        let (arg_1, arg_2) = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            offset += 4;
+            (read_u16(glyph, offset - 4)? as i16 as f32, read_u16(glyph, offset - 2)? as i16 as f32)
+        } else {
+            offset += 2;
+            (*glyph.get(offset - 2)? as i8 as f32, *glyph.get(offset - 1)? as i8 as f32)
+        };
+
+        // Aligning components by point numbers is not supported, the component is placed at the origin
+        let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 { (arg_1, arg_2) } else { (0.0, 0.0) };
+
+        let (a, b, c, d) = if flags & WE_HAVE_A_SCALE != 0 {
+            offset += 2;
+            let scale = read_f2dot14(offset - 2)?;
+            (scale, 0.0, 0.0, scale)
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            offset += 4;
+            (read_f2dot14(offset - 4)?, 0.0, 0.0, read_f2dot14(offset - 2)?)
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            offset += 8;
+            (read_f2dot14(offset - 8)?, read_f2dot14(offset - 6)?, read_f2dot14(offset - 4)?, read_f2dot14(offset - 2)?)
+        } else {
+            (1.0, 0.0, 0.0, 1.0)
+        };
+
+        // component transform, then the transform of the parent glyph
+        let component_transform = [
+            transform[0] * a + transform[2] * b,
+            transform[1] * a + transform[3] * b,
+            transform[0] * c + transform[2] * d,
+            transform[1] * c + transform[3] * d,
+            transform[0] * dx + transform[2] * dy + transform[4],
+            transform[1] * dx + transform[3] * dy + transform[5],
+        ];
+
+        append_glyph_contours(glyf, loca, long_offsets, component_glyph, &component_transform, depth + 1, contours)?;
+
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+
+    Some(())
+}
+
+/// Decodes the points of a simple glyph, as (point, is on curve) per contour
+fn get_simple_glyph_points(glyph: &[u8], number_of_contours: usize) -> Option<Vec<Vec<(LayoutPoint, bool)>>> {
+
+    const ON_CURVE_POINT: u8 = 0x01;
+    const X_SHORT_VECTOR: u8 = 0x02;
+    const Y_SHORT_VECTOR: u8 = 0x04;
+    const REPEAT_FLAG: u8 = 0x08;
+    const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+
+    let end_points = (0..number_of_contours).map(|i| read_u16(glyph, 10 + i * 2)).collect::<Option<Vec<_>>>()?;
+    let number_of_points = end_points.last().map(|last| *last as usize + 1).unwrap_or(0);
+    let instruction_length = read_u16(glyph, 10 + number_of_contours * 2)? as usize;
+    let mut offset = 12 + number_of_contours * 2 + instruction_length;
+
+    let mut flags = Vec::with_capacity(number_of_points);
+    while flags.len() < number_of_points {
+        let flag = *glyph.get(offset)?;
+        offset += 1;
+        flags.push(flag);
+        if flag & REPEAT_FLAG != 0 {
+            let repeat_count = *glyph.get(offset)?;
+            offset += 1;
+            flags.extend((0..repeat_count).map(|_| flag));
+        }
+    }
+    flags.truncate(number_of_points);
+
+    let mut read_coordinates = |short_flag: u8, same_or_positive_flag: u8| -> Option<Vec<f32>> {
+        let mut value = 0_i32;
+        flags.iter().map(|flag| {
+            if flag & short_flag != 0 {
+                let delta = *glyph.get(offset)? as i32;
+                offset += 1;
+                value += if flag & same_or_positive_flag != 0 { delta } else { -delta };
+            } else if flag & same_or_positive_flag == 0 {
+                value += read_u16(glyph, offset)? as i16 as i32;
+                offset += 2;
+            }
+            Some(value as f32)
+        }).collect()
+    };
+
+    let x_coordinates = read_coordinates(X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE)?;
+    let y_coordinates = read_coordinates(Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE)?;
+
+    let mut contour_start = 0;
+    end_points.iter().map(|end_point| {
+        let contour_end = (*end_point as usize + 1).min(number_of_points);
+        let contour = (contour_start..contour_end).map(|i| {
+            (LayoutPoint::new(x_coordinates[i], y_coordinates[i]), flags[i] & ON_CURVE_POINT != 0)
+        }).collect();
+        contour_start = contour_end;
+        Some(contour)
+    }).collect()
+}
+
+fn transform_point(transform: &[f32;6], p: LayoutPoint) -> LayoutPoint {
+    LayoutPoint::new(
+        transform[0] * p.x + transform[2] * p.y + transform[4],
+        transform[1] * p.x + transform[3] * p.y + transform[5],
+    )
+}
+
+/// Converts the TrueType points of a contour into segments: two consecutive
+/// off-curve points have an implied on-curve point in the middle between them
+fn get_contour_segments(points: &[(LayoutPoint, bool)]) -> Vec<OutlineSegment> {
+
+    let midpoint = |a: LayoutPoint, b: LayoutPoint| LayoutPoint::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first.0, last.0),
+        _ => return Vec::new(),
+    };
+
+    // Start at the first on-curve point (or between the first and last point, if all points are off-curve)
+    let (start, remaining_points) = match points.iter().position(|(_, on_curve)| *on_curve) {
+        Some(start_idx) => (points[start_idx].0, points[(start_idx + 1)..].iter().chain(points[..start_idx].iter()).cloned().collect::<Vec<_>>()),
+        None => (midpoint(first, last), points.to_vec()),
+    };
+
+    let mut segments = vec![OutlineSegment::MoveTo(start)];
+    let mut control_point = None;
+
+    for (point, on_curve) in remaining_points.into_iter().chain(std::iter::once((start, true))) {
+        match (on_curve, control_point) {
+            (true, None) => segments.push(OutlineSegment::LineTo(point)),
+            (true, Some(control)) => {
+                segments.push(OutlineSegment::QuadTo(control, point));
+                control_point = None;
+            },
+            (false, None) => control_point = Some(point),
+            (false, Some(control)) => {
+                segments.push(OutlineSegment::QuadTo(control, midpoint(control, point)));
+                control_point = Some(point);
+            },
+        }
+    }
+
+    segments
+}
+
+/// Glyph names of the standard Macintosh character set, in the order of the `post` table format 1.0
+const STANDARD_MAC_GLYPH_NAMES: [&str;258] = [
+    ".notdef", ".null", "nonmarkingreturn", "space", "exclam", "quotedbl", "numbersign", "dollar", "percent",
+    "ampersand", "quotesingle", "parenleft", "parenright", "asterisk", "plus", "comma", "hyphen", "period",
+    "slash", "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "colon",
+    "semicolon", "less", "equal", "greater", "question", "at", "A", "B", "C", "D", "E", "F", "G", "H", "I",
+    "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z", "bracketleft",
+    "backslash", "bracketright", "asciicircum", "underscore", "grave", "a", "b", "c", "d", "e", "f", "g", "h",
+    "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z", "braceleft",
+    "bar", "braceright", "asciitilde", "Adieresis", "Aring", "Ccedilla", "Eacute", "Ntilde", "Odieresis",
+    "Udieresis", "aacute", "agrave", "acircumflex", "adieresis", "atilde", "aring", "ccedilla", "eacute",
+    "egrave", "ecircumflex", "edieresis", "iacute", "igrave", "icircumflex", "idieresis", "ntilde", "oacute",
+    "ograve", "ocircumflex", "odieresis", "otilde", "uacute", "ugrave", "ucircumflex", "udieresis", "dagger",
+    "degree", "cent", "sterling", "section", "bullet", "paragraph", "germandbls", "registered", "copyright",
+    "trademark", "acute", "dieresis", "notequal", "AE", "Oslash", "infinity", "plusminus", "lessequal",
+    "greaterequal", "yen", "mu", "partialdiff", "summation", "product", "pi", "integral", "ordfeminine",
+    "ordmasculine", "Omega", "ae", "oslash", "questiondown", "exclamdown", "logicalnot", "radical", "florin",
+    "approxequal", "Delta", "guillemotleft", "guillemotright", "ellipsis", "nonbreakingspace", "Agrave",
+    "Atilde", "Otilde", "OE", "oe", "endash", "emdash", "quotedblleft", "quotedblright", "quoteleft",
+    "quoteright", "divide", "lozenge", "ydieresis", "Ydieresis", "fraction", "currency", "guilsinglleft",
+    "guilsinglright", "fi", "fl", "daggerdbl", "periodcentered", "quotesinglbase", "quotedblbase",
+    "perthousand", "Acircumflex", "Ecircumflex", "Aacute", "Edieresis", "Egrave", "Iacute", "Icircumflex",
+    "Idieresis", "Igrave", "Oacute", "Ocircumflex", "apple", "Ograve", "Uacute", "Ucircumflex", "Ugrave",
+    "dotlessi", "circumflex", "tilde", "macron", "breve", "dotaccent", "ring", "cedilla", "hungarumlaut",
+    "ogonek", "caron", "Lslash", "lslash", "Scaron", "scaron", "Zcaron", "zcaron", "brokenbar", "Eth", "eth",
+    "Yacute", "yacute", "Thorn", "thorn", "minus", "multiply", "onesuperior", "twosuperior", "threesuperior",
+    "onehalf", "onequarter", "threequarters", "franc", "Gbreve", "gbreve", "Idotaccent", "Scedilla",
+    "scedilla", "Cacute", "cacute", "Ccaron", "ccaron", "dcroat"
+];
+
+/// Glyph names as stored in a `post` table
+#[derive(Debug, Clone, PartialEq)]
+enum PostGlyphNames {
+    /// Format 1.0: glyph N has the N-th standard Macintosh glyph name
+    Standard,
+    /// Format 2.0: per glyph index into the standard names (< 258) or the custom `names` (>= 258)
+    Indexed { name_indices: Vec<u16>, names: Vec<String> },
+}
+
+impl<'a> HbFont<'a> {
+
+    /// Returns the PostScript name of the glyph from the `post` table (i.e. `"f_i"` for a ligature),
+    /// useful for debugging shaping output.
+    ///
+    /// Returns `None` if the font doesn't have glyph names (i.e. `post` table format 3.0).
+    pub fn get_glyph_name(&self, glyph_index: u16) -> Option<String> {
+        match self.glyph_names.as_ref()? {
+            PostGlyphNames::Standard => STANDARD_MAC_GLYPH_NAMES.get(glyph_index as usize).map(|name| name.to_string()),
+            PostGlyphNames::Indexed { name_indices, names } => {
+                let name_idx = *name_indices.get(glyph_index as usize)? as usize;
+                if name_idx < STANDARD_MAC_GLYPH_NAMES.len() {
+                    Some(STANDARD_MAC_GLYPH_NAMES[name_idx].to_string())
+                } else {
+                    names.get(name_idx - STANDARD_MAC_GLYPH_NAMES.len()).cloned()
+                }
+            },
+        }
+    }
+
+    fn parse_glyph_names(&self) -> Option<PostGlyphNames> {
+
+        const POST_TAG: hb_tag_t = create_hb_tag(('p', 'o', 's', 't'));
+
+        let post = self.get_table(POST_TAG)?;
+
+        match read_u32(&post, 0)? {
+            0x00010000 => Some(PostGlyphNames::Standard),
+            0x00020000 => {
+                let num_glyphs = read_u16(&post, 32)? as usize;
+                let name_indices = (0..num_glyphs)
+                    .map(|glyph_idx| read_u16(&post, 34 + glyph_idx * 2))
+                    .collect::<Option<Vec<_>>>()?;
+
+                // Custom names are stored as Pascal strings (length byte + bytes)
+                let mut names = Vec::new();
+                let mut offset = 34 + num_glyphs * 2;
+                while let Some(len) = post.get(offset) {
+                    let name = post.get((offset + 1)..(offset + 1 + *len as usize))?;
+                    names.push(String::from_utf8_lossy(name).into_owned());
+                    offset += 1 + *len as usize;
+                }
+
+                Some(PostGlyphNames::Indexed { name_indices, names })
+            },
+            _ => None,
+        }
+    }
+}
+
+// Name IDs of the `name` table
+const NAME_ID_FAMILY: u16 = 1;
+const NAME_ID_SUBFAMILY: u16 = 2;
+
+// Characters 0x80 - 0xFF of the Mac OS Roman encoding
+const MAC_ROMAN_HIGH_CHARS: &str = "\
+    ÄÅÇÉÑÖÜáàâäãåçéèêëíìîïñóòôöõúùûü†°¢£§•¶ß®©™´¨≠ÆØ∞±≤≥¥µ∂∑∏π∫ªºΩæø\
+    ¿¡¬√ƒ≈∆«»…\u{A0}ÀÃÕŒœ–—“”‘’÷◊ÿŸ⁄€‹›ﬁﬂ‡·‚„‰ÂÊÁËÈÍÎÏÌÓÔ\u{F8FF}ÒÚÛÙıˆ˜¯˘˙˚¸˝˛ˇ";
+
+fn decode_mac_roman(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| match *b {
+        0..=0x7F => *b as char,
+        _ => MAC_ROMAN_HIGH_CHARS.chars().nth(*b as usize - 0x80).unwrap_or('\u{FFFD}'),
+    }).collect()
+}
+
+impl<'a> HbFont<'a> {
+
+    /// Returns a string of the `name` table by its name ID (i.e. `1` = family name,
+    /// `4` = full name), `None` if the font doesn't have the name or it can't be decoded.
+    ///
+    /// Windows / Unicode records (preferably US English) are used over Macintosh records.
+    pub fn get_name(&self, name_id: u16) -> Option<String> {
+        self.names.get(&name_id).cloned()
+    }
+
+    /// Returns the font family name (i.e. `"Arial"`)
+    pub fn family_name(&self) -> Option<String> {
+        self.get_name(NAME_ID_FAMILY)
+    }
+
+    /// Returns the font subfamily name (i.e. `"Light"` or `"Bold Italic"`)
+    pub fn subfamily_name(&self) -> Option<String> {
+        self.get_name(NAME_ID_SUBFAMILY)
+    }
+
+    fn parse_names(&self) -> Option<BTreeMap<u16, String>> {
+
+        const NAME_TAG: hb_tag_t = create_hb_tag(('n', 'a', 'm', 'e'));
+
+        let name = self.get_table_ref(NAME_TAG)?;
+        let count = read_u16(name, 2)? as usize;
+        let string_offset = read_u16(name, 4)? as usize;
+
+        // name ID => (priority, string), a lower priority value wins
+        let mut names = BTreeMap::<u16, (u8, String)>::new();
+
+        for record_idx in 0..count {
+
+            let record = 6 + record_idx * 12;
+            let platform_id = read_u16(name, record)?;
+            let encoding_id = read_u16(name, record + 2)?;
+            let language_id = read_u16(name, record + 4)?;
+            let name_id = read_u16(name, record + 6)?;
+            let length = read_u16(name, record + 8)? as usize;
+            let offset = string_offset + read_u16(name, record + 10)? as usize;
+
+            let priority = match (platform_id, encoding_id, language_id) {
+                (3, 1, 0x0409) | (3, 10, 0x0409) => 0,
+                (3, 1, _) | (3, 10, _) | (0, _, _) => 1,
+                (1, 0, 0) => 2,
+                (1, 0, _) => 3,
+                _ => continue,
+            };
+
+            if names.get(&name_id).map(|(p, _)| *p <= priority).unwrap_or(false) {
+                continue;
+            }
+
+            let bytes = match name.get(offset..(offset + length)) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            let string = if platform_id == 1 {
+                decode_mac_roman(bytes)
+            } else {
+                let utf16 = bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect::<Vec<_>>();
+                String::from_utf16_lossy(&utf16)
+            };
+
+            names.insert(name_id, (priority, string));
+        }
+
+        Some(names.into_iter().map(|(name_id, (_, string))| (name_id, string)).collect())
+    }
+}
+
+/// Rasterization recommendations of the font author for a font size (from the `gasp` table)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GaspBehavior {
+    /// Use the hinting instructions of the font (grid-fitting)
+    pub gridfit: bool,
+    /// Use grayscale anti-aliasing
+    pub grayscale: bool,
+    /// Use smoothing along multiple axes with ClearType-style rendering
+    pub symmetric_smoothing: bool,
+    /// Use grid-fitting with ClearType symmetric smoothing
+    pub symmetric_gridfit: bool,
+}
+
+impl Default for GaspBehavior {
+    /// Behaviour for fonts without a `gasp` table: hinted and anti-aliased at all sizes
+    fn default() -> Self {
+        Self {
+            gridfit: true,
+            grayscale: true,
+            symmetric_smoothing: false,
+            symmetric_gridfit: false,
+        }
+    }
+}
+
+impl<'a> HbFont<'a> {
+
+    /// Returns how glyphs should be rasterized at the given size (in pixels per em), according
+    /// to the `gasp` table of the font. Returns `GaspBehavior::default()` if the font has
+    /// no (valid) `gasp` table or the size is larger than the last range of the table.
+    pub fn gasp_behavior(&self, ppem: u16) -> GaspBehavior {
+
+        const GASP_TAG: hb_tag_t = create_hb_tag(('g', 'a', 's', 'p'));
+
+        const GASP_GRIDFIT: u16 = 0x0001;
+        const GASP_DOGRAY: u16 = 0x0002;
+        const GASP_SYMMETRIC_GRIDFIT: u16 = 0x0004;
+        const GASP_SYMMETRIC_SMOOTHING: u16 = 0x0008;
+
+        let gasp = match self.get_table_ref(GASP_TAG) {
+            Some(s) => s,
+            None => return GaspBehavior::default(),
+        };
+
+        // The symmetric flags only exist since version 1 of the table
+        let version = read_u16(gasp, 0).unwrap_or(0);
+        let num_ranges = read_u16(gasp, 2).unwrap_or(0) as usize;
+
+        // Ranges are sorted by their (inclusive) upper limit
+        let flags = (0..num_ranges)
+            .filter_map(|range_idx| Some((read_u16(gasp, 4 + range_idx * 4)?, read_u16(gasp, 6 + range_idx * 4)?)))
+            .find(|(range_max_ppem, _)| ppem <= *range_max_ppem)
+            .map(|(_, flags)| flags);
+
+        match flags {
+            Some(flags) => GaspBehavior {
+                gridfit: flags & GASP_GRIDFIT != 0,
+                grayscale: flags & GASP_DOGRAY != 0,
+                symmetric_smoothing: version >= 1 && flags & GASP_SYMMETRIC_SMOOTHING != 0,
+                symmetric_gridfit: version >= 1 && flags & GASP_SYMMETRIC_GRIDFIT != 0,
+            },
+            None => GaspBehavior::default(),
+        }
+    }
+}
+
+/// Returns whether the character is a variation selector
+/// (VS1 - VS16, VS17 - VS256 or a Mongolian free variation selector)
+pub fn is_variation_selector(c: char) -> bool {
+    match c as u32 {
+        0xFE00..=0xFE0F | 0xE0100..=0xE01EF | 0x180B..=0x180D | 0x180F => true,
+        _ => false,
+    }
+}
+
+/// Variation sequence in a text that the font has no glyph for, so that
+/// the base character is displayed in its default form
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnsupportedVariationSelector {
+    /// Index of the variation selector in the text
+    pub index: usize,
+    pub base: char,
+    pub selector: char,
+}
+
+fn read_u24(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..(offset + 3))?;
+    Some(u32::from_be_bytes([0, b[0], b[1], b[2]]))
+}
+
+impl<'a> HbFont<'a> {
+
+    /// Returns whether the font supports the variation sequence `base` + `selector`,
+    /// i.e. whether the format 14 subtable of the `cmap` maps it to the default or to
+    /// a variant glyph of the `base`.
+    pub fn supports_variation_sequence(&self, base: char, selector: char) -> bool {
+
+        const CMAP_TAG: hb_tag_t = create_hb_tag(('c', 'm', 'a', 'p'));
+
+        let cmap = match self.get_table_ref(CMAP_TAG) {
+            Some(s) => s,
+            None => return false,
+        };
+
+        // Format 14 subtables are only allowed for the Unicode platform, encoding 5
+        let num_encodings = read_u16(cmap, 2).unwrap_or(0) as usize;
+        let subtable = (0..num_encodings)
+            .filter_map(|encoding_idx| {
+                let record = 4 + encoding_idx * 8;
+                Some((read_u16(cmap, record)?, read_u16(cmap, record + 2)?, read_u32(cmap, record + 4)? as usize))
+            })
+            .find(|(platform_id, encoding_id, _)| *platform_id == 0 && *encoding_id == 5)
+            .and_then(|(_, _, offset)| cmap.get(offset..))
+            .filter(|subtable| read_u16(subtable, 0) == Some(14));
+
+        let subtable = match subtable {
+            Some(s) => s,
+            None => return false,
+        };
+
+        let num_selectors = read_u32(subtable, 6).unwrap_or(0) as usize;
+        let selector_record = (0..num_selectors)
+            .map(|selector_idx| 10 + selector_idx * 11)
+            .find(|record| read_u24(subtable, *record) == Some(selector as u32));
+
+        let selector_record = match selector_record {
+            Some(s) => s,
+            None => return false,
+        };
+
+        // Default UVS table: ranges of base characters that use their default glyph
+        let default_uvs = read_u32(subtable, selector_record + 3).unwrap_or(0) as usize;
+        if default_uvs != 0 {
+            let num_ranges = read_u32(subtable, default_uvs).unwrap_or(0) as usize;
+            let in_range = (0..num_ranges).any(|range_idx| {
+                let range = default_uvs + 4 + range_idx * 4;
+                match (read_u24(subtable, range), subtable.get(range + 3)) {
+                    (Some(start), Some(additional_count)) => (start..=start + *additional_count as u32).contains(&(base as u32)),
+                    _ => false,
+                }
+            });
+            if in_range {
+                return true;
+            }
+        }
+
+        // Non-default UVS table: base characters that are mapped to a variant glyph
+        let non_default_uvs = read_u32(subtable, selector_record + 7).unwrap_or(0) as usize;
+        if non_default_uvs != 0 {
+            let num_mappings = read_u32(subtable, non_default_uvs).unwrap_or(0) as usize;
+            return (0..num_mappings).any(|mapping_idx| read_u24(subtable, non_default_uvs + 4 + mapping_idx * 5) == Some(base as u32));
+        }
+
+        false
+    }
+
+    /// Returns the variation selectors in the `text` that the font can't apply to the
+    /// preceding character. HarfBuzz hides these selectors while shaping and renders the
+    /// base character with its default glyph, so the requested variant is silently lost.
+    pub fn find_unsupported_variation_selectors(&self, text: &[char]) -> Vec<UnsupportedVariationSelector> {
+        text.iter().enumerate().skip(1)
+            .filter(|(_, selector)| is_variation_selector(**selector))
+            .map(|(index, selector)| UnsupportedVariationSelector { index, base: text[index - 1], selector: *selector })
+            .filter(|vs| !is_variation_selector(vs.base) && !self.supports_variation_sequence(vs.base, vs.selector))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct HbScaledFont<'a> {
+    pub font: &'a HbFont<'a>,
+    pub font_size_px: f32,
+}
+
+impl<'a> HbScaledFont<'a> {
+    /// Create a `HbScaledFont` from a
+    pub fn from_font(font: &'a HbFont<'a>, font_size_px: f32) -> Self {
+        let px = (font_size_px * HB_SCALE_FACTOR) as i32;
+        unsafe { hb_font_set_scale(font.hb_font, px, px) };
+        Self {
+            font,
+            font_size_px,
+        }
+    }
+}
+
+/// Subset of the constants of the OpenType `MATH` table that a formula layout needs.
+///
+/// All values are in pixels (scaled to the font size of the `HbScaledFont`),
+/// except for the `*_percent_*` values, which are percentages.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct MathConstants {
+    pub script_percent_scale_down: i32,
+    pub script_script_percent_scale_down: i32,
+    pub axis_height: f32,
+    pub accent_base_height: f32,
+    pub subscript_shift_down: f32,
+    pub superscript_shift_up: f32,
+    pub upper_limit_gap_min: f32,
+    pub lower_limit_gap_min: f32,
+    pub fraction_numerator_shift_up: f32,
+    pub fraction_denominator_shift_down: f32,
+    pub fraction_rule_thickness: f32,
+    pub radical_vertical_gap: f32,
+    pub radical_rule_thickness: f32,
+}
+
+impl<'a> HbScaledFont<'a> {
+
+    /// Returns whether the font has a `MATH` table
+    pub fn has_math_data(&self) -> bool {
+        unsafe { hb_ot_math_has_data(self.font.hb_face) != 0 }
+    }
+
+    /// Reads the constants from the `MATH` table, returns `None` if the font isn't a math font
+    pub fn math_constants(&self) -> Option<MathConstants> {
+
+        use harfbuzz_sys::{
+            HB_OT_MATH_CONSTANT_SCRIPT_PERCENT_SCALE_DOWN, HB_OT_MATH_CONSTANT_SCRIPT_SCRIPT_PERCENT_SCALE_DOWN,
+            HB_OT_MATH_CONSTANT_AXIS_HEIGHT, HB_OT_MATH_CONSTANT_ACCENT_BASE_HEIGHT,
+            HB_OT_MATH_CONSTANT_SUBSCRIPT_SHIFT_DOWN, HB_OT_MATH_CONSTANT_SUPERSCRIPT_SHIFT_UP,
+            HB_OT_MATH_CONSTANT_UPPER_LIMIT_GAP_MIN, HB_OT_MATH_CONSTANT_LOWER_LIMIT_GAP_MIN,
+            HB_OT_MATH_CONSTANT_FRACTION_NUMERATOR_SHIFT_UP, HB_OT_MATH_CONSTANT_FRACTION_DENOMINATOR_SHIFT_DOWN,
+            HB_OT_MATH_CONSTANT_FRACTION_RULE_THICKNESS, HB_OT_MATH_CONSTANT_RADICAL_VERTICAL_GAP,
+            HB_OT_MATH_CONSTANT_RADICAL_RULE_THICKNESS,
+        };
+
+        if !self.has_math_data() {
+            return None;
+        }
+
+        let get_px = |constant: hb_ot_math_constant_t| -> f32 {
+            unsafe { hb_ot_math_get_constant(self.font.hb_font, constant) as f32 / HB_SCALE_FACTOR }
+        };
+
+        let get_percent = |constant: hb_ot_math_constant_t| -> i32 {
+            unsafe { hb_ot_math_get_constant(self.font.hb_font, constant) }
+        };
+
+        Some(MathConstants {
+            script_percent_scale_down: get_percent(HB_OT_MATH_CONSTANT_SCRIPT_PERCENT_SCALE_DOWN),
+            script_script_percent_scale_down: get_percent(HB_OT_MATH_CONSTANT_SCRIPT_SCRIPT_PERCENT_SCALE_DOWN),
+            axis_height: get_px(HB_OT_MATH_CONSTANT_AXIS_HEIGHT),
+            accent_base_height: get_px(HB_OT_MATH_CONSTANT_ACCENT_BASE_HEIGHT),
+            subscript_shift_down: get_px(HB_OT_MATH_CONSTANT_SUBSCRIPT_SHIFT_DOWN),
+            superscript_shift_up: get_px(HB_OT_MATH_CONSTANT_SUPERSCRIPT_SHIFT_UP),
+            upper_limit_gap_min: get_px(HB_OT_MATH_CONSTANT_UPPER_LIMIT_GAP_MIN),
+            lower_limit_gap_min: get_px(HB_OT_MATH_CONSTANT_LOWER_LIMIT_GAP_MIN),
+            fraction_numerator_shift_up: get_px(HB_OT_MATH_CONSTANT_FRACTION_NUMERATOR_SHIFT_UP),
+            fraction_denominator_shift_down: get_px(HB_OT_MATH_CONSTANT_FRACTION_DENOMINATOR_SHIFT_DOWN),
+            fraction_rule_thickness: get_px(HB_OT_MATH_CONSTANT_FRACTION_RULE_THICKNESS),
+            radical_vertical_gap: get_px(HB_OT_MATH_CONSTANT_RADICAL_VERTICAL_GAP),
+            radical_rule_thickness: get_px(HB_OT_MATH_CONSTANT_RADICAL_RULE_THICKNESS),
+        })
+    }
+
+    /// Returns the italic correction (in pixels) of a glyph from the `MATH` table,
+    /// returns `None` if the font isn't a math font. Glyphs without an italic
+    /// correction return `Some(0.0)`.
+    pub fn math_italic_correction(&self, glyph_index: u32) -> Option<f32> {
+        if !self.has_math_data() {
+            return None;
+        }
+        let correction = unsafe { hb_ot_math_get_glyph_italics_correction(self.font.hb_font, glyph_index as hb_codepoint_t) };
+        Some(correction as f32 / HB_SCALE_FACTOR)
+    }
+}
+
+#[derive(Debug)]
+pub struct HbBuffer<'a> {
+    words: &'a str,
+    hb_buffer: *mut hb_buffer_t,
+}
+
+/// Script and language (OpenType tags, i.e. `create_hb_tag(('l', 'a', 't', 'n'))` and
+/// `create_hb_tag(('D', 'E', 'U', ' '))`) that all text is shaped with if the caller doesn't
+/// specify them, see `set_shape_defaults`. Values that are `None` are guessed from the text.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ShapeDefaults {
+    pub script: Option<hb_tag_t>,
+    pub lang: Option<hb_tag_t>,
+}
+
+// 0 = not set
+static DEFAULT_SCRIPT: AtomicU32 = AtomicU32::new(0);
+static DEFAULT_LANGUAGE: AtomicU32 = AtomicU32::new(0);
+
+/// Sets the process-wide script / language for shaping text without an explicit script or
+/// language (i.e. `HbBuffer::from_str`), for apps that know the language of their content.
+/// Note that a default script is used for all text, even if the text is in a different script.
+pub fn set_shape_defaults(defaults: ShapeDefaults) {
+    DEFAULT_SCRIPT.store(defaults.script.unwrap_or(0), Ordering::SeqCst);
+    DEFAULT_LANGUAGE.store(defaults.lang.unwrap_or(0), Ordering::SeqCst);
+}
+
+/// Returns the defaults set by `set_shape_defaults`
+pub fn get_shape_defaults() -> ShapeDefaults {
+    let get = |value: u32| if value == 0 { None } else { Some(value) };
+    ShapeDefaults {
+        script: get(DEFAULT_SCRIPT.load(Ordering::SeqCst)),
+        lang: get(DEFAULT_LANGUAGE.load(Ordering::SeqCst)),
+    }
+}
+
+/// Sets the script / language from the `ShapeDefaults` on the buffer, so that
+/// `hb_buffer_guess_segment_properties` only guesses the ones that aren't set
+unsafe fn apply_shape_defaults(hb_buffer: *mut hb_buffer_t, defaults: ShapeDefaults) {
+    if let Some(script) = defaults.script {
+        hb_buffer_set_script(hb_buffer, hb_ot_tag_to_script(script));
+    }
+    if let Some(lang) = defaults.lang {
+        hb_buffer_set_language(hb_buffer, hb_ot_tag_to_language(lang));
+    }
+}
+
+impl<'a> HbBuffer<'a> {
+    pub fn from_str(words: &'a str) -> Self {
+        Self::from_str_with_defaults(words, get_shape_defaults())
+    }
+
+    /// Same as `from_str`, but uses the given script / language instead
+    /// of the process-wide defaults from `set_shape_defaults`
+    pub fn from_str_with_defaults(words: &'a str, defaults: ShapeDefaults) -> Self {
+
+        let hb_buffer = unsafe { hb_buffer_create() };
+        unsafe { hb_buffer_allocation_successful(hb_buffer); };
+        let word_ptr = words.as_ptr() as *const c_char; // HB handles UTF-8
+
+        let word_len = words.len() as i32;
+
+        // NOTE: It's not possible to take a sub-string into a UTF-8 buffer!
+
+        unsafe {
+            hb_buffer_add_utf8(hb_buffer, word_ptr, word_len, 0, word_len);
+            replace_noncharacters(hb_buffer);
+            apply_shape_defaults(hb_buffer, defaults);
+            // Guess the script, language and direction from the buffer
+            hb_buffer_guess_segment_properties(hb_buffer);
+        }
+
+        Self {
+            words,
+            hb_buffer,
+        }
+    }
+
+    /// Creates a buffer that only shapes the `byte_range` of the `text` in the given direction
+    /// (the rest of the text is the context of the range). The clusters of the shaped glyphs are
+    /// byte offsets into the entire `text`. The script and language are guessed from the text.
+    pub fn from_str_range(text: &'a str, byte_range: Range<usize>, direction: TextDirection) -> Self {
+
+        let hb_buffer = unsafe { hb_buffer_create() };
+        unsafe { hb_buffer_allocation_successful(hb_buffer); };
+
+        let text_ptr = text.as_ptr() as *const c_char; // HB handles UTF-8
+        let hb_direction = match direction {
+            TextDirection::LeftToRight => HB_DIRECTION_LTR,
+            TextDirection::RightToLeft => HB_DIRECTION_RTL,
+        };
+
+        unsafe {
+            hb_buffer_add_utf8(hb_buffer, text_ptr, text.len() as i32, byte_range.start as c_uint, byte_range.len() as i32);
+            replace_noncharacters(hb_buffer);
+            hb_buffer_set_direction(hb_buffer, hb_direction);
+            apply_shape_defaults(hb_buffer, get_shape_defaults());
+            hb_buffer_guess_segment_properties(hb_buffer);
+        }
+
+        Self {
+            words: text,
+            hb_buffer,
+        }
+    }
+}
+
+impl HbBuffer<'static> {
+    /// Creates a buffer from a run of characters, but instead of guessing the script
+    /// and language from the text, uses the given OpenType script and language tags
+    /// (i.e. `create_hb_tag(('l', 'a', 't', 'n'))` and `create_hb_tag(('T', 'R', 'K', ' '))`).
+    ///
+    /// The direction is still guessed from the script.
+    pub fn from_chars_with_script_and_language(chars: &[char], script: hb_tag_t, language: hb_tag_t) -> Self {
+
+        let hb_buffer = unsafe { hb_buffer_create() };
+        unsafe { hb_buffer_allocation_successful(hb_buffer); };
+
+        // HarfBuzz copies the text, so the codepoints don't need to outlive the buffer
+        let codepoints = chars.iter().map(|c| *c as u32).collect::<Vec<u32>>();
+        let codepoints_len = codepoints.len() as i32;
+
+        unsafe {
+            hb_buffer_add_utf32(hb_buffer, codepoints.as_ptr(), codepoints_len, 0, codepoints_len);
+            replace_noncharacters(hb_buffer);
+            hb_buffer_set_script(hb_buffer, hb_ot_tag_to_script(script));
+            hb_buffer_set_language(hb_buffer, hb_ot_tag_to_language(language));
+            hb_buffer_guess_segment_properties(hb_buffer);
+        }
+
+        Self {
+            words: "",
+            hb_buffer,
+        }
+    }
+}
+
+/// Replaces all noncharacters in the (not yet shaped) buffer with U+FFFD, without changing
+/// the clusters, so that they are rendered like invalid input instead of being looked up in the font
+unsafe fn replace_noncharacters(hb_buffer: *mut hb_buffer_t) {
+    let mut glyph_count = 0;
+    let glyph_infos = hb_buffer_get_glyph_infos(hb_buffer, &mut glyph_count);
+    if glyph_infos.is_null() {
+        return;
+    }
+    for glyph_info in slice::from_raw_parts_mut(glyph_infos, glyph_count as usize) {
+        glyph_info.codepoint = sanitize_codepoint(glyph_info.codepoint);
+    }
+}
+
+impl HbBuffer<'static> {
+    /// Creates a buffer from a run of characters that is shaped in the given
+    /// direction (`HB_DIRECTION_LTR` or `HB_DIRECTION_RTL`), the script and
+    /// language are guessed from the text.
+    pub fn from_chars_with_direction(chars: &[char], direction: hb_direction_t) -> Self {
+        Self::from_chars_in_context(chars, 0..chars.len(), direction)
+    }
+
+    /// Same as `from_chars_with_direction`, but only the characters in the `run` are shaped,
+    /// the rest of the `chars` are the pre- and post-context of the run (i.e. the neighbouring
+    /// runs, so that contextual features at the edges of the run see the surrounding text).
+    /// The clusters of the shaped glyphs are indices into the `chars`, not into the `run`.
+    pub fn from_chars_in_context(chars: &[char], run: Range<usize>, direction: hb_direction_t) -> Self {
+
+        let hb_buffer = unsafe { hb_buffer_create() };
+        unsafe { hb_buffer_allocation_successful(hb_buffer); };
+
+        // HarfBuzz copies the text, so the codepoints don't need to outlive the buffer
+        let codepoints = chars.iter().map(|c| *c as u32).collect::<Vec<u32>>();
+        let codepoints_len = codepoints.len() as i32;
+        let run_len = run.end.saturating_sub(run.start) as i32;
+
+        unsafe {
+            hb_buffer_add_utf32(hb_buffer, codepoints.as_ptr(), codepoints_len, run.start as c_uint, run_len);
+            replace_noncharacters(hb_buffer);
+            hb_buffer_set_direction(hb_buffer, direction);
+            apply_shape_defaults(hb_buffer, get_shape_defaults());
+            hb_buffer_guess_segment_properties(hb_buffer);
+        }
+
+        Self {
+            words: "",
+            hb_buffer,
+        }
+    }
+}
+
+impl<'a> Drop for HbBuffer<'a> {
+    fn drop(&mut self) {
+        unsafe { hb_buffer_destroy(self.hb_buffer) };
+    }
+}
+
+// The glyph infos are allocated by HarfBuzz and freed
+// when the font is destroyed. This is a convenience wrapper that
+// directly dereferences the internal hb_glyph_info_t and
+// hb_glyph_position_t, to avoid extra allocations.
+#[derive(Debug)]
+pub struct CVec<T> {
+    ptr: *const T,
+    len: usize,
+}
+
+impl<T> Deref for CVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+pub type HbGlyphInfo = hb_glyph_info_t;
+pub type HbGlyphPosition = hb_glyph_position_t;
+
+/// Shaped word - memory of the glyph_infos and glyph_positions is owned by HarfBuzz,
+/// therefore the `buf` and `font` have to live as least as long as the word is in use.
+#[derive(Debug)]
+pub struct HbShapedWord<'a> {
+    pub buf: &'a HbBuffer<'a>,
+    pub scaled_font: &'a HbScaledFont<'a>,
+    pub glyph_infos: CVec<HbGlyphInfo>,
+    pub glyph_positions: CVec<HbGlyphPosition>,
+}
+
+pub(crate) fn shape_word_hb<'a>(
+    text: &'a HbBuffer<'a>,
+    scaled_font: &'a HbScaledFont<'a>,
+) -> HbShapedWord<'a> {
+    shape_word_hb_with_features(text, scaled_font, &[])
+}
+
+/// Same as `shape_word_hb`, but enables `extra_features` in addition to the default features
+/// (i.e. to select an alternate glyph for a single character, see `select_alternate_glyph`)
+pub(crate) fn shape_word_hb_with_features<'a>(
+    text: &'a HbBuffer<'a>,
+    scaled_font: &'a HbScaledFont<'a>,
+    extra_features: &[hb_feature_t],
+) -> HbShapedWord<'a> {
+    let active_features = ACTIVE_HB_FEATURES.iter().chain(extra_features.iter()).cloned().collect::<Vec<_>>();
+    shape_word_hb_with_feature_list(text, scaled_font, &active_features)
+}
+
+/// Same as `shape_word_hb`, but with the features from the `ShapingOptions` instead of the default features
+pub(crate) fn shape_word_hb_with_options<'a>(
+    text: &'a HbBuffer<'a>,
+    scaled_font: &'a HbScaledFont<'a>,
+    options: &ShapingOptions,
+) -> HbShapedWord<'a> {
+    shape_word_hb_with_feature_list(text, scaled_font, &options.get_hb_features())
+}
+
+fn shape_word_hb_with_feature_list<'a>(
+    text: &'a HbBuffer<'a>,
+    scaled_font: &'a HbScaledFont<'a>,
+    active_features: &[hb_feature_t],
+) -> HbShapedWord<'a> {
+
+    // Later features override earlier ones, so appending the "off" features
+    // disables the contextual features even if they were explicitly enabled
+    let bounded_features;
+    let active_features = if scaled_font.font.has_excessive_max_context() {
+        bounded_features = active_features.iter().cloned()
+            .chain([FEATURE_CLIG_OFF, FEATURE_CALT_OFF].iter().cloned())
+            .collect::<Vec<_>>();
+        &bounded_features[..]
+    } else {
+        active_features
+    };
+
+    let features = if active_features.is_empty() {
+        ptr::null()
+    } else {
+        active_features.as_ptr()
+    };
+
+    let num_features = active_features.len() as u32;
+
+    unsafe { hb_shape(scaled_font.font.hb_font, text.hb_buffer, features, num_features) };
+
+    let mut glyph_count = 0;
+    let glyph_infos = unsafe { hb_buffer_get_glyph_infos(text.hb_buffer, &mut glyph_count) };
+
+    let mut position_count = glyph_count;
+    let glyph_positions = unsafe { hb_buffer_get_glyph_positions(text.hb_buffer, &mut position_count) };
+
+    // Assert that there are as many glyph infos as there are glyph positions
+    assert_eq!(glyph_count, position_count);
+
+    HbShapedWord {
+        buf: text,
+        scaled_font,
+        glyph_infos: CVec {
+            ptr: glyph_infos,
+            len: glyph_count as usize,
+        },
+        glyph_positions: CVec {
+            ptr: glyph_positions,
+            len: glyph_count as usize,
+        },
+    }
+}
+
+/// Shapes multiple runs of text, each with its own OpenType script and language tag,
+/// and concatenates the results. Useful for paragraphs that mix languages of the
+/// same script, where language-specific substitutions (`locl`) differ between runs.
+///
+/// The `cluster` of each returned glyph is offset by the length of all previous runs,
+/// so that it indexes into the concatenated characters of all runs.
+pub fn shape_runs(runs: &[(&[char], hb_tag_t, hb_tag_t)], scaled_font: &HbScaledFont) -> ShapedWord {
+
+    use std::mem;
+
+    let mut glyph_infos = Vec::new();
+    let mut glyph_positions = Vec::new();
+    let mut cluster_offset = 0;
+
+    for (chars, script, language) in runs {
+
+        let hb_buffer = HbBuffer::from_chars_with_script_and_language(chars, *script, *language);
+        let hb_shaped_run = shape_word_hb(&hb_buffer, scaled_font);
+
+        // azul-core::GlyphInfo and hb_glyph_info_t have the same size / layout
+        // (both are repr(C)), so it's safe to just transmute them here
+        glyph_infos.extend(hb_shaped_run.glyph_infos.iter().map(|hb_glyph_info| {
+            let mut glyph_info: GlyphInfo = unsafe { mem::transmute(*hb_glyph_info) };
+            glyph_info.cluster += cluster_offset;
+            glyph_info
+        }));
+        glyph_positions.extend(hb_shaped_run.glyph_positions.iter().map(|hb_glyph_position| {
+            let glyph_position: GlyphPosition = unsafe { mem::transmute(*hb_glyph_position) };
+            glyph_position
+        }));
+
+        cluster_offset += chars.len() as u32;
+    }
+
+    ShapedWord {
+        glyph_infos,
+        glyph_positions,
+    }
+}
+
+/// Run of text that is written in a single script, see `segment_by_script`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScriptRun {
+    /// Byte range of the run in the segmented text
+    pub byte_range: Range<usize>,
+    /// OpenType script tag of the run (i.e. `latn` or `arab`), `DFLT` if the
+    /// text only consists of neutral characters (spaces, digits, punctuation)
+    pub script: hb_tag_t,
+    /// OpenType language tag of the run, always `dflt` (the language isn't detected)
+    pub language: hb_tag_t,
+}
+
+/// Splits the text into runs of the same script (using the Unicode script property of
+/// each character), so that each run can be shaped with the correct script tables,
+/// i.e. via `shape_runs`. Neutral characters (the `Common` and `Inherited` scripts, i.e.
+/// spaces, punctuation or combining marks) are attached to the run before them, or to the
+/// first run if the text starts with them.
+pub fn segment_by_script(text: &str) -> Vec<ScriptRun> {
+
+    const HB_SCRIPT_COMMON: u32 = create_hb_tag(('Z', 'y', 'y', 'y'));
+    const HB_SCRIPT_INHERITED: u32 = create_hb_tag(('Z', 'i', 'n', 'h'));
+    const HB_SCRIPT_UNKNOWN: u32 = create_hb_tag(('Z', 'z', 'z', 'z'));
+    const DFLT_SCRIPT: hb_tag_t = create_hb_tag(('D', 'F', 'L', 'T'));
+    const DFLT_LANGUAGE: hb_tag_t = create_hb_tag(('d', 'f', 'l', 't'));
+
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    let unicode_funcs = unsafe { hb_unicode_funcs_get_default() };
+
+    // (start byte, hb_script_t) of each run
+    let mut run_starts: Vec<(usize, u32)> = Vec::new();
+
+    for (byte_idx, c) in text.char_indices() {
+        let script = unsafe { hb_unicode_script(unicode_funcs, c as u32) };
+        if script == HB_SCRIPT_COMMON || script == HB_SCRIPT_INHERITED || script == HB_SCRIPT_UNKNOWN {
+            continue;
+        }
+        match run_starts.last_mut() {
+            Some((_, last_script)) if *last_script == script => { },
+            // Leading neutral characters belong to the first run
+            None => run_starts.push((0, script)),
+            Some(_) => run_starts.push((byte_idx, script)),
+        }
+    }
+
+    if run_starts.is_empty() {
+        return vec![ScriptRun { byte_range: 0..text.len(), script: DFLT_SCRIPT, language: DFLT_LANGUAGE }];
+    }
+
+    let run_ends = run_starts.iter().skip(1).map(|(start, _)| *start).chain(Some(text.len()));
+
+    run_starts.iter().zip(run_ends).map(|((start, script), end)| {
+
+        let mut script_tag = DFLT_SCRIPT;
+        let mut script_count: c_uint = 1;
+        let mut language_count: c_uint = 0;
+        unsafe {
+            hb_ot_tags_from_script_and_language(*script, ptr::null(), &mut script_count, &mut script_tag, &mut language_count, ptr::null_mut());
+        }
+
+        ScriptRun {
+            byte_range: *start..end,
+            script: if script_count == 0 { DFLT_SCRIPT } else { script_tag },
+            language: DFLT_LANGUAGE,
+        }
+    }).collect()
+}
+
+/// Simplified bidi class of a character, see `get_bidi_levels`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BidiClass {
+    LeftToRight,
+    RightToLeft,
+    Number,
+    Neutral,
+}
+
+fn get_bidi_class(unicode_funcs: *mut hb_unicode_funcs_t, c: char) -> BidiClass {
+
+    const HB_SCRIPT_COMMON: u32 = create_hb_tag(('Z', 'y', 'y', 'y'));
+    const HB_SCRIPT_INHERITED: u32 = create_hb_tag(('Z', 'i', 'n', 'h'));
+    const HB_SCRIPT_UNKNOWN: u32 = create_hb_tag(('Z', 'z', 'z', 'z'));
+
+    if c.is_numeric() {
+        return BidiClass::Number;
+    }
+
+    let script = unsafe { hb_unicode_script(unicode_funcs, c as u32) };
+    if script == HB_SCRIPT_COMMON || script == HB_SCRIPT_INHERITED || script == HB_SCRIPT_UNKNOWN {
+        BidiClass::Neutral
+    } else if unsafe { hb_script_get_horizontal_direction(script) } == HB_DIRECTION_RTL {
+        BidiClass::RightToLeft
+    } else {
+        BidiClass::LeftToRight
+    }
+}
+
+/// Resolves the bidi embedding level of each character (even = left-to-right, odd =
+/// right-to-left) in a paragraph with the given base direction.
+///
+/// This is a simplified version of the Unicode bidi algorithm without explicit embeddings,
+/// isolates or bracket pairs: the strong direction of a character is derived from its script,
+/// numbers are always laid out left-to-right (and take the direction of the preceding strong
+/// text for the resolution of neutrals) and neutral characters (spaces, punctuation) take the
+/// direction of the surrounding text if both sides agree, otherwise the base direction.
+pub fn get_bidi_levels(chars: &[char], base_direction: TextDirection) -> Vec<u8> {
+
+    use self::BidiClass::*;
+
+    let unicode_funcs = unsafe { hb_unicode_funcs_get_default() };
+    let base_level = match base_direction {
+        TextDirection::LeftToRight => 0,
+        TextDirection::RightToLeft => 1,
+    };
+    let base_class = if base_level == 0 { LeftToRight } else { RightToLeft };
+
+    let mut classes = chars.iter().map(|c| get_bidi_class(unicode_funcs, *c)).collect::<Vec<_>>();
+
+    // Numbers after left-to-right text (or at the start of a left-to-right paragraph) are left-to-right text
+    let mut last_strong = base_class;
+    for class in classes.iter_mut() {
+        match *class {
+            LeftToRight | RightToLeft => last_strong = *class,
+            Number if last_strong == LeftToRight => *class = LeftToRight,
+            _ => { },
+        }
+    }
+
+    // Neutrals take the direction of the surrounding strong text (numbers count as right-to-left)
+    let as_strong = |class: BidiClass| if class == Number { RightToLeft } else { class };
+    let mut char_idx = 0;
+    while char_idx < classes.len() {
+        if classes[char_idx] != Neutral {
+            char_idx += 1;
+            continue;
+        }
+        let run_start = char_idx;
+        while char_idx < classes.len() && classes[char_idx] == Neutral {
+            char_idx += 1;
+        }
+        let before = if run_start == 0 { base_class } else { as_strong(classes[run_start - 1]) };
+        let after = classes.get(char_idx).map(|c| as_strong(*c)).unwrap_or(base_class);
+        let resolved = if before == after { before } else { base_class };
+        for class in &mut classes[run_start..char_idx] {
+            *class = resolved;
+        }
+    }
+
+    classes.iter().map(|class| match (class, base_level) {
+        (LeftToRight, 0) => 0,
+        (RightToLeft, 0) => 1,
+        (RightToLeft, _) => 1,
+        _ => 2,
+    }).collect()
+}
+
+/// Returns the direction of the first character with a strong direction (rules P2 and P3 of
+/// the Unicode bidi algorithm, without isolates), i.e. the base direction of a paragraph.
+/// Returns `LeftToRight` if the text has no strongly directional characters.
+pub fn get_paragraph_direction(chars: &[char]) -> TextDirection {
+    let unicode_funcs = unsafe { hb_unicode_funcs_get_default() };
+    chars.iter().find_map(|c| match get_bidi_class(unicode_funcs, *c) {
+        BidiClass::LeftToRight => Some(TextDirection::LeftToRight),
+        BidiClass::RightToLeft => Some(TextDirection::RightToLeft),
+        BidiClass::Number | BidiClass::Neutral => None,
+    }).unwrap_or(TextDirection::LeftToRight)
+}
+
+/// Returns the range of the characters around the `run` (at most `context_window` characters
+/// on each side, clamped to the `text_len`) that the run is shaped in the context of
+fn get_context_range(run: &Range<usize>, text_len: usize, context_window: usize) -> Range<usize> {
+    run.start.saturating_sub(context_window)..(run.end + context_window).min(text_len)
+}
+
+/// Shapes a paragraph of mixed-direction text: the characters are split into runs of the
+/// same bidi level (see `get_bidi_levels`), each run is shaped in its own direction (with the
+/// neighbouring characters as context, see `HbFont::context_window`) and the
+/// runs are reordered for display. The returned glyphs are in visual order (left to right on
+/// the screen), the cluster of each glyph is the index of its first character in `chars`.
+pub fn shape_bidi(chars: &[char], base_direction: TextDirection, scaled_font: &HbScaledFont) -> ScaledWord {
+
+    use std::mem;
+
+    let levels = get_bidi_levels(chars, base_direction);
+
+    let mut runs = Vec::<(Range<usize>, u8)>::new();
+    for (char_idx, level) in levels.iter().enumerate() {
+        match runs.last_mut() {
+            Some((range, run_level)) if run_level == level => range.end = char_idx + 1,
+            _ => runs.push((char_idx..(char_idx + 1), *level)),
+        }
+    }
+
+    let run_levels = runs.iter().map(|(_, level)| *level).collect::<Vec<_>>();
+    let context_window = scaled_font.font.context_window();
+
+    let mut glyph_infos = Vec::new();
+    let mut glyph_positions = Vec::new();
+
+    for run_idx in get_visual_order(&run_levels) {
+
+        let (range, level) = &runs[run_idx];
+        let context = get_context_range(range, chars.len(), context_window);
+        let run_in_context = (range.start - context.start)..(range.end - context.start);
+        let direction = if level % 2 == 0 { HB_DIRECTION_LTR } else { HB_DIRECTION_RTL };
+        let hb_buffer = HbBuffer::from_chars_in_context(&chars[context.clone()], run_in_context, direction);
+        let hb_shaped_run = shape_word_hb(&hb_buffer, scaled_font);
+
+        // azul-core::GlyphInfo and hb_glyph_info_t have the same size / layout
+        // (both are repr(C)), so it's safe to just transmute them here
+        glyph_infos.extend(hb_shaped_run.glyph_infos.iter().map(|hb_glyph_info| {
+            let mut glyph_info: GlyphInfo = unsafe { mem::transmute(*hb_glyph_info) };
+            glyph_info.cluster += context.start as u32;
+            glyph_info
+        }));
+        glyph_positions.extend(hb_shaped_run.glyph_positions.iter().map(|hb_glyph_position| {
+            let glyph_position: GlyphPosition = unsafe { mem::transmute(*hb_glyph_position) };
+            glyph_position
+        }));
+    }
+
+    ScaledWord {
+        word_width: get_word_visual_width_hb(&glyph_positions),
+        glyph_infos,
+        glyph_positions,
+        direction: base_direction,
+        rotated_glyphs: Vec::new(),
+    }
+}
+
+/// Value of the Unicode `Vertical_Orientation` property (UAX #50) of a character,
+/// i.e. how the character is displayed in vertical text
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VerticalOrientation {
+    /// `R`: Rotated 90° clockwise (i.e. Latin letters)
+    Rotated,
+    /// `U`: Upright, same as in horizontal text (i.e. ideographs)
+    Upright,
+    /// `Tu`: Vertical alternate glyph (`vert` feature), upright if the font has none
+    TransformedUpright,
+    /// `Tr`: Vertical alternate glyph (`vert` feature), rotated if the font has none
+    TransformedRotated,
+}
+
+// Ranges of the characters that aren't `R` (the default for all other characters).
+// Simplified from the UAX #50 data, more specific ranges come first.
+const VERTICAL_ORIENTATION_RANGES: &[(u32, u32, VerticalOrientation)] = {
+    use self::VerticalOrientation::*;
+    &[
+        (0x3001, 0x3002, TransformedUpright), // ideographic comma / full stop
+        (0x3008, 0x3011, TransformedRotated), // CJK brackets
+        (0x3014, 0x301F, TransformedRotated), // CJK brackets, wave dash
+        (0x3030, 0x3030, TransformedRotated), // wavy dash
+        (0x3041, 0x3041, TransformedUpright), // small hiragana
+        (0x3043, 0x3043, TransformedUpright),
+        (0x3045, 0x3045, TransformedUpright),
+        (0x3047, 0x3047, TransformedUpright),
+        (0x3049, 0x3049, TransformedUpright),
+        (0x3063, 0x3063, TransformedUpright),
+        (0x3083, 0x3083, TransformedUpright),
+        (0x3085, 0x3085, TransformedUpright),
+        (0x3087, 0x3087, TransformedUpright),
+        (0x308E, 0x308E, TransformedUpright),
+        (0x3095, 0x3096, TransformedUpright),
+        (0x30A0, 0x30A0, TransformedRotated), // katakana-hiragana double hyphen
+        (0x30A1, 0x30A1, TransformedUpright), // small katakana
+        (0x30A3, 0x30A3, TransformedUpright),
+        (0x30A5, 0x30A5, TransformedUpright),
+        (0x30A7, 0x30A7, TransformedUpright),
+        (0x30A9, 0x30A9, TransformedUpright),
+        (0x30C3, 0x30C3, TransformedUpright),
+        (0x30E3, 0x30E3, TransformedUpright),
+        (0x30E5, 0x30E5, TransformedUpright),
+        (0x30E7, 0x30E7, TransformedUpright),
+        (0x30EE, 0x30EE, TransformedUpright),
+        (0x30F5, 0x30F6, TransformedUpright),
+        (0x30FC, 0x30FC, TransformedRotated), // prolonged sound mark
+        (0x31F0, 0x31FF, TransformedUpright), // katakana phonetic extensions
+        (0xFE59, 0xFE5E, TransformedRotated), // small brackets
+        (0xFF01, 0xFF01, TransformedUpright), // fullwidth punctuation
+        (0xFF08, 0xFF09, TransformedRotated),
+        (0xFF0C, 0xFF0C, TransformedUpright),
+        (0xFF0E, 0xFF0E, TransformedUpright),
+        (0xFF1A, 0xFF1E, TransformedRotated),
+        (0xFF1F, 0xFF1F, TransformedUpright),
+        (0xFF3B, 0xFF3B, TransformedRotated),
+        (0xFF3D, 0xFF3D, TransformedRotated),
+        (0xFF3F, 0xFF3F, TransformedRotated),
+        (0xFF5B, 0xFF60, TransformedRotated),
+        (0xFFE3, 0xFFE3, TransformedRotated),
+        (0x00A7, 0x00A7, Upright), // section sign, copyright, etc.
+        (0x00A9, 0x00A9, Upright),
+        (0x00AE, 0x00AE, Upright),
+        (0x00B1, 0x00B1, Upright),
+        (0x00BC, 0x00BE, Upright),
+        (0x00D7, 0x00D7, Upright),
+        (0x00F7, 0x00F7, Upright),
+        (0x1100, 0x11FF, Upright), // Hangul Jamo
+        (0x1400, 0x167F, Upright), // Canadian syllabics
+        (0x2E80, 0xA4CF, Upright), // CJK radicals, kana, bopomofo, ideographs, Yi
+        (0xA960, 0xA97F, Upright), // Hangul Jamo extended A
+        (0xAC00, 0xD7FF, Upright), // Hangul syllables, Jamo extended B
+        (0xE000, 0xFAFF, Upright), // private use area, CJK compatibility ideographs
+        (0xFE10, 0xFE1F, Upright), // vertical forms
+        (0xFE30, 0xFE6F, Upright), // CJK compatibility forms, small forms
+        (0xFF00, 0xFF60, Upright), // fullwidth forms
+        (0xFFE0, 0xFFE7, Upright),
+        (0x1F000, 0x1FAFF, Upright), // symbols, emoji
+        (0x20000, 0x3FFFD, Upright), // CJK ideographs (supplementary planes)
+    ]
+};
+
+/// Returns the `Vertical_Orientation` of a character (based on a simplified table of the Unicode data)
+pub fn get_vertical_orientation(c: char) -> VerticalOrientation {
+    let c = c as u32;
+    VERTICAL_ORIENTATION_RANGES.iter()
+        .find(|(start, end, _)| *start <= c && c <= *end)
+        .map(|(_, _, orientation)| *orientation)
+        .unwrap_or(VerticalOrientation::Rotated)
+}
+
+/// Returns for each glyph of vertically shaped text whether the renderer has to rotate it
+/// by 90° clockwise. The cluster of each glyph has to be the index of its first character
+/// in `chars` (as in `shape_bidi`).
+///
+/// `Tr` characters (i.e. brackets) are only rotated if shaping didn't replace them with a
+/// vertical alternate, which is detected by the glyph still being the nominal glyph of the character.
+pub fn get_vertical_glyph_rotations(chars: &[char], glyph_infos: &[GlyphInfo], font: &HbFont) -> Vec<bool> {
+    use self::VerticalOrientation::*;
+    glyph_infos.iter().map(|glyph_info| {
+        let c = match chars.get(glyph_info.cluster as usize) {
+            Some(c) => *c,
+            None => return false,
+        };
+        match get_vertical_orientation(c) {
+            Rotated => true,
+            Upright | TransformedUpright => false,
+            TransformedRotated => glyph_info.codepoint == font.lookup_glyph_index(c as u32),
+        }
+    }).collect()
+}
+
+/// Shapes a word for a vertical writing mode: runs of upright characters (see
+/// `get_vertical_orientation`) are shaped top to bottom with the vertical alternates of the
+/// `vert` / `vrt2` features, runs of sideways characters (i.e. Latin letters) are shaped
+/// horizontally. The cluster of each glyph is the index of its first character in `chars`.
+///
+/// The advance of each glyph along the column is stored in its `x_advance`: glyphs that are set
+/// sideways (see `ScaledWord::rotated_glyphs`) advance by their horizontal advance, upright glyphs
+/// by their vertical advance. The offsets of the upright glyphs don't apply to the column and are removed.
+pub fn shape_vertical(chars: &[char], scaled_font: &HbScaledFont) -> ScaledWord {
+
+    use std::mem;
+
+    let is_sideways = |c: &char| get_vertical_orientation(*c) == VerticalOrientation::Rotated;
+
+    let mut runs = Vec::<(Range<usize>, bool)>::new();
+    for (char_idx, c) in chars.iter().enumerate() {
+        let sideways = is_sideways(c);
+        match runs.last_mut() {
+            Some((range, run_sideways)) if *run_sideways == sideways => range.end = char_idx + 1,
+            _ => runs.push((char_idx..(char_idx + 1), sideways)),
+        }
+    }
+
+    let mut glyph_infos = Vec::<GlyphInfo>::new();
+    let mut glyph_positions = Vec::<GlyphPosition>::new();
+
+    for (range, sideways) in &runs {
+
+        let (direction, features): (_, &[hb_feature_t]) = if *sideways {
+            (HB_DIRECTION_LTR, &[])
+        } else {
+            (HB_DIRECTION_TTB, &[FEATURE_VERT_ON, FEATURE_VRT2_ON])
+        };
+
+        let hb_buffer = HbBuffer::from_chars_in_context(chars, range.clone(), direction);
+        let hb_shaped_run = shape_word_hb_with_features(&hb_buffer, scaled_font, features);
+
+        // azul-core::GlyphInfo and hb_glyph_info_t have the same size / layout
+        // (both are repr(C)), so it's safe to just transmute them here
+        glyph_infos.extend(hb_shaped_run.glyph_infos.iter().map(|i| -> GlyphInfo { unsafe { mem::transmute(*i) } }));
+        glyph_positions.extend(hb_shaped_run.glyph_positions.iter().map(|p| {
+            let mut glyph_position: GlyphPosition = unsafe { mem::transmute(*p) };
+            if !*sideways {
+                // HarfBuzz advances downwards along the negative y axis
+                glyph_position.x_advance = -glyph_position.y_advance;
+                glyph_position.y_advance = 0;
+                glyph_position.x_offset = 0;
+                glyph_position.y_offset = 0;
+            }
+            glyph_position
+        }));
+    }
+
+    // Characters without a vertical alternate (i.e. brackets) are rotated as well
+    let rotated_glyphs = get_vertical_glyph_rotations(chars, &glyph_infos, scaled_font.font);
+    for ((glyph_info, glyph_position), rotated) in glyph_infos.iter().zip(glyph_positions.iter_mut()).zip(rotated_glyphs.iter()) {
+        let char_is_sideways = chars.get(glyph_info.cluster as usize).map(is_sideways).unwrap_or(false);
+        if *rotated && !char_is_sideways {
+            glyph_position.x_advance = unsafe { hb_font_get_glyph_h_advance(scaled_font.font.hb_font, glyph_info.codepoint) };
+        }
+    }
+
+    ScaledWord {
+        word_width: get_word_visual_width_hb(&glyph_positions),
+        glyph_infos,
+        glyph_positions,
+        direction: TextDirection::LeftToRight,
+        rotated_glyphs,
+    }
+}
+
+/// Shapes the text with the given OpenType features (i.e. without ligatures or with
+/// small caps), `shape_with_options(text, font, &ShapingOptions::default())` shapes the
+/// text with the same features as the regular text layout
+pub fn shape_with_options(text: &str, scaled_font: &HbScaledFont, options: &ShapingOptions) -> ShapedWord {
+
+    use std::mem;
+
+    let hb_buffer = HbBuffer::from_str(text);
+    let hb_shaped_word = shape_word_hb_with_options(&hb_buffer, scaled_font, options);
+
+    // azul-core::GlyphInfo and hb_glyph_info_t have the same size / layout
+    // (both are repr(C)), so it's safe to just transmute them here
+    ShapedWord {
+        glyph_infos: hb_shaped_word.glyph_infos.iter().map(|i| unsafe { mem::transmute(*i) }).collect(),
+        glyph_positions: hb_shaped_word.glyph_positions.iter().map(|p| unsafe { mem::transmute(*p) }).collect(),
+    }
+}
+
+/// Shapes the `text` with the given OpenType script and language tags and returns only the
+/// resulting glyph IDs (after all substitutions), i.e. for golden tests of a font's `GSUB` rules
+pub fn shape_to_glyph_ids(text: &str, scaled_font: &HbScaledFont, script: hb_tag_t, language: hb_tag_t) -> Vec<u16> {
+    let chars = text.chars().collect::<Vec<_>>();
+    shape_runs(&[(&chars, script, language)], scaled_font).glyph_infos.iter().map(|i| i.codepoint as u16).collect()
+}
+
+/// Glyphs of the positional forms of a (joining) character, see `shape_isolated_forms`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JoiningForms {
+    pub isolated: u16,
+    pub initial: u16,
+    pub medial: u16,
+    pub final_: u16,
+}
+
+/// Returns the glyphs of the isolated, initial, medial and final form of a character
+/// (i.e. of an Arabic letter), for font previews or on-screen keyboards that show a
+/// character without any surrounding text.
+///
+/// Each form is shaped on its own, with zero-width joiners (U+200D) as the neighbours that
+/// the character joins to. Forms that the font doesn't have (or that the character doesn't
+/// have, i.e. for non-joining characters) fall back to the glyph of the isolated form.
+pub fn shape_isolated_forms(c: char, scaled_font: &HbScaledFont, script: hb_tag_t, language: hb_tag_t) -> JoiningForms {
+
+    const ZWJ: char = '\u{200D}';
+
+    let shape = |chars: &[char]| -> Vec<u16> {
+        let hb_buffer = HbBuffer::from_chars_with_script_and_language(chars, script, language);
+        let hb_shaped_word = shape_word_hb(&hb_buffer, scaled_font);
+        hb_shaped_word.glyph_infos.iter().map(|glyph_info| glyph_info.codepoint as u16).collect()
+    };
+
+    // Depending on the font, the joiners are shaped as an invisible glyph, a space or
+    // not at all (which also merges their cluster into the cluster of the character),
+    // so the glyph of the character is the one glyph that isn't a joiner
+    let joiner_glyphs = shape(&[ZWJ]).into_iter().collect::<BTreeSet<u16>>();
+    let shape_form = |chars: &[char]| -> Option<u16> {
+        shape(chars).into_iter().find(|glyph| *glyph != 0 && !joiner_glyphs.contains(glyph))
+    };
+
+    let isolated = shape_form(&[c]).unwrap_or(0);
+
+    JoiningForms {
+        isolated,
+        initial: shape_form(&[c, ZWJ]).unwrap_or(isolated),
+        medial: shape_form(&[ZWJ, c, ZWJ]).unwrap_or(isolated),
+        final_: shape_form(&[ZWJ, c]).unwrap_or(isolated),
+    }
+}
+
+/// East Asian Width property of a character, see [UAX #11](https://www.unicode.org/reports/tr11/)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EastAsianWidth {
+    /// Characters that don't occur in East Asian typography (i.e. Thai, Arabic)
+    Neutral,
+    /// Narrow characters with a wide counterpart (i.e. ASCII)
+    Narrow,
+    /// Wide characters (i.e. CJK ideographs, Hiragana, Hangul syllables)
+    Wide,
+    /// Fullwidth compatibility forms of narrow characters (i.e. `Ａ`)
+    Fullwidth,
+    /// Halfwidth compatibility forms of wide characters (i.e. halfwidth Katakana)
+    Halfwidth,
+    /// Characters that are wide in East Asian legacy encodings and narrow
+    /// everywhere else (i.e. Greek, Cyrillic, box drawing characters)
+    Ambiguous,
+}
+
+impl EastAsianWidth {
+    /// Returns whether the character takes up two columns in a monospace layout.
+    /// Whether `Ambiguous` characters are wide depends on the context (usually
+    /// they are only wide in East Asian locales).
+    pub fn is_wide(&self, ambiguous_is_wide: bool) -> bool {
+        use self::EastAsianWidth::*;
+        match self {
+            Wide | Fullwidth => true,
+            Ambiguous => ambiguous_is_wide,
+            Neutral | Narrow | Halfwidth => false,
+        }
+    }
+}
+
+/// Returns the East Asian Width of a character. Only covers the blocks that
+/// matter for layout, characters outside of them are classified as `Neutral`.
+pub fn east_asian_width(c: char) -> EastAsianWidth {
+    use self::EastAsianWidth::*;
+    match c as u32 {
+        0x3000 | 0xFF01..=0xFF60 | 0xFFE0..=0xFFE6 => Fullwidth,
+        0x20A9 | 0xFF61..=0xFFBE | 0xFFC2..=0xFFC7 | 0xFFCA..=0xFFCF |
+        0xFFD2..=0xFFD7 | 0xFFDA..=0xFFDC | 0xFFE8..=0xFFEE => Halfwidth,
+        0x0020..=0x007E | 0x00A2..=0x00A3 | 0x00A5..=0x00A6 | 0x00AC | 0x00AF |
+        0x27E6..=0x27ED | 0x2985..=0x2986 => Narrow,
+        // Hangul Jamo, emoji and other pictographs
+        0x1100..=0x115F | 0x231A..=0x231B | 0x2329..=0x232A | 0x23E9..=0x23EC | 0x23F0 | 0x23F3 |
+        0x25FD..=0x25FE | 0x2614..=0x2615 | 0x2648..=0x2653 | 0x267F | 0x2693 | 0x26A1 |
+        0x26AA..=0x26AB | 0x26BD..=0x26BE | 0x26C4..=0x26C5 | 0x26CE | 0x26D4 | 0x26EA |
+        0x26F2..=0x26F3 | 0x26F5 | 0x26FA | 0x26FD | 0x2705 | 0x270A..=0x270B | 0x2728 |
+        0x274C | 0x274E | 0x2753..=0x2755 | 0x2757 | 0x2795..=0x2797 | 0x27B0 | 0x27BF |
+        0x2B1B..=0x2B1C | 0x2B50 | 0x2B55 |
+        // CJK radicals, punctuation, Hiragana, Katakana, Bopomofo, CJK ideographs, Yi, Hangul
+        0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF |
+        0xA000..=0xA4CF | 0xA960..=0xA97F | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF |
+        0xFE10..=0xFE19 | 0xFE30..=0xFE6F |
+        0x16FE0..=0x16FE4 | 0x17000..=0x18AFF | 0x1B000..=0x1B2FF |
+        0x1F004 | 0x1F0CF | 0x1F18E | 0x1F191..=0x1F19A | 0x1F200..=0x1F202 |
+        0x1F210..=0x1F23B | 0x1F240..=0x1F248 | 0x1F250..=0x1F251 | 0x1F260..=0x1F265 |
+        0x1F300..=0x1F64F | 0x1F680..=0x1F6FF | 0x1F900..=0x1F9FF |
+        0x20000..=0x2FFFD | 0x30000..=0x3FFFD => Wide,
+        // Latin-1 symbols, Greek, Cyrillic, combining marks, punctuation,
+        // box drawing, geometric shapes, variation selectors, private use
+        0x00A1 | 0x00A4 | 0x00A7..=0x00A8 | 0x00AA | 0x00AD..=0x00AE | 0x00B0..=0x00B4 |
+        0x00B6..=0x00BA | 0x00BC..=0x00BF | 0x00C6 | 0x00D0 | 0x00D7..=0x00D8 |
+        0x00DE..=0x00E1 | 0x00E6 | 0x00E8..=0x00EA | 0x00EC..=0x00ED | 0x00F0 |
+        0x00F2..=0x00F3 | 0x00F7..=0x00FA | 0x00FC | 0x00FE |
+        0x0300..=0x036F | 0x0391..=0x03A9 | 0x03B1..=0x03C9 | 0x0401 | 0x0410..=0x044F | 0x0451 |
+        0x2010 | 0x2013..=0x2016 | 0x2018..=0x2019 | 0x201C..=0x201D | 0x2020..=0x2022 |
+        0x2024..=0x2027 | 0x2030 | 0x2032..=0x2033 | 0x2035 | 0x203B | 0x203E |
+        0x2460..=0x24E9 | 0x2500..=0x254B | 0x2550..=0x2573 | 0x2580..=0x258F |
+        0x2592..=0x2595 | 0x25A0..=0x25A1 | 0x25B2..=0x25B3 | 0x25C6..=0x25C8 | 0x25CB |
+        0x25CE..=0x25D1 | 0xE000..=0xF8FF | 0xFE00..=0xFE0F | 0xFFFD => Ambiguous,
+        _ => Neutral,
+    }
+}
+
+pub(crate) fn get_word_visual_width_hb(glyph_positions: &[GlyphPosition]) -> f32 {
+    glyph_positions.iter().map(|pos| pos.x_advance as f32 / HB_SCALE_FACTOR).sum()
+}
+
+pub(crate) fn get_glyph_instances_hb(
+    glyph_infos: &[GlyphInfo],
+    glyph_positions: &[GlyphPosition],
+) -> Vec<GlyphInstance> {
+
+    let mut current_cursor_x = 0.0;
+    let mut current_cursor_y = 0.0;
+
+    glyph_infos.iter().zip(glyph_positions.iter()).map(|(glyph_info, glyph_pos)| {
+        let glyph_index = glyph_info.codepoint;
+
+        let x_offset = glyph_pos.x_offset as f32 / HB_SCALE_FACTOR;
+        let y_offset = glyph_pos.y_offset as f32 / HB_SCALE_FACTOR;
+        let x_advance = glyph_pos.x_advance as f32 / HB_SCALE_FACTOR;
+        let y_advance = glyph_pos.y_advance as f32 / HB_SCALE_FACTOR;
+
+        let point = LayoutPoint::new(current_cursor_x + x_offset, current_cursor_y + y_offset);
+        let size = LayoutSize::new(x_advance, y_advance);
+
+        current_cursor_x += x_advance;
+        current_cursor_y += y_advance;
+
+        GlyphInstance {
+            index: glyph_index,
+            point,
+            size,
+        }
+    }).collect()
+}
+
+/// Range of `units_per_em` values allowed by the OpenType spec - values outside of this
+/// range only occur in broken fonts (and would result in absurd metrics)
+const VALID_UNITS_PER_EM: RangeInclusive<u16> = 16..=16384;
+
+/// Returns the offset of the table `tag` in the font file (or font collection)
+fn get_table_offset(font_bytes: &[u8], font_index: u32, tag: [u8;4]) -> Option<usize> {
+    get_table_range(font_bytes, font_index, tag).map(|(offset, _)| offset)
+}
+
+/// Returns the (offset, length) of the table `tag` in the font file (or font collection)
+fn get_table_range(font_bytes: &[u8], font_index: u32, tag: [u8;4]) -> Option<(usize, usize)> {
+
+    let font_offset = if font_bytes.get(0..4)? == b"ttcf" {
+        read_u32(font_bytes, 12 + font_index as usize * 4)? as usize
+    } else {
+        0
+    };
+
+    let num_tables = read_u16(font_bytes, font_offset + 4)? as usize;
+    (0..num_tables)
+        .map(|table_idx| font_offset + 12 + table_idx * 16)
+        .find(|record| font_bytes.get(*record..(*record + 4)) == Some(&tag[..]))
+        .and_then(|record| Some((read_u32(font_bytes, record + 8)? as usize, read_u32(font_bytes, record + 12)? as usize)))
+}
+
+/// Reason why a font couldn't be loaded, see `HbFont::try_from_bytes`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FontParseError {
+    /// The data isn't a TrueType / OpenType font or font collection
+    NotAFont,
+    /// The font index is out of range (fonts that aren't a collection only have index 0)
+    UnsupportedCollectionIndex { index: u32, count: u32 },
+    /// A required table is missing
+    MissingTable(hb_tag_t),
+    /// A required table is there, but can't be used
+    MalformedTable { tag: hb_tag_t, reason: &'static str },
+    /// The `cmap` table has no Unicode subtable, so no character can be mapped to a glyph
+    NoCmapSubtable,
+    /// The font file doesn't exist, can't be read or is empty (only returned by `FontFile::open`)
+    FileNotReadable,
+}
+
+impl fmt::Display for FontParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::FontParseError::*;
+        let tag_str = |tag: &hb_tag_t| String::from_utf8_lossy(&tag.to_be_bytes()).into_owned();
+        match self {
+            NotAFont => write!(f, "Data is not a TrueType / OpenType font"),
+            UnsupportedCollectionIndex { index, count } => write!(f, "Font index {} is out of range, the font file contains {} font(s)", index, count),
+            MissingTable(tag) => write!(f, "Font has no \"{}\" table", tag_str(tag)),
+            MalformedTable { tag, reason } => write!(f, "Malformed \"{}\" table: {}", tag_str(tag), reason),
+            NoCmapSubtable => write!(f, "Font has no Unicode cmap subtable"),
+            FileNotReadable => write!(f, "Font file could not be read"),
+        }
+    }
+}
+
+/// Returns how many fonts the font data contains (the valid range of the `font_index` is
+/// `0..count_fonts(font_bytes)`): the number of fonts of a font collection (`.ttc`),
+/// 1 for a single font or 0 if the data isn't a font at all
+pub fn count_fonts(font_bytes: &[u8]) -> usize {
+    let sfnt_version = match read_u32(font_bytes, 0) { Some(s) => s, None => return 0 };
+    match &sfnt_version.to_be_bytes() {
+        b"ttcf" => read_u32(font_bytes, 8).unwrap_or(0) as usize,
+        [0, 1, 0, 0] | b"OTTO" | b"true" => 1,
+        _ => 0,
+    }
+}
+
+/// Checks that the font data contains the tables necessary for shaping and layout
+fn validate_font(font_bytes: &[u8], font_index: u32) -> Result<(), FontParseError> {
+
+    use self::FontParseError::*;
+
+    let tag = |t: &[u8;4]| u32::from_be_bytes(*t);
+
+    let font_count = match count_fonts(font_bytes) {
+        0 => return Err(NotAFont),
+        n => n as u32,
+    };
+
+    if font_index >= font_count {
+        return Err(UnsupportedCollectionIndex { index: font_index, count: font_count });
+    }
+
+    let get_table = |table_tag: &[u8;4]| -> Result<&[u8], FontParseError> {
+        let (offset, length) = get_table_range(font_bytes, font_index, *table_tag).ok_or(MissingTable(tag(table_tag)))?;
+        font_bytes.get(offset..(offset + length)).ok_or(MalformedTable { tag: tag(table_tag), reason: "table extends past the end of the font data" })
+    };
+
+    let head = get_table(b"head")?;
+    if read_u32(head, 12) != Some(0x5F0F3CF5) {
+        return Err(MalformedTable { tag: tag(b"head"), reason: "wrong magic number" });
+    }
+
+    let hhea = get_table(b"hhea")?;
+    if read_u16(hhea, 34).unwrap_or(0) == 0 {
+        return Err(MalformedTable { tag: tag(b"hhea"), reason: "no horizontal metrics" });
+    }
+
+    get_table(b"maxp")?;
+    get_table(b"hmtx")?;
+
+    let cmap = get_table(b"cmap")?;
+    let subtable_count = read_u16(cmap, 2).ok_or(MalformedTable { tag: tag(b"cmap"), reason: "truncated header" })? as usize;
+    let has_unicode_subtable = (0..subtable_count).any(|i| {
+        match (read_u16(cmap, 4 + i * 8), read_u16(cmap, 6 + i * 8)) {
+            (Some(0), Some(_)) | (Some(3), Some(1)) | (Some(3), Some(10)) => true,
+            _ => false,
+        }
+    });
+
+    if !has_unicode_subtable {
+        return Err(NoCmapSubtable);
+    }
+
+    Ok(())
+}
+
+impl<'a> HbFont<'a> {
+    /// Same as `from_bytes`, but checks the font first and returns why the font can't
+    /// be used instead of creating an empty font (which shapes every character to glyph 0)
+    pub fn try_from_bytes(font_bytes: &'a [u8], font_index: u32) -> Result<Self, FontParseError> {
+        validate_font(font_bytes, font_index)?;
+        Ok(Self::from_bytes(font_bytes, font_index))
+    }
+}
+
+/// Font file that is memory-mapped by HarfBuzz (or read, on platforms without `mmap`),
+/// see `HbFont::from_file`. The file stays mapped until the `FontFile` is dropped.
+#[derive(Debug)]
+pub struct FontFile {
+    hb_blob: *mut hb_blob_t,
+}
+
+impl FontFile {
+    /// Maps the font file into memory, without checking whether it actually is a font
+    pub fn open(path: &Path) -> Result<Self, FontParseError> {
+
+        let path = path.to_str().and_then(|p| CString::new(p).ok()).ok_or(FontParseError::FileNotReadable)?;
+        let hb_blob = unsafe { hb_blob_create_from_file(path.as_ptr()) };
+
+        // HarfBuzz returns an empty blob if the file can't be opened
+        if unsafe { hb_blob_get_length(hb_blob) } == 0 {
+            unsafe { hb_blob_destroy(hb_blob) };
+            return Err(FontParseError::FileNotReadable);
+        }
+
+        Ok(Self { hb_blob })
+    }
+
+    /// Returns the (mapped) contents of the file
+    pub fn as_bytes(&self) -> &[u8] {
+        let mut len = 0;
+        let data = unsafe { hb_blob_get_data(self.hb_blob, &mut len) };
+        unsafe { slice::from_raw_parts(data as *const u8, len as usize) }
+    }
+}
+
+impl Drop for FontFile {
+    fn drop(&mut self) {
+        unsafe { hb_blob_destroy(self.hb_blob) };
+    }
+}
+
+impl<'a> HbFont<'a> {
+    /// Loads a font from a memory-mapped `FontFile` (without reading the whole file into
+    /// memory first), the font is checked like in `try_from_bytes`.
+    ///
+    /// HarfBuzz doesn't copy the tables, the glyph data is only read from the file when
+    /// shaping needs it - so the font borrows the `FontFile`, which has to outlive it.
+    pub fn from_file(font_file: &'a FontFile, font_index: u32) -> Result<Self, FontParseError> {
+        Self::try_from_bytes(font_file.as_bytes(), font_index)
+    }
+}
+
+/// If the `units_per_em` of the font is outside of the valid range, returns a copy
+/// of the font with a best-guess `units_per_em` (1000 for CFF outlines, 2048 for TrueType outlines)
+fn fix_units_per_em(font_bytes: &[u8], font_index: u32) -> Option<Vec<u8>> {
+
+    let units_per_em_offset = get_table_offset(font_bytes, font_index, *b"head")? + 18;
+    let units_per_em = read_u16(font_bytes, units_per_em_offset)?;
+
+    if VALID_UNITS_PER_EM.contains(&units_per_em) {
+        return None;
+    }
+
+    let best_guess: u16 = if get_table_offset(font_bytes, font_index, *b"CFF ").is_some() { 1000 } else { 2048 };
+    let mut fixed_font = font_bytes.to_vec();
+    fixed_font[units_per_em_offset..(units_per_em_offset + 2)].copy_from_slice(&best_guess.to_be_bytes());
+    Some(fixed_font)
+}
+
+/// Get the baseline for a font, you'll have to scale the
+/// font size then later on for your given font size
+///
+/// Fonts with a broken `units_per_em` are measured with a best-guess value
+/// instead, in which case `FontMetrics::metrics_suspect` is set.
+pub fn get_font_metrics_freetype(font_bytes: &[u8], font_index: i32) -> FontMetrics {
+    get_font_metrics_freetype_with_variations(font_bytes, font_index, &[])
+}
+
+/// Same as `get_font_metrics_freetype`, but returns the metrics of an instance of a variable
+/// font (including the `MVAR` adjustments), see `HbFont::from_bytes_with_variations`
+///
+/// If FreeType can't select the instance, the metrics of the default instance are returned
+/// and `FontMetrics::metrics_suspect` is set.
+pub fn get_font_metrics_freetype_with_variations(font_bytes: &[u8], font_index: i32, variations: &[(hb_tag_t, f32)]) -> FontMetrics {
+    match fix_units_per_em(font_bytes, font_index as u32) {
+        Some(fixed_font) => FontMetrics { metrics_suspect: true, .. get_font_metrics_freetype_inner(&fixed_font, font_index, variations) },
+        None => get_font_metrics_freetype_inner(font_bytes, font_index, variations),
+    }
+}
+
+fn get_font_metrics_freetype_inner(font_bytes: &[u8], font_index: i32, variations: &[(hb_tag_t, f32)]) -> FontMetrics {
+
+    use std::convert::TryInto;
+    use freetype::freetype::{
+        FT_Long, FT_F26Dot6, FT_UInt, FT_Fixed, FT_Error,
+        FT_Init_FreeType, FT_Done_FreeType, FT_New_Memory_Face,
+        FT_Done_Face, FT_Set_Char_Size, FT_Library, FT_Face,
+    };
+
+    // Not exposed by the freetype crate
+    extern "C" {
+        fn FT_Set_Var_Design_Coordinates(face: FT_Face, num_coords: FT_UInt, coords: *mut FT_Fixed) -> FT_Error;
+    }
+
+    const FT_ERR_OK: i32 = 0;
+    const FAKE_FONT_SIZE: FT_F26Dot6 = 1000;
+
+    let mut baseline = FontMetrics {
+        font_size: FAKE_FONT_SIZE as usize,
+        x_ppem: 0,
+        y_ppem: 0,
+        x_scale: 0,
+        y_scale: 0,
+        ascender: 0,
+        descender: 0,
+        height: 0,
+        max_advance: 0,
+        metrics_suspect: false,
+        us_max_context: None,
+    };
+
+    let buf_len: FT_Long = match font_bytes.len().try_into().ok() {
+        Some(s) => s,
+        None => return baseline, // font too large for freetype
+    };
+
+    unsafe {
+        // Initialize library
+        let mut ft_library: FT_Library = ptr::null_mut();
+        let error = FT_Init_FreeType(&mut ft_library);
+        if error != FT_ERR_OK {
+            return baseline;
+        }
+
+        // Load font
+        let mut ft_face: FT_Face = ptr::null_mut();
+        let error = FT_New_Memory_Face(ft_library, font_bytes.as_ptr(), buf_len, font_index as FT_Long, &mut ft_face);
+        if error != FT_ERR_OK {
+            FT_Done_FreeType(ft_library);
+            return baseline;
+        }
+
+        const DPI: u32 = 72;
+
+        // Select the instance of a variable font before the size metrics are calculated
+        let variation_axes = get_table_offset(font_bytes, font_index as u32, *b"fvar")
+            .and_then(|fvar_offset| font_bytes.get(fvar_offset..))
+            .map(parse_variation_axes)
+            .unwrap_or_default();
+
+        // If FreeType can't select the instance, the metrics are the ones of the default instance
+        let mut instance_not_selected = false;
+        if !variations.is_empty() && !variation_axes.is_empty() {
+            let mut coords = variation_axes.iter()
+                .map(|axis| (axis.resolve_value(variations) * 65536.0) as FT_Fixed)
+                .collect::<Vec<_>>();
+            let error = FT_Set_Var_Design_Coordinates(ft_face, coords.len() as FT_UInt, coords.as_mut_ptr());
+            instance_not_selected = error != FT_ERR_OK;
+        }
+
+        // Set font size to fake 1000px
+        let error = FT_Set_Char_Size(ft_face, 0, FAKE_FONT_SIZE, DPI, DPI);
+        if error != FT_ERR_OK {
+            FT_Done_Face(ft_face);
+            FT_Done_FreeType(ft_library);
+            return baseline;
+        }
+
+        let ft_face_ref = &*ft_face;
+        let ft_size_ref = &*ft_face_ref.size;
+        let metrics = ft_size_ref.metrics;
+
+        baseline = FontMetrics {
+            font_size: FAKE_FONT_SIZE as usize,
+            x_ppem: metrics.x_ppem,
+            y_ppem: metrics.y_ppem,
+            x_scale: metrics.x_scale as i64,
+            y_scale: metrics.y_scale as i64,
+            ascender: metrics.ascender as i64,
+            descender: metrics.descender as i64,
+            height: metrics.height as i64,
+            max_advance: metrics.max_advance as i64,
+            metrics_suspect: instance_not_selected,
+            us_max_context: get_table_offset(font_bytes, font_index as u32, *b"OS/2")
+                .and_then(|os2_offset| font_bytes.get(os2_offset..))
+                .and_then(get_max_context),
+        };
+
+        FT_Done_Face(ft_face);
+        FT_Done_FreeType(ft_library);
+    }
+
+    baseline
+}
+
+/// Embedded bitmap of a glyph (i.e. an emoji from the `sbix` or `CBDT` table)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitmapGlyph {
+    /// Width of the bitmap in pixels
+    pub width: usize,
+    /// Height of the bitmap in pixels
+    pub height: usize,
+    /// Pixels, row by row from the top, as RGBA with premultiplied alpha
+    pub rgba: Vec<u8>,
+    /// Horizontal distance from the glyph origin to the left edge of the bitmap (in pixels)
+    pub left: i32,
+    /// Vertical distance from the baseline up to the top edge of the bitmap (in pixels)
+    pub top: i32,
+    /// Size of the strike that the bitmap was taken from, the bitmap has
+    /// to be scaled by `font_size_px / pixels_per_em` when it is drawn
+    pub pixels_per_em: u16,
+}
+
+impl<'a> HbFont<'a> {
+
+    /// Returns the embedded bitmap of a glyph from the strike (bitmap size) that is
+    /// the closest to `pixels_per_em`, decoded to RGBA (`sbix` / `CBDT` PNG images need
+    /// FreeType to be built with PNG support). Grayscale bitmaps are returned as black
+    /// with the gray value as alpha.
+    ///
+    /// Returns `None` if the font doesn't have embedded bitmaps or the glyph isn't in the strike.
+    pub fn get_bitmap_glyph(&self, glyph_index: u16, pixels_per_em: u16) -> Option<BitmapGlyph> {
+
+        use std::convert::TryInto;
+        use freetype::freetype::{
+            FT_Long, FT_Int, FT_UInt, FT_Int32, FT_Init_FreeType, FT_Done_FreeType, FT_New_Memory_Face,
+            FT_Done_Face, FT_Select_Size, FT_Load_Glyph, FT_Library, FT_Face, FT_LOAD_COLOR, FT_Pixel_Mode,
+        };
+
+        const FT_ERR_OK: i32 = 0;
+
+        let has_bitmap_table = [*b"sbix", *b"CBLC", *b"EBLC"].iter()
+            .any(|tag| get_table_offset(self.font_bytes, self.font_index, *tag).is_some());
+
+        if !has_bitmap_table {
+            return None;
+        }
+
+        let buf_len: FT_Long = self.font_bytes.len().try_into().ok()?;
+
+        unsafe {
+
+            let mut ft_library: FT_Library = ptr::null_mut();
+            if FT_Init_FreeType(&mut ft_library) != FT_ERR_OK {
+                return None;
+            }
+
+            let mut ft_face: FT_Face = ptr::null_mut();
+            if FT_New_Memory_Face(ft_library, self.font_bytes.as_ptr(), buf_len, self.font_index as FT_Long, &mut ft_face) != FT_ERR_OK {
+                FT_Done_FreeType(ft_library);
+                return None;
+            }
+
+            let bitmap_glyph = (|| {
+
+                let ft_face_ref = &*ft_face;
+                if ft_face_ref.num_fixed_sizes <= 0 || ft_face_ref.available_sizes.is_null() {
+                    return None;
+                }
+
+                // y_ppem is in 26.6 fixed point
+                let strikes = slice::from_raw_parts(ft_face_ref.available_sizes, ft_face_ref.num_fixed_sizes as usize);
+                let (strike_idx, strike_ppem) = strikes.iter()
+                    .map(|strike| ((strike.y_ppem + 32) >> 6) as u16)
+                    .enumerate()
+                    .min_by_key(|(_, ppem)| (*ppem as i32 - pixels_per_em as i32).abs())?;
+
+                if FT_Select_Size(ft_face, strike_idx as FT_Int) != FT_ERR_OK {
+                    return None;
+                }
+
+                if FT_Load_Glyph(ft_face, glyph_index as FT_UInt, FT_LOAD_COLOR as FT_Int32) != FT_ERR_OK {
+                    return None;
+                }
+
+                let glyph_slot = &*(*ft_face).glyph;
+                let bitmap = &glyph_slot.bitmap;
+                let (width, height) = (bitmap.width as usize, bitmap.rows as usize);
+                if width == 0 || height == 0 || bitmap.buffer.is_null() {
+                    return None;
+                }
+
+                let mut rgba = Vec::with_capacity(width * height * 4);
+                for row in 0..height {
+                    let row_start = bitmap.buffer.offset(row as isize * bitmap.pitch as isize);
+                    if bitmap.pixel_mode == FT_Pixel_Mode::FT_PIXEL_MODE_BGRA as u8 {
+                        for bgra in slice::from_raw_parts(row_start, width * 4).chunks_exact(4) {
+                            rgba.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]]);
+                        }
+                    } else if bitmap.pixel_mode == FT_Pixel_Mode::FT_PIXEL_MODE_GRAY as u8 {
+                        for gray in slice::from_raw_parts(row_start, width) {
+                            rgba.extend_from_slice(&[0, 0, 0, *gray]);
+                        }
+                    } else {
+                        return None;
+                    }
+                }
+
+                Some(BitmapGlyph {
+                    width,
+                    height,
+                    rgba,
+                    left: glyph_slot.bitmap_left,
+                    top: glyph_slot.bitmap_top,
+                    pixels_per_em: strike_ppem,
+                })
+            })();
+
+            FT_Done_Face(ft_face);
+            FT_Done_FreeType(ft_library);
+
+            bitmap_glyph
+        }
+    }
+}
+
+#[test]
+fn test_shape_runs_with_different_languages() {
+
+    // KoHo has a `locl` substitution for Romanian, but not for English
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    const LATN: hb_tag_t = create_hb_tag(('l', 'a', 't', 'n'));
+    const ENG: hb_tag_t = create_hb_tag(('E', 'N', 'G', ' '));
+    const ROM: hb_tag_t = create_hb_tag(('R', 'O', 'M', ' '));
+
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+
+    let english = ['a', 'ş'];
+    let romanian = ['a', 'ş'];
+
+    let shaped_runs = shape_runs(&[(&english, LATN, ENG), (&romanian, LATN, ROM)], &hb_scaled_font);
+
+    // Clusters of the second run continue where the first run left off
+    let clusters = shaped_runs.glyph_infos.iter().map(|i| i.cluster).collect::<Vec<_>>();
+    assert_eq!(clusters, vec![0, 1, 2, 3]);
+
+    // Same characters, but the language-specific glyph differs
+    let glyphs = shaped_runs.glyph_infos.iter().map(|i| i.codepoint).collect::<Vec<_>>();
+    assert_eq!(glyphs[0], glyphs[2]);
+    assert!(glyphs[1] != glyphs[3]);
+}
+
+#[test]
+fn test_east_asian_width() {
+    assert_eq!(east_asian_width('A'), EastAsianWidth::Narrow);
+    assert_eq!(east_asian_width('世'), EastAsianWidth::Wide);
+    assert_eq!(east_asian_width('Ａ'), EastAsianWidth::Fullwidth);
+    assert_eq!(east_asian_width('ｱ'), EastAsianWidth::Halfwidth);
+    assert_eq!(east_asian_width('ก'), EastAsianWidth::Neutral);
+
+    assert_eq!(east_asian_width('°'), EastAsianWidth::Ambiguous);
+    assert!(!east_asian_width('°').is_wide(false));
+    assert!(east_asian_width('°').is_wide(true));
+}
+
+#[test]
+fn test_math_constants() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+    assert_eq!(hb_scaled_font.math_constants(), None);
+    assert_eq!(hb_scaled_font.math_italic_correction(0), None);
+
+    // MathConstants: percentages + 2 heights, 51 MathValueRecords (value, device table), 1 percentage
+    const AXIS_HEIGHT: usize = 1;
+    const FRACTION_RULE_THICKNESS: usize = 34;
+    let mut math_constants = vec![80, 60, 0, 0];
+    for record in 0..51 {
+        let value = match record {
+            AXIS_HEIGHT => 250,
+            FRACTION_RULE_THICKNESS => 125,
+            _ => 0,
+        };
+        math_constants.extend_from_slice(&[value, 0]);
+    }
+    math_constants.push(0);
+
+    let mut math = vec![1, 0, 10, 10 + math_constants.len() as u16 * 2, 0]; // version, constants, glyph info, variants
+    math.extend(math_constants);
+    math.extend_from_slice(&[8, 0, 0, 0]); // MathGlyphInfo: only italics corrections
+    math.extend_from_slice(&[8, 1, 375, 0]); // coverage, count, glyph 1: 375 units
+    math.extend_from_slice(&[1, 1, 1]); // coverage format 1: glyph 1
+
+    let math_font_bytes = build_test_font(&[
+        (*b"MATH", to_be_bytes(&math)),
+        (*b"head", build_test_head()),
+        (*b"maxp", to_be_bytes(&[0, 0x5000, 2])),
+    ]);
+
+    // 1000 units per em, at 16px the values are in font units * 16 / 1000
+    let hb_font = HbFont::from_bytes(&math_font_bytes, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+    let math_constants = hb_scaled_font.math_constants().unwrap();
+
+    assert_eq!(math_constants.script_percent_scale_down, 80);
+    assert_eq!(math_constants.script_script_percent_scale_down, 60);
+    assert_eq!(math_constants.axis_height, 4.0);
+    assert_eq!(math_constants.fraction_rule_thickness, 2.0);
+    assert_eq!(hb_scaled_font.math_italic_correction(1), Some(6.0));
+    assert_eq!(hb_scaled_font.math_italic_correction(0), Some(0.0));
+}
+
+/// Builds a font file that only contains the given tables (sorted by tag),
+/// for testing table parsers without having to ship a special font
+#[cfg(test)]
+fn build_test_font(tables: &[([u8;4], Vec<u8>)]) -> Vec<u8> {
+
+    let mut font = Vec::new();
+    font.extend_from_slice(&0x00010000_u32.to_be_bytes());
+    font.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+    font.extend_from_slice(&[0; 6]); // search range, entry selector, range shift
+
+    let mut table_offset = 12 + tables.len() * 16;
+    for (tag, table) in tables {
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&[0; 4]); // checksum
+        font.extend_from_slice(&(table_offset as u32).to_be_bytes());
+        font.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        table_offset += (table.len() + 3) & !3;
+    }
+
+    for (_, table) in tables {
+        font.extend_from_slice(table);
+        font.resize((font.len() + 3) & !3, 0);
+    }
+
+    font
+}
+
+/// `head` table of a font with 1000 units per em
+#[cfg(test)]
+fn build_test_head() -> Vec<u8> {
+    let mut head = to_be_bytes(&[1, 0, 1, 0, 0, 0, 0x5F0F, 0x3CF5, 0, 1000]);
+    head.resize(54, 0);
+    head
+}
+
+/// `hhea` / `vhea` table (ascender 800, descender -200) with the maximum advance
+/// and the number of advances in the `hmtx` / `vmtx` table
+#[cfg(test)]
+fn build_test_metrics_header(max_advance: u16, number_of_long_metrics: u16) -> Vec<u8> {
+    let mut header = to_be_bytes(&[1, 0, 800, 0xFF38, 0, max_advance]);
+    header.resize(34, 0);
+    header.extend(to_be_bytes(&[number_of_long_metrics]));
+    header
+}
+
+/// Font with the glyphs of "漢字かな" (glyphs 2 to 5, one em wide), a space (glyph 1, half
+/// an em wide), "A" (glyph 6, 0.6 em wide) and the brackets "「」" (glyphs 7 and 8), for testing
+/// vertical text: in the `vmtx` table, all glyphs except the space advance by 1.25 em, the space
+/// by half an em. Only "「" has a vertical alternate (glyph 9, `vert` feature in the `GSUB` table).
+/// The glyphs are empty, units per em are 1000.
+#[cfg(test)]
+pub(crate) fn build_test_cjk_font() -> Vec<u8> {
+
+    // Single substitution (format 2) of glyph 7 by glyph 9, coverage table at offset 8
+    let vert_subtable = to_be_bytes(&[2, 8, 1, 9, 1, 1, 7]);
+
+    build_test_font(&[
+        (*b"GSUB", build_test_layout_table(*b"DFLT", &[(*b"vert", 0)], &[(1, vec![vert_subtable])])),
+        (*b"cmap", build_test_cmap(&[(' ', 1), ('A', 6), ('「', 7), ('」', 8), ('か', 4), ('な', 5), ('字', 3), ('漢', 2)])),
+        (*b"glyf", vec![0; 4]),
+        (*b"head", build_test_head()),
+        (*b"hhea", build_test_metrics_header(1000, 10)),
+        (*b"hmtx", to_be_bytes(&[1000, 0, 500, 0, 1000, 0, 1000, 0, 1000, 0, 1000, 0, 600, 0, 1000, 0, 1000, 0, 1000, 0])),
+        (*b"loca", to_be_bytes(&[0; 11])),
+        (*b"maxp", to_be_bytes(&[0, 0x5000, 10])),
+        (*b"vhea", build_test_metrics_header(1250, 10)),
+        (*b"vmtx", to_be_bytes(&[1250, 0, 500, 0, 1250, 0, 1250, 0, 1250, 0, 1250, 0, 1250, 0, 1250, 0, 1250, 0, 1250, 0])),
+    ])
+}
+
+/// Tables of a variable font with a `wght` axis (100 to 900, default 400), where the advance of
+/// glyph 1 (`a`) grows from 500 to 700 font units (of 1000) towards the maximum weight (`HVAR`).
+/// The glyphs are empty, the `gvar` table only exists because FreeType requires it for variable fonts.
+/// The `axis_size` of the `fvar` table is normally 20, larger sizes are valid, but rejected by FreeType.
+#[cfg(test)]
+fn get_test_variable_font_tables(axis_size: u16) -> Vec<([u8;4], Vec<u8>)> {
+
+    let mut fvar = to_be_bytes(&[1, 0, 16, 2, 1, axis_size, 0, 8]);
+    fvar.extend_from_slice(b"wght");
+    fvar.extend(to_be_bytes(&[100, 0, 400, 0, 900, 0, 0, 256]));
+    fvar.resize(16 + axis_size as usize, 0);
+
+    let hvar = to_be_bytes(&[
+        1, 0, 0, 20, 0, 0, 0, 0, 0, 0,  // version, item variation store at 20, no mappings
+        1, 0, 12, 1, 0, 22,             // item variation store: region list at 12, one item variation data at 22
+        1, 1, 0, 0x4000, 0x4000,        // one region: wght from 0 to 1 (peak at 1)
+        2, 1, 1, 0,                     // two items (glyphs 0 and 1) with one 16-bit delta for region 0
+        0, 200,                         // glyph 0: + 0, glyph 1: + 200
+    ]);
+
+    vec![
+        (*b"HVAR", hvar),
+        (*b"cmap", build_test_cmap(&[('a', 1)])),
+        (*b"fvar", fvar),
+        (*b"glyf", vec![0; 4]),
+        (*b"gvar", to_be_bytes(&[1, 0, 1, 0, 0, 26, 2, 0, 0, 26, 0, 0, 0])),
+        (*b"head", build_test_head()),
+        (*b"hhea", build_test_metrics_header(700, 2)),
+        (*b"hmtx", to_be_bytes(&[500, 0, 500, 0])),
+        (*b"loca", to_be_bytes(&[0, 0, 0])),
+        (*b"maxp", to_be_bytes(&[0, 0x5000, 2])),
+    ]
+}
+
+/// `COLR` + `CPAL` tables where glyph 5 consists of the layers 6 (red) and 7 (text color)
+#[cfg(test)]
+fn get_test_color_tables() -> Vec<([u8;4], Vec<u8>)> {
+
+    let colr: Vec<u8> = vec![
+        0, 0,           // version
+        0, 1,           // number of base glyph records
+        0, 0, 0, 14,    // offset to base glyph records
+        0, 0, 0, 20,    // offset to layer records
+        0, 2,           // number of layer records
+        0, 5, 0, 0, 0, 2, // base glyph 5: first layer 0, 2 layers
+        0, 6, 0, 0,     // layer glyph 6, palette entry 0
+        0, 7, 255, 255, // layer glyph 7, text color
+    ];
+
+    let cpal: Vec<u8> = vec![
+        0, 0,           // version
+        0, 1,           // number of palette entries
+        0, 1,           // number of palettes
+        0, 1,           // number of color records
+        0, 0, 0, 14,    // offset to color records
+        0, 0,           // first color record of palette 0
+        0, 0, 255, 255, // BGRA: red
+    ];
+
+    vec![(*b"COLR", colr), (*b"CPAL", cpal)]
+}
+
+#[cfg(test)]
+fn to_be_bytes(values: &[u16]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_be_bytes().to_vec()).collect()
+}
+
+/// `cmap` table with a format 4 subtable that maps each character to a glyph
+/// (one segment per character, so the characters have to be sorted)
+#[cfg(test)]
+fn build_test_cmap(mappings: &[(char, u16)]) -> Vec<u8> {
+
+    let seg_count = mappings.len() + 1;
+    let codepoints = mappings.iter().map(|(c, _)| *c as u16).chain(Some(0xFFFF)).collect::<Vec<_>>();
+
+    let mut subtable = to_be_bytes(&[4, (16 + seg_count * 8) as u16, 0, (seg_count * 2) as u16, 0, 0, 0]);
+    subtable.extend(to_be_bytes(&codepoints));  // end codes
+    subtable.extend(to_be_bytes(&[0]));         // padding
+    subtable.extend(to_be_bytes(&codepoints));  // start codes
+    let id_deltas = mappings.iter().map(|(c, glyph)| glyph.wrapping_sub(*c as u16)).chain(Some(1)).collect::<Vec<_>>();
+    subtable.extend(to_be_bytes(&id_deltas));
+    subtable.extend(to_be_bytes(&vec![0; seg_count])); // id range offsets
+
+    let mut cmap = to_be_bytes(&[0, 1, 3, 1, 0, 12]); // Windows Unicode BMP subtable at 12
+    cmap.extend(subtable);
+    cmap
+}
+
+/// `GSUB` / `GPOS` table with a single script, whose default language system uses all
+/// `features` (tag + lookup index, sorted by tag). Each lookup is a lookup type and
+/// the bytes of its subtables, see `build_test_extension_subtable`.
+#[cfg(test)]
+fn build_test_layout_table(script: [u8;4], features: &[([u8;4], u16)], lookups: &[(u16, Vec<Vec<u8>>)]) -> Vec<u8> {
+    build_test_layout_table_for_language(script, None, features, lookups)
+}
+
+/// Same as `build_test_layout_table`, but if a `language` is given, only the language
+/// system of that language uses the `features` (the default language system has none)
+#[cfg(test)]
+fn build_test_layout_table_for_language(script: [u8;4], language: Option<[u8;4]>, features: &[([u8;4], u16)], lookups: &[(u16, Vec<Vec<u8>>)]) -> Vec<u8> {
+
+    let tag = |t: &[u8;4]| [u16::from_be_bytes([t[0], t[1]]), u16::from_be_bytes([t[2], t[3]])];
+
+    let mut script_list = to_be_bytes(&[1, tag(&script)[0], tag(&script)[1], 8]);
+    match language {
+        None => script_list.extend(to_be_bytes(&[4, 0, 0, 0xFFFF, features.len() as u16])),
+        // default language system at 10 (without features), the language system at 16
+        Some(language) => script_list.extend(to_be_bytes(&[10, 1, tag(&language)[0], tag(&language)[1], 16, 0, 0xFFFF, 0, 0, 0xFFFF, features.len() as u16])),
+    }
+    script_list.extend(to_be_bytes(&(0..features.len() as u16).collect::<Vec<_>>()));
+
+    let mut feature_list = to_be_bytes(&[features.len() as u16]);
+    for (feature_idx, (feature_tag, _)) in features.iter().enumerate() {
+        let feature_offset = 2 + features.len() * 6 + feature_idx * 6;
+        feature_list.extend(to_be_bytes(&[tag(feature_tag)[0], tag(feature_tag)[1], feature_offset as u16]));
+    }
+    for (_, lookup_idx) in features {
+        feature_list.extend(to_be_bytes(&[0, 1, *lookup_idx]));
+    }
+
+    let mut lookup_list = to_be_bytes(&[lookups.len() as u16]);
+    let mut lookup_tables = Vec::new();
+    for (lookup_type, subtables) in lookups {
+        lookup_list.extend(to_be_bytes(&[(2 + lookups.len() * 2 + lookup_tables.len()) as u16]));
+        let mut subtable_offset = 6 + subtables.len() * 2;
+        lookup_tables.extend(to_be_bytes(&[*lookup_type, 0, subtables.len() as u16]));
+        for subtable in subtables {
+            lookup_tables.extend(to_be_bytes(&[subtable_offset as u16]));
+            subtable_offset += subtable.len();
+        }
+        for subtable in subtables {
+            lookup_tables.extend_from_slice(subtable);
+        }
+    }
+    lookup_list.extend(lookup_tables);
+
+    let feature_list_offset = 10 + script_list.len();
+    let lookup_list_offset = feature_list_offset + feature_list.len();
+    let mut table = to_be_bytes(&[1, 0, 10, feature_list_offset as u16, lookup_list_offset as u16]);
+    table.extend(script_list);
+    table.extend(feature_list);
+    table.extend(lookup_list);
+    table
+}
+
+/// Subtable of an extension lookup (`GSUB` type 7 / `GPOS` type 9) that contains the `subtable` of the given type
+#[cfg(test)]
+fn build_test_extension_subtable(lookup_type: u16, subtable: Vec<u8>) -> Vec<u8> {
+    let mut extension = to_be_bytes(&[1, lookup_type, 0, 8]);
+    extension.extend(subtable);
+    extension
+}
+
+#[test]
+fn test_get_color_glyphs() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    assert_eq!(HbFont::from_bytes(FONT, 0).get_color_glyphs(), None);
+
+    let font_bytes = build_test_font(&get_test_color_tables());
+    let color_glyphs = HbFont::from_bytes(&font_bytes, 0).get_color_glyphs().unwrap();
+    let red = ColorU { r: 255, g: 0, b: 0, a: 255 };
+
+    assert_eq!(color_glyphs.layers.get(&5), Some(&vec![(6, 0), (7, PALETTE_INDEX_TEXT_COLOR)]));
+    assert_eq!(color_glyphs.palettes, vec![vec![red]]);
+    assert_eq!(color_glyphs.get_layer_color(0, 0), Some(red));
+    assert_eq!(color_glyphs.get_layer_color(0, PALETTE_INDEX_TEXT_COLOR), None);
+}
+
+#[test]
+fn test_glyph_alternates() {
+
+    fn get_glyph(hb_scaled_font: &HbScaledFont, text: &str, extra_features: &[hb_feature_t]) -> u32 {
+        let hb_buffer = HbBuffer::from_str(text);
+        shape_word_hb_with_features(&hb_buffer, hb_scaled_font, extra_features).glyph_infos[0].codepoint
+    }
+
+    const SALT_TAG: hb_tag_t = create_hb_tag(('s', 'a', 'l', 't'));
+
+    // No font with `aalt` is bundled with the repository: "a" (glyph 1) has the alternates 3 + 4,
+    // "b" (glyph 2) has the alternate 5, stored in two subtables of an extension lookup.
+    // The `salt` feature (stylistic alternates) replaces "b" with glyph 6.
+    let alternate_subtable = |glyph: u16, alternates: &[u16]| {
+        let mut subtable = to_be_bytes(&[1, 10 + alternates.len() as u16 * 2, 1, 8, alternates.len() as u16]);
+        subtable.extend(to_be_bytes(alternates));
+        subtable.extend(to_be_bytes(&[1, 1, glyph])); // coverage
+        subtable
+    };
+    let gsub = build_test_layout_table(*b"latn", &[(*b"aalt", 0), (*b"salt", 1)], &[
+        (7, vec![
+            build_test_extension_subtable(3, alternate_subtable(2, &[5])),
+            build_test_extension_subtable(3, alternate_subtable(1, &[3, 4])),
+        ]),
+        (3, vec![alternate_subtable(2, &[6])]),
+    ]);
+    let aalt_font_bytes = build_test_font(&[
+        (*b"GSUB", gsub),
+        (*b"cmap", build_test_cmap(&[('a', 1), ('b', 2)])),
+        (*b"maxp", to_be_bytes(&[0, 0x5000, 7])),
+    ]);
+
+    let hb_font = HbFont::from_bytes(&aalt_font_bytes, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+    let default_glyph = get_glyph(&hb_scaled_font, "a", &[]);
+    assert_eq!(default_glyph, 1);
+    let alternates = hb_font.alternates_for(default_glyph as u16);
+    assert_eq!(alternates, vec![3, 4]);
+    assert_eq!(hb_font.alternates_for(2), vec![5]);
+    assert_eq!(hb_font.get_glyph_alternates(2, SALT_TAG), vec![6]);
+    assert!(hb_font.get_glyph_alternates(1, SALT_TAG).is_empty());
+
+    let alternate_glyph = get_glyph(&hb_scaled_font, "a", &[select_alternate_glyph(0, 1)]);
+    assert_ne!(alternate_glyph, default_glyph);
+    assert_eq!(alternate_glyph, alternates[0] as u32);
+}
+
+#[test]
+fn test_noncharacters_are_replaced() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+
+    assert!(is_noncharacter(0xFDD0));
+    assert!(is_noncharacter(0x1FFFF));
+    assert!(!is_noncharacter(0xFFFD));
+    assert_eq!(sanitize_codepoint(0xD800), REPLACEMENT_CHARACTER);
+    assert_eq!(sanitize_codepoint(0x110000), REPLACEMENT_CHARACTER);
+    assert_eq!(sanitize_codepoint('a' as u32), 'a' as u32);
+
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let replacement_glyph = hb_font.lookup_glyph_index(REPLACEMENT_CHARACTER);
+    assert_eq!(hb_font.lookup_glyph_index(0xFFFF), replacement_glyph);
+    assert_eq!(hb_font.lookup_glyph_index(0xD800), replacement_glyph);
+    assert_eq!(hb_font.lookup_glyph_index(u32::MAX), replacement_glyph);
+    assert_ne!(hb_font.lookup_glyph_index('a' as u32), 0);
+
+    // Shaping keeps the byte offsets of the characters after the noncharacter
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+    let hb_buffer = HbBuffer::from_str("\u{FDD0}a");
+    let shaped_word = shape_word_hb(&hb_buffer, &hb_scaled_font);
+    assert_eq!(shaped_word.glyph_infos.len(), 2);
+    assert_eq!(shaped_word.glyph_infos[0].codepoint, replacement_glyph);
+    assert_eq!(shaped_word.glyph_infos[1].cluster, 3);
+}
+
+#[test]
+fn test_color_glyph_palettes() {
+
+    let cpal: Vec<u8> = vec![
+        0, 0,           // version
+        0, 1,           // number of palette entries
+        0, 2,           // number of palettes
+        0, 2,           // number of color records
+        0, 0, 0, 16,    // offset to color records
+        0, 0,           // first color record of palette 0
+        0, 1,           // first color record of palette 1
+        0, 0, 255, 255, // BGRA: red
+        255, 0, 0, 255, // BGRA: blue
+    ];
+
+    let mut tables = get_test_color_tables();
+    tables[1].1 = cpal;
+
+    let font_bytes = build_test_font(&tables);
+    let color_glyphs = HbFont::from_bytes(&font_bytes, 0).get_color_glyphs().unwrap();
+    let red = ColorU { r: 255, g: 0, b: 0, a: 255 };
+    let blue = ColorU { r: 0, g: 0, b: 255, a: 255 };
+
+    assert_eq!(color_glyphs.palette_count(), 2);
+    assert_eq!(color_glyphs.get_color_glyph(5, 0), Some(vec![(6, Some(red)), (7, None)]));
+    assert_eq!(color_glyphs.get_color_glyph(5, 1), Some(vec![(6, Some(blue)), (7, None)]));
+    // Out-of-range palettes fall back to the default palette
+    assert_eq!(color_glyphs.get_color_glyph(5, 7), Some(vec![(6, Some(red)), (7, None)]));
+    assert_eq!(color_glyphs.get_color_glyph(6, 0), None);
+
+    let halfway = ColorU { r: 128, g: 0, b: 128, a: 255 };
+    assert_eq!(color_glyphs.get_color_glyph_blended(5, 0, 1, 0.5), Some(vec![(6, Some(halfway)), (7, None)]));
+    assert_eq!(interpolate_color(red, blue, 2.0), blue);
+}
+
+#[test]
+fn test_tracking_adjustment() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    assert_eq!(HbFont::from_bytes(FONT, 0).tracking_adjustment(16.0), 0.0);
+
+    let mut head = vec![0; 54];
+    head[18..20].copy_from_slice(&1000_u16.to_be_bytes()); // units per em
+
+    let trak: Vec<u8> = vec![
+        0, 1, 0, 0,     // version
+        0, 0,           // format
+        0, 12,          // offset to horizontal track data
+        0, 0,           // offset to vertical track data
+        0, 0,           // reserved
+        0, 1,           // number of tracks
+        0, 2,           // number of sizes
+        0, 0, 0, 28,    // offset to size table
+        0, 0, 0, 0,     // track 0.0 (normal)
+        1, 0,           // name index
+        0, 36,          // offset to the values of the track
+        0, 12, 0, 0,    // 12pt
+        0, 24, 0, 0,    // 24pt
+        255, 236,       // -20 at 12pt
+        255, 216,       // -40 at 24pt
+    ];
+
+    let font_bytes = build_test_font(&[(*b"head", head), (*b"trak", trak)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+
+    assert_eq!(hb_font.tracking_adjustment(12.0), -20.0 * 12.0 / 1000.0);
+    assert_eq!(hb_font.tracking_adjustment(18.0), -30.0 * 18.0 / 1000.0);
+    assert_eq!(hb_font.tracking_adjustment(48.0), -40.0 * 48.0 / 1000.0);
+}
+
+#[test]
+fn test_broken_units_per_em_fallback() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+
+    let metrics = get_font_metrics_freetype(FONT, 0);
+    assert!(!metrics.metrics_suspect);
+
+    let units_per_em_offset = get_table_offset(FONT, 0, *b"head").unwrap() + 18;
+    let mut broken_font = FONT.to_vec();
+    broken_font[units_per_em_offset..(units_per_em_offset + 2)].copy_from_slice(&1_u16.to_be_bytes());
+
+    // KoHo has TrueType outlines, so the fallback is 2048 units per em
+    let fixed_font = fix_units_per_em(&broken_font, 0).unwrap();
+    assert_eq!(read_u16(&fixed_font, units_per_em_offset), Some(2048));
+
+    let broken_metrics = get_font_metrics_freetype(&broken_font, 0);
+    assert!(broken_metrics.metrics_suspect);
+    assert!(broken_metrics.ascender > 0);
+    assert!(broken_metrics.ascender < broken_metrics.font_size as i64 * 64 * 2);
+}
+
+#[test]
+fn test_max_context() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    const LATN: hb_tag_t = create_hb_tag(('l', 'a', 't', 'n'));
+    const ENG: hb_tag_t = create_hb_tag(('E', 'N', 'G', ' '));
+
+    let max_context_offset = get_table_offset(FONT, 0, *b"OS/2").unwrap() + 92;
+    let with_max_context = |max_context: u16| {
+        let mut font = FONT.to_vec();
+        font[max_context_offset..(max_context_offset + 2)].copy_from_slice(&max_context.to_be_bytes());
+        font
+    };
+
+    let original_font = HbFont::from_bytes(FONT, 0);
+    assert_eq!(original_font.max_context(), Some(32));
+    let original_glyphs = shape_to_glyph_ids("office", &HbScaledFont::from_font(&original_font, 16.0), LATN, ENG);
+
+    // Small context: shapes exactly like the original font
+    let small_context_bytes = with_max_context(3);
+    let small_context_font = HbFont::from_bytes(&small_context_bytes, 0);
+    assert_eq!(small_context_font.max_context(), Some(3));
+    assert_eq!(small_context_font.context_window(), 3);
+    assert!(!small_context_font.has_excessive_max_context());
+    assert_eq!(get_context_range(&(10..12), 100, small_context_font.context_window()), 7..15);
+    assert_eq!(get_context_range(&(1..12), 13, small_context_font.context_window()), 0..13);
+    assert_eq!(get_font_metrics_freetype(&small_context_bytes, 0).us_max_context, Some(3));
+    assert_eq!(shape_to_glyph_ids("office", &HbScaledFont::from_font(&small_context_font, 16.0), LATN, ENG), original_glyphs);
+
+    // Pathologically large context: still shapes, but the context window is bounded
+    let large_context_bytes = with_max_context(u16::MAX);
+    let large_context_font = HbFont::from_bytes(&large_context_bytes, 0);
+    assert!(large_context_font.has_excessive_max_context());
+    assert_eq!(large_context_font.context_window(), MAX_CONTEXT_LIMIT as usize);
+    assert!(!shape_to_glyph_ids("office", &HbScaledFont::from_font(&large_context_font, 16.0), LATN, ENG).is_empty());
+
+    // OS/2 version 1 doesn't have a usMaxContext field
+    let mut os2 = vec![0; 86];
+    os2[1] = 1;
+    let old_font_bytes = build_test_font(&[(*b"OS/2", os2)]);
+    let old_font = HbFont::from_bytes(&old_font_bytes, 0);
+    assert_eq!(old_font.max_context(), None);
+    assert_eq!(old_font.context_window(), DEFAULT_MAX_CONTEXT as usize);
+}
+
+#[test]
+fn test_zero_mark_advance() {
+
+    use azul_core::app_resources::HbVarIntT;
+
+    let gdef: Vec<u8> = vec![
+        0, 1, 0, 0,     // version
+        0, 12,          // offset to glyph class definitions
+        0, 0,           // offset to attachment point list
+        0, 0,           // offset to ligature caret list
+        0, 0,           // offset to mark attachment class definitions
+        0, 1,           // class definition format 1
+        0, 5,           // start glyph
+        0, 2,           // glyph count
+        0, 1,           // glyph 5: base glyph
+        0, 3,           // glyph 6: mark
+    ];
+
+    let font_bytes = build_test_font(&[(*b"GDEF", gdef)]);
+    let mut hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert!(!hb_font.is_mark_glyph(5));
+    assert!(hb_font.is_mark_glyph(6));
+
+    // glyph 6 is a mark with a (wrong) non-zero advance
+    let glyph_infos = [5, 6].iter().map(|glyph| GlyphInfo {
+        codepoint: *glyph,
+        mask: 0,
+        cluster: 0,
+        var1: HbVarIntT { u32: 0 },
+        var2: HbVarIntT { u32: 0 },
+    }).collect::<Vec<_>>();
+    let get_positions = || (0..2).map(|_| GlyphPosition {
+        x_advance: 500,
+        y_advance: 0,
+        x_offset: 0,
+        y_offset: 0,
+        var: HbVarIntT { u32: 0 },
+    }).collect::<Vec<_>>();
+
+    let mut glyph_positions = get_positions();
+    hb_font.apply_zero_mark_advance(&glyph_infos, &mut glyph_positions);
+    assert_eq!(glyph_positions[0].x_advance, 500);
+    assert_eq!(glyph_positions[1].x_advance, 0);
+
+    hb_font.set_force_zero_mark_advance(false);
+    let mut glyph_positions = get_positions();
+    hb_font.apply_zero_mark_advance(&glyph_infos, &mut glyph_positions);
+    assert_eq!(glyph_positions[1].x_advance, 500);
+}
+
+#[test]
+fn test_get_advance_short_hmtx() {
+
+    let mut hhea = vec![0; 36];
+    hhea[10..12].copy_from_slice(&900_u16.to_be_bytes()); // advance width max
+    hhea[34..36].copy_from_slice(&2_u16.to_be_bytes());   // number of h metrics
+
+    let hmtx: Vec<u8> = vec![
+        1, 244, 0, 0,   // glyph 0: advance 500
+        2, 88, 0, 0,    // glyph 1: advance 600
+        0, 10,          // glyph 2: left side bearing only
+        0, 20,          // glyph 3: left side bearing only
+    ];
+
+    let font_bytes = build_test_font(&[(*b"hhea", hhea.clone()), (*b"hmtx", hmtx.clone())]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert_eq!(hb_font.get_advance(0), Some(500));
+    assert_eq!(hb_font.get_advance(1), Some(600));
+    assert_eq!(hb_font.get_advance(3), Some(600));
+
+    // truncated hmtx: only the first metric record is complete
+    let font_bytes = build_test_font(&[(*b"hhea", hhea.clone()), (*b"hmtx", hmtx[..6].to_vec())]);
+    assert_eq!(HbFont::from_bytes(&font_bytes, 0).get_advance(1), Some(500));
+
+    // no hmtx at all
+    let font_bytes = build_test_font(&[(*b"hhea", hhea)]);
+    assert_eq!(HbFont::from_bytes(&font_bytes, 0).get_advance(1), Some(900));
+}
+
+#[test]
+fn test_get_glyph_outline() {
+
+    let p = |x: f32, y: f32| LayoutPoint::new(x, y);
+    let i16_bytes = |values: &[i16]| values.iter().flat_map(|v| v.to_be_bytes().to_vec()).collect::<Vec<u8>>();
+
+    // glyph 1: square, all points on-curve (flags use the repeat flag)
+    let mut square = i16_bytes(&[1, 0, 0, 100, 100, 3, 0]);
+    square.extend_from_slice(&[0x09, 3]);
+    square.extend(i16_bytes(&[0, 100, 0, -100, 0, 0, 100, 0]));
+
+    // glyph 2: composite of glyph 1, moved by (100, 50)
+    let composite = i16_bytes(&[-1, 0, 0, 200, 150, 0x0003, 1, 100, 50]);
+
+    // glyph 3: on-curve, off-curve, on-curve point
+    let mut curve = i16_bytes(&[1, 0, 0, 100, 100, 2, 0]);
+    curve.extend_from_slice(&[0x01, 0x00, 0x01]);
+    curve.extend(i16_bytes(&[0, 50, 50, 0, 100, -100]));
+    curve.push(0); // padding, glyphs have to start at an even offset
+
+    let mut glyf = Vec::new();
+    let mut loca = vec![0, 0, 0, 0]; // glyph 0 is empty
+    for glyph in &[square, composite, curve] {
+        glyf.extend_from_slice(glyph);
+        loca.extend_from_slice(&((glyf.len() / 2) as u16).to_be_bytes());
+    }
+
+    let head = vec![0; 54]; // short loca offsets
+    let font_bytes = build_test_font(&[(*b"glyf", glyf), (*b"head", head), (*b"loca", loca)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+
+    assert_eq!(hb_font.get_glyph_outline(0), None);
+
+    let square_outline = vec![
+        OutlineSegment::MoveTo(p(0.0, 0.0)),
+        OutlineSegment::LineTo(p(100.0, 0.0)),
+        OutlineSegment::LineTo(p(100.0, 100.0)),
+        OutlineSegment::LineTo(p(0.0, 100.0)),
+        OutlineSegment::LineTo(p(0.0, 0.0)),
+    ];
+    assert_eq!(hb_font.get_glyph_outline(1), Some(GlyphOutline { contours: vec![square_outline] }));
+
+    let composite_outline = hb_font.get_glyph_outline(2).unwrap();
+    assert_eq!(composite_outline.contours[0][0], OutlineSegment::MoveTo(p(100.0, 50.0)));
+    assert_eq!(composite_outline.contours[0][2], OutlineSegment::LineTo(p(200.0, 150.0)));
+
+    assert_eq!(hb_font.get_glyph_outline(3), Some(GlyphOutline { contours: vec![vec![
+        OutlineSegment::MoveTo(p(0.0, 0.0)),
+        OutlineSegment::QuadTo(p(50.0, 100.0), p(100.0, 0.0)),
+        OutlineSegment::LineTo(p(0.0, 0.0)),
+    ]] }));
+
+    // real font: the space has no outline, "o" has an outer and an inner contour
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let koho = HbFont::from_bytes(FONT, 0);
+    assert_eq!(koho.get_glyph_outline(koho.lookup_glyph_index(' ' as u32) as u16), None);
+    assert_eq!(koho.get_glyph_outline(koho.lookup_glyph_index('o' as u32) as u16).unwrap().contours.len(), 2);
+}
+
+#[test]
+fn test_script_baseline() {
+
+    const DEV2: hb_tag_t = create_hb_tag(('d', 'e', 'v', '2'));
+    const LATN: hb_tag_t = create_hb_tag(('l', 'a', 't', 'n'));
+    const ROMN: hb_tag_t = create_hb_tag(('r', 'o', 'm', 'n'));
+    const HANG: hb_tag_t = create_hb_tag(('h', 'a', 'n', 'g'));
+    const IDEO: hb_tag_t = create_hb_tag(('i', 'd', 'e', 'o'));
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    assert_eq!(HbFont::from_bytes(FONT, 0).script_baseline(DEV2, HANG), None);
+
+    let base: Vec<u8> = vec![
+        0, 1, 0, 0,         // version
+        0, 8,               // offset to horizontal axis
+        0, 0,               // offset to vertical axis
+        // horizontal axis
+        0, 4,               // offset to base tag list
+        0, 14,              // offset to base script list
+        0, 2,               // base tag count
+        b'r', b'o', b'm', b'n',
+        b'h', b'a', b'n', b'g',
+        // base script list
+        0, 1,               // base script count
+        b'd', b'e', b'v', b'2',
+        0, 8,               // offset to base script
+        // base script
+        0, 6,               // offset to base values
+        0, 0,               // offset to default min / max
+        0, 0,               // base lang sys count
+        // base values
+        0, 1,               // default baseline index
+        0, 2,               // base coord count
+        0, 8,               // offset to roman baseline
+        0, 12,              // offset to hanging baseline
+        0, 1, 0, 0,         // format 1, roman baseline at 0
+        0, 1, 2, 188,       // format 1, hanging baseline at 700
+    ];
+
+    let font_bytes = build_test_font(&[(*b"BASE", base)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+
+    assert_eq!(hb_font.script_baseline(DEV2, HANG), Some(700));
+    assert_eq!(hb_font.script_baseline(DEV2, ROMN), Some(0));
+    assert_eq!(hb_font.script_baseline(DEV2, IDEO), None);
+    assert_eq!(hb_font.script_baseline(LATN, ROMN), None);
+}
+
+#[test]
+fn test_variation_axes() {
+
+    const WGHT: hb_tag_t = create_hb_tag(('w', 'g', 'h', 't'));
+    const WDTH: hb_tag_t = create_hb_tag(('w', 'd', 't', 'h'));
+
+    let fixed = |v: i32| (v << 16).to_be_bytes().to_vec();
+
+    let mut fvar: Vec<u8> = vec![
+        0, 1, 0, 0,     // version
+        0, 16,          // offset to axes
+        0, 2,           // reserved
+        0, 2,           // axis count
+        0, 20,          // axis size
+        0, 0,           // instance count
+        0, 0,           // instance size
+    ];
+    for (tag, min, default, max) in &[(b"wght", 100, 400, 900), (b"wdth", 75, 100, 100)] {
+        fvar.extend_from_slice(*tag);
+        fvar.extend(fixed(*min));
+        fvar.extend(fixed(*default));
+        fvar.extend(fixed(*max));
+        fvar.extend_from_slice(&[0, 0, 1, 0]); // flags, name id
+    }
+
+    let font_bytes = build_test_font(&[(*b"fvar", fvar)]);
+    let axes = HbFont::from_bytes(&font_bytes, 0).get_variation_axes();
+    assert_eq!(axes.len(), 2);
+    assert_eq!(axes[0], VariationAxis { tag: WGHT, min_value: 100.0, default_value: 400.0, max_value: 900.0 });
+    assert_eq!(axes[0].resolve_value(&[(WGHT, 700.0)]), 700.0);
+    assert_eq!(axes[0].resolve_value(&[(WGHT, 1000.0)]), 900.0);
+    assert_eq!(axes[1].resolve_value(&[(WGHT, 700.0)]), 100.0);
+    assert_eq!(axes[1].tag, WDTH);
+
+    // Fonts without fvar behave exactly like without variations
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let variations = [(WGHT, 700.0)];
+    assert!(HbFont::from_bytes(FONT, 0).get_variation_axes().is_empty());
+    assert_eq!(get_font_metrics_freetype_with_variations(FONT, 0, &variations), get_font_metrics_freetype(FONT, 0));
+
+    let get_advances = |hb_font: &HbFont| {
+        let hb_scaled_font = HbScaledFont::from_font(hb_font, 16.0);
+        let hb_buffer = HbBuffer::from_str("Hello");
+        let shaped = shape_word_hb(&hb_buffer, &hb_scaled_font);
+        shaped.glyph_positions.iter().map(|p| p.x_advance).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        get_advances(&HbFont::from_bytes_with_variations(FONT, 0, &variations)),
+        get_advances(&HbFont::from_bytes(FONT, 0)),
+    );
+
+    // Variable font: the advances of the instance are used for shaping
+    let variable_font = build_test_font(&get_test_variable_font_tables(20));
+    let get_advance = |variations: &[(hb_tag_t, f32)]| {
+        let hb_font = HbFont::from_bytes_with_variations(&variable_font, 0, variations);
+        let hb_scaled_font = HbScaledFont::from_font(&hb_font, 1000.0);
+        let hb_buffer = HbBuffer::from_str("a");
+        let advance = shape_word_hb(&hb_buffer, &hb_scaled_font).glyph_positions[0].x_advance;
+        advance as f32 / HB_SCALE_FACTOR
+    };
+    assert_eq!(get_advance(&[]), 500.0);
+    assert_eq!(get_advance(&[(WGHT, 400.0)]), 500.0);
+    assert_eq!(get_advance(&[(WGHT, 650.0)]), 600.0);
+    assert_eq!(get_advance(&[(WGHT, 900.0)]), 700.0);
+    assert_eq!(HbFont::from_bytes_with_variations(&variable_font, 0, &[(WGHT, 650.0)]).get_advance(1), Some(600));
+    assert_eq!(HbFont::from_bytes_with_variations(&variable_font, 0, &[(WGHT, 250.0)]).get_advance(1), Some(500));
+
+    // If FreeType can't select the instance, the metrics are marked as suspect
+    assert!(!get_font_metrics_freetype_with_variations(&variable_font, 0, &[(WGHT, 900.0)]).metrics_suspect);
+    let rejected_fvar = build_test_font(&get_test_variable_font_tables(24));
+    assert!(get_font_metrics_freetype_with_variations(&rejected_fvar, 0, &[(WGHT, 900.0)]).metrics_suspect);
+}
+
+#[test]
+fn test_shape_to_glyph_ids() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    const LATN: hb_tag_t = create_hb_tag(('l', 'a', 't', 'n'));
+    const ENG: hb_tag_t = create_hb_tag(('E', 'N', 'G', ' '));
+
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+
+    let f = hb_font.lookup_glyph_index('f' as u32) as u16;
+    let i = hb_font.lookup_glyph_index('i' as u32) as u16;
+
+    let ligature = shape_to_glyph_ids("fi", &hb_scaled_font, LATN, ENG);
+    assert_eq!(ligature.len(), 1);
+    assert!(ligature[0] != f && ligature[0] != i);
+
+    assert_eq!(shape_to_glyph_ids("if", &hb_scaled_font, LATN, ENG), vec![i, f]);
+}
+
+#[test]
+fn test_font_parse_errors() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let tag = |t: &[u8;4]| u32::from_be_bytes(*t);
+
+    assert!(HbFont::try_from_bytes(FONT, 0).is_ok());
+    assert_eq!(HbFont::try_from_bytes(b"not a font", 0).err(), Some(FontParseError::NotAFont));
+    assert_eq!(HbFont::try_from_bytes(FONT, 1).err(), Some(FontParseError::UnsupportedCollectionIndex { index: 1, count: 1 }));
+
+    let mut head = vec![0; 54];
+    assert_eq!(
+        HbFont::try_from_bytes(&build_test_font(&[(*b"head", head.clone())]), 0).err(),
+        Some(FontParseError::MalformedTable { tag: tag(b"head"), reason: "wrong magic number" }),
+    );
+
+    head[12..16].copy_from_slice(&0x5F0F3CF5_u32.to_be_bytes());
+    assert_eq!(
+        HbFont::try_from_bytes(&build_test_font(&[(*b"head", head)]), 0).err(),
+        Some(FontParseError::MissingTable(tag(b"hhea"))),
+    );
+
+    // Replace the cmap table with one that only has a (Macintosh, Roman) subtable
+    let (cmap_offset, _) = get_table_range(FONT, 0, *b"cmap").unwrap();
+    let mut no_unicode_cmap = FONT.to_vec();
+    no_unicode_cmap[(cmap_offset + 2)..(cmap_offset + 8)].copy_from_slice(&[0, 1, 0, 1, 0, 0]);
+    assert_eq!(HbFont::try_from_bytes(&no_unicode_cmap, 0).err(), Some(FontParseError::NoCmapSubtable));
+}
+
+#[test]
+fn test_get_vertical_advance_without_vmtx() {
+
+    // KoHo has no vertical metrics, so the advance is synthesized from units_per_em
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let units_per_em = read_u16(FONT, get_table_offset(FONT, 0, *b"head").unwrap() + 18).unwrap();
+    assert!(get_table_offset(FONT, 0, *b"vmtx").is_none());
+    assert!(HbFont::try_from_bytes(FONT, 0).is_ok());
+    assert_eq!(HbFont::from_bytes(FONT, 0).get_vertical_advance(10), units_per_em);
+
+    let mut vhea = vec![0; 36];
+    vhea[10..12].copy_from_slice(&1200_u16.to_be_bytes()); // advance height max
+    vhea[34..36].copy_from_slice(&1_u16.to_be_bytes());    // number of long vertical metrics
+    let vmtx: Vec<u8> = vec![3, 232, 0, 0]; // glyph 0: advance 1000
+
+    let font_bytes = build_test_font(&[(*b"vhea", vhea), (*b"vmtx", vmtx)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert_eq!(hb_font.get_vertical_advance(0), 1000);
+    assert_eq!(hb_font.get_vertical_advance(5), 1000);
+
+    assert_eq!(HbFont::from_bytes(&build_test_font(&[]), 0).get_vertical_advance(0), 0);
+}
+
+#[test]
+fn test_get_glyph_name() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    assert_eq!(hb_font.get_glyph_name(0), Some(".notdef".to_string()));
+    let a_glyph = hb_font.lookup_glyph_index('a' as u32) as u16;
+    assert_eq!(hb_font.get_glyph_name(a_glyph), Some("a".to_string()));
+    assert_eq!(hb_font.get_glyph_name(u16::MAX), None);
+
+    let post_with_version = |version: u32, data: &[u8]| {
+        let mut post = vec![0; 32];
+        post[0..4].copy_from_slice(&version.to_be_bytes());
+        post.extend_from_slice(data);
+        post
+    };
+
+    // format 1.0: standard Macintosh names
+    let font_bytes = build_test_font(&[(*b"post", post_with_version(0x00010000, &[]))]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert_eq!(hb_font.get_glyph_name(3), Some("space".to_string()));
+    assert_eq!(hb_font.get_glyph_name(257), Some("dcroat".to_string()));
+
+    // format 2.0: 3 glyphs, one standard name and two custom names
+    let format_2 = [0, 3, 0, 36, 1, 2, 1, 3, 3, b'f', b'_', b'i', 7, b'u', b'n', b'i', b'0', b'E', b'3', b'F'];
+    let font_bytes = build_test_font(&[(*b"post", post_with_version(0x00020000, &format_2))]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert_eq!(hb_font.get_glyph_name(0), Some("A".to_string()));
+    assert_eq!(hb_font.get_glyph_name(1), Some("f_i".to_string()));
+    assert_eq!(hb_font.get_glyph_name(2), Some("uni0E3F".to_string()));
+    assert_eq!(hb_font.get_glyph_name(3), None);
+
+    // format 3.0: no glyph names
+    let font_bytes = build_test_font(&[(*b"post", post_with_version(0x00030000, &[]))]);
+    assert_eq!(HbFont::from_bytes(&font_bytes, 0).get_glyph_name(0), None);
+}
+
+#[test]
+fn test_kern_between() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let latn = create_hb_tag(('l', 'a', 't', 'n'));
+    let dflt = create_hb_tag(('d', 'f', 'l', 't'));
+    let kern = |a: char, b: char| {
+        hb_font.kern_between(hb_font.lookup_glyph_index(a as u32) as u16, hb_font.lookup_glyph_index(b as u32) as u16, latn, dflt)
+    };
+
+    // Same values as the difference of the shaped advances (with kerning) and the hmtx advances
+    assert_eq!(kern('A', 'V'), -80);
+    assert_eq!(kern('T', 'o'), -150);
+    assert_eq!(kern('H', 'H'), 0);
+
+    // legacy kern table, version 0 with one horizontal format 0 subtable (glyphs 4 + 7 = -50)
+    let kern_table = vec![
+        0, 0, 0, 1,
+        0, 0, 0, 20, 0, 1,
+        0, 1, 0, 6, 0, 0, 0, 0,
+        0, 4, 0, 7, 0xFF, 0xCE,
+    ];
+    let font_bytes = build_test_font(&[(*b"kern", kern_table.clone())]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert_eq!(hb_font.kern_between(4, 7, latn, dflt), -50);
+    assert_eq!(hb_font.kern_between(7, 4, latn, dflt), 0);
+
+    // GPOS pair adjustments (format 1, x advance only) in two subtables of an extension lookup:
+    // glyphs 1 + 2 = -30 in the first subtable, glyphs 4 + 7 = -70 in the second one
+    let pair_subtable = |left: u16, right: u16, x_advance: i16| {
+        to_be_bytes(&[1, 18, 0x0004, 0, 1, 12, 1, right, x_advance as u16, 1, 1, left])
+    };
+    let gpos = build_test_layout_table(*b"latn", &[(*b"kern", 0)], &[(9, vec![
+        build_test_extension_subtable(2, pair_subtable(1, 2, -30)),
+        build_test_extension_subtable(2, pair_subtable(4, 7, -70)),
+    ])]);
+    let font_bytes = build_test_font(&[(*b"GPOS", gpos), (*b"kern", kern_table.clone())]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert_eq!(hb_font.kern_between(1, 2, latn, dflt), -30);
+    assert_eq!(hb_font.kern_between(4, 7, latn, dflt), -70);
+
+    // GPOS without a kern feature: falls back to the legacy kern table
+    let gpos = build_test_layout_table(*b"latn", &[(*b"mark", 0)], &[(9, vec![
+        build_test_extension_subtable(2, pair_subtable(4, 7, -70)),
+    ])]);
+    let font_bytes = build_test_font(&[(*b"GPOS", gpos), (*b"kern", kern_table)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert_eq!(hb_font.kern_between(4, 7, latn, dflt), -50);
+}
+
+#[test]
+fn test_segment_by_script() {
+
+    const LATN: hb_tag_t = create_hb_tag(('l', 'a', 't', 'n'));
+    const ARAB: hb_tag_t = create_hb_tag(('a', 'r', 'a', 'b'));
+    const DFLT: hb_tag_t = create_hb_tag(('D', 'F', 'L', 'T'));
+
+    let text = "(Hello, \u{645}\u{631}\u{62D}\u{628}\u{627}! world)";
+    let runs = segment_by_script(text);
+    let scripts = runs.iter().map(|run| run.script).collect::<Vec<_>>();
+    let texts = runs.iter().map(|run| &text[run.byte_range.clone()]).collect::<Vec<_>>();
+
+    // Spaces and punctuation stick to the run before them, the leading "(" to the first run
+    assert_eq!(scripts, vec![LATN, ARAB, LATN]);
+    assert_eq!(texts, vec!["(Hello, ", "\u{645}\u{631}\u{62D}\u{628}\u{627}! ", "world)"]);
+
+    assert_eq!(segment_by_script("12 + 3").iter().map(|run| run.script).collect::<Vec<_>>(), vec![DFLT]);
+    assert!(segment_by_script("").is_empty());
+}
+
+#[test]
+fn test_shape_bidi() {
+
+    let ltr = TextDirection::LeftToRight;
+    let rtl = TextDirection::RightToLeft;
+
+    // "abc \u{5E9}\u{5DC}\u{5D5}\u{5DD} def": the Hebrew word is embedded in left-to-right text
+    let mixed = "abc \u{5E9}\u{5DC}\u{5D5}\u{5DD} def".chars().collect::<Vec<_>>();
+    assert_eq!(get_bidi_levels(&mixed, ltr), vec![0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0]);
+
+    // Arabic text with a number: the number is not reversed
+    let arabic = "\u{627}\u{628}\u{62A} 12 \u{62B}\u{62C}".chars().collect::<Vec<_>>();
+    assert_eq!(get_bidi_levels(&arabic, rtl), vec![1, 1, 1, 1, 2, 2, 1, 1, 1]);
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+
+    let shaped = shape_bidi(&arabic, rtl, &hb_scaled_font);
+    let clusters = shaped.glyph_infos.iter().map(|i| i.cluster).collect::<Vec<_>>();
+    assert_eq!(clusters, vec![8, 7, 6, 4, 5, 3, 2, 1, 0]);
+    assert_eq!(shaped.direction, rtl);
+
+    let shaped = shape_bidi(&mixed, ltr, &hb_scaled_font);
+    let clusters = shaped.glyph_infos.iter().map(|i| i.cluster).collect::<Vec<_>>();
+    assert_eq!(clusters, vec![0, 1, 2, 3, 7, 6, 5, 4, 8, 9, 10, 11]);
+}
+
+#[test]
+fn test_shape_with_options() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+    let glyph_count = |text, options: &ShapingOptions| shape_with_options(text, &hb_scaled_font, options).glyph_infos.len();
+    let first_advance = |text, options: &ShapingOptions| shape_with_options(text, &hb_scaled_font, options).glyph_positions[0].x_advance;
+
+    let default_options = ShapingOptions::default();
+    let no_ligatures = ShapingOptions { ligatures: false, .. ShapingOptions::default() };
+    let no_kerning = ShapingOptions { kerning: false, .. ShapingOptions::default() };
+
+    assert_eq!(glyph_count("fi", &default_options), 1);
+    assert_eq!(glyph_count("fi", &no_ligatures), 2);
+
+    // "AV" is kerned in KoHo
+    assert!(first_advance("AV", &default_options) < first_advance("AV", &no_kerning));
+    assert_eq!(first_advance("AV", &no_kerning), first_advance("A", &no_kerning));
+
+    // Custom features: stylistic alternates for the digits in weblysleek
+    const WEBLYSLEEK: &[u8] = include_bytes!("../assets/fonts/weblysleekuil.ttf");
+    const SALT_TAG: hb_tag_t = create_hb_tag(('s', 'a', 'l', 't'));
+    let hb_font = HbFont::from_bytes(WEBLYSLEEK, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+    let salt = ShapingOptions { custom_features: vec![(SALT_TAG, 1)], .. ShapingOptions::default() };
+    let default_zero = shape_with_options("0", &hb_scaled_font, &default_options).glyph_infos[0].codepoint;
+    let salt_zero = shape_with_options("0", &hb_scaled_font, &salt).glyph_infos[0].codepoint;
+    assert_ne!(default_zero, salt_zero);
+}
+
+#[test]
+fn test_hb_font_from_file() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+
+    // Unique per process, so that concurrent test runs don't overwrite each other's file
+    let path = std::env::temp_dir().join(format!("azul_test_hb_font_from_file_{}.ttf", std::process::id()));
+    std::fs::write(&path, FONT).unwrap();
+
+    let font_file = FontFile::open(&path).unwrap();
+    assert_eq!(font_file.as_bytes(), FONT);
+
+    let hb_font = HbFont::from_file(&font_file, 0).unwrap();
+    let hb_font_from_bytes = HbFont::from_bytes(FONT, 0);
+    let glyph_ids = |font: &HbFont| {
+        let shaped_word = shape_with_options("AVfi", &HbScaledFont::from_font(font, 16.0), &ShapingOptions::default());
+        shaped_word.glyph_infos.iter().map(|i| i.codepoint).collect::<Vec<_>>()
+    };
+
+    assert_eq!(glyph_ids(&hb_font), glyph_ids(&hb_font_from_bytes));
+    assert_eq!(hb_font.get_advance(3), hb_font_from_bytes.get_advance(3));
+    assert_eq!(HbFont::from_file(&font_file, 1).err(), Some(FontParseError::UnsupportedCollectionIndex { index: 1, count: 1 }));
+
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(FontFile::open(&path).err(), Some(FontParseError::FileNotReadable));
+}
+
+#[test]
+fn test_get_vertical_glyph_rotations() {
+
+    use azul_core::app_resources::HbVarIntT;
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+
+    assert_eq!(get_vertical_orientation('A'), VerticalOrientation::Rotated);
+    assert_eq!(get_vertical_orientation('漢'), VerticalOrientation::Upright);
+    assert_eq!(get_vertical_orientation('、'), VerticalOrientation::TransformedUpright);
+    assert_eq!(get_vertical_orientation('「'), VerticalOrientation::TransformedRotated);
+
+    let chars = "漢A「「".chars().collect::<Vec<_>>();
+    let nominal_bracket = hb_font.lookup_glyph_index('「' as u32);
+    let glyph_info = |cluster: u32, codepoint: u32| GlyphInfo { codepoint, mask: 0, cluster, var1: HbVarIntT { u32: 0 }, var2: HbVarIntT { u32: 0 } };
+    let glyph_infos = vec![
+        glyph_info(0, 0),
+        glyph_info(1, hb_font.lookup_glyph_index('A' as u32)),
+        glyph_info(2, nominal_bracket),        // no vertical alternate
+        glyph_info(3, nominal_bracket + 1),    // replaced by the `vert` feature
+    ];
+
+    assert_eq!(get_vertical_glyph_rotations(&chars, &glyph_infos, &hb_font), vec![false, true, true, false]);
+}
+
+#[test]
+fn test_shape_vertical() {
+
+    let font = build_test_cjk_font();
+    let hb_font = HbFont::from_bytes(&font, 0);
+    let scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+
+    // "「" is replaced by its vertical alternate, "」" has none and is rotated,
+    // "A" is set sideways and advances by its horizontal advance
+    let chars = "漢「」A".chars().collect::<Vec<_>>();
+    let scaled_word = shape_vertical(&chars, &scaled_font);
+
+    assert_eq!(scaled_word.glyph_infos.iter().map(|i| i.codepoint).collect::<Vec<_>>(), vec![2, 9, 8, 6]);
+    assert_eq!(scaled_word.glyph_infos.iter().map(|i| i.cluster).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    assert_eq!(scaled_word.rotated_glyphs, vec![false, false, true, true]);
+    // 20px, 20px, 16px and 9.6px (rounded to 1/128 px)
+    assert_eq!(scaled_word.glyph_positions.iter().map(|p| p.x_advance).collect::<Vec<_>>(), vec![2560, 2560, 2048, 1229]);
+    assert!(scaled_word.glyph_positions.iter().all(|p| p.y_advance == 0));
+}
+
+#[test]
+fn test_count_fonts() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    assert_eq!(count_fonts(FONT), 1);
+
+    let mut collection = b"ttcf".to_vec();
+    collection.extend_from_slice(&[0, 1, 0, 0]);    // version
+    collection.extend_from_slice(&3_u32.to_be_bytes()); // number of fonts
+    assert_eq!(count_fonts(&collection), 3);
+
+    assert_eq!(count_fonts(&collection[..10]), 0);
+    assert_eq!(count_fonts(b"not a font"), 0);
+    assert_eq!(count_fonts(&[]), 0);
+}
+
+#[test]
+fn test_get_table_ref() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+
+    for tag in &[*b"head", *b"hhea", *b"hmtx", *b"GPOS", *b"GDEF", *b"glyf", *b"loca", *b"post", *b"vmtx", *b"COLR"] {
+        let tag = u32::from_be_bytes(*tag);
+        assert_eq!(hb_font.get_table_ref(tag).map(|t| t.to_vec()), hb_font.get_table(tag));
+    }
+
+    // The table is borrowed from the font data, not copied
+    let head = hb_font.get_table_ref(u32::from_be_bytes(*b"head")).unwrap();
+    let font_start = FONT.as_ptr() as usize;
+    let head_start = head.as_ptr() as usize;
+    assert!(head_start >= font_start && head_start < font_start + FONT.len());
+}
+
+#[test]
+fn test_get_table_ref_shapes_identically() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    const LATN: hb_tag_t = create_hb_tag(('l', 'a', 't', 'n'));
+    const DFLT: hb_tag_t = create_hb_tag(('d', 'f', 'l', 't'));
+
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let owned_font_bytes = FONT.to_vec();
+    let owned_hb_font = HbFont::from_bytes(&owned_font_bytes, 0);
+
+    let shape = |font: &HbFont| {
+        let shaped_word = shape_with_options("AVATAR Tofi", &HbScaledFont::from_font(font, 16.0), &ShapingOptions::default());
+        shaped_word.glyph_infos.iter().map(|i| i.codepoint)
+            .zip(shaped_word.glyph_positions.iter().map(|p| (p.x_advance, p.x_offset, p.y_offset)))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(shape(&hb_font), shape(&owned_hb_font));
+
+    // The advances and kerning read from the borrowed tables are the same
+    // as the ones read from copies of the tables
+    let hhea = hb_font.get_table(create_hb_tag(('h', 'h', 'e', 'a'))).unwrap();
+    let hmtx = hb_font.get_table(create_hb_tag(('h', 'm', 't', 'x'))).unwrap();
+    let gpos = hb_font.get_table(create_hb_tag(('G', 'P', 'O', 'S'))).unwrap();
+    let a = hb_font.lookup_glyph_index('A' as u32) as u16;
+    let v = hb_font.lookup_glyph_index('V' as u32) as u16;
+
+    for glyph_index in &[a, v] {
+        assert_eq!(hb_font.get_advance(*glyph_index), get_metrics_advance(&hhea, &hmtx, *glyph_index));
+        assert_eq!(hb_font.get_advance(*glyph_index), owned_hb_font.get_advance(*glyph_index));
+    }
+
+    let copied_kerning = get_lang_sys_feature_lookups(&gpos, LATN, DFLT, KERN_TAG).into_iter()
+        .filter_map(|lookup_idx| get_lookup_pair_adjustment(&gpos, lookup_idx, a, v))
+        .map(|kerning| kerning as i32)
+        .sum::<i32>();
+    assert_eq!(hb_font.kern_between(a, v, LATN, DFLT), copied_kerning);
+    assert_eq!(hb_font.kern_between(a, v, LATN, DFLT), owned_hb_font.kern_between(a, v, LATN, DFLT));
+}
+
+#[test]
+fn test_get_name() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    assert_eq!(hb_font.family_name(), Some("KoHo Light".to_string()));
+    assert_eq!(hb_font.subfamily_name(), Some("Regular".to_string()));
+    assert_eq!(hb_font.get_name(1000), None);
+
+    // Windows record for the family name, only a Macintosh record for the subfamily name
+    let records: &[(u16, u16, u16, u16, &[u8])] = &[
+        (1, 0, 0, 1, b"Mac Family"),
+        (3, 1, 0x0409, 1, &[0, b'W', 0, b'i', 0, b'n', 0x20, 0x3A]),
+        (1, 0, 0, 2, &[b'C', b'a', b'f', 0x8E]),
+    ];
+    let mut name = vec![0, 0];
+    name.extend_from_slice(&(records.len() as u16).to_be_bytes());
+    name.extend_from_slice(&(6 + records.len() as u16 * 12).to_be_bytes());
+    let mut strings = Vec::new();
+    for (platform_id, encoding_id, language_id, name_id, string) in records {
+        for value in &[*platform_id, *encoding_id, *language_id, *name_id, string.len() as u16, strings.len() as u16] {
+            name.extend_from_slice(&value.to_be_bytes());
+        }
+        strings.extend_from_slice(string);
+    }
+    name.extend_from_slice(&strings);
+
+    let font_bytes = build_test_font(&[(*b"name", name)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert_eq!(hb_font.family_name(), Some("Win\u{203A}".to_string()));
+    assert_eq!(hb_font.subfamily_name(), Some("Café".to_string()));
+}
+
+#[test]
+fn test_get_color_glyph_outlines() {
+
+    let i16_bytes = |values: &[i16]| values.iter().flat_map(|v| v.to_be_bytes().to_vec()).collect::<Vec<u8>>();
+
+    // glyph 6: square, all other glyphs are empty
+    let mut glyf = i16_bytes(&[1, 0, 0, 100, 100, 3, 0]);
+    glyf.extend_from_slice(&[0x09, 3]);
+    glyf.extend(i16_bytes(&[0, 100, 0, -100, 0, 0, 100, 0]));
+    let loca = i16_bytes(&[0, 0, 0, 0, 0, 0, 0, (glyf.len() / 2) as i16, (glyf.len() / 2) as i16]);
+
+    let mut tables = get_test_color_tables();
+    tables.extend(vec![(*b"glyf", glyf), (*b"head", vec![0; 54]), (*b"loca", loca)]);
+    let font_bytes = build_test_font(&tables);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    let red = ColorU { r: 255, g: 0, b: 0, a: 255 };
+
+    let layers = hb_font.get_color_glyph(5, 0).unwrap();
+    assert_eq!(layers.len(), 2);
+    assert_eq!((layers[0].glyph_index, layers[0].color), (6, Some(red)));
+    assert_eq!(layers[0].outline, hb_font.get_glyph_outline(6));
+    assert!(layers[0].outline.is_some());
+    assert_eq!((layers[1].glyph_index, layers[1].color, layers[1].outline.clone()), (7, None, None));
+
+    // Out-of-range palette falls back to the default palette, glyphs without layers are not color glyphs
+    assert_eq!(hb_font.get_color_glyph(5, 3), Some(layers));
+    assert_eq!(hb_font.get_color_glyph(6, 0), None);
+}
+
+#[test]
+fn test_clear_caches() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let mut hb_font = HbFont::from_bytes(FONT, 0);
+
+    let get_advances = |font: &HbFont| (0..20).map(|glyph| font.get_advance(glyph)).collect::<Vec<_>>();
+    let shape = |font: &HbFont| {
+        let shaped_word = shape_with_options("AVfi", &HbScaledFont::from_font(font, 16.0), &ShapingOptions::default());
+        shaped_word.glyph_infos.iter().map(|i| i.codepoint).zip(shaped_word.glyph_positions.iter().map(|p| p.x_advance)).collect::<Vec<_>>()
+    };
+
+    let advances = get_advances(&hb_font);
+    assert_eq!(hb_font.cached_advance_count(), 20);
+    let shaped = shape(&hb_font);
+
+    hb_font.clear_caches();
+    assert_eq!(hb_font.cached_advance_count(), 0);
+
+    // Shaping looks the advances up through the cache as well
+    assert_eq!(shape(&hb_font), shaped);
+    assert!(hb_font.cached_advance_count() > 0);
+    assert_eq!(get_advances(&hb_font), advances);
+}
+
+#[test]
+fn test_get_bitmap_glyph() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    assert_eq!(HbFont::from_bytes(FONT, 0).get_bitmap_glyph(3, 20), None);
+
+    // KoHo with a 20ppem CBLC / CBDT strike that only contains glyph 3 (2x2 pixels, uncompressed BGRA)
+    let mut cblc: Vec<u8> = vec![
+        0, 3, 0, 0,         // version 3.0
+        0, 0, 0, 1,         // number of strikes
+        0, 0, 0, 56,        // offset to the index subtable array
+        0, 0, 0, 24,        // size of the index subtables
+        0, 0, 0, 1,         // number of index subtables
+        0, 0, 0, 0,         // color ref
+    ];
+    cblc.extend_from_slice(&[16, 252, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]); // horizontal line metrics
+    cblc.extend_from_slice(&[16, 252, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]); // vertical line metrics
+    cblc.extend_from_slice(&[
+        0, 3, 0, 3,         // first / last glyph
+        20, 20, 32, 1,      // ppem x / y, bit depth, horizontal metrics
+        0, 3, 0, 3,         // first / last glyph of the index subtable
+        0, 0, 0, 8,         // offset to the index subtable
+        0, 1, 0, 1,         // index format 1, image format 1 (small metrics, byte-aligned)
+        0, 0, 0, 4,         // image data offset in CBDT
+        0, 0, 0, 0,         // glyph 3 image offset
+        0, 0, 0, 21,        // end of the glyph 3 image
+    ]);
+
+    let mut cbdt: Vec<u8> = vec![
+        0, 3, 0, 0,         // version 3.0
+        2, 2, 1, 2, 4,      // height, width, bearing x / y, advance
+    ];
+    cbdt.extend_from_slice(&[
+        0, 0, 255, 255,   0, 255, 0, 255,       // red, green
+        255, 0, 0, 255,   0, 0, 0, 0,           // blue, transparent
+    ]);
+
+    let mut tables = Vec::new();
+    let num_tables = read_u16(FONT, 4).unwrap() as usize;
+    for table_idx in 0..num_tables {
+        let record = 12 + table_idx * 16;
+        let tag = [FONT[record], FONT[record + 1], FONT[record + 2], FONT[record + 3]];
+        let (offset, length) = get_table_range(FONT, 0, tag).unwrap();
+        tables.push((tag, FONT[offset..(offset + length)].to_vec()));
+    }
+    tables.push((*b"CBDT", cbdt));
+    tables.push((*b"CBLC", cblc));
+    tables.sort_by_key(|(tag, _)| *tag);
+
+    let font_bytes = build_test_font(&tables);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+
+    let bitmap_glyph = hb_font.get_bitmap_glyph(3, 64).unwrap();
+    assert_eq!(bitmap_glyph.pixels_per_em, 20);
+    assert_eq!((bitmap_glyph.width, bitmap_glyph.height), (2, 2));
+    assert_eq!((bitmap_glyph.left, bitmap_glyph.top), (1, 2));
+    assert_eq!(bitmap_glyph.rgba, vec![
+        255, 0, 0, 255,   0, 255, 0, 255,
+        0, 0, 255, 255,   0, 0, 0, 0,
+    ]);
+
+    // Glyph isn't in the strike
+    assert_eq!(hb_font.get_bitmap_glyph(4, 20), None);
+}
+
+#[test]
+fn test_shape_defaults() {
+
+    // Font with a German `locl` substitution (a -> glyph 2), the defaults are passed explicitly
+    // instead of via `set_shape_defaults`, which would affect other tests running in parallel
+    const DEU: hb_tag_t = create_hb_tag(('D', 'E', 'U', ' '));
+
+    let locl = to_be_bytes(&[1, 6, 1, 1, 1, 1]);
+    let gsub = build_test_layout_table_for_language(*b"latn", Some(*b"DEU "), &[(*b"locl", 0)], &[(1, vec![locl])]);
+    let font_bytes = build_test_font(&[
+        (*b"GSUB", gsub),
+        (*b"cmap", build_test_cmap(&[('a', 1)])),
+        (*b"maxp", to_be_bytes(&[0, 0x5000, 3])),
+    ]);
+
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+    let shape = |defaults| shape_word_hb(&HbBuffer::from_str_with_defaults("a", defaults), &hb_scaled_font).glyph_infos[0].codepoint;
+
+    assert_eq!(shape(ShapeDefaults::default()), 1);
+    assert_eq!(shape(ShapeDefaults { script: None, lang: Some(DEU) }), 2);
+}
+
+#[test]
+fn test_optical_margin_offset() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 100.0);
+    let word = |text: &str| shape_bidi(&text.chars().collect::<Vec<_>>(), TextDirection::LeftToRight, &hb_scaled_font);
+
+    let t_offset = hb_font.optical_margin_offset(&word("To"), true, 100.0);
+    let h_offset = hb_font.optical_margin_offset(&word("Ho"), true, 100.0);
+    let a_offset = hb_font.optical_margin_offset(&word("Ao"), true, 100.0);
+
+    assert!(t_offset < -5.0, "{}", t_offset);
+    assert!(a_offset < -5.0, "{}", a_offset);
+    assert!(h_offset.abs() < 0.5, "{}", h_offset);
+
+    // At the end of the line, the glyph is moved to the right
+    assert!(hb_font.optical_margin_offset(&word("oT"), false, 100.0) > 5.0);
+    assert!(hb_font.optical_margin_offset(&word("oH"), false, 100.0).abs() < 0.5);
+    assert_eq!(hb_font.optical_margin_offset(&word(""), true, 100.0), 0.0);
+}
+
+#[test]
+fn test_gasp_behavior() {
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+
+    // KoHo uses the same behaviour for all sizes
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let all_flags = GaspBehavior { gridfit: true, grayscale: true, symmetric_smoothing: true, symmetric_gridfit: true };
+    assert_eq!(hb_font.gasp_behavior(9), all_flags);
+    assert_eq!(hb_font.gasp_behavior(72), all_flags);
+
+    // Version 1, two ranges: only grid-fitting up to 8 ppem, smoothing above
+    let mut gasp = Vec::new();
+    for value in &[1_u16, 2, 8, 0x0001 | 0x0004, 0xFFFF, 0x0002 | 0x0008] {
+        gasp.extend_from_slice(&value.to_be_bytes());
+    }
+    let font_bytes = build_test_font(&[(*b"gasp", gasp)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+
+    let small = hb_font.gasp_behavior(8);
+    assert!(small.gridfit && small.symmetric_gridfit);
+    assert!(!small.grayscale);
+
+    let large = hb_font.gasp_behavior(9);
+    assert!(large.grayscale && large.symmetric_smoothing);
+    assert!(!large.gridfit);
+
+    // No gasp table
+    let font_bytes = build_test_font(&[]);
+    assert_eq!(HbFont::from_bytes(&font_bytes, 0).gasp_behavior(12), GaspBehavior::default());
+}
+
+#[test]
+fn test_find_unsupported_variation_selectors() {
+
+    // cmap with a format 14 subtable: "a" + VS1 uses the default glyph,
+    // "b" + VS2 is mapped to glyph 7
+    let cmap: Vec<u8> = vec![
+        0, 0,           // version
+        0, 1,           // number of encoding records
+        0, 0, 0, 5, 0, 0, 0, 12, // platform 0 (Unicode), encoding 5, offset 12
+        // format 14 subtable
+        0, 14,          // format
+        0, 0, 0, 49,    // length
+        0, 0, 0, 2,     // number of variation selector records
+        0x00, 0xFE, 0x00, 0, 0, 0, 32, 0, 0, 0, 0, // VS1: default UVS at 32
+        0x00, 0xFE, 0x01, 0, 0, 0, 0, 0, 0, 0, 40, // VS2: non-default UVS at 40
+        0, 0, 0, 1, 0, 0, 0x61, 0, // default UVS: 1 range, "a" (no additional characters)
+        0, 0, 0, 1, 0, 0, 0x62, 0, 7, // non-default UVS: 1 mapping, "b" -> glyph 7
+    ];
+
+    let font_bytes = build_test_font(&[(*b"cmap", cmap)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+
+    assert!(hb_font.supports_variation_sequence('a', '\u{FE00}'));
+    assert!(hb_font.supports_variation_sequence('b', '\u{FE01}'));
+    assert!(!hb_font.supports_variation_sequence('a', '\u{FE01}'));
+    assert!(!hb_font.supports_variation_sequence('b', '\u{FE00}'));
+
+    let text = ['a', '\u{FE00}', 'b', '\u{FE01}', 'a', '\u{FE03}', 'c'];
+    assert_eq!(hb_font.find_unsupported_variation_selectors(&text), vec![
+        UnsupportedVariationSelector { index: 5, base: 'a', selector: '\u{FE03}' },
+    ]);
+
+    // Without a format 14 subtable, no variation sequence is supported
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    assert_eq!(hb_font.find_unsupported_variation_selectors(&['a', '\u{E0100}', 'b']), vec![
+        UnsupportedVariationSelector { index: 1, base: 'a', selector: '\u{E0100}' },
+    ]);
+    assert!(hb_font.find_unsupported_variation_selectors(&['a', 'b']).is_empty());
+}
+
+#[test]
+fn test_supports_horizontal_vertical() {
+
+    // Latin font, only horizontal metrics
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    assert!(hb_font.supports_horizontal());
+    assert!(!hb_font.supports_vertical());
+
+    // Font with only vertical metrics (one record: advance 1000)
+    let mut vhea = vec![0; 36];
+    vhea[35] = 1;
+    let vmtx = vec![0x03, 0xE8, 0, 0];
+    let font_bytes = build_test_font(&[(*b"vhea", vhea.clone()), (*b"vmtx", vmtx)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    assert!(!hb_font.supports_horizontal());
+    assert!(hb_font.supports_vertical());
+
+    // The vhea table announces a record, but there is no vmtx table
+    let font_bytes = build_test_font(&[(*b"vhea", vhea)]);
+    assert!(!HbFont::from_bytes(&font_bytes, 0).supports_vertical());
+}
+
+#[test]
+fn test_shape_isolated_forms() {
+
+    const ARAB: hb_tag_t = create_hb_tag(('a', 'r', 'a', 'b'));
+    const DFLT: hb_tag_t = create_hb_tag(('d', 'f', 'l', 't'));
+
+    // GSUB: the fina, init and medi features substitute glyph 1 (beh) with glyph 4, 2 and 3
+    // (single substitutions, format 2, coverage table at offset 8)
+    let substitute = |glyph: u16| vec![to_be_bytes(&[2, 8, 1, glyph, 1, 1, 1])];
+    let gsub = build_test_layout_table(
+        *b"arab",
+        &[(*b"fina", 0), (*b"init", 1), (*b"medi", 2)],
+        &[(1, substitute(4)), (1, substitute(2)), (1, substitute(3))],
+    );
+    let cmap = build_test_cmap(&[('\u{0628}', 1)]);
+    let maxp = to_be_bytes(&[0, 0x5000, 5]);
+
+    let font_bytes = build_test_font(&[(*b"GSUB", gsub), (*b"cmap", cmap), (*b"maxp", maxp)]);
+    let hb_font = HbFont::from_bytes(&font_bytes, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+
+    assert_eq!(shape_isolated_forms('\u{0628}', &hb_scaled_font, ARAB, DFLT), JoiningForms {
+        isolated: 1,
+        initial: 2,
+        medial: 3,
+        final_: 4,
+    });
+
+    // Characters that don't have positional forms (or aren't in the font) fall back to the isolated form
+    const FONT: &[u8] = include_bytes!("../assets/fonts/KoHo-Light.ttf");
+    const LATN: hb_tag_t = create_hb_tag(('l', 'a', 't', 'n'));
+    let hb_font = HbFont::from_bytes(FONT, 0);
+    let hb_scaled_font = HbScaledFont::from_font(&hb_font, 16.0);
+    let a = hb_font.lookup_glyph_index('a' as u32) as u16;
+    assert_eq!(shape_isolated_forms('a', &hb_scaled_font, LATN, DFLT), JoiningForms {
+        isolated: a,
+        initial: a,
+        medial: a,
+        final_: a,
+    });
 }
@@ -32,6 +32,7 @@ use azul_core::{
     FastHashMap,
     window::{RendererType, WindowCreateOptions, WindowSize, DebugState, WindowState, FullWindowState},
     dom::{DomId, NodeId, ScrollTagId},
+    diff::DomDiff,
     gl::GlShader,
     traits::Layout,
     ui_state::UiState,
@@ -448,6 +449,9 @@ impl<T: 'static> App<T> {
 
                                 match state {
                                     Pressed => {
+                                        if !full_window_state.mouse_state.mouse_down() {
+                                            full_window_state.mouse_state.mouse_down_position = full_window_state.mouse_state.cursor_position.get_position();
+                                        }
                                         match button {
                                             Left => full_window_state.mouse_state.left_down = true,
                                             Right => full_window_state.mouse_state.right_down = true,
@@ -462,6 +466,9 @@ impl<T: 'static> App<T> {
                                             Middle => full_window_state.mouse_state.middle_down = false,
                                             _ => full_window_state.mouse_state.left_down = false,
                                         }
+                                        if !full_window_state.mouse_state.mouse_down() {
+                                            full_window_state.mouse_state.mouse_down_position = None;
+                                        }
                                     },
                                 }
                             }
@@ -924,7 +931,7 @@ fn send_user_event<'a, T>(
                     None => return,
                 };
 
-                let full_window_state = &eld.full_window_states[&glutin_window_id];
+                let full_window_state = eld.full_window_states.get_mut(&glutin_window_id).unwrap();
                 let new_ui_state = call_layout_fn(
                     &*eld.data,
                     eld.gl_context.clone(),
@@ -933,7 +940,15 @@ fn send_user_event<'a, T>(
                     eld.layout_callback,
                 );
 
-                *eld.ui_state_cache.get_mut(&glutin_window_id).unwrap() = new_ui_state;
+                // Carry the focus / hover / active / drag state of the nodes over to the new DOM
+                let old_ui_state = eld.ui_state_cache.get_mut(&glutin_window_id).unwrap();
+                for (dom_id, new_dom_ui_state) in &new_ui_state {
+                    if let Some(old_dom_ui_state) = old_ui_state.get(dom_id) {
+                        DomDiff::new(&old_dom_ui_state.dom, &new_dom_ui_state.dom).preserve_node_state(dom_id, full_window_state);
+                    }
+                }
+
+                *old_ui_state = new_ui_state;
             } // end of borrowing eld
 
             // optimization: create diff to previous UI State:
@@ -1372,8 +1387,11 @@ fn call_callbacks<T>(
     let mut modifiable_window_state: WindowState = full_window_state.clone().into();
 
     // Run all default callbacks - **before** the user-defined callbacks are run!
+    // See `CallbacksOfHitTest::get_callback_order` for the order of the callbacks.
     for (dom_id, ui_state) in ui_state_map.iter() {
-        for (node_id, callback_results) in callbacks_filter_list[dom_id].nodes_with_callbacks.iter() {
+        let callbacks_of_hit_test = &callbacks_filter_list[dom_id];
+        for node_id in callbacks_of_hit_test.get_nodes_in_event_order(&ui_state.dom.arena.node_layout).iter() {
+            let callback_results = &callbacks_of_hit_test.nodes_with_callbacks[node_id];
             let hit_item = &callback_results.hit_test_item;
             for event_filter in callback_results.default_callbacks.keys() {
 
@@ -1407,6 +1425,7 @@ fn call_callbacks<T>(
                     hit_dom_node: (dom_id.clone(), *node_id),
                     cursor_relative_to_item: hit_item.as_ref().map(|hi| (hi.point_relative_to_item.x, hi.point_relative_to_item.y)),
                     cursor_in_viewport: hit_item.as_ref().map(|hi| (hi.point_in_viewport.x, hi.point_in_viewport.y)),
+                    event: *event_filter,
                 });
 
                 if default_callback_return == Redraw {
@@ -1421,10 +1440,13 @@ fn call_callbacks<T>(
     }
 
     // Run all regular callbacks
-    for dom_id in ui_state_map.keys().cloned() {
-        for (node_id, callback_results) in callbacks_filter_list[&dom_id].nodes_with_callbacks.iter() {
+    for (dom_id, ui_state) in ui_state_map.iter() {
+        let dom_id = dom_id.clone();
+        let callbacks_of_hit_test = &callbacks_filter_list[&dom_id];
+        for node_id in callbacks_of_hit_test.get_nodes_in_event_order(&ui_state.dom.arena.node_layout).iter() {
+            let callback_results = &callbacks_of_hit_test.nodes_with_callbacks[node_id];
             let hit_item = &callback_results.hit_test_item;
-            for callback in callback_results.normal_callbacks.values() {
+            for (event_filter, callback) in callback_results.normal_callbacks.iter() {
 
                 let mut new_focus = None;
 
@@ -1446,6 +1468,7 @@ fn call_callbacks<T>(
                     hit_dom_node: (dom_id.clone(), *node_id),
                     cursor_relative_to_item: hit_item.as_ref().map(|hi| (hi.point_relative_to_item.x, hi.point_relative_to_item.y)),
                     cursor_in_viewport: hit_item.as_ref().map(|hi| (hi.point_in_viewport.x, hi.point_in_viewport.y)),
+                    event: *event_filter,
                 }) == Redraw {
                     ret.callbacks_update_screen = Redraw;
                 }
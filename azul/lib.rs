@@ -372,9 +372,12 @@ pub mod widgets {
             let words = text_layout::split_text_into_words(text);
             let font_metrics = get_font_metrics_freetype(font_bytes, font_index as i32);
             let scaled_words = text_layout::words_to_scaled_words(&words, font_bytes, font_index, font_metrics, SVG_FAKE_FONT_SIZE);
-            let word_positions = text_layout::position_words(&words, &scaled_words, &text_layout_options);
+            let mut word_positions = text_layout::position_words(&words, &scaled_words, &text_layout_options);
 
             let mut inline_text_layout = text_layout::word_positions_to_inline_text_layout(&word_positions, &scaled_words);
+            if horizontal_alignment == StyleTextAlignmentHorz::Justify {
+                text_layout::justify_inline_text_layout(&mut inline_text_layout, &mut word_positions, &words, &scaled_words);
+            }
             inline_text_layout.align_children_horizontal(horizontal_alignment);
 
             let layouted_glyphs = text_layout::get_layouted_glyphs(&word_positions, &scaled_words, &inline_text_layout, LayoutPoint::zero());
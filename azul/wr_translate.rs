@@ -7,6 +7,7 @@
 use webrender::api::{
     LayoutPrimitiveInfo as WrLayoutPrimitiveInfo,
     HitTestItem as WrHitTestItem,
+    ItemTag as WrItemTag,
     FontKey as WrFontKey,
     FontInstanceKey as WrFontInstanceKey,
     ImageKey as WrImageKey,
@@ -70,7 +71,7 @@ use azul_core::{
         DisplayListFrame, LayoutRectContent, DisplayListMsg,
         AlphaType, ImageRendering, StyleBorderRadius,
     },
-    dom::TagId,
+    dom::{TagId, HitTestTag},
     ui_solver::ExternalScrollId,
     window::{LogicalSize, DebugState},
 };
@@ -462,14 +463,14 @@ pub(crate) const fn wr_translate_epoch(epoch: Epoch) -> WrEpoch {
 }
 
 #[inline(always)]
-const fn wr_translate_tag_id(input: TagId) -> (u64, u16) {
-    (input.0, 0)
+const fn wr_translate_tag_id(input: TagId) -> WrItemTag {
+    (input.into_hit_test_tag().0, 0)
 }
 
 pub(crate) fn wr_translate_hittest_item(input: WrHitTestItem) -> HitTestItem {
     HitTestItem {
         pipeline: PipelineId(input.pipeline.0, input.pipeline.1),
-        tag: TagId(input.tag.0),
+        tag: TagId::from_hit_test_tag(HitTestTag(input.tag.0)),
         point_in_viewport: CssLayoutPoint::new(input.point_in_viewport.x, input.point_in_viewport.y),
         point_relative_to_item: CssLayoutPoint::new(input.point_relative_to_item.x, input.point_relative_to_item.y),
     }
@@ -45,7 +45,7 @@ impl Layout for DragMeApp {
 
         Dom::new(NodeType::Div).with_id("container")
             .with_callback(On::MouseOver, update_drag)
-            .with_callback(On::MouseUp, stop_drag)
+            .with_callback(EventFilter::Window(WindowEventFilter::MouseUp), stop_drag)
             .with_child(left)
             .with_child(dragger)
             .with_child(right)